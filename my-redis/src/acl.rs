@@ -0,0 +1,659 @@
+//! Access control lists.
+//!
+//! Connections authenticate as a user, and may then only run the commands,
+//! access the keys and the pub/sub channels the user is granted. Users are
+//! described by rules, as given to `ACL SETUSER` and written in the ACL file:
+//!
+//! - `on`, `off`: allow or refuse authenticating as the user.
+//! - `>password`, `<password`: add or remove a password. `#hash` and `!hash`
+//!   do the same with the SHA256 of the password. `nopass` accepts any
+//!   password, `resetpass` removes the passwords along with `nopass`.
+//! - `~pattern`: grant the keys matching the glob-style pattern. Keys are
+//!   only granted for reading with `%R~pattern`, or writing with
+//!   `%W~pattern`. `allkeys` is `~*`, `resetkeys` removes the patterns.
+//! - `&pattern`: grant the channels matching the pattern. `allchannels` is
+//!   `&*`, `resetchannels` removes the patterns.
+//! - `+command`, `-command`: grant or revoke a command, `+@category` and
+//!   `-@category` every command of a category. `allcommands` is `+@all`,
+//!   `nocommands` is `-@all`.
+//! - `reset`: remove everything, leaving a user disabled and granted nothing.
+//!
+//! New connections are authenticated as the `default` user if it is enabled
+//! with `nopass`. It is granted everything, and requires the password given
+//! by `requirepass` if any.
+//!
+//! Commands are checked when applied, see `Command::apply`. The keys of a
+//! command writing to the key space must be granted for writing, those of
+//! other commands for reading.
+//!
+//! The ACL file holds one user per line, written `user <name> <rule> ...`,
+//! like the reply of `ACL LIST`. It is loaded when the server starts, and
+//! written by `ACL SAVE`.
+
+use crate::db::glob_match;
+use crate::Command;
+
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Categories the commands are grouped in, as listed by `ACL CAT`.
+const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+];
+
+/// The commands, along with their categories.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("get", &["read", "string", "fast"]),
+    ("set", &["write", "string", "slow"]),
+    ("incr", &["write", "string", "fast"]),
+    ("decr", &["write", "string", "fast"]),
+    ("incrby", &["write", "string", "fast"]),
+    ("decrby", &["write", "string", "fast"]),
+    ("incrbyfloat", &["write", "string", "fast"]),
+    ("lpush", &["write", "list", "fast"]),
+    ("rpush", &["write", "list", "fast"]),
+    ("lpop", &["write", "list", "fast"]),
+    ("rpop", &["write", "list", "fast"]),
+    ("lrange", &["read", "list", "slow"]),
+    ("llen", &["read", "list", "fast"]),
+    ("lindex", &["read", "list", "slow"]),
+    ("lset", &["write", "list", "slow"]),
+    ("ltrim", &["write", "list", "slow"]),
+    ("hset", &["write", "hash", "fast"]),
+    ("hget", &["read", "hash", "fast"]),
+    ("hdel", &["write", "hash", "fast"]),
+    ("hgetall", &["read", "hash", "slow"]),
+    ("hmget", &["read", "hash", "fast"]),
+    ("hincrby", &["write", "hash", "fast"]),
+    ("hkeys", &["read", "hash", "slow"]),
+    ("hvals", &["read", "hash", "slow"]),
+    ("hlen", &["read", "hash", "fast"]),
+    ("hexists", &["read", "hash", "fast"]),
+    ("hscan", &["read", "hash", "slow"]),
+    ("sadd", &["write", "set", "fast"]),
+    ("srem", &["write", "set", "fast"]),
+    ("smembers", &["read", "set", "slow"]),
+    ("sismember", &["read", "set", "fast"]),
+    ("scard", &["read", "set", "fast"]),
+    ("sinter", &["read", "set", "slow"]),
+    ("sunion", &["read", "set", "slow"]),
+    ("sdiff", &["read", "set", "slow"]),
+    ("sinterstore", &["write", "set", "slow"]),
+    ("sunionstore", &["write", "set", "slow"]),
+    ("sdiffstore", &["write", "set", "slow"]),
+    ("sscan", &["read", "set", "slow"]),
+    ("zadd", &["write", "sortedset", "fast"]),
+    ("zrange", &["read", "sortedset", "slow"]),
+    ("zrangebyscore", &["read", "sortedset", "slow"]),
+    ("zrank", &["read", "sortedset", "fast"]),
+    ("zincrby", &["write", "sortedset", "fast"]),
+    ("zrem", &["write", "sortedset", "fast"]),
+    ("zcard", &["read", "sortedset", "fast"]),
+    ("zpopmin", &["write", "sortedset", "fast"]),
+    ("zscan", &["read", "sortedset", "slow"]),
+    ("xadd", &["write", "stream", "fast"]),
+    ("xrange", &["read", "stream", "slow"]),
+    ("xrevrange", &["read", "stream", "slow"]),
+    ("xlen", &["read", "stream", "fast"]),
+    ("xtrim", &["write", "stream", "slow"]),
+    ("xread", &["read", "stream", "slow", "blocking"]),
+    ("xgroup", &["write", "stream", "slow"]),
+    ("xreadgroup", &["write", "stream", "slow", "blocking"]),
+    ("xack", &["write", "stream", "fast"]),
+    ("xpending", &["read", "stream", "slow"]),
+    ("xclaim", &["write", "stream", "fast"]),
+    ("expire", &["write", "keyspace", "fast"]),
+    ("pexpire", &["write", "keyspace", "fast"]),
+    ("expireat", &["write", "keyspace", "fast"]),
+    ("pexpireat", &["write", "keyspace", "fast"]),
+    ("ttl", &["read", "keyspace", "fast"]),
+    ("pttl", &["read", "keyspace", "fast"]),
+    ("persist", &["write", "keyspace", "fast"]),
+    ("del", &["write", "keyspace", "slow"]),
+    ("unlink", &["write", "keyspace", "fast"]),
+    ("exists", &["read", "keyspace", "fast"]),
+    ("touch", &["read", "keyspace", "fast"]),
+    ("type", &["read", "keyspace", "fast"]),
+    ("rename", &["write", "keyspace", "slow"]),
+    ("renamenx", &["write", "keyspace", "fast"]),
+    ("copy", &["write", "keyspace", "slow"]),
+    ("keys", &["read", "keyspace", "slow", "dangerous"]),
+    ("scan", &["read", "keyspace", "slow"]),
+    ("publish", &["pubsub", "fast"]),
+    ("subscribe", &["pubsub", "slow"]),
+    ("unsubscribe", &["pubsub", "slow"]),
+    ("multi", &["transaction", "fast"]),
+    ("exec", &["transaction", "slow"]),
+    ("discard", &["transaction", "fast"]),
+    ("watch", &["transaction", "fast"]),
+    ("unwatch", &["transaction", "fast"]),
+    ("eval", &["scripting", "slow"]),
+    ("evalsha", &["scripting", "slow"]),
+    ("script", &["scripting", "slow"]),
+    ("save", &["admin", "slow", "dangerous"]),
+    ("bgsave", &["admin", "slow", "dangerous"]),
+    ("bgrewriteaof", &["admin", "slow", "dangerous"]),
+    ("replicaof", &["admin", "slow", "dangerous"]),
+    ("psync", &["admin", "slow", "dangerous"]),
+    ("replconf", &["admin", "slow", "dangerous"]),
+    ("role", &["admin", "fast", "dangerous"]),
+    ("cluster", &["admin", "slow", "dangerous"]),
+    ("acl", &["admin", "slow", "dangerous"]),
+    ("ping", &["connection", "fast"]),
+    ("hello", &["connection", "fast"]),
+    ("auth", &["connection", "fast"]),
+    ("asking", &["connection", "fast"]),
+];
+
+/// Users of the server, by name.
+#[derive(Debug)]
+pub(crate) struct AclState {
+    users: BTreeMap<String, User>,
+
+    /// ACL file the users are loaded from and saved to, `None` if not
+    /// configured.
+    path: Option<PathBuf>,
+}
+
+/// A user, and what it is granted.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct User {
+    /// Whether connections may authenticate as the user.
+    enabled: bool,
+
+    /// Whether any password is accepted.
+    nopass: bool,
+
+    /// SHA256 of the passwords, hex encoded.
+    passwords: BTreeSet<String>,
+
+    /// Names of the commands granted.
+    commands: BTreeSet<&'static str>,
+
+    /// Rules granting `commands`, starting with `+@all` or `-@all`.
+    command_rules: Vec<String>,
+
+    keys: Vec<KeyPattern>,
+
+    /// Patterns of the channels granted.
+    channels: Vec<String>,
+}
+
+/// Keys granted to a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+/// Why a command is refused to a user.
+#[derive(Debug)]
+pub(crate) enum AclError {
+    /// The command is not granted.
+    Command { user: String, command: String },
+
+    /// One of the keys is not granted.
+    Key,
+
+    /// One of the channels is not granted.
+    Channel,
+}
+
+impl AclState {
+    /// Create the ACL holding the `default` user alone, granted everything
+    /// and requiring `requirepass` if any.
+    pub(crate) fn new(requirepass: Option<&str>) -> AclState {
+        let mut default = User {
+            enabled: true,
+            keys: vec![KeyPattern::new("*", true, true)],
+            channels: vec!["*".to_string()],
+            ..User::default()
+        };
+        default.grant_all(true);
+        match requirepass {
+            Some(password) => {
+                default.passwords.insert(hash(password));
+            }
+            None => default.nopass = true,
+        }
+
+        let mut users = BTreeMap::new();
+        users.insert("default".to_string(), default);
+        AclState { users, path: None }
+    }
+
+    /// Load the users from the ACL file at `path`, which `ACL SAVE` then
+    /// writes to.
+    ///
+    /// The users replace the current ones, except the `default` user which
+    /// is kept if the file does not describe it. Nothing is changed if the
+    /// file is invalid.
+    pub(crate) fn load(&mut self, path: &Path) -> crate::Result<()> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| format!("ERR Error loading the ACL file {:?}: {}", path, err))?;
+
+        let mut users = BTreeMap::new();
+        for (index, line) in data.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (name, rules) = match &fields[..] {
+                [] => continue,
+                ["user", name, rules @ ..] => (name, rules),
+                _ => {
+                    return Err(format!(
+                        "ERR Error in ACL file line {}: should start with user keyword",
+                        index + 1
+                    )
+                    .into())
+                }
+            };
+
+            let mut user = User::new();
+            for rule in rules {
+                user.apply(rule)
+                    .map_err(|err| format!("ERR Error in ACL file line {}: {}", index + 1, err))?;
+            }
+            users.insert(name.to_string(), user);
+        }
+
+        if !users.contains_key("default") {
+            let default = self.users.remove("default").expect("default user");
+            users.insert("default".to_string(), default);
+        }
+        self.users = users;
+        self.path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Load the users from the ACL file again, see `load`.
+    pub(crate) fn reload(&mut self) -> crate::Result<()> {
+        let path = self.path.clone().ok_or_else(no_acl_file)?;
+        self.load(&path)
+    }
+
+    /// Write the users to the ACL file.
+    pub(crate) fn save(&self) -> crate::Result<()> {
+        let path = self.path.as_ref().ok_or_else(no_acl_file)?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let data: String = self.list().into_iter().map(|line| line + "\n").collect();
+        let write = || -> io::Result<()> {
+            fs::write(&tmp, data)?;
+            fs::rename(&tmp, path)
+        };
+        write().map_err(|err| {
+            format!("ERR There was an error trying to save the ACLs: {}", err).into()
+        })
+    }
+
+    /// Returns the user `name`.
+    pub(crate) fn user(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+
+    /// Returns the names of the users.
+    pub(crate) fn usernames(&self) -> impl Iterator<Item = &str> {
+        self.users.keys().map(String::as_str)
+    }
+
+    /// Create the user `name` or change it, applying `rules` in order.
+    ///
+    /// The user is left unchanged if a rule is invalid.
+    pub(crate) fn set_user(&mut self, name: &str, rules: &[String]) -> crate::Result<()> {
+        let mut user = self.users.get(name).cloned().unwrap_or_else(User::new);
+        for rule in rules {
+            user.apply(rule)
+                .map_err(|err| format!("ERR Error in ACL SETUSER modifier '{}': {}", rule, err))?;
+        }
+        self.users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    /// Delete the users `names`, returning the number of users deleted.
+    pub(crate) fn del_users(&mut self, names: &[String]) -> crate::Result<usize> {
+        if names.iter().any(|name| name == "default") {
+            return Err("ERR The 'default' user cannot be removed".into());
+        }
+        Ok(names
+            .iter()
+            .filter(|name| self.users.remove(*name).is_some())
+            .count())
+    }
+
+    /// Describe each user, as listed by `ACL LIST`.
+    pub(crate) fn list(&self) -> Vec<String> {
+        self.users
+            .iter()
+            .map(|(name, user)| format!("user {} {}", name, user.describe()))
+            .collect()
+    }
+
+    /// Returns the user new connections are authenticated as: `default` if
+    /// it is enabled with `nopass`, `None` otherwise.
+    pub(crate) fn default_user(&self) -> Option<String> {
+        let default = &self.users["default"];
+        (default.enabled && default.nopass).then(|| "default".to_string())
+    }
+
+    /// Returns `true` if `password` lets connections authenticate as the
+    /// user `name`.
+    pub(crate) fn authenticate(&self, name: &str, password: &str) -> bool {
+        match self.users.get(name) {
+            Some(user) => user.enabled && (user.nopass || user.passwords.contains(&hash(password))),
+            None => false,
+        }
+    }
+
+    /// Returns `Ok` if the user `name` may run `cmd`, or why it may not.
+    pub(crate) fn check(&self, name: &str, cmd: &Command) -> Result<(), AclError> {
+        let command = cmd.get_name();
+        let refused = || AclError::Command {
+            user: name.to_string(),
+            command: command.to_string(),
+        };
+        let user = self.users.get(name).ok_or_else(refused)?;
+
+        // Unknown commands are replied an error of their own.
+        if !user.commands.contains(command) && is_command(command) {
+            return Err(refused());
+        }
+
+        let write = cmd.is_write();
+        let granted = |key: &str| {
+            user.keys.iter().any(|pattern| {
+                (if write { pattern.write } else { pattern.read })
+                    && glob_match(pattern.pattern.as_bytes(), key.as_bytes())
+            })
+        };
+        if !cmd.keys().into_iter().all(granted) {
+            return Err(AclError::Key);
+        }
+
+        let granted = |channel: &str| {
+            user.channels
+                .iter()
+                .any(|pattern| glob_match(pattern.as_bytes(), channel.as_bytes()))
+        };
+        if !cmd.channels().into_iter().all(granted) {
+            return Err(AclError::Channel);
+        }
+        Ok(())
+    }
+}
+
+impl User {
+    /// Create a user, disabled and granted nothing, as `ACL SETUSER` does.
+    fn new() -> User {
+        let mut user = User::default();
+        user.grant_all(false);
+        user
+    }
+
+    /// Apply `rule` to the user, see the module documentation.
+    fn apply(&mut self, rule: &str) -> Result<(), String> {
+        let syntax_error = || "Syntax error".to_string();
+
+        match rule {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.keys = vec![KeyPattern::new("*", true, true)],
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => self.channels = vec!["*".to_string()],
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => self.grant_all(true),
+            "nocommands" => self.grant_all(false),
+            "reset" => *self = User::new(),
+            _ => match rule.split_at(rule.chars().next().map_or(0, char::len_utf8)) {
+                (">", password) => {
+                    self.passwords.insert(hash(password));
+                    self.nopass = false;
+                }
+                ("<", password) => {
+                    if !self.passwords.remove(&hash(password)) {
+                        return Err("no such password".to_string());
+                    }
+                }
+                ("#", digest) => {
+                    check_hash(digest)?;
+                    self.passwords.insert(digest.to_string());
+                    self.nopass = false;
+                }
+                ("!", digest) => {
+                    check_hash(digest)?;
+                    if !self.passwords.remove(digest) {
+                        return Err("no such password".to_string());
+                    }
+                }
+                ("~", pattern) => self.add_key_pattern(KeyPattern::new(pattern, true, true)),
+                ("%", rest) => {
+                    let (access, pattern) = rest.split_once('~').ok_or_else(syntax_error)?;
+                    let read = access.contains('R');
+                    let write = access.contains('W');
+                    if access.is_empty() || access.chars().any(|c| c != 'R' && c != 'W') {
+                        return Err(syntax_error());
+                    }
+                    self.add_key_pattern(KeyPattern::new(pattern, read, write));
+                }
+                ("&", pattern) => {
+                    if !self.channels.iter().any(|channel| channel == pattern) {
+                        self.channels.push(pattern.to_string());
+                    }
+                }
+                ("+", name) => self.grant(name, true, rule)?,
+                ("-", name) => self.grant(name, false, rule)?,
+                _ => return Err(syntax_error()),
+            },
+        }
+        Ok(())
+    }
+
+    /// Grant or revoke `name`, a command or a category prefixed with `@`,
+    /// given by `rule`.
+    fn grant(&mut self, name: &str, granted: bool, rule: &str) -> Result<(), String> {
+        let unknown = || "Unknown command or category name in ACL".to_string();
+        let name = name.to_lowercase();
+
+        let commands: Vec<&'static str> = match name.strip_prefix('@') {
+            Some("all") => {
+                self.grant_all(granted);
+                return Ok(());
+            }
+            Some(category) => category_commands(category).ok_or_else(unknown)?,
+            None => {
+                let (command, _) = COMMANDS
+                    .iter()
+                    .find(|(command, _)| *command == name)
+                    .ok_or_else(unknown)?;
+                vec![*command]
+            }
+        };
+
+        for command in commands {
+            if granted {
+                self.commands.insert(command);
+            } else {
+                self.commands.remove(command);
+            }
+        }
+        self.command_rules.push(rule.to_lowercase());
+        Ok(())
+    }
+
+    /// Grant or revoke every command.
+    fn grant_all(&mut self, granted: bool) {
+        self.commands = if granted {
+            COMMANDS.iter().map(|(command, _)| *command).collect()
+        } else {
+            BTreeSet::new()
+        };
+        let rule = if granted { "+@all" } else { "-@all" };
+        self.command_rules = vec![rule.to_string()];
+    }
+
+    fn add_key_pattern(&mut self, pattern: KeyPattern) {
+        if !self.keys.contains(&pattern) {
+            self.keys.push(pattern);
+        }
+    }
+
+    /// Returns the flags of the user, as replied by `ACL GETUSER`.
+    pub(crate) fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    /// Returns the SHA256 of the passwords, hex encoded.
+    pub(crate) fn passwords(&self) -> impl Iterator<Item = &str> {
+        self.passwords.iter().map(String::as_str)
+    }
+
+    /// Returns the rules granting the commands.
+    pub(crate) fn describe_commands(&self) -> String {
+        self.command_rules.join(" ")
+    }
+
+    /// Returns the rules granting the keys, empty if none is.
+    pub(crate) fn describe_keys(&self) -> String {
+        let patterns: Vec<String> = self.keys.iter().map(KeyPattern::to_string).collect();
+        patterns.join(" ")
+    }
+
+    /// Returns the rules granting the channels, empty if none is.
+    pub(crate) fn describe_channels(&self) -> String {
+        let patterns: Vec<String> = self
+            .channels
+            .iter()
+            .map(|pattern| format!("&{}", pattern))
+            .collect();
+        patterns.join(" ")
+    }
+
+    /// Returns the rules describing the user, as listed by `ACL LIST`.
+    fn describe(&self) -> String {
+        let mut rules: Vec<String> = self.flags().into_iter().map(str::to_string).collect();
+        rules.extend(self.passwords.iter().map(|digest| format!("#{}", digest)));
+        rules.extend(self.keys.iter().map(KeyPattern::to_string));
+        if self.channels.is_empty() {
+            rules.push("resetchannels".to_string());
+        } else {
+            rules.push(self.describe_channels());
+        }
+        rules.push(self.describe_commands());
+        rules.join(" ")
+    }
+}
+
+impl KeyPattern {
+    fn new(pattern: &str, read: bool, write: bool) -> KeyPattern {
+        KeyPattern {
+            pattern: pattern.to_string(),
+            read,
+            write,
+        }
+    }
+}
+
+impl fmt::Display for KeyPattern {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match (self.read, self.write) {
+            (true, false) => write!(fmt, "%R~{}", self.pattern),
+            (false, true) => write!(fmt, "%W~{}", self.pattern),
+            _ => write!(fmt, "~{}", self.pattern),
+        }
+    }
+}
+
+impl fmt::Display for AclError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AclError::Command { user, command } => write!(
+                fmt,
+                "NOPERM User {} has no permissions to run the '{}' command",
+                user, command
+            ),
+            AclError::Key => "NOPERM No permissions to access a key".fmt(fmt),
+            AclError::Channel => "NOPERM No permissions to access a channel".fmt(fmt),
+        }
+    }
+}
+
+/// Returns the categories, as listed by `ACL CAT`.
+pub(crate) fn categories() -> &'static [&'static str] {
+    CATEGORIES
+}
+
+/// Returns the commands of `category`, `None` if there is no such category.
+pub(crate) fn category_commands(category: &str) -> Option<Vec<&'static str>> {
+    if !CATEGORIES.contains(&category) {
+        return None;
+    }
+    let commands = COMMANDS
+        .iter()
+        .filter(|(_, categories)| categories.contains(&category))
+        .map(|(command, _)| *command)
+        .collect();
+    Some(commands)
+}
+
+/// Returns `true` if `command` is a known command.
+fn is_command(command: &str) -> bool {
+    COMMANDS.iter().any(|(name, _)| *name == command)
+}
+
+/// Returns the SHA256 of `password`, hex encoded.
+fn hash(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Check `digest` is a SHA256, hex encoded.
+fn check_hash(digest: &str) -> Result<(), String> {
+    if digest.len() == 64
+        && digest
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    {
+        Ok(())
+    } else {
+        Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".to_string())
+    }
+}
+
+fn no_acl_file() -> crate::Error {
+    "ERR This Redis instance is not configured to use an ACL file.".into()
+}
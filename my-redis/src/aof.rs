@@ -0,0 +1,243 @@
+//! Append only file.
+//!
+//! Every write applied to the key space is logged as the commands
+//! reproducing it, encoded in RESP like a client would send them. Loading the
+//! file replays the commands through `Command::from_frame`. Commands whose
+//! effect depends on when they are applied, like relative expirations or
+//! generated stream identifiers, are logged in a form reproducing the same
+//! effect, see `Command::propagated`.
+//!
+//! `BGREWRITEAOF` compacts the file: the key space is written as an RDB
+//! preamble, followed by the commands logged while the preamble was written.
+
+use crate::cmd::{Exec, Multi};
+use crate::frame::{self, Frame};
+use crate::{AppendFsync, Command, Connection, Db};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+use tracing::error;
+
+/// Append only file the writes are logged to.
+#[derive(Debug)]
+pub(crate) struct Aof {
+    file: File,
+
+    fsync: AppendFsync,
+
+    /// Set when writes were logged since the file was last flushed to disk.
+    unsynced: bool,
+
+    /// Commands logged since a rewrite started, `None` outside of rewrites.
+    /// They are appended to the rewritten file once its preamble is written.
+    rewrite: Option<Vec<u8>>,
+}
+
+impl Aof {
+    /// Open the file at `path` for appending, creating it if missing.
+    pub(crate) fn open(path: &Path, fsync: AppendFsync) -> io::Result<Aof> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Aof {
+            file,
+            fsync,
+            unsynced: false,
+            rewrite: None,
+        })
+    }
+
+    /// Log `data`, commands encoded by `encode`.
+    ///
+    /// With `AppendFsync::Always`, the file is flushed to disk before
+    /// returning.
+    pub(crate) fn append(&mut self, data: &[u8]) {
+        if let Some(rewrite) = &mut self.rewrite {
+            rewrite.extend_from_slice(data);
+        }
+
+        let result = self.file.write_all(data).and_then(|()| {
+            if self.fsync == AppendFsync::Always {
+                self.file.sync_data()
+            } else {
+                self.unsynced = true;
+                Ok(())
+            }
+        });
+        if let Err(err) = result {
+            error!(cause = %err, "failed to write the append only file");
+        }
+    }
+
+    /// Returns a handle to flush the file to disk, if writes were logged
+    /// since it was last flushed.
+    pub(crate) fn unsynced_file(&mut self) -> io::Result<Option<File>> {
+        if !std::mem::take(&mut self.unsynced) {
+            return Ok(None);
+        }
+        self.file.try_clone().map(Some)
+    }
+
+    /// Returns `true` while a rewrite is in progress.
+    pub(crate) fn is_rewriting(&self) -> bool {
+        self.rewrite.is_some()
+    }
+
+    /// Start holding on to the commands logged, to append them to the
+    /// rewritten file.
+    pub(crate) fn start_rewrite(&mut self) {
+        self.rewrite = Some(vec![]);
+    }
+
+    /// Complete a rewrite: the commands logged since it started are appended
+    /// to `tmp`, holding the preamble, which then replaces the file at `path`.
+    ///
+    /// Must be called with the lock on the database held, so that no command
+    /// is logged meanwhile.
+    pub(crate) fn finish_rewrite(&mut self, tmp: &Path, path: &Path) -> io::Result<()> {
+        let logged = self.rewrite.take().unwrap_or_default();
+
+        let mut file = OpenOptions::new().append(true).open(tmp)?;
+        file.write_all(&logged)?;
+        file.sync_all()?;
+        fs::rename(tmp, path)?;
+
+        // The renamed file is now the one the commands are logged to.
+        self.file = file;
+        self.unsynced = false;
+        Ok(())
+    }
+
+    /// Give up on a rewrite which failed.
+    pub(crate) fn abort_rewrite(&mut self) {
+        self.rewrite = None;
+    }
+}
+
+/// Apply the commands logged in `data` to `db`.
+///
+/// A file ending in the middle of a command, as left by a crash while the
+/// command was written, is loaded up to the last complete command. So is a
+/// transaction missing its `EXEC`.
+///
+/// Returns the length of `data` which was applied.
+pub(crate) fn replay(db: &Db, data: &[u8]) -> crate::Result<usize> {
+    let mut replay = Replay::new();
+    let mut src = Cursor::new(data);
+    let mut applied = 0;
+
+    while (src.position() as usize) < data.len() {
+        let start = src.position();
+        match Frame::check(&mut src) {
+            Ok(()) => {}
+            Err(frame::Error::Incomplete) => break,
+            Err(err) => return Err(invalid(start, err.into())),
+        }
+        src.set_position(start);
+        let frame = Frame::parse(&mut src).map_err(|err| invalid(start, err.into()))?;
+
+        if replay.apply(db, frame).map_err(|err| invalid(start, err))? {
+            applied = src.position() as usize;
+        }
+    }
+
+    Ok(applied)
+}
+
+/// Applies commands logged by `encode`, on behalf of the server itself.
+///
+/// Used to load the append only file, and by followers to apply the writes
+/// streamed by their leader.
+pub(crate) struct Replay {
+    /// Replies are returned by `apply_now` and thrown away.
+    dst: Connection,
+
+    /// Commands queued since `MULTI`, `None` outside of a transaction.
+    transaction: Option<Vec<Command>>,
+}
+
+impl Replay {
+    pub(crate) fn new() -> Replay {
+        Replay {
+            dst: Connection::detached(),
+            transaction: None,
+        }
+    }
+
+    /// Apply the command `frame` to `db`, or queue it within a transaction.
+    ///
+    /// Returns `true` if no transaction is left open, every command received
+    /// so far being applied.
+    pub(crate) fn apply(&mut self, db: &Db, frame: Frame) -> crate::Result<bool> {
+        match Command::from_frame(frame)? {
+            Command::Multi(_) => self.transaction = Some(vec![]),
+            Command::Exec(_) => {
+                let queued = self.transaction.take().ok_or("EXEC without MULTI")?;
+                db.atomically(&[], || {
+                    for cmd in queued {
+                        cmd.apply_now(db, &mut self.dst);
+                    }
+                });
+            }
+            Command::Unknown(cmd) => {
+                return Err(format!("unknown command '{}'", cmd.get_name()).into());
+            }
+            cmd => match &mut self.transaction {
+                Some(queued) => queued.push(cmd),
+                None => {
+                    cmd.apply_now(db, &mut self.dst);
+                }
+            },
+        }
+
+        Ok(self.transaction.is_none())
+    }
+}
+
+/// Encode the commands `frames` the way they are logged to the file and
+/// streamed to followers. Several commands are wrapped in a transaction, so
+/// that they are applied all or none.
+///
+/// An error is returned if a frame is not a command, such as a frame
+/// received from a misbehaving leader.
+pub(crate) fn encode(frames: &[Frame]) -> crate::Result<Vec<u8>> {
+    let mut data = vec![];
+    if frames.len() > 1 {
+        encode_command(&Multi::new().into_frame(), &mut data)?;
+    }
+    for frame in frames {
+        encode_command(frame, &mut data)?;
+    }
+    if frames.len() > 1 {
+        encode_command(&Exec::new().into_frame(), &mut data)?;
+    }
+    Ok(data)
+}
+
+/// Encode a single command. Commands are arrays of bulk strings, integers
+/// being written as strings like clients do.
+fn encode_command(frame: &Frame, out: &mut Vec<u8>) -> crate::Result<()> {
+    match frame {
+        Frame::Array(frames) => {
+            out.extend_from_slice(format!("*{}\r\n", frames.len()).as_bytes());
+            for frame in frames {
+                encode_command(frame, out)?;
+            }
+        }
+        Frame::Bulk(data) => encode_bulk(data, out),
+        Frame::Simple(data) => encode_bulk(data.as_bytes(), out),
+        Frame::Integer(value) => encode_bulk(value.to_string().as_bytes(), out),
+        frame => return Err(format!("{:?} is not part of a command", frame).into()),
+    }
+    Ok(())
+}
+
+fn encode_bulk(data: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(format!("${}\r\n", data.len()).as_bytes());
+    out.extend_from_slice(data);
+    out.extend_from_slice(b"\r\n");
+}
+
+fn invalid(offset: u64, err: crate::Error) -> crate::Error {
+    format!("invalid append only file at offset {}: {}", offset, err).into()
+}
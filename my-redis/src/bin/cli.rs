@@ -1,9 +1,10 @@
-use my_redis::{clients::Client, DEFAULT_PORT};
+use my_redis::{clients::Client, TlsClientConfig, DEFAULT_PORT};
 
 use bytes::Bytes;
 use clap::{Parser, Subcommand};
 use std::convert::Infallible;
 use std::num::ParseIntError;
+use std::path::PathBuf;
 use std::str;
 use std::time::Duration;
 
@@ -23,6 +24,32 @@ struct Cli {
 
     #[clap(long, default_value_t = DEFAULT_PORT)]
     port: u16,
+
+    /// Password to authenticate with, when the server requires one.
+    #[clap(long)]
+    password: Option<String>,
+
+    /// User to authenticate as with `--password`, `default` if not given.
+    #[clap(long)]
+    user: Option<String>,
+
+    /// Connect to the Unix socket at this path instead of the hostname and
+    /// port.
+    #[clap(long, conflicts_with = "cacert")]
+    socket: Option<PathBuf>,
+
+    /// Connect over TLS, checking the certificate of the server is signed by
+    /// this CA certificate and issued for the hostname.
+    #[clap(long)]
+    cacert: Option<PathBuf>,
+
+    /// Certificate presented to the server over TLS, when it requires one.
+    #[clap(long, requires = "key")]
+    cert: Option<PathBuf>,
+
+    /// Private key of the certificate given to `--cert`.
+    #[clap(long, requires = "cert")]
+    key: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -87,7 +114,24 @@ async fn main() -> my_redis::Result<()> {
     let addr = format!("{}:{}", cli.host, cli.port);
 
     // Establish a connection
-    let mut client = Client::connect(&addr).await?;
+    let mut client = match (cli.socket, cli.cacert) {
+        #[cfg(unix)]
+        (Some(path), _) => Client::connect_unix(path).await?,
+        (_, Some(ca_cert_file)) => {
+            let config = TlsClientConfig {
+                ca_cert_file,
+                cert_file: cli.cert,
+                key_file: cli.key,
+            };
+            Client::connect_tls(&addr, &cli.host, &config).await?
+        }
+        _ => Client::connect(&addr).await?,
+    };
+    match (&cli.user, &cli.password) {
+        (Some(user), Some(password)) => client.auth_user(user, password).await?,
+        (None, Some(password)) => client.auth(password).await?,
+        _ => {}
+    }
     println!("successful");
     // Process the requested command
     match cli.command {
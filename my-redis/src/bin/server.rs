@@ -6,11 +6,20 @@
 //!
 //! The `clap` crate is used for parsing arguments.
 
-use my_redis::{server, DEFAULT_PORT};
+use my_redis::{server, Directive, ServerConfig};
 
 use clap::Parser;
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
 use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::signal;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
 
 #[cfg(feature = "otel")]
 // To be able to set the XrayPropagator
@@ -24,41 +33,311 @@ use opentelemetry_aws::trace::XrayPropagator;
 #[cfg(feature = "otel")]
 // The `Ext` traits are to allow the Registry to accept the
 // OpenTelemetry-specific types (such as `OpenTelemetryLayer`)
-use tracing_subscriber::{
-    fmt, layer::SubscriberExt, util::SubscriberInitExt, util::TryInitError, EnvFilter,
-};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, util::TryInitError};
 
 #[tokio::main]
 pub async fn main() -> my_redis::Result<()> {
-    set_up_logging()?;
-
     let cli = Cli::parse();
-    let port = cli.port.unwrap_or(DEFAULT_PORT);
 
-    // Bind a TCP listener
-    let listener = TcpListener::bind(&format!("127.0.0.1:{}", port)).await?;
+    let mut directives = match &cli.config {
+        Some(path) => {
+            let src = std::fs::read_to_string(path)
+                .map_err(|err| format!("cannot read {:?}: {}", path, err))?;
+            Directive::parse_file(&src)?
+        }
+        None => vec![],
+    };
+    // Options given on the command line override the config file.
+    directives.extend(cli.directives());
+    let config = ServerConfig::from_directives(&directives)?;
+
+    set_up_logging(config.loglevel)?;
 
-    server::run(listener, signal::ctrl_c()).await;
+    // Bind a TCP listener per address, unless the port is 0 to only listen
+    // on the Unix socket.
+    let mut listeners = vec![];
+    if config.port != 0 {
+        for addr in &config.bind {
+            listeners.push(TcpListener::bind((&addr[..], config.port)).await?);
+        }
+    }
+    #[cfg(unix)]
+    let unix_listener = match &config.unixsocket {
+        Some(path) => Some(bind_unix(path, config.unixsocketperm)?),
+        None => None,
+    };
+    #[cfg(not(unix))]
+    let unix_listener: Option<TcpListener> = match &config.unixsocket {
+        Some(_) => return Err("Unix sockets are not supported on this platform".into()),
+        None => None,
+    };
 
-    Ok(())
+    let unixsocket = config.unixsocket.clone();
+    let shutdown = signal::ctrl_c();
+    let res = match (listeners.is_empty(), unix_listener) {
+        (false, Some(unix_listener)) => {
+            server::run_with((listeners, unix_listener), config, shutdown).await
+        }
+        (false, None) => server::run_with(listeners, config, shutdown).await,
+        (true, Some(unix_listener)) => server::run_with(unix_listener, config, shutdown).await,
+        (true, None) => Err("port 0 requires a Unix socket to listen on".into()),
+    };
+
+    if let Some(path) = &unixsocket {
+        let _ = std::fs::remove_file(path);
+    }
+    res
 }
 
 #[derive(Parser, Debug)]
 #[clap(name = "mini-redis-server", version, author, about = "A Redis server")]
 struct Cli {
+    /// Config file written like `redis.conf`, one directive per line. The
+    /// options below override it.
+    config: Option<PathBuf>,
+
+    /// Addresses to listen on, `127.0.0.1` by default.
+    #[clap(long, num_args = 1..)]
+    bind: Option<Vec<String>>,
+
     #[clap(long)]
     port: Option<u16>,
+
+    /// Maximum number of concurrent connections, 250 by default.
+    #[clap(long)]
+    maxclients: Option<usize>,
+
+    /// Close the connection of clients idle for this many seconds, 0 to keep
+    /// them open.
+    #[clap(long)]
+    timeout: Option<u64>,
+
+    /// Number of messages buffered per pub/sub channel, 1024 by default.
+    #[clap(long)]
+    pubsub_buffer_size: Option<usize>,
+
+    /// Most verbose events logged: `debug`, `verbose`, `notice`, `warning` or
+    /// `nothing`. `RUST_LOG` is used by default.
+    #[clap(long)]
+    loglevel: Option<String>,
+
+    /// Time in milliseconds a Lua script runs before other clients are
+    /// replied BUSY. The script is then aborted unless it wrote. 0 replies
+    /// BUSY right away, and never aborts scripts.
+    #[clap(long)]
+    lua_time_limit: Option<u64>,
+
+    /// Directory the RDB file is stored in.
+    #[clap(long)]
+    dir: Option<PathBuf>,
+
+    /// Name of the RDB file, `dump.rdb` by default.
+    #[clap(long)]
+    dbfilename: Option<PathBuf>,
+
+    /// Snapshot rule written "<seconds> <changes>", may be repeated. An empty
+    /// rule disables the automatic snapshots.
+    #[clap(long)]
+    save: Option<Vec<String>>,
+
+    /// Log the writes to the append only file, and load the key space from
+    /// it on startup.
+    #[clap(long)]
+    appendonly: bool,
+
+    /// Name of the append only file, `appendonly.aof` by default.
+    #[clap(long)]
+    appendfilename: Option<PathBuf>,
+
+    /// When the append only file is flushed to disk: `always`, `everysec` or
+    /// `no`.
+    #[clap(long)]
+    appendfsync: Option<String>,
+
+    /// Leader to follow from startup, written "<host> <port>".
+    #[clap(long)]
+    replicaof: Option<String>,
+
+    /// Whether writes from clients are refused while following a leader,
+    /// `true` by default.
+    #[clap(long)]
+    replica_read_only: Option<bool>,
+
+    /// Enable cluster mode, with the topology kept in this file. The file is
+    /// created if missing, holding this node alone.
+    #[clap(long)]
+    cluster_config_file: Option<PathBuf>,
+
+    /// Require clients to authenticate with this password before issuing
+    /// commands.
+    #[clap(long)]
+    requirepass: Option<String>,
+
+    /// Password to authenticate with to the leader, when it requires one.
+    #[clap(long)]
+    masterauth: Option<String>,
+
+    /// ACL file the users are loaded from on startup, and saved to by
+    /// `ACL SAVE`.
+    #[clap(long)]
+    aclfile: Option<PathBuf>,
+
+    /// Accept TLS connections only, presenting this certificate in PEM.
+    /// Requires `--tls-key-file`. The Unix socket stays plaintext.
+    #[clap(long)]
+    tls_cert_file: Option<PathBuf>,
+
+    /// Private key of the certificate given to `--tls-cert-file`, in PEM.
+    #[clap(long)]
+    tls_key_file: Option<PathBuf>,
+
+    /// Require clients to present a certificate signed by this CA
+    /// certificate, in PEM.
+    #[clap(long)]
+    tls_ca_cert_file: Option<PathBuf>,
+
+    /// Listen on a Unix socket at this path as well, or only with `--port 0`.
+    #[clap(long)]
+    unixsocket: Option<PathBuf>,
+
+    /// Permissions of the Unix socket, in octal such as `770`.
+    #[clap(long)]
+    unixsocketperm: Option<String>,
+}
+
+impl Cli {
+    /// Returns the options given on the command line as config file
+    /// directives.
+    fn directives(&self) -> Vec<Directive> {
+        let mut directives = vec![];
+        let mut push = |name: &str, args: Vec<String>| directives.push(Directive::new(name, args));
+        let path = |path: &PathBuf| vec![path.to_string_lossy().into_owned()];
+
+        if let Some(addrs) = &self.bind {
+            push("bind", addrs.clone());
+        }
+        if let Some(port) = self.port {
+            push("port", vec![port.to_string()]);
+        }
+        if let Some(maxclients) = self.maxclients {
+            push("maxclients", vec![maxclients.to_string()]);
+        }
+        if let Some(timeout) = self.timeout {
+            push("timeout", vec![timeout.to_string()]);
+        }
+        if let Some(size) = self.pubsub_buffer_size {
+            push("pubsub-buffer-size", vec![size.to_string()]);
+        }
+        if let Some(level) = &self.loglevel {
+            push("loglevel", vec![level.clone()]);
+        }
+        if let Some(limit) = self.lua_time_limit {
+            push("lua-time-limit", vec![limit.to_string()]);
+        }
+        if let Some(dir) = &self.dir {
+            push("dir", path(dir));
+        }
+        if let Some(filename) = &self.dbfilename {
+            push("dbfilename", path(filename));
+        }
+        for rule in self.save.iter().flatten() {
+            // `--save ""` disables the automatic snapshots.
+            match rule.trim() {
+                "" => push("save", vec![String::new()]),
+                rule => push(
+                    "save",
+                    rule.split_whitespace().map(str::to_string).collect(),
+                ),
+            }
+        }
+        if self.appendonly {
+            push("appendonly", vec!["yes".to_string()]);
+        }
+        if let Some(filename) = &self.appendfilename {
+            push("appendfilename", path(filename));
+        }
+        if let Some(policy) = &self.appendfsync {
+            push("appendfsync", vec![policy.clone()]);
+        }
+        if let Some(leader) = &self.replicaof {
+            push(
+                "replicaof",
+                leader.split_whitespace().map(str::to_string).collect(),
+            );
+        }
+        if let Some(read_only) = self.replica_read_only {
+            let read_only = if read_only { "yes" } else { "no" };
+            push("replica-read-only", vec![read_only.to_string()]);
+        }
+        if let Some(file) = &self.cluster_config_file {
+            push("cluster-config-file", path(file));
+        }
+        if let Some(password) = &self.requirepass {
+            push("requirepass", vec![password.clone()]);
+        }
+        if let Some(password) = &self.masterauth {
+            push("masterauth", vec![password.clone()]);
+        }
+        if let Some(file) = &self.aclfile {
+            push("aclfile", path(file));
+        }
+        if let Some(file) = &self.tls_cert_file {
+            push("tls-cert-file", path(file));
+        }
+        if let Some(file) = &self.tls_key_file {
+            push("tls-key-file", path(file));
+        }
+        if let Some(file) = &self.tls_ca_cert_file {
+            push("tls-ca-cert-file", path(file));
+        }
+        if let Some(file) = &self.unixsocket {
+            push("unixsocket", path(file));
+        }
+        if let Some(perm) = &self.unixsocketperm {
+            push("unixsocketperm", vec![perm.clone()]);
+        }
+
+        directives
+    }
+}
+
+/// Bind the Unix socket at `path`, replacing the one a previous run may have
+/// left behind, and set its permissions.
+#[cfg(unix)]
+fn bind_unix(path: &Path, perm: Option<u32>) -> my_redis::Result<UnixListener> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    let listener = UnixListener::bind(path)?;
+    if let Some(perm) = perm {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(perm))?;
+    }
+    Ok(listener)
+}
+
+/// Returns the filter of the logged events: `level` if given, else the one
+/// set by the `RUST_LOG` environment variable, logging errors only if unset.
+fn log_filter(level: Option<LevelFilter>) -> EnvFilter {
+    match level {
+        Some(level) => EnvFilter::default().add_directive(level.into()),
+        None => EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::default().add_directive(LevelFilter::ERROR.into())),
+    }
 }
 
 #[cfg(not(feature = "otel"))]
-fn set_up_logging() -> my_redis::Result<()> {
+fn set_up_logging(level: Option<LevelFilter>) -> my_redis::Result<()> {
     // See https://docs.rs/tracing for more info
-    tracing_subscriber::fmt::try_init()
+    tracing_subscriber::fmt()
+        .with_env_filter(log_filter(level))
+        .try_init()
 }
 
 #[cfg(feature = "otel")]
-fn set_up_logging() -> Result<(), TryInitError> {
-
+fn set_up_logging(level: Option<LevelFilter>) -> Result<(), TryInitError> {
     global::set_text_map_propagator(XrayPropagator::default());
 
     let tracer = opentelemetry_otlp::new_pipeline()
@@ -76,9 +355,9 @@ fn set_up_logging() -> Result<(), TryInitError> {
     // Create a tracing layer with the configured tracer
     let opentelemetry = tracing_opentelemetry::layer().with_tracer(tracer);
 
-    // Parse an `EnvFilter` configuration from the `RUST_LOG`
-    // environment variable.
-    let filter = EnvFilter::from_default_env();
+    // Parse an `EnvFilter` configuration from the log level, or the
+    // `RUST_LOG` environment variable.
+    let filter = log_filter(level);
 
     // Use the tracing subscriber `Registry`, or any other subscriber
     // that impls `LookupSpan`
@@ -87,4 +366,4 @@ fn set_up_logging() -> Result<(), TryInitError> {
         .with(filter)
         .with(fmt::Layer::default())
         .try_init()
-}
\ No newline at end of file
+}
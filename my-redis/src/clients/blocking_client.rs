@@ -3,6 +3,7 @@
 //! Provides a blocking connect and methods for issuing the supported commands.
 
 use bytes::Bytes;
+use std::collections::HashMap;
 use std::time::Duration;
 use tokio::net::ToSocketAddrs;
 use tokio::runtime::Runtime;
@@ -60,7 +61,7 @@ impl BlockingClient {
     /// # Examples
     ///
     /// ```no_run
-    /// use mini_redis::clients::BlockingClient;
+    /// use my_redis::clients::BlockingClient;
     ///
     /// fn main() {
     ///     let client = match BlockingClient::connect("localhost:6379") {
@@ -89,7 +90,7 @@ impl BlockingClient {
     /// Demonstrates basic usage.
     ///
     /// ```no_run
-    /// use mini_redis::clients::BlockingClient;
+    /// use my_redis::clients::BlockingClient;
     ///
     /// fn main() {
     ///     let mut client = BlockingClient::connect("localhost:6379").unwrap();
@@ -115,7 +116,7 @@ impl BlockingClient {
     /// Demonstrates basic usage.
     ///
     /// ```no_run
-    /// use mini_redis::clients::BlockingClient;
+    /// use my_redis::clients::BlockingClient;
     ///
     /// fn main() {
     ///     let mut client = BlockingClient::connect("localhost:6379").unwrap();
@@ -149,7 +150,7 @@ impl BlockingClient {
     /// favorable.
     ///
     /// ```no_run
-    /// use mini_redis::clients::BlockingClient;
+    /// use my_redis::clients::BlockingClient;
     /// use std::thread;
     /// use std::time::Duration;
     ///
@@ -191,7 +192,7 @@ impl BlockingClient {
     /// Demonstrates basic usage.
     ///
     /// ```no_run
-    /// use mini_redis::clients::BlockingClient;
+    /// use my_redis::clients::BlockingClient;
     ///
     /// fn main() {
     ///     let mut client = BlockingClient::connect("localhost:6379").unwrap();
@@ -204,6 +205,75 @@ impl BlockingClient {
         self.rt.block_on(self.inner.publish(channel, message))
     }
 
+    /// Set the given `fields` of the hash stored at `key`.
+    ///
+    /// Returns the number of fields that were added.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::BlockingClient;
+    ///
+    /// fn main() {
+    ///     let mut client = BlockingClient::connect("localhost:6379").unwrap();
+    ///
+    ///     client.hset("session:1", vec![("user".into(), "alice".into())]).unwrap();
+    ///
+    ///     let user = client.hget("session:1", "user".into()).unwrap();
+    ///     assert_eq!(user.unwrap(), "alice");
+    /// }
+    /// ```
+    pub fn hset(&mut self, key: &str, fields: Vec<(Bytes, Bytes)>) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.hset(key, fields))
+    }
+
+    /// Get the value of `field` in the hash stored at `key`.
+    pub fn hget(&mut self, key: &str, field: Bytes) -> crate::Result<Option<Bytes>> {
+        self.rt.block_on(self.inner.hget(key, field))
+    }
+
+    /// Remove `fields` from the hash stored at `key`.
+    pub fn hdel(&mut self, key: &str, fields: Vec<Bytes>) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.hdel(key, fields))
+    }
+
+    /// Get all the fields and values of the hash stored at `key`.
+    pub fn hgetall(&mut self, key: &str) -> crate::Result<HashMap<Bytes, Bytes>> {
+        self.rt.block_on(self.inner.hgetall(key))
+    }
+
+    /// Get the values of `fields` in the hash stored at `key`.
+    pub fn hmget(&mut self, key: &str, fields: Vec<Bytes>) -> crate::Result<Vec<Option<Bytes>>> {
+        self.rt.block_on(self.inner.hmget(key, fields))
+    }
+
+    /// Increment the integer stored in `field` of the hash stored at `key`.
+    pub fn hincrby(&mut self, key: &str, field: Bytes, increment: i64) -> crate::Result<i64> {
+        self.rt.block_on(self.inner.hincrby(key, field, increment))
+    }
+
+    /// Get all the field names of the hash stored at `key`.
+    pub fn hkeys(&mut self, key: &str) -> crate::Result<Vec<Bytes>> {
+        self.rt.block_on(self.inner.hkeys(key))
+    }
+
+    /// Get all the values of the hash stored at `key`.
+    pub fn hvals(&mut self, key: &str) -> crate::Result<Vec<Bytes>> {
+        self.rt.block_on(self.inner.hvals(key))
+    }
+
+    /// Get the number of fields of the hash stored at `key`.
+    pub fn hlen(&mut self, key: &str) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.hlen(key))
+    }
+
+    /// Returns `true` if `field` exists in the hash stored at `key`.
+    pub fn hexists(&mut self, key: &str, field: Bytes) -> crate::Result<bool> {
+        self.rt.block_on(self.inner.hexists(key, field))
+    }
+
     /// Subscribes the client to the specified channels.
     ///
     /// Once a client issues a subscribe command, it may no longer issue any
@@ -2,14 +2,30 @@
 //!
 //! Provides an async connect and methods for issuing the supported commands.
 
-use crate::cmd::{Get, Ping, Publish, Set, Subscribe, Unsubscribe};
-use crate::{Connection, Frame};
+use crate::clients::SlotRange;
+use crate::cmd::{
+    Acl, Auth, BgRewriteAof, BgSave, Cluster, Copy, Decr, DecrBy, Del, Discard, Eval, EvalSha, Exec, Exists,
+    Expire, ExpireAt, Get, HDel, HExists, HGet, HGetAll, HIncrBy, HKeys, HLen, HMGet, HScan, HSet,
+    HVals, Hello, Incr, IncrBy, IncrByFloat, Keys, LIndex, LLen, LPop, LPush, LRange, LSet, LTrim,
+    Multi, PExpire, PExpireAt, PTtl, Persist, Ping, Publish, RPop, RPush, Rename, RenameNx,
+    ReplicaOf, Role, SAdd, SCard, SDiff, SDiffStore, SInter, SInterStore, SIsMember, SMembers,
+    SRem, SScan, SUnion, SUnionStore, Save, Scan, Script, Set, Subscribe, Touch, Ttl, Type, Unlink,
+    Unsubscribe, Unwatch, Watch, XAck, XAdd, XClaim, XGroupCreate, XLen, XPending, XRange, XRead,
+    XReadGroup, XRevRange, XTrim, ZAdd, ZCard, ZIncrBy, ZPopMin, ZRange, ZRangeByScore, ZRank,
+    ZRem, ZScan,
+};
+use crate::{tls, Connection, Frame, Protocol, StreamEntry, StreamId, TlsClientConfig};
 
 use async_stream::try_stream;
 use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
 use std::io::{Error, ErrorKind};
+#[cfg(unix)]
+use std::path::Path;
 use std::time::Duration;
 use tokio::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio_stream::Stream;
 use tracing::{debug, instrument};
 
@@ -44,6 +60,18 @@ pub struct Subscriber {
     subscribed_channels: Vec<String>,
 }
 
+/// A transaction being built, see [`Client::transaction`].
+///
+/// Commands are buffered on the client and only sent to the server, wrapped
+/// in `MULTI` and `EXEC`, when [`exec`](Transaction::exec) is called.
+pub struct Transaction<'a> {
+    /// The client issuing the transaction.
+    client: &'a mut Client,
+
+    /// The queued commands, converted into frames.
+    commands: Vec<Frame>,
+}
+
 /// A message received on a subscribed channel.
 #[derive(Debug, Clone)]
 pub struct Message {
@@ -61,7 +89,7 @@ impl Client {
     /// # Examples
     ///
     /// ```no_run
-    /// use mini_redis::clients::Client;
+    /// use my_redis::clients::Client;
     ///
     /// #[tokio::main]
     /// async fn main() {
@@ -87,6 +115,95 @@ impl Client {
         Ok(Client { connection })
     }
 
+    /// Establish a connection with the Redis server listening on the Unix
+    /// socket at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect_unix("/tmp/redis.sock").await.unwrap();
+    ///
+    ///     client.set("foo", "bar".into()).await.unwrap();
+    /// }
+    /// ```
+    #[cfg(unix)]
+    pub async fn connect_unix(path: impl AsRef<Path>) -> crate::Result<Client> {
+        let socket = UnixStream::connect(path).await?;
+
+        Ok(Client {
+            connection: Connection::new(socket),
+        })
+    }
+
+    /// Establish a TLS connection with the Redis server located at `addr`.
+    ///
+    /// The certificate of the server must be issued for `server_name` and
+    /// signed by the CA of `config`, which also holds the certificate
+    /// presented to servers requiring one.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    /// use my_redis::TlsClientConfig;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let config = TlsClientConfig {
+    ///         ca_cert_file: "ca.crt".into(),
+    ///         cert_file: None,
+    ///         key_file: None,
+    ///     };
+    ///     let mut client = Client::connect_tls("localhost:6379", "localhost", &config)
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     client.set("foo", "bar".into()).await.unwrap();
+    /// }
+    /// ```
+    pub async fn connect_tls<T: ToSocketAddrs>(
+        addr: T,
+        server_name: &str,
+        config: &TlsClientConfig,
+    ) -> crate::Result<Client> {
+        let socket = TcpStream::connect(addr).await?;
+        let stream = tls::connect(socket, server_name, config).await?;
+
+        Ok(Client {
+            connection: Connection::new(stream),
+        })
+    }
+
+    /// Establish a connection with the Redis server located at `addr`, then
+    /// authenticate with `password`, see [`auth`](Client::auth).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect_with_password("localhost:6379", "secret")
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     client.set("foo", "bar".into()).await.unwrap();
+    /// }
+    /// ```
+    pub async fn connect_with_password<T: ToSocketAddrs>(
+        addr: T,
+        password: &str,
+    ) -> crate::Result<Client> {
+        let mut client = Client::connect(addr).await?;
+        client.auth(password).await?;
+        Ok(client)
+    }
+
     /// Ping to the server.
     ///
     /// Returns PONG if no argument is provided, otherwise
@@ -99,7 +216,7 @@ impl Client {
     ///
     /// Demonstrates basic usage.
     /// ```no_run
-    /// use mini_redis::clients::Client;
+    /// use my_redis::clients::Client;
     ///
     /// #[tokio::main]
     /// async fn main() {
@@ -122,6 +239,59 @@ impl Client {
         }
     }
 
+    /// Switch the protocol spoken on the connection to `protocol`.
+    ///
+    /// Replies are decoded the same way with both protocols, only the server
+    /// side encoding changes. With RESP3, pub/sub messages arrive as push
+    /// frames.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    /// use my_redis::Protocol;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.hello(Protocol::Resp3).await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn hello(&mut self, protocol: Protocol) -> crate::Result<()> {
+        let frame = Hello::new(Some(protocol)).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Map(_) | Frame::Array(_) => {
+                self.connection.set_protocol(protocol);
+                Ok(())
+            }
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Authenticate the connection with `password`.
+    ///
+    /// A server started with a password refuses every other command but
+    /// `HELLO` and `PING` until the connection is authenticated.
+    #[instrument(skip(self, password))]
+    pub async fn auth(&mut self, password: &str) -> crate::Result<()> {
+        let frame = Auth::new(None, password).into_frame();
+        self.secret_ok_cmd(frame).await
+    }
+
+    /// Authenticate the connection as the user `username`, see the `acl`
+    /// rules of the server.
+    #[instrument(skip(self, password))]
+    pub async fn auth_user(&mut self, username: &str, password: &str) -> crate::Result<()> {
+        let frame = Auth::new(Some(username), password).into_frame();
+        self.secret_ok_cmd(frame).await
+    }
+
     /// Get the value of key.
     ///
     /// If the key does not exist the special value `None` is returned.
@@ -131,7 +301,7 @@ impl Client {
     /// Demonstrates basic usage.
     ///
     /// ```no_run
-    /// use mini_redis::clients::Client;
+    /// use my_redis::clients::Client;
     ///
     /// #[tokio::main]
     /// async fn main() {
@@ -177,7 +347,7 @@ impl Client {
     /// Demonstrates basic usage.
     ///
     /// ```no_run
-    /// use mini_redis::clients::Client;
+    /// use my_redis::clients::Client;
     ///
     /// #[tokio::main]
     /// async fn main() {
@@ -216,7 +386,7 @@ impl Client {
     /// favorable.
     ///
     /// ```no_run
-    /// use mini_redis::clients::Client;
+    /// use my_redis::clients::Client;
     /// use tokio::time;
     /// use std::time::Duration;
     ///
@@ -281,7 +451,7 @@ impl Client {
     /// Demonstrates basic usage.
     ///
     /// ```no_run
-    /// use mini_redis::clients::Client;
+    /// use my_redis::clients::Client;
     ///
     /// #[tokio::main]
     /// async fn main() {
@@ -303,169 +473,1821 @@ impl Client {
 
         // Read the response
         match self.read_response().await? {
-            Frame::Integer(response) => Ok(response),
+            Frame::Integer(response) => Ok(response as u64),
             frame => Err(frame.to_error()),
         }
     }
 
-    /// Subscribes the client to the specified channels.
+    /// Increment the integer stored at `key` by one.
     ///
-    /// Once a client issues a subscribe command, it may no longer issue any
-    /// non-pub/sub commands. The function consumes `self` and returns a `Subscriber`.
+    /// A missing key is set to `0` before being incremented. The time to live
+    /// of the key, if any, is preserved.
     ///
-    /// The `Subscriber` value is used to receive messages as well as manage the
-    /// list of channels the client is subscribed to.
+    /// Returns the value of the key after the increment. An error is returned
+    /// if the value stored at `key` is not an integer.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let hits = client.incr("hits").await.unwrap();
+    ///     println!("hits = {}", hits);
+    /// }
+    /// ```
     #[instrument(skip(self))]
-    pub async fn subscribe(mut self, channels: Vec<String>) -> crate::Result<Subscriber> {
-        // Issue the subscribe command to the server and wait for confirmation.
-        // The client will then have been transitioned into the "subscriber"
-        // state and may only issue pub/sub commands from that point on.
-        self.subscribe_cmd(&channels).await?;
-
-        // Return the `Subscriber` type
-        Ok(Subscriber {
-            client: self,
-            subscribed_channels: channels,
-        })
+    pub async fn incr(&mut self, key: &str) -> crate::Result<i64> {
+        let frame = Incr::new(key).into_frame();
+        self.signed_integer_cmd(frame).await
     }
 
-    /// The core `SUBSCRIBE` logic, used by misc subscribe fns
-    async fn subscribe_cmd(&mut self, channels: &[String]) -> crate::Result<()> {
-        // Convert the `Subscribe` command into a frame
-        let frame = Subscribe::new(channels.to_vec()).into_frame();
+    /// Decrement the integer stored at `key` by one. Works like `incr`.
+    #[instrument(skip(self))]
+    pub async fn decr(&mut self, key: &str) -> crate::Result<i64> {
+        let frame = Decr::new(key).into_frame();
+        self.signed_integer_cmd(frame).await
+    }
 
-        debug!(request = ?frame);
+    /// Increment the integer stored at `key` by `increment`. Works like
+    /// `incr`.
+    #[instrument(skip(self))]
+    pub async fn incrby(&mut self, key: &str, increment: i64) -> crate::Result<i64> {
+        let frame = IncrBy::new(key, increment).into_frame();
+        self.signed_integer_cmd(frame).await
+    }
 
-        // Write the frame to the socket
-        self.connection.write_frame(&frame).await?;
+    /// Decrement the integer stored at `key` by `decrement`. Works like
+    /// `incr`.
+    #[instrument(skip(self))]
+    pub async fn decrby(&mut self, key: &str, decrement: i64) -> crate::Result<i64> {
+        let frame = DecrBy::new(key, decrement).into_frame();
+        self.signed_integer_cmd(frame).await
+    }
 
-        // For each channel being subscribed to, the server responds with a
-        // message confirming subscription to that channel.
-        for channel in channels {
-            // Read the response
-            let response = self.read_response().await?;
+    /// Increment the floating point number stored at `key` by `increment`.
+    /// Works like `incr`.
+    #[instrument(skip(self))]
+    pub async fn incrbyfloat(&mut self, key: &str, increment: f64) -> crate::Result<f64> {
+        let frame = IncrByFloat::new(key, increment).into_frame();
 
-            // Verify it is confirmation of subscription.
-            match response {
-                Frame::Array(ref frame) => match frame.as_slice() {
-                    // The server responds with an array frame in the form of:
-                    //
-                    // ```
-                    // [ "subscribe", channel, num-subscribed ]
-                    // ```
-                    //
-                    // where channel is the name of the channel and
-                    // num-subscribed is the number of channels that the client
-                    // is currently subscribed to.
-                    [subscribe, schannel, ..]
-                        if *subscribe == "subscribe" && *schannel == channel => {}
-                    _ => return Err(response.to_error()),
-                },
-                frame => return Err(frame.to_error()),
-            };
+        match self.optional_bulk_cmd(frame).await? {
+            Some(value) => parse_score(&value),
+            None => Err("protocol error; unexpected nil value".into()),
         }
-
-        Ok(())
     }
 
-    /// Reads a response frame from the socket.
+    /// Remove `keys`, returning the number of keys that existed.
     ///
-    /// If an `Error` frame is received, it is converted to `Err`.
-    async fn read_response(&mut self) -> crate::Result<Frame> {
-        let response = self.connection.read_frame().await?;
-
-        debug!(?response);
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.set("foo", "bar".into()).await.unwrap();
+    ///
+    ///     let removed = client.del(vec!["foo".into(), "baz".into()]).await.unwrap();
+    ///     assert_eq!(removed, 1);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn del(&mut self, keys: Vec<String>) -> crate::Result<u64> {
+        let frame = Del::new(keys).into_frame();
+        self.integer_cmd(frame).await
+    }
 
-        match response {
-            // Error frames are converted to `Err`
-            Some(Frame::Error(msg)) => Err(msg.into()),
-            Some(frame) => Ok(frame),
-            None => {
-                // Receiving `None` here indicates the server has closed the
-                // connection without sending a frame. This is unexpected and is
-                // represented as a "connection reset by peer" error.
-                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by server");
+    /// Remove `keys` like `del`, the server reclaiming the memory of their
+    /// values in the background.
+    #[instrument(skip(self))]
+    pub async fn unlink(&mut self, keys: Vec<String>) -> crate::Result<u64> {
+        let frame = Unlink::new(keys).into_frame();
+        self.integer_cmd(frame).await
+    }
 
-                Err(err.into())
-            }
-        }
+    /// Returns the number of `keys` that exist. A key given several times is
+    /// counted several times.
+    #[instrument(skip(self))]
+    pub async fn exists(&mut self, keys: Vec<String>) -> crate::Result<u64> {
+        let frame = Exists::new(keys).into_frame();
+        self.integer_cmd(frame).await
     }
-}
 
-impl Subscriber {
-    /// Returns the set of channels currently subscribed to.
-    pub fn get_subscribed(&self) -> &[String] {
-        &self.subscribed_channels
+    /// Touch `keys`, returning the number of keys that exist.
+    #[instrument(skip(self))]
+    pub async fn touch(&mut self, keys: Vec<String>) -> crate::Result<u64> {
+        let frame = Touch::new(keys).into_frame();
+        self.integer_cmd(frame).await
     }
 
-    /// Receive the next message published on a subscribed channel, waiting if
-    /// necessary.
-    ///
-    /// `None` indicates the subscription has been terminated.
-    pub async fn next_message(&mut self) -> crate::Result<Option<Message>> {
-        match self.client.connection.read_frame().await? {
-            Some(mframe) => {
-                debug!(?mframe);
+    /// Get the type of the value stored at `key`, such as `string` or `list`,
+    /// or `none` if the key does not exist.
+    #[instrument(skip(self))]
+    pub async fn key_type(&mut self, key: &str) -> crate::Result<String> {
+        let frame = Type::new(key).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
 
-                match mframe {
-                    Frame::Array(ref frame) => match frame.as_slice() {
-                        [message, channel, content] if *message == "message" => Ok(Some(Message {
-                            channel: channel.to_string(),
-                            content: Bytes::from(content.to_string()),
-                        })),
-                        _ => Err(mframe.to_error()),
-                    },
-                    frame => Err(frame.to_error()),
-                }
-            }
-            None => Ok(None),
+        match self.read_response().await? {
+            Frame::Simple(response) => Ok(response),
+            frame => Err(frame.to_error()),
         }
     }
 
-    /// Convert the subscriber into a `Stream` yielding new messages published
-    /// on subscribed channels.
-    ///
-    /// `Subscriber` does not implement stream itself as doing so with safe code
-    /// is non trivial. The usage of async/await would require a manual Stream
-    /// implementation to use `unsafe` code. Instead, a conversion function is
-    /// provided and the returned stream is implemented with the help of the
-    /// `async-stream` crate.
-    pub fn into_stream(mut self) -> impl Stream<Item = crate::Result<Message>> {
-        // Uses the `try_stream` macro from the `async-stream` crate. Generators
-        // are not stable in Rust. The crate uses a macro to simulate generators
-        // on top of async/await. There are limitations, so read the
-        // documentation there.
-        try_stream! {
-            while let Some(message) = self.next_message().await? {
-                yield message;
-            }
-        }
+    /// Rename `key` to `new_key`, overwriting any value stored at `new_key`.
+    /// The time to live of `key` is kept.
+    #[instrument(skip(self))]
+    pub async fn rename(&mut self, key: &str, new_key: &str) -> crate::Result<()> {
+        let frame = Rename::new(key, new_key).into_frame();
+        self.ok_cmd(frame).await
     }
 
-    /// Subscribe to a list of new channels
+    /// Rename `key` to `new_key` if `new_key` does not exist. Returns whether
+    /// the key was renamed.
     #[instrument(skip(self))]
-    pub async fn subscribe(&mut self, channels: &[String]) -> crate::Result<()> {
-        // Issue the subscribe command
-        self.client.subscribe_cmd(channels).await?;
+    pub async fn renamenx(&mut self, key: &str, new_key: &str) -> crate::Result<bool> {
+        let frame = RenameNx::new(key, new_key).into_frame();
+        Ok(self.integer_cmd(frame).await? == 1)
+    }
 
-        // Update the set of subscribed channels.
-        self.subscribed_channels
-            .extend(channels.iter().map(Clone::clone));
+    /// Copy the value stored at `source` to `destination`, along with its time
+    /// to live.
+    ///
+    /// Unless `replace` is set, nothing is copied if `destination` exists.
+    /// Returns whether the value was copied.
+    #[instrument(skip(self))]
+    pub async fn copy(
+        &mut self,
+        source: &str,
+        destination: &str,
+        replace: bool,
+    ) -> crate::Result<bool> {
+        let frame = Copy::new(source, destination, replace).into_frame();
+        Ok(self.integer_cmd(frame).await? == 1)
+    }
 
-        Ok(())
+    /// Get all the keys matching the glob-style `pattern`.
+    ///
+    /// The server walks the whole key space to reply, `scan_stream` should be
+    /// preferred on large databases.
+    #[instrument(skip(self))]
+    pub async fn keys(&mut self, pattern: &str) -> crate::Result<Vec<String>> {
+        let frame = Keys::new(Bytes::copy_from_slice(pattern.as_bytes())).into_frame();
+        let keys = self.bulk_array_cmd(frame).await?;
+
+        keys.into_iter()
+            .map(|key| Ok(String::from_utf8(key.to_vec())?))
+            .collect()
     }
 
-    /// Unsubscribe to a list of new channels
+    /// Get a page of keys, resuming the iteration at `cursor`.
+    ///
+    /// Returns the cursor to pass to the next call, `0` once the iteration is
+    /// complete. Keys are filtered by the glob-style `pattern` and by
+    /// `type_name`, such as `list` or `hash`, and `count` hints at the number
+    /// of keys visited.
     #[instrument(skip(self))]
-    pub async fn unsubscribe(&mut self, channels: &[String]) -> crate::Result<()> {
-        let frame = Unsubscribe::new(channels).into_frame();
+    pub async fn scan(
+        &mut self,
+        cursor: u64,
+        pattern: Option<&str>,
+        count: Option<u64>,
+        type_name: Option<&str>,
+    ) -> crate::Result<(u64, Vec<String>)> {
+        let pattern = pattern.map(|pattern| Bytes::copy_from_slice(pattern.as_bytes()));
+        let type_name = type_name.map(ToString::to_string);
+        let frame = Scan::new(cursor, pattern, count, type_name).into_frame();
 
-        debug!(request = ?frame);
+        let (cursor, keys) = self.scan_cmd(frame).await?;
+        let keys = keys
+            .into_iter()
+            .map(|key| Ok(String::from_utf8(key.to_vec())?))
+            .collect::<crate::Result<_>>()?;
 
-        // Write the frame to the socket
-        self.client.connection.write_frame(&frame).await?;
+        Ok((cursor, keys))
+    }
 
-        // if the input channel list is empty, server acknowledges as unsubscribing
+    /// Iterate over the keys matching `pattern` and `type_name`, issuing
+    /// `SCAN` commands as the stream is polled.
+    ///
+    /// A key present during the whole iteration is yielded at least once, and
+    /// may be yielded more than once.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    /// use tokio_stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let keys = client.scan_stream(Some("session:*"), None, None);
+    ///     tokio::pin!(keys);
+    ///
+    ///     while let Some(key) = keys.next().await {
+    ///         println!("{}", key.unwrap());
+    ///     }
+    /// }
+    /// ```
+    pub fn scan_stream<'a>(
+        &'a mut self,
+        pattern: Option<&'a str>,
+        count: Option<u64>,
+        type_name: Option<&'a str>,
+    ) -> impl Stream<Item = crate::Result<String>> + 'a {
+        try_stream! {
+            let mut cursor = 0;
+
+            loop {
+                let (next, keys) = self.scan(cursor, pattern, count, type_name).await?;
+
+                for key in keys {
+                    yield key;
+                }
+
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+        }
+    }
+
+    /// Set a timeout of `seconds` on `key`, after which the key is deleted.
+    ///
+    /// A timeout which is not positive deletes the key right away. Returns
+    /// `false` if the key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.set("session", "data".into()).await.unwrap();
+    ///     client.expire("session", 60).await.unwrap();
+    ///
+    ///     let ttl = client.ttl("session").await.unwrap();
+    ///     assert!(ttl <= 60);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn expire(&mut self, key: &str, seconds: i64) -> crate::Result<bool> {
+        let frame = Expire::new(key, seconds).into_frame();
+        Ok(self.integer_cmd(frame).await? == 1)
+    }
+
+    /// Set a timeout of `milliseconds` on `key`. Works like `expire`.
+    #[instrument(skip(self))]
+    pub async fn pexpire(&mut self, key: &str, milliseconds: i64) -> crate::Result<bool> {
+        let frame = PExpire::new(key, milliseconds).into_frame();
+        Ok(self.integer_cmd(frame).await? == 1)
+    }
+
+    /// Delete `key` at the Unix time `timestamp`, in seconds. Works like
+    /// `expire`.
+    #[instrument(skip(self))]
+    pub async fn expireat(&mut self, key: &str, timestamp: i64) -> crate::Result<bool> {
+        let frame = ExpireAt::new(key, timestamp).into_frame();
+        Ok(self.integer_cmd(frame).await? == 1)
+    }
+
+    /// Delete `key` at the Unix time `timestamp`, in milliseconds. Works like
+    /// `expire`.
+    #[instrument(skip(self))]
+    pub async fn pexpireat(&mut self, key: &str, timestamp: i64) -> crate::Result<bool> {
+        let frame = PExpireAt::new(key, timestamp).into_frame();
+        Ok(self.integer_cmd(frame).await? == 1)
+    }
+
+    /// Get the remaining time to live of `key`, in seconds.
+    ///
+    /// Returns `-2` if the key does not exist and `-1` if it has no
+    /// expiration.
+    #[instrument(skip(self))]
+    pub async fn ttl(&mut self, key: &str) -> crate::Result<i64> {
+        let frame = Ttl::new(key).into_frame();
+        self.signed_integer_cmd(frame).await
+    }
+
+    /// Get the remaining time to live of `key`, in milliseconds. Works like
+    /// `ttl`.
+    #[instrument(skip(self))]
+    pub async fn pttl(&mut self, key: &str) -> crate::Result<i64> {
+        let frame = PTtl::new(key).into_frame();
+        self.signed_integer_cmd(frame).await
+    }
+
+    /// Remove the expiration of `key`. Returns `false` if the key does not
+    /// exist or has no expiration.
+    #[instrument(skip(self))]
+    pub async fn persist(&mut self, key: &str) -> crate::Result<bool> {
+        let frame = Persist::new(key).into_frame();
+        Ok(self.integer_cmd(frame).await? == 1)
+    }
+
+    /// Insert all `values` at the head of the list stored at `key`.
+    ///
+    /// Values are inserted one after the other, so the last value ends up
+    /// first. Returns the length of the list after the push.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let len = client.lpush("jobs", vec!["a".into(), "b".into()]).await.unwrap();
+    ///     assert_eq!(len, 2);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn lpush(&mut self, key: &str, values: Vec<Bytes>) -> crate::Result<u64> {
+        let frame = LPush::new(key, values).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Insert all `values` at the tail of the list stored at `key`.
+    ///
+    /// Returns the length of the list after the push.
+    #[instrument(skip(self))]
+    pub async fn rpush(&mut self, key: &str, values: Vec<Bytes>) -> crate::Result<u64> {
+        let frame = RPush::new(key, values).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Remove and return the first element of the list stored at `key`.
+    ///
+    /// `None` is returned if the key does not exist.
+    #[instrument(skip(self))]
+    pub async fn lpop(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        let frame = LPop::new(key, None).into_frame();
+        self.optional_bulk_cmd(frame).await
+    }
+
+    /// Remove and return the last element of the list stored at `key`.
+    ///
+    /// `None` is returned if the key does not exist.
+    #[instrument(skip(self))]
+    pub async fn rpop(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        let frame = RPop::new(key, None).into_frame();
+        self.optional_bulk_cmd(frame).await
+    }
+
+    /// Return the elements between `start` and `stop`, both inclusive, of the
+    /// list stored at `key`. Negative indices count from the tail of the list.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.rpush("jobs", vec!["a".into(), "b".into()]).await.unwrap();
+    ///
+    ///     let jobs = client.lrange("jobs", 0, -1).await.unwrap();
+    ///     assert_eq!(jobs, vec!["a", "b"]);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn lrange(&mut self, key: &str, start: i64, stop: i64) -> crate::Result<Vec<Bytes>> {
+        let frame = LRange::new(key, start, stop).into_frame();
+        self.bulk_array_cmd(frame).await
+    }
+
+    /// Return the length of the list stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn llen(&mut self, key: &str) -> crate::Result<u64> {
+        let frame = LLen::new(key).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Return the element at `index` of the list stored at `key`.
+    ///
+    /// `None` is returned when the index is out of range.
+    #[instrument(skip(self))]
+    pub async fn lindex(&mut self, key: &str, index: i64) -> crate::Result<Option<Bytes>> {
+        let frame = LIndex::new(key, index).into_frame();
+        self.optional_bulk_cmd(frame).await
+    }
+
+    /// Replace the element at `index` of the list stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn lset(&mut self, key: &str, index: i64, value: Bytes) -> crate::Result<()> {
+        let frame = LSet::new(key, index, value).into_frame();
+        self.ok_cmd(frame).await
+    }
+
+    /// Trim the list stored at `key` to the elements between `start` and
+    /// `stop`, both inclusive.
+    #[instrument(skip(self))]
+    pub async fn ltrim(&mut self, key: &str, start: i64, stop: i64) -> crate::Result<()> {
+        let frame = LTrim::new(key, start, stop).into_frame();
+        self.ok_cmd(frame).await
+    }
+
+    /// Set the given `fields` of the hash stored at `key`.
+    ///
+    /// Returns the number of fields that were added. Fields that already
+    /// existed are overwritten but not counted.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.hset("session:1", vec![("user".into(), "alice".into())]).await.unwrap();
+    ///
+    ///     let user = client.hget("session:1", "user".into()).await.unwrap();
+    ///     assert_eq!(user.unwrap(), "alice");
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn hset(&mut self, key: &str, fields: Vec<(Bytes, Bytes)>) -> crate::Result<u64> {
+        let frame = HSet::new(key, fields).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Get the value of `field` in the hash stored at `key`.
+    ///
+    /// `None` is returned if the key or the field does not exist.
+    #[instrument(skip(self))]
+    pub async fn hget(&mut self, key: &str, field: Bytes) -> crate::Result<Option<Bytes>> {
+        let frame = HGet::new(key, field).into_frame();
+        self.optional_bulk_cmd(frame).await
+    }
+
+    /// Remove `fields` from the hash stored at `key`.
+    ///
+    /// Returns the number of fields that were removed.
+    #[instrument(skip(self))]
+    pub async fn hdel(&mut self, key: &str, fields: Vec<Bytes>) -> crate::Result<u64> {
+        let frame = HDel::new(key, fields).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Get a page of the fields and values of the hash stored at `key`,
+    /// resuming the iteration at `cursor`. Works like `scan`.
+    #[instrument(skip(self))]
+    pub async fn hscan(
+        &mut self,
+        key: &str,
+        cursor: u64,
+        pattern: Option<&str>,
+        count: Option<u64>,
+    ) -> crate::Result<(u64, Vec<(Bytes, Bytes)>)> {
+        let pattern = pattern.map(|pattern| Bytes::copy_from_slice(pattern.as_bytes()));
+        let frame = HScan::new(key, cursor, pattern, count).into_frame();
+
+        let (cursor, values) = self.scan_cmd(frame).await?;
+        if values.len() % 2 != 0 {
+            return Err("protocol error; expected field value pairs".into());
+        }
+
+        let fields = values
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+
+        Ok((cursor, fields))
+    }
+
+    /// Get all the fields and values of the hash stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn hgetall(&mut self, key: &str) -> crate::Result<HashMap<Bytes, Bytes>> {
+        let frame = HGetAll::new(key).into_frame();
+        let values = self.bulk_array_cmd(frame).await?;
+
+        let mut fields = HashMap::with_capacity(values.len() / 2);
+        let mut values = values.into_iter();
+        while let (Some(field), Some(value)) = (values.next(), values.next()) {
+            fields.insert(field, value);
+        }
+
+        Ok(fields)
+    }
+
+    /// Get the values of `fields` in the hash stored at `key`.
+    ///
+    /// The values are returned in the order of `fields`, `None` standing for
+    /// a missing field.
+    #[instrument(skip(self))]
+    pub async fn hmget(
+        &mut self,
+        key: &str,
+        fields: Vec<Bytes>,
+    ) -> crate::Result<Vec<Option<Bytes>>> {
+        let frame = HMGet::new(key, fields).into_frame();
+
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    Frame::Bulk(value) => Ok(Some(value)),
+                    Frame::Null => Ok(None),
+                    frame => Err(frame.to_error()),
+                })
+                .collect(),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Increment the integer stored in `field` of the hash stored at `key` by
+    /// `increment`.
+    ///
+    /// Returns the value of the field after the increment.
+    #[instrument(skip(self))]
+    pub async fn hincrby(&mut self, key: &str, field: Bytes, increment: i64) -> crate::Result<i64> {
+        let frame = HIncrBy::new(key, field, increment).into_frame();
+        self.signed_integer_cmd(frame).await
+    }
+
+    /// Get all the field names of the hash stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn hkeys(&mut self, key: &str) -> crate::Result<Vec<Bytes>> {
+        let frame = HKeys::new(key).into_frame();
+        self.bulk_array_cmd(frame).await
+    }
+
+    /// Get all the values of the hash stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn hvals(&mut self, key: &str) -> crate::Result<Vec<Bytes>> {
+        let frame = HVals::new(key).into_frame();
+        self.bulk_array_cmd(frame).await
+    }
+
+    /// Get the number of fields of the hash stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn hlen(&mut self, key: &str) -> crate::Result<u64> {
+        let frame = HLen::new(key).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Returns `true` if `field` exists in the hash stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn hexists(&mut self, key: &str, field: Bytes) -> crate::Result<bool> {
+        let frame = HExists::new(key, field).into_frame();
+        Ok(self.integer_cmd(frame).await? == 1)
+    }
+
+    /// Add `members` to the set stored at `key`.
+    ///
+    /// Returns the number of members that were not already in the set.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.sadd("tags:1", vec!["rust".into(), "tokio".into()]).await.unwrap();
+    ///     client.sadd("tags:2", vec!["rust".into()]).await.unwrap();
+    ///
+    ///     let common = client.sinter(vec!["tags:1".into(), "tags:2".into()]).await.unwrap();
+    ///     assert!(common.contains("rust".as_bytes()));
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn sadd(&mut self, key: &str, members: Vec<Bytes>) -> crate::Result<u64> {
+        let frame = SAdd::new(key, members).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Remove `members` from the set stored at `key`.
+    ///
+    /// Returns the number of members that were removed.
+    #[instrument(skip(self))]
+    pub async fn srem(&mut self, key: &str, members: Vec<Bytes>) -> crate::Result<u64> {
+        let frame = SRem::new(key, members).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Get a page of the members of the set stored at `key`, resuming the
+    /// iteration at `cursor`. Works like `scan`.
+    #[instrument(skip(self))]
+    pub async fn sscan(
+        &mut self,
+        key: &str,
+        cursor: u64,
+        pattern: Option<&str>,
+        count: Option<u64>,
+    ) -> crate::Result<(u64, Vec<Bytes>)> {
+        let pattern = pattern.map(|pattern| Bytes::copy_from_slice(pattern.as_bytes()));
+        let frame = SScan::new(key, cursor, pattern, count).into_frame();
+        self.scan_cmd(frame).await
+    }
+
+    /// Get all the members of the set stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn smembers(&mut self, key: &str) -> crate::Result<HashSet<Bytes>> {
+        let frame = SMembers::new(key).into_frame();
+        Ok(self.bulk_array_cmd(frame).await?.into_iter().collect())
+    }
+
+    /// Returns `true` if `member` belongs to the set stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn sismember(&mut self, key: &str, member: Bytes) -> crate::Result<bool> {
+        let frame = SIsMember::new(key, member).into_frame();
+        Ok(self.integer_cmd(frame).await? == 1)
+    }
+
+    /// Get the number of members of the set stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn scard(&mut self, key: &str) -> crate::Result<u64> {
+        let frame = SCard::new(key).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Get the members present in all of the sets stored at `keys`.
+    #[instrument(skip(self))]
+    pub async fn sinter(&mut self, keys: Vec<String>) -> crate::Result<HashSet<Bytes>> {
+        let frame = SInter::new(keys).into_frame();
+        Ok(self.bulk_array_cmd(frame).await?.into_iter().collect())
+    }
+
+    /// Get the members present in any of the sets stored at `keys`.
+    #[instrument(skip(self))]
+    pub async fn sunion(&mut self, keys: Vec<String>) -> crate::Result<HashSet<Bytes>> {
+        let frame = SUnion::new(keys).into_frame();
+        Ok(self.bulk_array_cmd(frame).await?.into_iter().collect())
+    }
+
+    /// Get the members of the first set in `keys` that are not present in any
+    /// of the following sets.
+    #[instrument(skip(self))]
+    pub async fn sdiff(&mut self, keys: Vec<String>) -> crate::Result<HashSet<Bytes>> {
+        let frame = SDiff::new(keys).into_frame();
+        Ok(self.bulk_array_cmd(frame).await?.into_iter().collect())
+    }
+
+    /// Like `sinter`, but stores the result at `destination`.
+    ///
+    /// Returns the number of members of the resulting set.
+    #[instrument(skip(self))]
+    pub async fn sinterstore(
+        &mut self,
+        destination: &str,
+        keys: Vec<String>,
+    ) -> crate::Result<u64> {
+        let frame = SInterStore::new(destination, keys).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Like `sunion`, but stores the result at `destination`.
+    ///
+    /// Returns the number of members of the resulting set.
+    #[instrument(skip(self))]
+    pub async fn sunionstore(
+        &mut self,
+        destination: &str,
+        keys: Vec<String>,
+    ) -> crate::Result<u64> {
+        let frame = SUnionStore::new(destination, keys).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Like `sdiff`, but stores the result at `destination`.
+    ///
+    /// Returns the number of members of the resulting set.
+    #[instrument(skip(self))]
+    pub async fn sdiffstore(&mut self, destination: &str, keys: Vec<String>) -> crate::Result<u64> {
+        let frame = SDiffStore::new(destination, keys).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Add `members`, given as `(score, member)` pairs, to the sorted set
+    /// stored at `key`. The score of members already in the set is updated.
+    ///
+    /// Returns the number of members that were added.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.zadd("scores", vec![(10.0, "alice".into()), (7.5, "bob".into())]).await.unwrap();
+    ///
+    ///     let ranking = client.zrange_withscores("scores", 0, -1).await.unwrap();
+    ///     assert_eq!(ranking[0], ("bob".into(), 7.5));
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn zadd(&mut self, key: &str, members: Vec<(f64, Bytes)>) -> crate::Result<u64> {
+        let frame = ZAdd::new(key, members).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Increment the score of `member` in the sorted set stored at `key` by
+    /// `increment`.
+    ///
+    /// Returns the new score of the member.
+    #[instrument(skip(self))]
+    pub async fn zincrby(
+        &mut self,
+        key: &str,
+        increment: f64,
+        member: Bytes,
+    ) -> crate::Result<f64> {
+        let frame = ZIncrBy::new(key, increment, member).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Double(score) => Ok(score),
+            Frame::Bulk(score) => parse_score(&score),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Get the members of the sorted set stored at `key` ranked between
+    /// `start` and `stop`, both inclusive, from the lowest to the highest
+    /// score. Negative ranks count from the end of the set.
+    #[instrument(skip(self))]
+    pub async fn zrange(&mut self, key: &str, start: i64, stop: i64) -> crate::Result<Vec<Bytes>> {
+        let frame = ZRange::new(key, start, stop, false).into_frame();
+        self.bulk_array_cmd(frame).await
+    }
+
+    /// Like `zrange`, but also returns the score of each member.
+    #[instrument(skip(self))]
+    pub async fn zrange_withscores(
+        &mut self,
+        key: &str,
+        start: i64,
+        stop: i64,
+    ) -> crate::Result<Vec<(Bytes, f64)>> {
+        let frame = ZRange::new(key, start, stop, true).into_frame();
+        self.scored_array_cmd(frame).await
+    }
+
+    /// Get the members of the sorted set stored at `key` with a score between
+    /// `min` and `max`, both inclusive, from the lowest to the highest score.
+    #[instrument(skip(self))]
+    pub async fn zrangebyscore(
+        &mut self,
+        key: &str,
+        min: f64,
+        max: f64,
+    ) -> crate::Result<Vec<Bytes>> {
+        let frame = ZRangeByScore::new(key, min, max, false).into_frame();
+        self.bulk_array_cmd(frame).await
+    }
+
+    /// Get the rank of `member` in the sorted set stored at `key`, the member
+    /// with the lowest score having rank 0.
+    ///
+    /// `None` is returned if the key or the member does not exist.
+    #[instrument(skip(self))]
+    pub async fn zrank(&mut self, key: &str, member: Bytes) -> crate::Result<Option<u64>> {
+        let frame = ZRank::new(key, member).into_frame();
+
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(rank) => Ok(Some(rank as u64)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove `members` from the sorted set stored at `key`.
+    ///
+    /// Returns the number of members that were removed.
+    #[instrument(skip(self))]
+    pub async fn zrem(&mut self, key: &str, members: Vec<Bytes>) -> crate::Result<u64> {
+        let frame = ZRem::new(key, members).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Get the number of members of the sorted set stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn zcard(&mut self, key: &str) -> crate::Result<u64> {
+        let frame = ZCard::new(key).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Remove and return up to `count` members with the lowest scores from
+    /// the sorted set stored at `key`, along with their scores.
+    #[instrument(skip(self))]
+    pub async fn zpopmin(&mut self, key: &str, count: u64) -> crate::Result<Vec<(Bytes, f64)>> {
+        let frame = ZPopMin::new(key, Some(count)).into_frame();
+        self.scored_array_cmd(frame).await
+    }
+
+    /// Get a page of the members of the sorted set stored at `key` along with
+    /// their scores, resuming the iteration at `cursor`. Works like `scan`.
+    #[instrument(skip(self))]
+    pub async fn zscan(
+        &mut self,
+        key: &str,
+        cursor: u64,
+        pattern: Option<&str>,
+        count: Option<u64>,
+    ) -> crate::Result<(u64, Vec<(Bytes, f64)>)> {
+        let pattern = pattern.map(|pattern| Bytes::copy_from_slice(pattern.as_bytes()));
+        let frame = ZScan::new(key, cursor, pattern, count).into_frame();
+
+        let (cursor, values) = self.scan_cmd(frame).await?;
+        if values.len() % 2 != 0 {
+            return Err("protocol error; expected member score pairs".into());
+        }
+
+        let members = values
+            .chunks_exact(2)
+            .map(|pair| Ok((pair[0].clone(), parse_score(&pair[1])?)))
+            .collect::<crate::Result<_>>()?;
+
+        Ok((cursor, members))
+    }
+
+    /// Append an entry made of `fields` to the stream stored at `key`,
+    /// creating the stream if needed.
+    ///
+    /// Returns the identifier generated for the entry.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    /// use my_redis::StreamId;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let id = client.xadd("events", vec![("kind".into(), "signup".into())]).await.unwrap();
+    ///
+    ///     let entries = client.xrange("events", StreamId::MIN, StreamId::MAX).await.unwrap();
+    ///     assert_eq!(entries.last().unwrap().0, id);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xadd(
+        &mut self,
+        key: &str,
+        fields: Vec<(Bytes, Bytes)>,
+    ) -> crate::Result<StreamId> {
+        let frame = XAdd::new(key, fields).into_frame();
+
+        match self.optional_bulk_cmd(frame).await? {
+            Some(id) => parse_stream_id(&id),
+            None => Err("protocol error; unexpected nil stream ID".into()),
+        }
+    }
+
+    /// Get the entries of the stream stored at `key` with an identifier
+    /// between `start` and `end`, both inclusive.
+    #[instrument(skip(self))]
+    pub async fn xrange(
+        &mut self,
+        key: &str,
+        start: StreamId,
+        end: StreamId,
+    ) -> crate::Result<Vec<StreamEntry>> {
+        let frame = XRange::new(key, start, end).into_frame();
+        self.stream_entries_cmd(frame).await
+    }
+
+    /// Like `xrange`, but returns the newest entries first.
+    #[instrument(skip(self))]
+    pub async fn xrevrange(
+        &mut self,
+        key: &str,
+        end: StreamId,
+        start: StreamId,
+    ) -> crate::Result<Vec<StreamEntry>> {
+        let frame = XRevRange::new(key, end, start).into_frame();
+        self.stream_entries_cmd(frame).await
+    }
+
+    /// Get the number of entries of the stream stored at `key`.
+    #[instrument(skip(self))]
+    pub async fn xlen(&mut self, key: &str) -> crate::Result<u64> {
+        let frame = XLen::new(key).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Trim the stream stored at `key` to its `max_len` newest entries.
+    ///
+    /// Returns the number of evicted entries.
+    #[instrument(skip(self))]
+    pub async fn xtrim(&mut self, key: &str, max_len: u64) -> crate::Result<u64> {
+        let frame = XTrim::new(key, max_len).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Read the entries added to each of `streams` after the associated
+    /// identifier.
+    ///
+    /// When `block` is set and no stream has such an entry yet, the server
+    /// waits up to `block` for one to be added. A zero duration waits
+    /// forever. Streams without new entries are left out of the result.
+    #[instrument(skip(self))]
+    pub async fn xread(
+        &mut self,
+        streams: Vec<(String, StreamId)>,
+        block: Option<Duration>,
+    ) -> crate::Result<Vec<(String, Vec<StreamEntry>)>> {
+        let frame = XRead::new(streams, block).into_frame();
+        self.stream_read_cmd(frame).await
+    }
+
+    /// Create the consumer group `group` of the stream stored at `key`. The
+    /// group delivers the entries added after `id`.
+    #[instrument(skip(self))]
+    pub async fn xgroup_create(
+        &mut self,
+        key: &str,
+        group: &str,
+        id: StreamId,
+    ) -> crate::Result<()> {
+        let frame = XGroupCreate::new(key, group, id).into_frame();
+        self.ok_cmd(frame).await
+    }
+
+    /// Read, on behalf of `consumer`, the entries of the streams stored at
+    /// `keys` never delivered to the consumer group `group`.
+    ///
+    /// The entries are added to the pending entries list of the consumer
+    /// until acknowledged with `xack`. `block` behaves as for `xread`.
+    #[instrument(skip(self))]
+    pub async fn xreadgroup(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        keys: Vec<String>,
+        block: Option<Duration>,
+    ) -> crate::Result<Vec<(String, Vec<StreamEntry>)>> {
+        let frame = XReadGroup::new(group, consumer, keys, block).into_frame();
+        self.stream_read_cmd(frame).await
+    }
+
+    /// Acknowledge the entries `ids` delivered by the consumer group `group`
+    /// of the stream stored at `key`.
+    ///
+    /// Returns the number of acknowledged entries.
+    #[instrument(skip(self))]
+    pub async fn xack(&mut self, key: &str, group: &str, ids: Vec<StreamId>) -> crate::Result<u64> {
+        let frame = XAck::new(key, group, ids).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Summarize the entries delivered by the consumer group `group` of the
+    /// stream stored at `key` and not acknowledged yet.
+    ///
+    /// Returns the number of pending entries, along with the number of
+    /// pending entries of every consumer owning at least one.
+    #[instrument(skip(self))]
+    pub async fn xpending(
+        &mut self,
+        key: &str,
+        group: &str,
+    ) -> crate::Result<(u64, Vec<(String, u64)>)> {
+        let frame = XPending::new(key, group).into_frame();
+
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        let summary = match self.read_response().await? {
+            Frame::Array(summary) => summary,
+            frame => return Err(frame.to_error()),
+        };
+
+        let (count, consumers) = match <[Frame; 4]>::try_from(summary) {
+            Ok([Frame::Integer(count), _, _, consumers]) => (count, consumers),
+            _ => return Err("protocol error; invalid pending summary".into()),
+        };
+
+        let consumers = match consumers {
+            Frame::Array(consumers) => consumers,
+            Frame::Null => vec![],
+            frame => return Err(frame.to_error()),
+        };
+
+        let consumers = consumers
+            .into_iter()
+            .map(|consumer| match consumer {
+                Frame::Array(consumer) => match <[Frame; 2]>::try_from(consumer) {
+                    Ok([Frame::Bulk(name), Frame::Bulk(count)]) => {
+                        let count = atoi::atoi(&count).ok_or("protocol error; invalid number")?;
+                        Ok((String::from_utf8(name.to_vec())?, count))
+                    }
+                    _ => Err("protocol error; invalid pending summary".into()),
+                },
+                frame => Err(frame.to_error()),
+            })
+            .collect::<crate::Result<_>>()?;
+
+        Ok((count as u64, consumers))
+    }
+
+    /// Transfer to `consumer` the entries `ids` delivered by the consumer
+    /// group `group` of the stream stored at `key`, if they have not been
+    /// acknowledged for at least `min_idle`.
+    ///
+    /// Returns the claimed entries.
+    #[instrument(skip(self))]
+    pub async fn xclaim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: Duration,
+        ids: Vec<StreamId>,
+    ) -> crate::Result<Vec<StreamEntry>> {
+        let frame = XClaim::new(key, group, consumer, min_idle, ids).into_frame();
+        self.stream_entries_cmd(frame).await
+    }
+
+    /// Run the Lua script `script` on the server, with `keys` and `args` as
+    /// the `KEYS` and `ARGV` tables.
+    ///
+    /// Returns the reply of the script, which may be of any type.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let script = "return redis.call('incrby', KEYS[1], ARGV[1])";
+    ///     let reply = client
+    ///         .eval(script, vec!["counter".into()], vec!["5".into()])
+    ///         .await
+    ///         .unwrap();
+    ///     println!("counter = {:?}", reply);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn eval(
+        &mut self,
+        script: &str,
+        keys: Vec<String>,
+        args: Vec<Bytes>,
+    ) -> crate::Result<Frame> {
+        let frame = Eval::new(Bytes::copy_from_slice(script.as_bytes()), keys, args).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        self.read_response().await
+    }
+
+    /// Run the cached Lua script with the SHA1 digest `sha1`, see `eval`.
+    ///
+    /// Fails with a `NOSCRIPT` error if the script is not cached.
+    #[instrument(skip(self))]
+    pub async fn evalsha(
+        &mut self,
+        sha1: &str,
+        keys: Vec<String>,
+        args: Vec<Bytes>,
+    ) -> crate::Result<Frame> {
+        let frame = EvalSha::new(sha1, keys, args).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        self.read_response().await
+    }
+
+    /// Add the Lua script `script` to the script cache of the server.
+    ///
+    /// Returns the SHA1 digest to pass to `evalsha`.
+    #[instrument(skip(self))]
+    pub async fn script_load(&mut self, script: &str) -> crate::Result<String> {
+        let frame = Script::load(Bytes::copy_from_slice(script.as_bytes())).into_frame();
+        match self.optional_bulk_cmd(frame).await? {
+            Some(sha1) => Ok(String::from_utf8(sha1.to_vec())?),
+            None => Err("protocol error; expected a SHA1 digest".into()),
+        }
+    }
+
+    /// Returns, for each of the SHA1 digests `sha1s`, whether the script is in
+    /// the script cache of the server.
+    #[instrument(skip(self))]
+    pub async fn script_exists(&mut self, sha1s: Vec<String>) -> crate::Result<Vec<bool>> {
+        let frame = Script::exists(sha1s).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    Frame::Integer(exists) => Ok(exists == 1),
+                    frame => Err(frame.to_error()),
+                })
+                .collect(),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Empty the script cache of the server.
+    #[instrument(skip(self))]
+    pub async fn script_flush(&mut self) -> crate::Result<()> {
+        let frame = Script::flush().into_frame();
+        self.ok_cmd(frame).await
+    }
+
+    /// Write a snapshot of the key space to the RDB file of the server,
+    /// returning once it is written.
+    #[instrument(skip(self))]
+    pub async fn save(&mut self) -> crate::Result<()> {
+        let frame = Save::new().into_frame();
+        self.ok_cmd(frame).await
+    }
+
+    /// Start writing a snapshot of the key space to the RDB file of the server
+    /// in the background.
+    #[instrument(skip(self))]
+    pub async fn bgsave(&mut self) -> crate::Result<()> {
+        let frame = BgSave::new().into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(_) => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Start rewriting the append only file of the server in the background.
+    #[instrument(skip(self))]
+    pub async fn bgrewriteaof(&mut self) -> crate::Result<()> {
+        let frame = BgRewriteAof::new().into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(_) => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Make the server follow the leader at `host:port`, replacing its key
+    /// space with the one of the leader.
+    #[instrument(skip(self))]
+    pub async fn replicaof(&mut self, host: &str, port: u16) -> crate::Result<()> {
+        let frame = ReplicaOf::new(host, port).into_frame();
+        self.ok_cmd(frame).await
+    }
+
+    /// Make the server stop following its leader, accepting writes again.
+    #[instrument(skip(self))]
+    pub async fn replicaof_no_one(&mut self) -> crate::Result<()> {
+        let frame = ReplicaOf::no_one().into_frame();
+        self.ok_cmd(frame).await
+    }
+
+    /// Returns the replication role of the server, as replied by `ROLE`.
+    ///
+    /// A leader replies `master`, its offset and the followers connected. A
+    /// follower replies `slave`, its leader, the state of the link and its
+    /// offset.
+    #[instrument(skip(self))]
+    pub async fn role(&mut self) -> crate::Result<Frame> {
+        let frame = Role::new().into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        self.read_response().await
+    }
+
+    /// Returns the ranges of slots served by each node of the cluster, as
+    /// known by the server.
+    #[instrument(skip(self))]
+    pub async fn cluster_slots(&mut self) -> crate::Result<Vec<SlotRange>> {
+        let frame = Cluster::slots().into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        let ranges = match self.read_response().await? {
+            Frame::Array(ranges) => ranges,
+            frame => return Err(frame.to_error()),
+        };
+
+        ranges
+            .into_iter()
+            .map(|range| {
+                let range = match range {
+                    Frame::Array(range) => range,
+                    frame => return Err(frame.to_error()),
+                };
+                // Replicas serving the range may follow the node, ignored.
+                match &range[..] {
+                    [Frame::Integer(first), Frame::Integer(last), Frame::Array(node), ..] => {
+                        match &node[..] {
+                            [Frame::Bulk(host), Frame::Integer(port), ..] => Ok(SlotRange {
+                                first: u16::try_from(*first)?,
+                                last: u16::try_from(*last)?,
+                                host: String::from_utf8(host.to_vec())?,
+                                port: u16::try_from(*port)?,
+                            }),
+                            _ => Err("protocol error; invalid node in slots reply".into()),
+                        }
+                    }
+                    _ => Err("protocol error; invalid slots reply".into()),
+                }
+            })
+            .collect()
+    }
+
+    /// Create the user `username` or change it, applying `rules` in order,
+    /// such as `on`, `>password`, `~key:*` or `+@read`.
+    #[instrument(skip(self, rules))]
+    pub async fn acl_setuser(&mut self, username: &str, rules: &[&str]) -> crate::Result<()> {
+        let frame = Acl::set_user(username, rules).into_frame();
+        // The rules may hold passwords.
+        self.secret_ok_cmd(frame).await
+    }
+
+    /// Delete the users `usernames`, returning the number of users deleted.
+    #[instrument(skip(self))]
+    pub async fn acl_deluser(&mut self, usernames: &[&str]) -> crate::Result<u64> {
+        let frame = Acl::del_user(usernames).into_frame();
+        self.integer_cmd(frame).await
+    }
+
+    /// Returns the user the connection is authenticated as.
+    #[instrument(skip(self))]
+    pub async fn acl_whoami(&mut self) -> crate::Result<String> {
+        let frame = Acl::whoami().into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Bulk(user) => Ok(String::from_utf8(user.to_vec())?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Watch `keys` for the next transaction.
+    ///
+    /// The next transaction is not executed if one of the keys is modified in
+    /// the meantime, `exec` returning `None` instead.
+    #[instrument(skip(self))]
+    pub async fn watch(&mut self, keys: Vec<String>) -> crate::Result<()> {
+        let frame = Watch::new(keys).into_frame();
+        self.ok_cmd(frame).await
+    }
+
+    /// Forget the keys watched by `watch`.
+    #[instrument(skip(self))]
+    pub async fn unwatch(&mut self) -> crate::Result<()> {
+        let frame = Unwatch::new().into_frame();
+        self.ok_cmd(frame).await
+    }
+
+    /// Start building a transaction.
+    ///
+    /// The commands added to the returned `Transaction` are executed
+    /// atomically by the server: no other client observes the state between
+    /// two of them.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates optimistic locking, incrementing a counter stored as a
+    /// string.
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     loop {
+    ///         client.watch(vec!["counter".into()]).await.unwrap();
+    ///
+    ///         let value = client.get("counter").await.unwrap();
+    ///         let value: u64 = value.map_or(0, |value| {
+    ///             std::str::from_utf8(&value).unwrap().parse().unwrap()
+    ///         });
+    ///
+    ///         let replies = client
+    ///             .transaction()
+    ///             .set("counter", (value + 1).to_string().into())
+    ///             .exec()
+    ///             .await
+    ///             .unwrap();
+    ///
+    ///         // `None` means the counter was modified in the meantime.
+    ///         if replies.is_some() {
+    ///             break;
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        Transaction {
+            client: self,
+            commands: vec![],
+        }
+    }
+
+    /// Subscribes the client to the specified channels.
+    ///
+    /// Once a client issues a subscribe command, it may no longer issue any
+    /// non-pub/sub commands. The function consumes `self` and returns a `Subscriber`.
+    ///
+    /// The `Subscriber` value is used to receive messages as well as manage the
+    /// list of channels the client is subscribed to.
+    #[instrument(skip(self))]
+    pub async fn subscribe(mut self, channels: Vec<String>) -> crate::Result<Subscriber> {
+        // Issue the subscribe command to the server and wait for confirmation.
+        // The client will then have been transitioned into the "subscriber"
+        // state and may only issue pub/sub commands from that point on.
+        self.subscribe_cmd(&channels).await?;
+
+        // Return the `Subscriber` type
+        Ok(Subscriber {
+            client: self,
+            subscribed_channels: channels,
+        })
+    }
+
+    /// The core `SUBSCRIBE` logic, used by misc subscribe fns
+    async fn subscribe_cmd(&mut self, channels: &[String]) -> crate::Result<()> {
+        // Convert the `Subscribe` command into a frame
+        let frame = Subscribe::new(channels.to_vec()).into_frame();
+
+        debug!(request = ?frame);
+
+        // Write the frame to the socket
+        self.connection.write_frame(&frame).await?;
+
+        // For each channel being subscribed to, the server responds with a
+        // message confirming subscription to that channel.
+        for channel in channels {
+            // Read the response
+            let response = self.read_response().await?;
+
+            // Verify it is confirmation of subscription.
+            match response {
+                Frame::Array(ref frame) | Frame::Push(ref frame) => match frame.as_slice() {
+                    // The server responds with an array frame in the form of:
+                    //
+                    // ```
+                    // [ "subscribe", channel, num-subscribed ]
+                    // ```
+                    //
+                    // where channel is the name of the channel and
+                    // num-subscribed is the number of channels that the client
+                    // is currently subscribed to.
+                    [subscribe, schannel, ..]
+                        if *subscribe == "subscribe" && *schannel == channel => {}
+                    _ => return Err(response.to_error()),
+                },
+                frame => return Err(frame.to_error()),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Issue a command replying with a count, which is never negative.
+    async fn integer_cmd(&mut self, frame: Frame) -> crate::Result<u64> {
+        let response = self.signed_integer_cmd(frame).await?;
+        u64::try_from(response).map_err(|_| Frame::Integer(response).to_error())
+    }
+
+    /// Issue a command replying with an integer.
+    async fn signed_integer_cmd(&mut self, frame: Frame) -> crate::Result<i64> {
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(response) => Ok(response),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Issue a command replying with either a bulk string or nil.
+    async fn optional_bulk_cmd(&mut self, frame: Frame) -> crate::Result<Option<Bytes>> {
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Issue a command replying with an array of bulk strings.
+    ///
+    /// RESP3 sets are accepted as well, and maps are flattened into
+    /// alternating keys and values the way RESP2 replies them.
+    async fn bulk_array_cmd(&mut self, frame: Frame) -> crate::Result<Vec<Bytes>> {
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        let values = match self.read_response().await? {
+            Frame::Array(values) | Frame::Set(values) => values,
+            Frame::Map(pairs) => pairs
+                .into_iter()
+                .flat_map(|(key, value)| [key, value])
+                .collect(),
+            frame => return Err(frame.to_error()),
+        };
+
+        values
+            .into_iter()
+            .map(|value| match value {
+                Frame::Bulk(value) => Ok(value),
+                frame => Err(frame.to_error()),
+            })
+            .collect()
+    }
+
+    /// Issue a command replying with an array of members, each one followed
+    /// by its score.
+    async fn scored_array_cmd(&mut self, frame: Frame) -> crate::Result<Vec<(Bytes, f64)>> {
+        let values = self.bulk_array_cmd(frame).await?;
+
+        if values.len() % 2 != 0 {
+            return Err("protocol error; expected member score pairs".into());
+        }
+
+        values
+            .chunks_exact(2)
+            .map(|pair| Ok((pair[0].clone(), parse_score(&pair[1])?)))
+            .collect()
+    }
+
+    /// Issue a command replying with an array of stream entries.
+    async fn stream_entries_cmd(&mut self, frame: Frame) -> crate::Result<Vec<StreamEntry>> {
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        parse_stream_entries(self.read_response().await?)
+    }
+
+    /// Issue a command replying with the entries read from several streams,
+    /// or nil if there are none.
+    async fn stream_read_cmd(
+        &mut self,
+        frame: Frame,
+    ) -> crate::Result<Vec<(String, Vec<StreamEntry>)>> {
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        let streams = match self.read_response().await? {
+            Frame::Array(streams) => streams,
+            Frame::Null => return Ok(vec![]),
+            frame => return Err(frame.to_error()),
+        };
+
+        streams
+            .into_iter()
+            .map(|stream| match stream {
+                Frame::Array(stream) => match <[Frame; 2]>::try_from(stream) {
+                    Ok([Frame::Bulk(key), entries]) => Ok((
+                        String::from_utf8(key.to_vec())?,
+                        parse_stream_entries(entries)?,
+                    )),
+                    _ => Err("protocol error; invalid stream reply".into()),
+                },
+                frame => Err(frame.to_error()),
+            })
+            .collect()
+    }
+
+    /// Issue a scan command, replying with the next cursor and a page of bulk
+    /// strings.
+    async fn scan_cmd(&mut self, frame: Frame) -> crate::Result<(u64, Vec<Bytes>)> {
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        let response = match self.read_response().await? {
+            Frame::Array(response) => response,
+            frame => return Err(frame.to_error()),
+        };
+
+        match <[Frame; 2]>::try_from(response) {
+            Ok([Frame::Bulk(cursor), Frame::Array(page)]) => {
+                let cursor = atoi::atoi::<u64>(&cursor).ok_or("protocol error; invalid cursor")?;
+                let page = page
+                    .into_iter()
+                    .map(|value| match value {
+                        Frame::Bulk(value) => Ok(value),
+                        frame => Err(frame.to_error()),
+                    })
+                    .collect::<crate::Result<_>>()?;
+
+                Ok((cursor, page))
+            }
+            _ => Err("protocol error; invalid scan reply".into()),
+        }
+    }
+
+    /// Issue a command replying with `OK` on success.
+    async fn ok_cmd(&mut self, frame: Frame) -> crate::Result<()> {
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Issue a command replying `OK` like `ok_cmd`, without logging the
+    /// request which holds a password.
+    async fn secret_ok_cmd(&mut self, frame: Frame) -> crate::Result<()> {
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Issue any command, given as the frame sent to the server, returning
+    /// the reply.
+    pub(crate) async fn request(&mut self, frame: Frame) -> crate::Result<Frame> {
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        self.read_response().await
+    }
+
+    /// Reads a response frame from the socket.
+    ///
+    /// If an `Error` frame is received, it is converted to `Err`. RESP3
+    /// attributes preceding the response are skipped.
+    async fn read_response(&mut self) -> crate::Result<Frame> {
+        let mut response = self.connection.read_frame().await?;
+
+        while let Some(Frame::Attribute(_)) = response {
+            response = self.connection.read_frame().await?;
+        }
+
+        debug!(?response);
+
+        match response {
+            // Error frames are converted to `Err`
+            Some(Frame::Error(msg)) => Err(msg.into()),
+            Some(frame) => Ok(frame),
+            None => {
+                // Receiving `None` here indicates the server has closed the
+                // connection without sending a frame. This is unexpected and is
+                // represented as a "connection reset by peer" error.
+                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by server");
+
+                Err(err.into())
+            }
+        }
+    }
+}
+
+impl Transaction<'_> {
+    /// Queue a `GET key`.
+    pub fn get(self, key: &str) -> Self {
+        self.queue(Get::new(key).into_frame())
+    }
+
+    /// Queue a `SET key value`.
+    pub fn set(self, key: &str, value: Bytes) -> Self {
+        self.queue(Set::new(key, value, None).into_frame())
+    }
+
+    /// Queue a `SET key value PX expiration`.
+    pub fn set_expires(self, key: &str, value: Bytes, expiration: Duration) -> Self {
+        self.queue(Set::new(key, value, Some(expiration)).into_frame())
+    }
+
+    /// Queue a `DEL key [key ...]`.
+    pub fn del(self, keys: Vec<String>) -> Self {
+        self.queue(Del::new(keys).into_frame())
+    }
+
+    /// Queue an `INCR key`.
+    pub fn incr(self, key: &str) -> Self {
+        self.queue(Incr::new(key).into_frame())
+    }
+
+    /// Queue an `INCRBY key increment`.
+    pub fn incrby(self, key: &str, increment: i64) -> Self {
+        self.queue(IncrBy::new(key, increment).into_frame())
+    }
+
+    /// Queue an `EXPIRE key seconds`.
+    pub fn expire(self, key: &str, seconds: i64) -> Self {
+        self.queue(Expire::new(key, seconds).into_frame())
+    }
+
+    /// Queue an `LPUSH key value [value ...]`.
+    pub fn lpush(self, key: &str, values: Vec<Bytes>) -> Self {
+        self.queue(LPush::new(key, values).into_frame())
+    }
+
+    /// Queue an `RPUSH key value [value ...]`.
+    pub fn rpush(self, key: &str, values: Vec<Bytes>) -> Self {
+        self.queue(RPush::new(key, values).into_frame())
+    }
+
+    /// Queue an `HSET key field value [field value ...]`.
+    pub fn hset(self, key: &str, fields: Vec<(Bytes, Bytes)>) -> Self {
+        self.queue(HSet::new(key, fields).into_frame())
+    }
+
+    /// Queue an `HINCRBY key field increment`.
+    pub fn hincrby(self, key: &str, field: Bytes, increment: i64) -> Self {
+        self.queue(HIncrBy::new(key, field, increment).into_frame())
+    }
+
+    /// Queue an `SADD key member [member ...]`.
+    pub fn sadd(self, key: &str, members: Vec<Bytes>) -> Self {
+        self.queue(SAdd::new(key, members).into_frame())
+    }
+
+    /// Queue an `SREM key member [member ...]`.
+    pub fn srem(self, key: &str, members: Vec<Bytes>) -> Self {
+        self.queue(SRem::new(key, members).into_frame())
+    }
+
+    /// Queue a `PUBLISH channel message`.
+    pub fn publish(self, channel: &str, message: Bytes) -> Self {
+        self.queue(Publish::new(channel, message).into_frame())
+    }
+
+    /// Queue any command, given as the frame sent to the server.
+    pub fn queue(mut self, frame: Frame) -> Self {
+        self.commands.push(frame);
+        self
+    }
+
+    /// Execute the transaction.
+    ///
+    /// Returns the reply of each command, or `None` if a watched key was
+    /// modified, in which case nothing was executed. A command failing at
+    /// runtime, e.g. on a key of the wrong type, does not prevent the other
+    /// ones from running: its reply is an `Error` frame.
+    ///
+    /// If the server refuses to queue a command, the transaction is discarded
+    /// and the error is returned.
+    #[instrument(skip(self))]
+    pub async fn exec(self) -> crate::Result<Option<Vec<Frame>>> {
+        let client = self.client;
+        client.ok_cmd(Multi::new().into_frame()).await?;
+
+        for frame in self.commands {
+            debug!(request = ?frame);
+            client.connection.write_frame(&frame).await?;
+
+            let queued = match client.read_response().await {
+                Ok(Frame::Simple(response)) if response == "QUEUED" => Ok(()),
+                Ok(frame) => Err(frame.to_error()),
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = queued {
+                client.ok_cmd(Discard::new().into_frame()).await?;
+                return Err(err);
+            }
+        }
+
+        let frame = Exec::new().into_frame();
+        debug!(request = ?frame);
+        client.connection.write_frame(&frame).await?;
+
+        match client.read_response().await? {
+            Frame::Array(replies) => Ok(Some(replies)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+}
+
+impl Subscriber {
+    /// Returns the set of channels currently subscribed to.
+    pub fn get_subscribed(&self) -> &[String] {
+        &self.subscribed_channels
+    }
+
+    /// Receive the next message published on a subscribed channel, waiting if
+    /// necessary.
+    ///
+    /// `None` indicates the subscription has been terminated.
+    pub async fn next_message(&mut self) -> crate::Result<Option<Message>> {
+        match self.client.connection.read_frame().await? {
+            Some(mframe) => {
+                debug!(?mframe);
+
+                match mframe {
+                    Frame::Array(ref frame) | Frame::Push(ref frame) => match frame.as_slice() {
+                        [message, channel, content] if *message == "message" => Ok(Some(Message {
+                            channel: channel.to_string(),
+                            content: Bytes::from(content.to_string()),
+                        })),
+                        _ => Err(mframe.to_error()),
+                    },
+                    frame => Err(frame.to_error()),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Convert the subscriber into a `Stream` yielding new messages published
+    /// on subscribed channels.
+    ///
+    /// `Subscriber` does not implement stream itself as doing so with safe code
+    /// is non trivial. The usage of async/await would require a manual Stream
+    /// implementation to use `unsafe` code. Instead, a conversion function is
+    /// provided and the returned stream is implemented with the help of the
+    /// `async-stream` crate.
+    pub fn into_stream(mut self) -> impl Stream<Item = crate::Result<Message>> {
+        // Uses the `try_stream` macro from the `async-stream` crate. Generators
+        // are not stable in Rust. The crate uses a macro to simulate generators
+        // on top of async/await. There are limitations, so read the
+        // documentation there.
+        try_stream! {
+            while let Some(message) = self.next_message().await? {
+                yield message;
+            }
+        }
+    }
+
+    /// Subscribe to a list of new channels
+    #[instrument(skip(self))]
+    pub async fn subscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        // Issue the subscribe command
+        self.client.subscribe_cmd(channels).await?;
+
+        // Update the set of subscribed channels.
+        self.subscribed_channels
+            .extend(channels.iter().map(Clone::clone));
+
+        Ok(())
+    }
+
+    /// Unsubscribe to a list of new channels
+    #[instrument(skip(self))]
+    pub async fn unsubscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        let frame = Unsubscribe::new(channels).into_frame();
+
+        debug!(request = ?frame);
+
+        // Write the frame to the socket
+        self.client.connection.write_frame(&frame).await?;
+
+        // if the input channel list is empty, server acknowledges as unsubscribing
         // from all subscribed channels, so we assert that the unsubscribe list received
         // matches the client subscribed one
         let num = if channels.is_empty() {
@@ -479,7 +2301,7 @@ impl Subscriber {
             let response = self.client.read_response().await?;
 
             match response {
-                Frame::Array(ref frame) => match frame.as_slice() {
+                Frame::Array(ref frame) | Frame::Push(ref frame) => match frame.as_slice() {
                     [unsubscribe, channel, ..] if *unsubscribe == "unsubscribe" => {
                         let len = self.subscribed_channels.len();
 
@@ -505,4 +2327,49 @@ impl Subscriber {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+/// Parse a floating point number replied by the server, e.g. a score such as
+/// `1.5` or `-inf`.
+fn parse_score(src: &[u8]) -> crate::Result<f64> {
+    std::str::from_utf8(src)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| "protocol error; invalid score".into())
+}
+
+/// Parse a stream entry identifier replied by the server.
+fn parse_stream_id(src: &[u8]) -> crate::Result<StreamId> {
+    StreamId::parse(src, 0).ok_or_else(|| "protocol error; invalid stream ID".into())
+}
+
+/// Parse an array of stream entries, each one being an identifier followed
+/// by the flattened field/value pairs.
+fn parse_stream_entries(frame: Frame) -> crate::Result<Vec<StreamEntry>> {
+    let entries = match frame {
+        Frame::Array(entries) => entries,
+        frame => return Err(frame.to_error()),
+    };
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let (id, fields) = match entry {
+                Frame::Array(entry) => match <[Frame; 2]>::try_from(entry) {
+                    Ok([Frame::Bulk(id), Frame::Array(fields)]) => (id, fields),
+                    _ => return Err("protocol error; invalid stream entry".into()),
+                },
+                frame => return Err(frame.to_error()),
+            };
+
+            let fields = fields
+                .chunks_exact(2)
+                .map(|pair| match pair {
+                    [Frame::Bulk(field), Frame::Bulk(value)] => Ok((field.clone(), value.clone())),
+                    _ => Err("protocol error; invalid stream entry".into()),
+                })
+                .collect::<crate::Result<_>>()?;
+
+            Ok((parse_stream_id(&id)?, fields))
+        })
+        .collect()
+}
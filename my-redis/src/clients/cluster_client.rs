@@ -0,0 +1,308 @@
+use crate::clients::Client;
+use crate::cluster::{self, Redirect, SLOTS};
+use crate::cmd::{Asking, Del, Exists, Expire, Get, Incr, IncrBy, Set, Ttl};
+use crate::{Command, Frame};
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+use tracing::{debug, instrument, warn};
+
+/// Maximum number of redirections followed by a request before giving up.
+const MAX_REDIRECTS: usize = 5;
+
+/// Delay before a request refused with `-TRYAGAIN`, while its slot migrates,
+/// is sent again.
+const TRYAGAIN_DELAY: Duration = Duration::from_millis(100);
+
+/// Client of a Redis Cluster.
+///
+/// The topology of the cluster is discovered with `CLUSTER SLOTS`, and each
+/// request is sent to the node serving the slot of its keys, connections to
+/// the nodes being kept open. A `-MOVED` redirection refreshes the slot map
+/// before the request is sent again to the node given. A `-ASK` redirection
+/// only applies to the request redirected, which is sent again preceded by
+/// `ASKING`.
+///
+/// A command operating on keys which hash to different slots fails without
+/// being sent.
+pub struct ClusterClient {
+    /// Addresses the topology was first discovered from, tried again when
+    /// refreshing it.
+    seeds: Vec<String>,
+
+    /// Connections to the nodes, by address.
+    nodes: HashMap<String, Client>,
+
+    /// Address of the node serving each slot, `None` for a slot not served.
+    slots: Vec<Option<String>>,
+}
+
+/// A range of slots served by a node, as replied by `CLUSTER SLOTS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotRange {
+    pub first: u16,
+    pub last: u16,
+    pub host: String,
+    pub port: u16,
+}
+
+impl ClusterClient {
+    /// Connect to the cluster the nodes at `seeds`, given as `host:port`,
+    /// belong to.
+    ///
+    /// The slot map is fetched from the first node reachable.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use my_redis::clients::ClusterClient;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = ClusterClient::connect(&["127.0.0.1:7000", "127.0.0.1:7001"])
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     client.set("{user1}.name", "alice".into()).await.unwrap();
+    ///     let name = client.get("{user1}.name").await.unwrap();
+    ///     println!("name = {:?}", name);
+    /// }
+    /// ```
+    pub async fn connect(seeds: &[&str]) -> crate::Result<ClusterClient> {
+        let mut client = ClusterClient {
+            seeds: seeds.iter().map(|seed| seed.to_string()).collect(),
+            nodes: HashMap::new(),
+            slots: vec![None; SLOTS as usize],
+        };
+        client.refresh().await?;
+        Ok(client)
+    }
+
+    /// Fetch the slot map again, from the first node reachable among the
+    /// nodes known and the seeds.
+    #[instrument(skip(self))]
+    pub async fn refresh(&mut self) -> crate::Result<()> {
+        let mut addrs: Vec<String> = self.nodes.keys().cloned().collect();
+        addrs.extend(self.seeds.iter().cloned());
+
+        let mut last_err = None;
+        for addr in addrs {
+            let ranges = match self.node(&addr).await {
+                Ok(node) => node.cluster_slots().await,
+                Err(err) => Err(err),
+            };
+            match ranges {
+                Ok(ranges) => {
+                    self.slots = vec![None; SLOTS as usize];
+                    for range in ranges {
+                        let addr = format!("{}:{}", range.host, range.port);
+                        for slot in range.first..=range.last.min(SLOTS - 1) {
+                            self.slots[slot as usize] = Some(addr.clone());
+                        }
+                    }
+                    return Ok(());
+                }
+                Err(err) => {
+                    warn!(cause = %err, %addr, "failed to fetch the slot map");
+                    self.nodes.remove(&addr);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| "no node to fetch the slot map from".into()))
+    }
+
+    /// Get the value of `key`, see `Client::get`.
+    #[instrument(skip(self))]
+    pub async fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        match self.request(Get::new(key).into_frame()).await? {
+            Frame::Simple(value) => Ok(Some(value.into())),
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set `key` to hold `value`, see `Client::set`.
+    #[instrument(skip(self))]
+    pub async fn set(&mut self, key: &str, value: Bytes) -> crate::Result<()> {
+        let frame = Set::new(key, value, None).into_frame();
+        self.ok_request(frame).await
+    }
+
+    /// Set `key` to hold `value`, expiring after `expiration`, see
+    /// `Client::set_expires`.
+    #[instrument(skip(self))]
+    pub async fn set_expires(
+        &mut self,
+        key: &str,
+        value: Bytes,
+        expiration: Duration,
+    ) -> crate::Result<()> {
+        let frame = Set::new(key, value, Some(expiration)).into_frame();
+        self.ok_request(frame).await
+    }
+
+    /// Increment the integer stored at `key` by one, see `Client::incr`.
+    #[instrument(skip(self))]
+    pub async fn incr(&mut self, key: &str) -> crate::Result<i64> {
+        let frame = Incr::new(key).into_frame();
+        self.integer_request(frame).await
+    }
+
+    /// Increment the integer stored at `key` by `increment`, see
+    /// `Client::incrby`.
+    #[instrument(skip(self))]
+    pub async fn incrby(&mut self, key: &str, increment: i64) -> crate::Result<i64> {
+        let frame = IncrBy::new(key, increment).into_frame();
+        self.integer_request(frame).await
+    }
+
+    /// Remove `keys`, returning the number of keys that existed. The keys must
+    /// hash to the same slot.
+    #[instrument(skip(self))]
+    pub async fn del(&mut self, keys: Vec<String>) -> crate::Result<u64> {
+        let frame = Del::new(keys).into_frame();
+        let count = self.integer_request(frame).await?;
+        u64::try_from(count).map_err(|_| Frame::Integer(count).to_error())
+    }
+
+    /// Returns the number of `keys` that exist. The keys must hash to the
+    /// same slot.
+    #[instrument(skip(self))]
+    pub async fn exists(&mut self, keys: Vec<String>) -> crate::Result<u64> {
+        let frame = Exists::new(keys).into_frame();
+        let count = self.integer_request(frame).await?;
+        u64::try_from(count).map_err(|_| Frame::Integer(count).to_error())
+    }
+
+    /// Set a timeout of `seconds` on `key`, see `Client::expire`.
+    #[instrument(skip(self))]
+    pub async fn expire(&mut self, key: &str, seconds: i64) -> crate::Result<bool> {
+        let frame = Expire::new(key, seconds).into_frame();
+        Ok(self.integer_request(frame).await? == 1)
+    }
+
+    /// Get the remaining time to live of `key`, see `Client::ttl`.
+    #[instrument(skip(self))]
+    pub async fn ttl(&mut self, key: &str) -> crate::Result<i64> {
+        let frame = Ttl::new(key).into_frame();
+        self.integer_request(frame).await
+    }
+
+    /// Issue any command, given as the frame sent to the server, returning
+    /// the reply.
+    ///
+    /// The command is sent to the node serving the slot of its keys, or to
+    /// any node if it has none, following the redirections received.
+    pub async fn request(&mut self, frame: Frame) -> crate::Result<Frame> {
+        let slot = request_slot(&frame)?;
+        let mut addr = self.addr_for(slot)?;
+        let mut asking = false;
+
+        for _ in 0..MAX_REDIRECTS {
+            let node = self.node(&addr).await?;
+            let mut result = Ok(Frame::Null);
+            if asking {
+                result = node.request(Asking::new().into_frame()).await;
+            }
+            if result.is_ok() {
+                result = node.request(frame.clone()).await;
+            }
+
+            let err = match result {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            };
+            // The connection is broken, it is established again next time.
+            if err.downcast_ref::<io::Error>().is_some() {
+                self.nodes.remove(&addr);
+                return Err(err);
+            }
+
+            asking = false;
+            match Redirect::parse(&err.to_string()) {
+                Some(Redirect::Moved { slot, addr: to }) => {
+                    debug!(slot, %to, "moved");
+                    if let Err(err) = self.refresh().await {
+                        warn!(cause = %err, "failed to refresh the slot map");
+                    }
+                    // The node redirecting knows best, whatever the slot map
+                    // says.
+                    self.slots[slot as usize] = Some(to.clone());
+                    addr = to;
+                }
+                Some(Redirect::Ask { slot, addr: to }) => {
+                    debug!(slot, %to, "ask");
+                    addr = to;
+                    asking = true;
+                }
+                Some(Redirect::TryAgain) => tokio::time::sleep(TRYAGAIN_DELAY).await,
+                _ => return Err(err),
+            }
+        }
+
+        Err("too many cluster redirections".into())
+    }
+
+    /// Issue a command replying with `OK` on success.
+    async fn ok_request(&mut self, frame: Frame) -> crate::Result<()> {
+        match self.request(frame).await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Issue a command replying with an integer.
+    async fn integer_request(&mut self, frame: Frame) -> crate::Result<i64> {
+        match self.request(frame).await? {
+            Frame::Integer(response) => Ok(response),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Returns the address of the node serving `slot`, or of any node for a
+    /// command without keys or a slot not served.
+    fn addr_for(&self, slot: Option<u16>) -> crate::Result<String> {
+        let served = slot.and_then(|slot| self.slots[slot as usize].clone());
+        served
+            .or_else(|| self.slots.iter().flatten().next().cloned())
+            .or_else(|| self.seeds.first().cloned())
+            .ok_or_else(|| "no node to send the request to".into())
+    }
+
+    /// Returns the connection to the node at `addr`, established if needed.
+    async fn node(&mut self, addr: &str) -> crate::Result<&mut Client> {
+        if !self.nodes.contains_key(addr) {
+            let client = Client::connect(addr).await?;
+            self.nodes.insert(addr.to_string(), client);
+        }
+        Ok(self.nodes.get_mut(addr).expect("connection established"))
+    }
+}
+
+/// Returns the slot of the keys of the command `frame`, `None` if it has no
+/// keys. Fails if the keys hash to different slots.
+fn request_slot(frame: &Frame) -> crate::Result<Option<u16>> {
+    let cmd = Command::from_frame(frame.clone())?;
+    let keys = cmd.keys();
+
+    let mut slots = keys
+        .iter()
+        .map(|key| (key, cluster::key_slot(key.as_bytes())));
+    let (first, slot) = match slots.next() {
+        Some(first) => first,
+        None => return Ok(None),
+    };
+    match slots.find(|&(_, other)| other != slot) {
+        Some((other, _)) => Err(format!(
+            "CROSSSLOT keys '{}' and '{}' hash to different slots, use a hash tag such as '{{tag}}' to group them",
+            first, other
+        )
+        .into()),
+        None => Ok(Some(slot)),
+    }
+}
@@ -1,5 +1,8 @@
 mod client;
-pub use client::{Client, Message, Subscriber};
+pub use client::{Client, Message, Subscriber, Transaction};
+
+mod cluster_client;
+pub use cluster_client::{ClusterClient, SlotRange};
 
 mod blocking_client;
 pub use blocking_client::BlockingClient;
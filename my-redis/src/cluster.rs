@@ -0,0 +1,493 @@
+//! Cluster mode.
+//!
+//! The key space is split in 16384 hash slots, each served by one node of the
+//! cluster. The slot of a key is the CRC16 of the key modulo 16384. When the
+//! key holds a hash tag, the part between the first `{` and the next `}` if
+//! not empty, only the hash tag is hashed: keys sharing a hash tag hash to the
+//! same slot, so that multi-key commands may operate on them.
+//!
+//! The topology is read from the cluster config file, written like the reply
+//! of `CLUSTER NODES`, and rewritten whenever `CLUSTER ADDSLOTS` or
+//! `CLUSTER SETSLOT` changes it. There is no cluster bus: nodes do not gossip,
+//! the topology of each node is changed on that node only.
+//!
+//! A command whose keys hash to a slot served by another node is refused with
+//! `-MOVED <slot> <host>:<port>`. While a slot migrates, the keys missing from
+//! the node serving it are redirected with `-ASK` to the node importing it,
+//! which serves them to the clients sending `ASKING` first.
+
+use crate::replication;
+
+use crc::{Crc, CRC_16_XMODEM};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Number of hash slots the key space is split in.
+pub(crate) const SLOTS: u16 = 16384;
+
+const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_XMODEM);
+
+/// Topology of the cluster, as known by this node.
+#[derive(Debug)]
+pub(crate) struct ClusterState {
+    /// Config file the topology is saved to.
+    path: PathBuf,
+
+    /// Nodes of the cluster, this node included.
+    nodes: Vec<Node>,
+
+    /// Index of this node in `nodes`.
+    myself: usize,
+
+    /// Index in `nodes` of the node serving each slot, `None` for a slot not
+    /// served.
+    slots: Vec<Option<usize>>,
+
+    /// Slots served by this node migrating to another one, along with the
+    /// index of the target node.
+    migrating: BTreeMap<u16, usize>,
+
+    /// Slots imported by this node from another one, along with the index of
+    /// the source node.
+    importing: BTreeMap<u16, usize>,
+}
+
+/// A node of the cluster.
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+    pub(crate) id: String,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+/// Why a command may not be served by this node.
+#[derive(Debug)]
+pub(crate) enum Redirect {
+    /// The slot is served by the node at `addr`.
+    Moved { slot: u16, addr: String },
+
+    /// The keys were migrated to the node at `addr`, which serves them to a
+    /// client sending `ASKING` first.
+    Ask { slot: u16, addr: String },
+
+    /// The keys hash to different slots.
+    CrossSlot,
+
+    /// Some keys of a multi-key command were migrated, others not yet.
+    TryAgain,
+
+    /// No node serves the slot.
+    Down,
+}
+
+/// Change made to a slot by `CLUSTER SETSLOT`, naming nodes by their id.
+#[derive(Debug, Clone)]
+pub(crate) enum SetSlot {
+    /// Start migrating the slot to another node.
+    Migrating(String),
+
+    /// Start importing the slot from another node.
+    Importing(String),
+
+    /// Stop migrating or importing the slot.
+    Stable,
+
+    /// Assign the slot to a node, ending any migration.
+    Node(String),
+}
+
+/// Returns the hash slot of `key`.
+pub(crate) fn key_slot(key: &[u8]) -> u16 {
+    let hashed = match key.iter().position(|&b| b == b'{') {
+        Some(open) => {
+            let tag = &key[open + 1..];
+            match tag.iter().position(|&b| b == b'}') {
+                // An empty hash tag, `{}`, is not one: the whole key is hashed.
+                Some(0) | None => key,
+                Some(close) => &tag[..close],
+            }
+        }
+        None => key,
+    };
+    CRC16.checksum(hashed) % SLOTS
+}
+
+/// Parse a slot number given to a `CLUSTER` command.
+pub(crate) fn parse_slot(slot: &str) -> crate::Result<u16> {
+    match slot.parse() {
+        Ok(slot) if slot < SLOTS => Ok(slot),
+        _ => Err("ERR Invalid or out of range slot".into()),
+    }
+}
+
+impl ClusterState {
+    /// Load the topology from the config file at `path`.
+    ///
+    /// When the file does not exist, the cluster starts with this node alone,
+    /// reachable at `addr` and serving no slot, and the file is created.
+    pub(crate) fn load(path: &Path, addr: SocketAddr) -> crate::Result<ClusterState> {
+        let state = match fs::read_to_string(path) {
+            Ok(data) => ClusterState::parse(path, &data)
+                .map_err(|err| format!("invalid cluster config file {:?}: {}", path, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let myself = Node {
+                    id: replication::new_replid(),
+                    host: addr.ip().to_string(),
+                    port: addr.port(),
+                };
+                info!(id = %myself.id, ?path, "created a new cluster config file");
+
+                let state = ClusterState {
+                    path: path.to_path_buf(),
+                    nodes: vec![myself],
+                    myself: 0,
+                    slots: vec![None; SLOTS as usize],
+                    migrating: BTreeMap::new(),
+                    importing: BTreeMap::new(),
+                };
+                state.save()?;
+                state
+            }
+            Err(err) => return Err(err.into()),
+        };
+        Ok(state)
+    }
+
+    /// Parse the config file, one node per line:
+    ///
+    /// ```text
+    /// <id> <host>:<port>[@<bus port>] <flags> <leader> <ping sent> <pong received> <epoch> <link state> [<slot> | <first>-<last> ...]
+    /// ```
+    ///
+    /// The flags of this node include `myself`. Slots are followed by the
+    /// migrations, `[<slot>->-<id>]` and `[<slot>-<-<id>]`. Empty lines, and
+    /// lines starting with `#` or `vars`, are skipped.
+    fn parse(path: &Path, data: &str) -> crate::Result<ClusterState> {
+        let lines: Vec<Vec<&str>> = data
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .filter(|fields| match fields.first() {
+                Some(first) => !first.starts_with('#') && *first != "vars",
+                None => false,
+            })
+            .collect();
+
+        let mut nodes = vec![];
+        let mut myself = None;
+        for fields in &lines {
+            if fields.len() < 8 {
+                return Err(format!("missing fields for node '{}'", fields[0]).into());
+            }
+            let addr = fields[1].split('@').next().unwrap_or_default();
+            let (host, port) = addr
+                .rsplit_once(':')
+                .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+                .ok_or_else(|| format!("invalid address '{}'", fields[1]))?;
+            if fields[2].split(',').any(|flag| flag == "myself") {
+                myself = Some(nodes.len());
+            }
+            nodes.push(Node {
+                id: fields[0].to_string(),
+                host: host.to_string(),
+                port,
+            });
+        }
+        let myself = myself.ok_or("no node flagged 'myself'")?;
+
+        let mut state = ClusterState {
+            path: path.to_path_buf(),
+            nodes,
+            myself,
+            slots: vec![None; SLOTS as usize],
+            migrating: BTreeMap::new(),
+            importing: BTreeMap::new(),
+        };
+        for (index, fields) in lines.iter().enumerate() {
+            for range in &fields[8..] {
+                state.parse_slots(index, range)?;
+            }
+        }
+        Ok(state)
+    }
+
+    /// Parse the slots `range` served by the node `index`, or a migration.
+    fn parse_slots(&mut self, index: usize, range: &str) -> crate::Result<()> {
+        let invalid = || format!("invalid slots '{}'", range);
+
+        if let Some(migration) = range.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+            let (slot, migrating, id) =
+                match (migration.split_once("->-"), migration.split_once("-<-")) {
+                    (Some((slot, id)), _) => (slot, true, id),
+                    (_, Some((slot, id))) => (slot, false, id),
+                    _ => return Err(invalid().into()),
+                };
+            let slot = parse_slot(slot).map_err(|_| invalid())?;
+            let other = self.node_index(id)?;
+            if migrating {
+                self.migrating.insert(slot, other);
+            } else {
+                self.importing.insert(slot, other);
+            }
+            return Ok(());
+        }
+
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let first = parse_slot(first).map_err(|_| invalid())?;
+        let last = parse_slot(last).map_err(|_| invalid())?;
+        for slot in first..=last {
+            self.slots[slot as usize] = Some(index);
+        }
+        Ok(())
+    }
+
+    /// Write the topology to the config file.
+    fn save(&self) -> crate::Result<()> {
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let write = || -> io::Result<()> {
+            fs::write(&tmp, self.nodes_info())?;
+            fs::rename(&tmp, &self.path)
+        };
+        write().map_err(|err| format!("ERR Failed writing {:?}: {}", self.path, err).into())
+    }
+
+    /// Returns `Ok` if this node serves the command operating on `keys`, or
+    /// the reason it does not.
+    ///
+    /// `asking` is set when the client sent `ASKING` before the command.
+    /// `exists` tells whether a key is held by this node, for slots which are
+    /// migrating.
+    pub(crate) fn check(
+        &self,
+        keys: &[&str],
+        asking: bool,
+        exists: impl Fn(&str) -> bool,
+    ) -> Result<(), Redirect> {
+        let mut slots = keys.iter().map(|key| key_slot(key.as_bytes()));
+        let slot = match slots.next() {
+            Some(slot) => slot,
+            None => return Ok(()),
+        };
+        if slots.any(|other| other != slot) {
+            return Err(Redirect::CrossSlot);
+        }
+        if asking && self.importing.contains_key(&slot) {
+            return Ok(());
+        }
+
+        match self.slots[slot as usize] {
+            None => Err(Redirect::Down),
+            Some(owner) if owner != self.myself => Err(Redirect::Moved {
+                slot,
+                addr: self.nodes[owner].addr(),
+            }),
+            Some(_) => {
+                let target = match self.migrating.get(&slot) {
+                    Some(&target) => target,
+                    None => return Ok(()),
+                };
+                match keys.iter().filter(|key| !exists(key)).count() {
+                    0 => Ok(()),
+                    missing if missing == keys.len() => Err(Redirect::Ask {
+                        slot,
+                        addr: self.nodes[target].addr(),
+                    }),
+                    _ => Err(Redirect::TryAgain),
+                }
+            }
+        }
+    }
+
+    /// Assign `slots` to this node. Fails without assigning any if one of
+    /// them is already served.
+    pub(crate) fn add_slots(&mut self, slots: &[u16]) -> crate::Result<()> {
+        let mut seen = HashSet::new();
+        for &slot in slots {
+            if !seen.insert(slot) {
+                return Err(format!("ERR Slot {} specified multiple times", slot).into());
+            }
+            if self.slots[slot as usize].is_some() {
+                return Err(format!("ERR Slot {} is already busy", slot).into());
+            }
+        }
+
+        for &slot in slots {
+            self.slots[slot as usize] = Some(self.myself);
+            self.importing.remove(&slot);
+        }
+        self.save()
+    }
+
+    /// Change the state of `slot`, see `SetSlot`.
+    pub(crate) fn set_slot(&mut self, slot: u16, change: SetSlot) -> crate::Result<()> {
+        let owner = self.slots[slot as usize];
+
+        match change {
+            SetSlot::Migrating(id) => {
+                let target = self.node_index(&id)?;
+                if owner != Some(self.myself) {
+                    return Err(format!("ERR I'm not the owner of hash slot {}", slot).into());
+                }
+                if target == self.myself {
+                    return Err("ERR Can't MIGRATE to myself".into());
+                }
+                self.migrating.insert(slot, target);
+            }
+            SetSlot::Importing(id) => {
+                let source = self.node_index(&id)?;
+                if owner == Some(self.myself) {
+                    return Err(format!("ERR I'm already the owner of hash slot {}", slot).into());
+                }
+                if source == self.myself {
+                    return Err("ERR Can't IMPORT from myself".into());
+                }
+                self.importing.insert(slot, source);
+            }
+            SetSlot::Stable => {
+                self.migrating.remove(&slot);
+                self.importing.remove(&slot);
+            }
+            SetSlot::Node(id) => {
+                let node = self.node_index(&id)?;
+                self.slots[slot as usize] = Some(node);
+                self.migrating.remove(&slot);
+                self.importing.remove(&slot);
+            }
+        }
+        self.save()
+    }
+
+    /// Returns the ranges of consecutive slots served by the same node, in
+    /// order, along with that node.
+    pub(crate) fn slot_ranges(&self) -> Vec<(u16, u16, &Node)> {
+        let mut ranges: Vec<(u16, u16, usize)> = vec![];
+        for (slot, owner) in self.slots.iter().enumerate() {
+            let (slot, owner) = match owner {
+                Some(owner) => (slot as u16, *owner),
+                None => continue,
+            };
+            match ranges.last_mut() {
+                Some((_, last, node)) if *last + 1 == slot && *node == owner => *last = slot,
+                _ => ranges.push((slot, slot, owner)),
+            }
+        }
+        ranges
+            .into_iter()
+            .map(|(first, last, node)| (first, last, &self.nodes[node]))
+            .collect()
+    }
+
+    /// Returns every node, along with the ranges of slots it serves.
+    pub(crate) fn shards(&self) -> Vec<(&Node, Vec<(u16, u16)>)> {
+        let ranges = self.slot_ranges();
+        self.nodes
+            .iter()
+            .map(|node| {
+                let served = ranges
+                    .iter()
+                    .filter(|(_, _, owner)| owner.id == node.id)
+                    .map(|&(first, last, _)| (first, last))
+                    .collect();
+                (node, served)
+            })
+            .collect()
+    }
+
+    /// Returns the topology in the format of `CLUSTER NODES`, which is also
+    /// the one of the config file.
+    pub(crate) fn nodes_info(&self) -> String {
+        let ranges = self.slot_ranges();
+        let mut info = String::new();
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let flags = if index == self.myself {
+                "myself,master"
+            } else {
+                "master"
+            };
+            info.push_str(&format!(
+                "{} {}:{}@{} {} - 0 0 0 connected",
+                node.id,
+                node.host,
+                node.port,
+                node.port as u32 + 10000,
+                flags
+            ));
+            for &(first, last, owner) in &ranges {
+                if owner.id != node.id {
+                    continue;
+                }
+                if first == last {
+                    info.push_str(&format!(" {}", first));
+                } else {
+                    info.push_str(&format!(" {}-{}", first, last));
+                }
+            }
+            if index == self.myself {
+                for (slot, &target) in &self.migrating {
+                    info.push_str(&format!(" [{}->-{}]", slot, self.nodes[target].id));
+                }
+                for (slot, &source) in &self.importing {
+                    info.push_str(&format!(" [{}-<-{}]", slot, self.nodes[source].id));
+                }
+            }
+            info.push('\n');
+        }
+        info
+    }
+
+    /// Returns the index in `nodes` of the node `id`.
+    fn node_index(&self, id: &str) -> crate::Result<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.id == id)
+            .ok_or_else(|| format!("ERR I don't know about node {}", id).into())
+    }
+}
+
+impl Node {
+    /// Returns the address of the node, as given in redirections.
+    pub(crate) fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+impl Redirect {
+    /// Parse the redirection a client received as the error `reply`, `None`
+    /// if the reply is not one of `-MOVED`, `-ASK` or `-TRYAGAIN`.
+    pub(crate) fn parse(reply: &str) -> Option<Redirect> {
+        let mut parts = reply.split_whitespace();
+        let kind = parts.next()?;
+        if kind == "TRYAGAIN" {
+            return Some(Redirect::TryAgain);
+        }
+
+        let slot = parts.next()?.parse().ok()?;
+        let addr = parts.next()?.to_string();
+        match kind {
+            "MOVED" => Some(Redirect::Moved { slot, addr }),
+            "ASK" => Some(Redirect::Ask { slot, addr }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Redirect::Moved { slot, addr } => write!(fmt, "MOVED {} {}", slot, addr),
+            Redirect::Ask { slot, addr } => write!(fmt, "ASK {} {}", slot, addr),
+            Redirect::CrossSlot => "CROSSSLOT Keys in request don't hash to the same slot".fmt(fmt),
+            Redirect::TryAgain => {
+                "TRYAGAIN Multiple keys request during rehashing of slot".fmt(fmt)
+            }
+            Redirect::Down => "CLUSTERDOWN Hash slot not served".fmt(fmt),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::acl::{self, AclState};
+use crate::{Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use std::fmt;
+use tracing::instrument;
+
+/// Inspects or changes the users, see the `acl` module.
+#[derive(Clone)]
+pub struct Acl {
+    subcommand: AclSubcommand,
+}
+
+#[derive(Clone)]
+enum AclSubcommand {
+    SetUser(String, Vec<String>),
+    GetUser(String),
+    DelUser(Vec<String>),
+    List,
+    Users,
+    WhoAmI,
+    Cat(Option<String>),
+    Load,
+    Save,
+}
+
+impl Acl {
+    /// Create a new `ACL SETUSER` command applying `rules` to the user
+    /// `username`.
+    pub fn set_user(username: &str, rules: &[&str]) -> Acl {
+        Acl {
+            subcommand: AclSubcommand::SetUser(
+                username.to_string(),
+                rules.iter().map(|rule| rule.to_string()).collect(),
+            ),
+        }
+    }
+
+    /// Create a new `ACL DELUSER` command.
+    pub fn del_user(usernames: &[&str]) -> Acl {
+        Acl {
+            subcommand: AclSubcommand::DelUser(
+                usernames.iter().map(|name| name.to_string()).collect(),
+            ),
+        }
+    }
+
+    /// Create a new `ACL WHOAMI` command.
+    pub fn whoami() -> Acl {
+        Acl {
+            subcommand: AclSubcommand::WhoAmI,
+        }
+    }
+
+    /// Parse an `Acl` instance from a received frame.
+    ///
+    /// ```text
+    /// ACL SETUSER username [rule ...]
+    /// ACL GETUSER username
+    /// ACL DELUSER username [username ...]
+    /// ACL LIST
+    /// ACL USERS
+    /// ACL WHOAMI
+    /// ACL CAT [category]
+    /// ACL LOAD
+    /// ACL SAVE
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Acl> {
+        let subcommand = parse.next_string()?;
+
+        let subcommand = match &subcommand.to_uppercase()[..] {
+            "SETUSER" => {
+                let username = parse.next_string()?;
+                let rules = match parse.remaining_strings() {
+                    Ok(rules) => rules,
+                    Err(ParseError::EndOfStream) => vec![],
+                    Err(err) => return Err(err.into()),
+                };
+                AclSubcommand::SetUser(username, rules)
+            }
+            "GETUSER" => AclSubcommand::GetUser(parse.next_string()?),
+            "DELUSER" => AclSubcommand::DelUser(parse.remaining_strings()?),
+            "LIST" => AclSubcommand::List,
+            "USERS" => AclSubcommand::Users,
+            "WHOAMI" => AclSubcommand::WhoAmI,
+            "CAT" => match parse.next_string() {
+                Ok(category) => AclSubcommand::Cat(Some(category)),
+                Err(ParseError::EndOfStream) => AclSubcommand::Cat(None),
+                Err(err) => return Err(err.into()),
+            },
+            "LOAD" => AclSubcommand::Load,
+            "SAVE" => AclSubcommand::Save,
+            _ => return Err(format!("ERR unknown subcommand '{}'", subcommand).into()),
+        };
+
+        Ok(Acl { subcommand })
+    }
+
+    /// Apply the `Acl` command to the specified `Db` instance, for the
+    /// connection authenticated as `user`.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db, user: Option<&str>) -> Frame {
+        match self.reply(db, user) {
+            Ok(response) => response,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Returns the reply to the command, applying it to `db` for the
+    /// connection authenticated as `user`.
+    fn reply(self, db: &Db, user: Option<&str>) -> crate::Result<Frame> {
+        let response = match self.subcommand {
+            AclSubcommand::SetUser(username, rules) => {
+                db.with_acl(|acl| acl.set_user(&username, &rules))?;
+                Frame::Simple("OK".to_string())
+            }
+            AclSubcommand::GetUser(username) => db.with_acl(|acl| user_frame(acl, &username)),
+            AclSubcommand::DelUser(usernames) => {
+                let deleted = db.with_acl(|acl| acl.del_users(&usernames))?;
+                Frame::Integer(deleted as i64)
+            }
+            AclSubcommand::List => {
+                let users = db.with_acl(|acl| acl.list());
+                Frame::Array(users.iter().map(|line| bulk(line)).collect())
+            }
+            AclSubcommand::Users => {
+                db.with_acl(|acl| Frame::Array(acl.usernames().map(bulk).collect()))
+            }
+            // Detached connections run as no user at all.
+            AclSubcommand::WhoAmI => bulk(user.unwrap_or("default")),
+            AclSubcommand::Cat(None) => Frame::Array(
+                acl::categories()
+                    .iter()
+                    .map(|category| bulk(category))
+                    .collect(),
+            ),
+            AclSubcommand::Cat(Some(category)) => {
+                let commands = acl::category_commands(&category.to_lowercase())
+                    .ok_or_else(|| format!("ERR Unknown category '{}'", category))?;
+                Frame::Array(commands.into_iter().map(bulk).collect())
+            }
+            AclSubcommand::Load => {
+                db.with_acl(|acl| acl.reload())?;
+                Frame::Simple("OK".to_string())
+            }
+            AclSubcommand::Save => {
+                db.with_acl(|acl| acl.save())?;
+                Frame::Simple("OK".to_string())
+            }
+        };
+        Ok(response)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("acl".as_bytes()));
+        frame.push_bulk(Bytes::from(self.subcommand.name().as_bytes()));
+        let args = match self.subcommand {
+            AclSubcommand::SetUser(username, rules) => {
+                std::iter::once(username).chain(rules).collect()
+            }
+            AclSubcommand::GetUser(username) => vec![username],
+            AclSubcommand::DelUser(usernames) => usernames,
+            AclSubcommand::Cat(category) => category.into_iter().collect(),
+            _ => vec![],
+        };
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl AclSubcommand {
+    /// Returns the name of the subcommand.
+    fn name(&self) -> &'static str {
+        match self {
+            AclSubcommand::SetUser(..) => "setuser",
+            AclSubcommand::GetUser(_) => "getuser",
+            AclSubcommand::DelUser(_) => "deluser",
+            AclSubcommand::List => "list",
+            AclSubcommand::Users => "users",
+            AclSubcommand::WhoAmI => "whoami",
+            AclSubcommand::Cat(_) => "cat",
+            AclSubcommand::Load => "load",
+            AclSubcommand::Save => "save",
+        }
+    }
+}
+
+// The rules given to `ACL SETUSER` may hold passwords, which are kept out of
+// the logs.
+impl fmt::Debug for Acl {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Acl")
+            .field("subcommand", &self.subcommand.name())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns the user `username` as replied by `ACL GETUSER`, nil if there is
+/// no such user.
+fn user_frame(acl: &AclState, username: &str) -> Frame {
+    let user = match acl.user(username) {
+        Some(user) => user,
+        None => return Frame::Null,
+    };
+
+    Frame::Map(vec![
+        (
+            bulk("flags"),
+            Frame::Array(user.flags().into_iter().map(bulk).collect()),
+        ),
+        (
+            bulk("passwords"),
+            Frame::Array(user.passwords().map(bulk).collect()),
+        ),
+        (bulk("commands"), bulk(&user.describe_commands())),
+        (bulk("keys"), bulk(&user.describe_keys())),
+        (bulk("channels"), bulk(&user.describe_channels())),
+        (bulk("selectors"), Frame::Array(vec![])),
+    ])
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+}
@@ -0,0 +1,86 @@
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use std::fmt;
+use tracing::{debug, instrument};
+
+/// Authenticates the connection.
+///
+/// While a password is required, the server refuses every command but
+/// `AUTH`, `HELLO` and `PING` until the connection is authenticated. A failed
+/// attempt leaves the connection as it was.
+#[derive(Clone)]
+pub struct Auth {
+    /// The user to authenticate as, `default` if `None`.
+    username: Option<String>,
+    password: String,
+}
+
+impl Auth {
+    /// Create a new `Auth` command authenticating with `password`, as the
+    /// default user unless `username` is given.
+    pub fn new(username: Option<&str>, password: &str) -> Auth {
+        Auth {
+            username: username.map(str::to_string),
+            password: password.to_string(),
+        }
+    }
+
+    /// Parse an `Auth` instance from a received frame.
+    ///
+    /// ```text
+    /// AUTH [username] password
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Auth> {
+        let first = parse.next_string()?;
+
+        match parse.next_string() {
+            Ok(password) => Ok(Auth {
+                username: Some(first),
+                password,
+            }),
+            Err(ParseError::EndOfStream) => Ok(Auth {
+                username: None,
+                password: first,
+            }),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Check the credentials against the specified `Db` instance,
+    /// authenticating the connection as the user if they are valid.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.authenticate(self.username.as_deref(), &self.password) {
+            Ok(user) => {
+                dst.set_user(Some(user));
+                Frame::Simple("OK".to_string())
+            }
+            Err(err) => Frame::Error(err.to_string()),
+        };
+
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("auth".as_bytes()));
+        if let Some(username) = self.username {
+            frame.push_bulk(Bytes::from(username.into_bytes()));
+        }
+        frame.push_bulk(Bytes::from(self.password.into_bytes()));
+        frame
+    }
+}
+
+// The password is kept out of the logs.
+impl fmt::Debug for Auth {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Auth")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
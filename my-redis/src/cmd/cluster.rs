@@ -0,0 +1,240 @@
+use crate::cluster::{self, Node, SetSlot};
+use crate::{Db, Frame, Parse};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Inspects or changes the topology of the cluster.
+///
+/// Only available in cluster mode, see the `cluster` module.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    subcommand: ClusterSubcommand,
+}
+
+#[derive(Debug, Clone)]
+enum ClusterSubcommand {
+    Slots,
+    Shards,
+    Nodes,
+    KeySlot(String),
+    AddSlots(Vec<u16>),
+    SetSlot(u16, SetSlot),
+}
+
+/// Lets the next command of the connection access a slot imported by the
+/// node, after an `-ASK` redirection.
+#[derive(Debug, Clone, Default)]
+pub struct Asking;
+
+impl Cluster {
+    /// Create a new `CLUSTER SLOTS` command.
+    pub fn slots() -> Cluster {
+        Cluster {
+            subcommand: ClusterSubcommand::Slots,
+        }
+    }
+
+    /// Parse a `Cluster` instance from a received frame.
+    ///
+    /// ```text
+    /// CLUSTER SLOTS
+    /// CLUSTER SHARDS
+    /// CLUSTER NODES
+    /// CLUSTER KEYSLOT key
+    /// CLUSTER ADDSLOTS slot [slot ...]
+    /// CLUSTER SETSLOT slot IMPORTING node-id | MIGRATING node-id | STABLE | NODE node-id
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Cluster> {
+        let subcommand = parse.next_string()?;
+
+        let subcommand = match &subcommand.to_uppercase()[..] {
+            "SLOTS" => ClusterSubcommand::Slots,
+            "SHARDS" => ClusterSubcommand::Shards,
+            "NODES" => ClusterSubcommand::Nodes,
+            "KEYSLOT" => ClusterSubcommand::KeySlot(parse.next_string()?),
+            "ADDSLOTS" => ClusterSubcommand::AddSlots(
+                parse
+                    .remaining_strings()?
+                    .iter()
+                    .map(|slot| cluster::parse_slot(slot))
+                    .collect::<crate::Result<_>>()?,
+            ),
+            "SETSLOT" => {
+                let slot = cluster::parse_slot(&parse.next_string()?)?;
+                let change = match &parse.next_string()?.to_uppercase()[..] {
+                    "MIGRATING" => SetSlot::Migrating(parse.next_string()?),
+                    "IMPORTING" => SetSlot::Importing(parse.next_string()?),
+                    "STABLE" => SetSlot::Stable,
+                    "NODE" => SetSlot::Node(parse.next_string()?),
+                    _ => {
+                        return Err(
+                            "ERR Invalid CLUSTER SETSLOT action or number of arguments".into()
+                        )
+                    }
+                };
+                ClusterSubcommand::SetSlot(slot, change)
+            }
+            _ => return Err(format!("ERR unknown subcommand '{}'", subcommand).into()),
+        };
+
+        Ok(Cluster { subcommand })
+    }
+
+    /// Apply the `Cluster` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match self.reply(db) {
+            Ok(response) => response,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Returns the reply to the command, applying it to `db`.
+    fn reply(self, db: &Db) -> crate::Result<Frame> {
+        let response = match self.subcommand {
+            // The slot of a key is known outside of cluster mode as well.
+            ClusterSubcommand::KeySlot(key) => {
+                Frame::Integer(cluster::key_slot(key.as_bytes()) as i64)
+            }
+            ClusterSubcommand::Slots => db.with_cluster(|cluster| {
+                let ranges = cluster
+                    .slot_ranges()
+                    .into_iter()
+                    .map(|(first, last, node)| {
+                        Frame::Array(vec![
+                            Frame::Integer(first as i64),
+                            Frame::Integer(last as i64),
+                            node_frame(node),
+                        ])
+                    })
+                    .collect();
+                Frame::Array(ranges)
+            })?,
+            ClusterSubcommand::Shards => db.with_cluster(|cluster| {
+                let shards = cluster
+                    .shards()
+                    .into_iter()
+                    .map(|(node, ranges)| {
+                        let slots = ranges
+                            .into_iter()
+                            .flat_map(|(first, last)| {
+                                [Frame::Integer(first as i64), Frame::Integer(last as i64)]
+                            })
+                            .collect();
+                        Frame::Map(vec![
+                            (bulk("slots"), Frame::Array(slots)),
+                            (bulk("nodes"), Frame::Array(vec![shard_node_frame(node)])),
+                        ])
+                    })
+                    .collect();
+                Frame::Array(shards)
+            })?,
+            ClusterSubcommand::Nodes => {
+                let info = db.with_cluster(|cluster| cluster.nodes_info())?;
+                Frame::Bulk(Bytes::from(info.into_bytes()))
+            }
+            ClusterSubcommand::AddSlots(slots) => {
+                db.with_cluster(|cluster| cluster.add_slots(&slots))??;
+                Frame::Simple("OK".to_string())
+            }
+            ClusterSubcommand::SetSlot(slot, change) => {
+                db.with_cluster(|cluster| cluster.set_slot(slot, change))??;
+                Frame::Simple("OK".to_string())
+            }
+        };
+        Ok(response)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("cluster".as_bytes()));
+        match self.subcommand {
+            ClusterSubcommand::Slots => frame.push_bulk(Bytes::from("slots".as_bytes())),
+            ClusterSubcommand::Shards => frame.push_bulk(Bytes::from("shards".as_bytes())),
+            ClusterSubcommand::Nodes => frame.push_bulk(Bytes::from("nodes".as_bytes())),
+            ClusterSubcommand::KeySlot(key) => {
+                frame.push_bulk(Bytes::from("keyslot".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+            }
+            ClusterSubcommand::AddSlots(slots) => {
+                frame.push_bulk(Bytes::from("addslots".as_bytes()));
+                for slot in slots {
+                    frame.push_bulk(Bytes::from(slot.to_string().into_bytes()));
+                }
+            }
+            ClusterSubcommand::SetSlot(slot, change) => {
+                frame.push_bulk(Bytes::from("setslot".as_bytes()));
+                frame.push_bulk(Bytes::from(slot.to_string().into_bytes()));
+                let (action, id) = match change {
+                    SetSlot::Migrating(id) => ("migrating", Some(id)),
+                    SetSlot::Importing(id) => ("importing", Some(id)),
+                    SetSlot::Stable => ("stable", None),
+                    SetSlot::Node(id) => ("node", Some(id)),
+                };
+                frame.push_bulk(Bytes::from(action.as_bytes()));
+                if let Some(id) = id {
+                    frame.push_bulk(Bytes::from(id.into_bytes()));
+                }
+            }
+        }
+        frame
+    }
+}
+
+impl Asking {
+    /// Create a new `Asking` command.
+    pub fn new() -> Asking {
+        Asking
+    }
+
+    /// Parse an `Asking` instance from a received frame.
+    ///
+    /// ```text
+    /// ASKING
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Asking> {
+        Ok(Asking)
+    }
+
+    /// Reply to the `Asking` command. The connection handler lets the next
+    /// command access the imported slots.
+    #[instrument(skip(self))]
+    pub(crate) fn execute(self) -> Frame {
+        Frame::Simple("OK".to_string())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("asking".as_bytes()));
+        frame
+    }
+}
+
+/// Returns `node` as listed by `CLUSTER SLOTS`: its host, port and id.
+fn node_frame(node: &Node) -> Frame {
+    Frame::Array(vec![
+        bulk(&node.host),
+        Frame::Integer(node.port as i64),
+        bulk(&node.id),
+    ])
+}
+
+/// Returns `node` as listed by `CLUSTER SHARDS`.
+fn shard_node_frame(node: &Node) -> Frame {
+    Frame::Map(vec![
+        (bulk("id"), bulk(&node.id)),
+        (bulk("port"), Frame::Integer(node.port as i64)),
+        (bulk("ip"), bulk(&node.host)),
+        (bulk("endpoint"), bulk(&node.host)),
+        (bulk("role"), bulk("master")),
+        (bulk("replication-offset"), Frame::Integer(0)),
+        (bulk("health"), bulk("online")),
+    ])
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+}
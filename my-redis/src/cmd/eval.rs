@@ -0,0 +1,284 @@
+use crate::{scripting, Connection, Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Run a Lua script.
+///
+/// The script is given the key names in the `KEYS` table and the other
+/// arguments in the `ARGV` table, and calls commands with `redis.call` or
+/// `redis.pcall`. It runs atomically: no other client observes the database
+/// before it completes. The script is also added to the script cache.
+#[derive(Debug, Clone)]
+pub struct Eval {
+    script: Bytes,
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+}
+
+/// Run a Lua script from the script cache, by its SHA1 digest. Works like
+/// `EVAL` otherwise.
+#[derive(Debug, Clone)]
+pub struct EvalSha {
+    sha1: String,
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+}
+
+/// Manage the script cache.
+///
+/// `SCRIPT LOAD` adds a script and replies its SHA1 digest, `SCRIPT EXISTS`
+/// tells whether scripts are cached and `SCRIPT FLUSH` empties the cache.
+/// `SCRIPT KILL` aborts the script running, unless it wrote to the key space.
+#[derive(Debug, Clone)]
+pub struct Script {
+    subcommand: ScriptSubcommand,
+}
+
+#[derive(Debug, Clone)]
+enum ScriptSubcommand {
+    Load(Bytes),
+    Exists(Vec<String>),
+    Flush,
+    Kill,
+}
+
+impl Eval {
+    /// Create a new `Eval` command running `script`.
+    pub fn new(script: Bytes, keys: Vec<String>, args: Vec<Bytes>) -> Eval {
+        Eval { script, keys, args }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `Eval` instance from a received frame.
+    ///
+    /// ```text
+    /// EVAL script numkeys [key [key ...]] [arg [arg ...]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Eval> {
+        let script = parse.next_bytes()?;
+        let (keys, args) = parse_arguments(parse)?;
+
+        Ok(Eval { script, keys, args })
+    }
+
+    /// Apply the `Eval` command to the specified `Db` instance, the script
+    /// calling commands on behalf of the user of `dst`.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) fn execute(self, db: &Db, dst: &mut Connection) -> Frame {
+        db.script_load(self.script.clone());
+        scripting::eval(&self.script, self.keys, self.args, db, dst)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("eval".as_bytes()));
+        frame.push_bulk(self.script);
+        push_arguments(&mut frame, self.keys, self.args);
+        frame
+    }
+}
+
+impl EvalSha {
+    /// Create a new `EvalSha` command running the cached script with the SHA1
+    /// digest `sha1`.
+    pub fn new(sha1: impl ToString, keys: Vec<String>, args: Vec<Bytes>) -> EvalSha {
+        EvalSha {
+            sha1: sha1.to_string(),
+            keys,
+            args,
+        }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `EvalSha` instance from a received frame.
+    ///
+    /// ```text
+    /// EVALSHA sha1 numkeys [key [key ...]] [arg [arg ...]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<EvalSha> {
+        let sha1 = parse.next_string()?;
+        let (keys, args) = parse_arguments(parse)?;
+
+        Ok(EvalSha { sha1, keys, args })
+    }
+
+    /// Apply the `EvalSha` command to the specified `Db` instance, see
+    /// `Eval::execute`.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) fn execute(self, db: &Db, dst: &mut Connection) -> Frame {
+        match db.script(&self.sha1) {
+            Some(script) => scripting::eval(&script, self.keys, self.args, db, dst),
+            None => Frame::Error("NOSCRIPT No matching script. Please use EVAL.".to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("evalsha".as_bytes()));
+        frame.push_bulk(Bytes::from(self.sha1.into_bytes()));
+        push_arguments(&mut frame, self.keys, self.args);
+        frame
+    }
+}
+
+impl Script {
+    /// Create a new `SCRIPT LOAD` command caching `script`.
+    pub fn load(script: Bytes) -> Script {
+        Script {
+            subcommand: ScriptSubcommand::Load(script),
+        }
+    }
+
+    /// Create a new `SCRIPT EXISTS` command checking the scripts with the
+    /// SHA1 digests `sha1s`.
+    pub fn exists(sha1s: Vec<String>) -> Script {
+        Script {
+            subcommand: ScriptSubcommand::Exists(sha1s),
+        }
+    }
+
+    /// Create a new `SCRIPT FLUSH` command.
+    pub fn flush() -> Script {
+        Script {
+            subcommand: ScriptSubcommand::Flush,
+        }
+    }
+
+    /// Create a new `SCRIPT KILL` command.
+    pub fn kill() -> Script {
+        Script {
+            subcommand: ScriptSubcommand::Kill,
+        }
+    }
+
+    /// Returns `true` for `SCRIPT KILL`, the only command served while a
+    /// script is running.
+    pub(crate) fn is_kill(&self) -> bool {
+        matches!(self.subcommand, ScriptSubcommand::Kill)
+    }
+
+    /// Parse a `Script` instance from a received frame.
+    ///
+    /// ```text
+    /// SCRIPT LOAD script
+    /// SCRIPT EXISTS sha1 [sha1 ...]
+    /// SCRIPT FLUSH [ASYNC | SYNC]
+    /// SCRIPT KILL
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Script> {
+        let subcommand = parse.next_string()?;
+
+        let subcommand = match &subcommand.to_uppercase()[..] {
+            "LOAD" => ScriptSubcommand::Load(parse.next_bytes()?),
+            "EXISTS" => ScriptSubcommand::Exists(parse.remaining_strings()?),
+            // The cache is small enough to always be flushed right away,
+            // whatever the mode.
+            "FLUSH" => match parse.next_string() {
+                Ok(mode) if ["ASYNC", "SYNC"].contains(&&mode.to_uppercase()[..]) => {
+                    ScriptSubcommand::Flush
+                }
+                Ok(_) => return Err("ERR syntax error".into()),
+                Err(ParseError::EndOfStream) => ScriptSubcommand::Flush,
+                Err(err) => return Err(err.into()),
+            },
+            "KILL" => ScriptSubcommand::Kill,
+            _ => return Err(format!("ERR unknown subcommand '{}'", subcommand).into()),
+        };
+
+        Ok(Script { subcommand })
+    }
+
+    /// Apply the `Script` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match self.subcommand {
+            ScriptSubcommand::Load(script) => Frame::Bulk(Bytes::from(db.script_load(script))),
+            ScriptSubcommand::Exists(sha1s) => {
+                let mut response = Frame::array();
+                for exists in db.scripts_exist(&sha1s) {
+                    response.push_int(exists as i64);
+                }
+                response
+            }
+            ScriptSubcommand::Flush => {
+                db.script_flush();
+                Frame::Simple("OK".to_string())
+            }
+            ScriptSubcommand::Kill => match db.running_script().kill() {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(err) => Frame::Error(err.to_string()),
+            },
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("script".as_bytes()));
+        match self.subcommand {
+            ScriptSubcommand::Load(script) => {
+                frame.push_bulk(Bytes::from_static(b"LOAD"));
+                frame.push_bulk(script);
+            }
+            ScriptSubcommand::Exists(sha1s) => {
+                frame.push_bulk(Bytes::from_static(b"EXISTS"));
+                for sha1 in sha1s {
+                    frame.push_bulk(Bytes::from(sha1.into_bytes()));
+                }
+            }
+            ScriptSubcommand::Flush => frame.push_bulk(Bytes::from_static(b"FLUSH")),
+            ScriptSubcommand::Kill => frame.push_bulk(Bytes::from_static(b"KILL")),
+        }
+        frame
+    }
+}
+
+/// Parse the `numkeys` argument of `EVAL` and `EVALSHA`, followed by the keys
+/// and the other arguments.
+fn parse_arguments(parse: &mut Parse) -> crate::Result<(Vec<String>, Vec<Bytes>)> {
+    let numkeys = parse.next_int()?;
+    if numkeys < 0 {
+        return Err("ERR Number of keys can't be negative".into());
+    }
+    // Checked before allocating the keys, `numkeys` being sent by the client.
+    if numkeys as u64 > parse.remaining() as u64 {
+        return Err("ERR Number of keys can't be greater than number of args".into());
+    }
+
+    let mut keys = Vec::with_capacity(numkeys as usize);
+    for _ in 0..numkeys {
+        keys.push(parse.next_string()?);
+    }
+
+    let mut args = vec![];
+    loop {
+        match parse.next_bytes() {
+            Ok(arg) => args.push(arg),
+            Err(ParseError::EndOfStream) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok((keys, args))
+}
+
+fn push_arguments(frame: &mut Frame, keys: Vec<String>, args: Vec<Bytes>) {
+    frame.push_bulk(Bytes::from(keys.len().to_string()));
+    for key in keys {
+        frame.push_bulk(Bytes::from(key.into_bytes()));
+    }
+    for arg in args {
+        frame.push_bulk(arg);
+    }
+}
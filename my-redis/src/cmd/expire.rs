@@ -0,0 +1,513 @@
+use crate::db::ExpireOptions;
+use crate::{Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{Duration, Instant};
+use tracing::instrument;
+
+/// Set a timeout on key, in seconds. After the timeout has expired, the key
+/// is automatically deleted.
+///
+/// A timeout which is not positive deletes the key right away. Replies `1` if
+/// the timeout was set and `0` if the key does not exist or the options
+/// prevented the update.
+#[derive(Debug, Clone)]
+pub struct Expire {
+    key: String,
+    seconds: i64,
+    options: ExpireOptions,
+}
+
+/// Set a timeout on key, in milliseconds. Works like `EXPIRE`.
+#[derive(Debug, Clone)]
+pub struct PExpire {
+    key: String,
+    milliseconds: i64,
+    options: ExpireOptions,
+}
+
+/// Set key to expire at the given Unix time, in seconds. Works like `EXPIRE`,
+/// a time in the past deleting the key.
+#[derive(Debug, Clone)]
+pub struct ExpireAt {
+    key: String,
+    timestamp: i64,
+    options: ExpireOptions,
+}
+
+/// Set key to expire at the given Unix time, in milliseconds. Works like
+/// `EXPIREAT`.
+#[derive(Debug, Clone)]
+pub struct PExpireAt {
+    key: String,
+    timestamp: i64,
+    options: ExpireOptions,
+}
+
+/// Returns the remaining time to live of a key, in seconds.
+///
+/// Replies `-2` if the key does not exist and `-1` if the key exists but has
+/// no associated expiration.
+#[derive(Debug, Clone)]
+pub struct Ttl {
+    key: String,
+}
+
+/// Returns the remaining time to live of a key, in milliseconds. Works like
+/// `TTL`.
+#[derive(Debug, Clone)]
+pub struct PTtl {
+    key: String,
+}
+
+/// Remove the existing timeout on key.
+///
+/// Replies `1` if the timeout was removed and `0` if the key does not exist or
+/// has no associated timeout.
+#[derive(Debug, Clone)]
+pub struct Persist {
+    key: String,
+}
+
+impl Expire {
+    /// Create a new `Expire` command setting the timeout of `key`.
+    pub fn new(key: impl ToString, seconds: i64) -> Expire {
+        Expire {
+            key: key.to_string(),
+            seconds,
+            options: ExpireOptions::default(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `Expire` instance from a received frame.
+    ///
+    /// ```text
+    /// EXPIRE key seconds [NX | XX | GT | LT]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Expire> {
+        let key = parse.next_string()?;
+        let seconds = parse.next_int()?;
+        let options = parse_options(parse)?;
+
+        Ok(Expire {
+            key,
+            seconds,
+            options,
+        })
+    }
+
+    /// Apply the `Expire` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let when = self.seconds.checked_mul(1000).and_then(deadline);
+        expire_frame(db, &self.key, when, self.options, "expire")
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("expire".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.seconds.to_string()));
+        push_options(&mut frame, self.options);
+        frame
+    }
+
+    /// Converts the command into the `PExpireAt` command setting the same
+    /// expiration when applied now.
+    pub(crate) fn into_pexpireat(self) -> PExpireAt {
+        PExpireAt {
+            key: self.key,
+            timestamp: unix_now().saturating_add(self.seconds.saturating_mul(1000)),
+            options: self.options,
+        }
+    }
+}
+
+impl PExpire {
+    /// Create a new `PExpire` command setting the timeout of `key`.
+    pub fn new(key: impl ToString, milliseconds: i64) -> PExpire {
+        PExpire {
+            key: key.to_string(),
+            milliseconds,
+            options: ExpireOptions::default(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `PExpire` instance from a received frame.
+    ///
+    /// ```text
+    /// PEXPIRE key milliseconds [NX | XX | GT | LT]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<PExpire> {
+        let key = parse.next_string()?;
+        let milliseconds = parse.next_int()?;
+        let options = parse_options(parse)?;
+
+        Ok(PExpire {
+            key,
+            milliseconds,
+            options,
+        })
+    }
+
+    /// Apply the `PExpire` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let when = deadline(self.milliseconds);
+        expire_frame(db, &self.key, when, self.options, "pexpire")
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("pexpire".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.milliseconds.to_string()));
+        push_options(&mut frame, self.options);
+        frame
+    }
+
+    /// Converts the command into the `PExpireAt` command setting the same
+    /// expiration when applied now.
+    pub(crate) fn into_pexpireat(self) -> PExpireAt {
+        PExpireAt {
+            key: self.key,
+            timestamp: unix_now().saturating_add(self.milliseconds),
+            options: self.options,
+        }
+    }
+}
+
+impl ExpireAt {
+    /// Create a new `ExpireAt` command setting the expiration of `key`.
+    pub fn new(key: impl ToString, timestamp: i64) -> ExpireAt {
+        ExpireAt {
+            key: key.to_string(),
+            timestamp,
+            options: ExpireOptions::default(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `ExpireAt` instance from a received frame.
+    ///
+    /// ```text
+    /// EXPIREAT key unix-time-seconds [NX | XX | GT | LT]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ExpireAt> {
+        let key = parse.next_string()?;
+        let timestamp = parse.next_int()?;
+        let options = parse_options(parse)?;
+
+        Ok(ExpireAt {
+            key,
+            timestamp,
+            options,
+        })
+    }
+
+    /// Apply the `ExpireAt` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let when = self
+            .timestamp
+            .checked_mul(1000)
+            .and_then(unix_deadline)
+            .and_then(deadline);
+        expire_frame(db, &self.key, when, self.options, "expireat")
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("expireat".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.timestamp.to_string()));
+        push_options(&mut frame, self.options);
+        frame
+    }
+}
+
+impl PExpireAt {
+    /// Create a new `PExpireAt` command setting the expiration of `key`.
+    pub fn new(key: impl ToString, timestamp: i64) -> PExpireAt {
+        PExpireAt {
+            key: key.to_string(),
+            timestamp,
+            options: ExpireOptions::default(),
+        }
+    }
+
+    /// Create a new `PExpireAt` command expiring `key` once `timeout` elapsed
+    /// from now.
+    pub(crate) fn after(key: impl ToString, timeout: Duration) -> PExpireAt {
+        let timeout = i64::try_from(timeout.as_millis()).unwrap_or(i64::MAX);
+        PExpireAt::new(key, unix_now().saturating_add(timeout))
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `PExpireAt` instance from a received frame.
+    ///
+    /// ```text
+    /// PEXPIREAT key unix-time-milliseconds [NX | XX | GT | LT]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<PExpireAt> {
+        let key = parse.next_string()?;
+        let timestamp = parse.next_int()?;
+        let options = parse_options(parse)?;
+
+        Ok(PExpireAt {
+            key,
+            timestamp,
+            options,
+        })
+    }
+
+    /// Apply the `PExpireAt` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let when = unix_deadline(self.timestamp).and_then(deadline);
+        expire_frame(db, &self.key, when, self.options, "pexpireat")
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("pexpireat".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.timestamp.to_string()));
+        push_options(&mut frame, self.options);
+        frame
+    }
+}
+
+impl Ttl {
+    /// Create a new `Ttl` command reading the time to live of `key`.
+    pub fn new(key: impl ToString) -> Ttl {
+        Ttl {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Ttl` instance from a received frame.
+    ///
+    /// ```text
+    /// TTL key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Ttl> {
+        let key = parse.next_string()?;
+
+        Ok(Ttl { key })
+    }
+
+    /// Apply the `Ttl` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        // The remaining time is rounded to the closest second.
+        ttl_frame(db.ttl(&self.key), |ttl| {
+            (ttl.as_millis() as i64 + 500) / 1000
+        })
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("ttl".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl PTtl {
+    /// Create a new `PTtl` command reading the time to live of `key`.
+    pub fn new(key: impl ToString) -> PTtl {
+        PTtl {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `PTtl` instance from a received frame.
+    ///
+    /// ```text
+    /// PTTL key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<PTtl> {
+        let key = parse.next_string()?;
+
+        Ok(PTtl { key })
+    }
+
+    /// Apply the `PTtl` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        ttl_frame(db.ttl(&self.key), |ttl| ttl.as_millis() as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("pttl".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl Persist {
+    /// Create a new `Persist` command removing the timeout of `key`.
+    pub fn new(key: impl ToString) -> Persist {
+        Persist {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Persist` instance from a received frame.
+    ///
+    /// ```text
+    /// PERSIST key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Persist> {
+        let key = parse.next_string()?;
+
+        Ok(Persist { key })
+    }
+
+    /// Apply the `Persist` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        Frame::Integer(db.persist(&self.key) as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("persist".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+/// Parse the optional condition ending the expire commands.
+fn parse_options(parse: &mut Parse) -> crate::Result<ExpireOptions> {
+    let mut options = ExpireOptions::default();
+
+    loop {
+        match parse.next_string() {
+            Ok(s) => match &s.to_uppercase()[..] {
+                "NX" => options.nx = true,
+                "XX" => options.xx = true,
+                "GT" => options.gt = true,
+                "LT" => options.lt = true,
+                _ => return Err(format!("ERR Unsupported option {}", s).into()),
+            },
+            Err(ParseError::EndOfStream) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    if options.nx && (options.xx || options.gt || options.lt) {
+        return Err("ERR NX and XX, GT or LT options at the same time are not compatible".into());
+    }
+    if options.gt && options.lt {
+        return Err("ERR GT and LT options at the same time are not compatible".into());
+    }
+
+    Ok(options)
+}
+
+fn push_options(frame: &mut Frame, options: ExpireOptions) {
+    let flags = [
+        (options.nx, "NX"),
+        (options.xx, "XX"),
+        (options.gt, "GT"),
+        (options.lt, "LT"),
+    ];
+
+    for (set, flag) in flags {
+        if set {
+            frame.push_bulk(Bytes::from_static(flag.as_bytes()));
+        }
+    }
+}
+
+/// Returns the instant `milliseconds` from now, or now if the value is not
+/// positive. `None` if the instant cannot be represented.
+fn deadline(milliseconds: i64) -> Option<Instant> {
+    let now = Instant::now();
+
+    match u64::try_from(milliseconds) {
+        Ok(milliseconds) => now.checked_add(Duration::from_millis(milliseconds)),
+        Err(_) => Some(now),
+    }
+}
+
+/// Returns the current Unix time, in milliseconds.
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Returns the number of milliseconds from now until the Unix time
+/// `timestamp`, in milliseconds.
+fn unix_deadline(timestamp: i64) -> Option<i64> {
+    timestamp.checked_sub(unix_now())
+}
+
+/// Builds the reply to the commands setting an expiration.
+fn expire_frame(
+    db: &Db,
+    key: &str,
+    when: Option<Instant>,
+    options: ExpireOptions,
+    name: &str,
+) -> Frame {
+    match when {
+        Some(when) => Frame::Integer(db.expire(key, when, options) as i64),
+        None => Frame::Error(format!("ERR invalid expire time in '{}' command", name)),
+    }
+}
+
+/// Builds the reply to the commands reading the time to live of a key.
+fn ttl_frame(ttl: Option<Option<Duration>>, unit: impl Fn(Duration) -> i64) -> Frame {
+    match ttl {
+        None => Frame::Integer(-2),
+        Some(None) => Frame::Integer(-1),
+        Some(Some(ttl)) => Frame::Integer(unit(ttl)),
+    }
+}
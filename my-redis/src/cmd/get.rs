@@ -1,14 +1,14 @@
-use crate::{Connection, Db, Frame, Parse};
+use crate::{Db, Frame, Parse};
 
 use bytes::Bytes;
-use tracing::{debug, instrument};
+use tracing::instrument;
 
 /// Get the value of key.
 ///
 /// If the key does not exist the special value nil is returned. An error is
 /// returned if the value stored at key is not a string, because GET only
 /// handles string values.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Get {
     /// Name of the key to get
     key: String,
@@ -58,26 +58,20 @@ impl Get {
 
     /// Apply the `Get` command to the specified `Db` instance.
     ///
-    /// The response is written to `dst`. This is called by the server in order
-    /// to execute a received command.
-    #[instrument(skip(self, db, dst))]
-    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+    /// The response is returned to be written to the client. This is called by
+    /// the server in order to execute a received command.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
         // Get the value from the shared database state
-        let response = if let Some(value) = db.get(&self.key) {
+        match db.get(&self.key) {
             // If a value is present, it is written to the client in "bulk"
             // format.
-            Frame::Bulk(value)
-        } else {
+            Ok(Some(value)) => Frame::Bulk(value),
             // If there is no value, `Null` is written.
-            Frame::Null
-        };
-
-        debug!(?response);
-
-        // Write the response back to the client
-        dst.write_frame(&response).await?;
-
-        Ok(())
+            Ok(None) => Frame::Null,
+            // The key holds a value which is not a string.
+            Err(err) => Frame::Error(err.to_string()),
+        }
     }
 
     /// Converts the command into an equivalent `Frame`.
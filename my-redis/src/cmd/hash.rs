@@ -0,0 +1,571 @@
+use crate::cmd::Parse;
+use crate::{Db, Frame};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Sets the specified fields to their respective values in the hash stored at
+/// key.
+///
+/// If key does not exist, a new key holding a hash is created. Replies with the
+/// number of fields that were added.
+#[derive(Debug, Clone)]
+pub struct HSet {
+    key: String,
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+/// Returns the value associated with field in the hash stored at key.
+#[derive(Debug, Clone)]
+pub struct HGet {
+    key: String,
+    field: Bytes,
+}
+
+/// Removes the specified fields from the hash stored at key.
+///
+/// Replies with the number of fields that were removed.
+#[derive(Debug, Clone)]
+pub struct HDel {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+/// Returns all fields and values of the hash stored at key.
+///
+/// The reply is a map of field names to values, flattened into an array
+/// alternating field names and values on RESP2 connections.
+#[derive(Debug, Clone)]
+pub struct HGetAll {
+    key: String,
+}
+
+/// Returns the values associated with the specified fields in the hash stored
+/// at key. Nil is replied for every field that does not exist.
+#[derive(Debug, Clone)]
+pub struct HMGet {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+/// Increments the number stored at field in the hash stored at key by
+/// increment.
+///
+/// If the field does not exist it is set to `0` before the operation.
+#[derive(Debug, Clone)]
+pub struct HIncrBy {
+    key: String,
+    field: Bytes,
+    increment: i64,
+}
+
+/// Returns all field names in the hash stored at key.
+#[derive(Debug, Clone)]
+pub struct HKeys {
+    key: String,
+}
+
+/// Returns all values in the hash stored at key.
+#[derive(Debug, Clone)]
+pub struct HVals {
+    key: String,
+}
+
+/// Returns the number of fields contained in the hash stored at key.
+#[derive(Debug, Clone)]
+pub struct HLen {
+    key: String,
+}
+
+/// Returns if field is an existing field in the hash stored at key.
+#[derive(Debug, Clone)]
+pub struct HExists {
+    key: String,
+    field: Bytes,
+}
+
+impl HSet {
+    /// Create a new `HSet` command setting `fields` in the hash at `key`.
+    pub fn new(key: impl ToString, fields: Vec<(Bytes, Bytes)>) -> HSet {
+        HSet {
+            key: key.to_string(),
+            fields,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HSet` instance from a received frame.
+    ///
+    /// ```text
+    /// HSET key field value [field value ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HSet> {
+        let key = parse.next_string()?;
+        let args = parse.remaining_bytes()?;
+
+        if args.len() % 2 != 0 {
+            return Err("protocol error; `HSET` expects field value pairs".into());
+        }
+
+        let fields = args
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+
+        Ok(HSet { key, fields })
+    }
+
+    /// Apply the `HSet` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hset(self.key, self.fields) {
+            Ok(added) => Frame::Integer(added as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hset".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for (field, value) in self.fields {
+            frame.push_bulk(field);
+            frame.push_bulk(value);
+        }
+        frame
+    }
+}
+
+impl HGet {
+    /// Create a new `HGet` command fetching `field` from the hash at `key`.
+    pub fn new(key: impl ToString, field: Bytes) -> HGet {
+        HGet {
+            key: key.to_string(),
+            field,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HGet` instance from a received frame.
+    ///
+    /// ```text
+    /// HGET key field
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HGet> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+
+        Ok(HGet { key, field })
+    }
+
+    /// Apply the `HGet` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hget(&self.key, &self.field) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hget".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.field);
+        frame
+    }
+}
+
+impl HDel {
+    /// Create a new `HDel` command removing `fields` from the hash at `key`.
+    pub fn new(key: impl ToString, fields: Vec<Bytes>) -> HDel {
+        HDel {
+            key: key.to_string(),
+            fields,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HDel` instance from a received frame.
+    ///
+    /// ```text
+    /// HDEL key field [field ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HDel> {
+        let key = parse.next_string()?;
+        let fields = parse.remaining_bytes()?;
+
+        Ok(HDel { key, fields })
+    }
+
+    /// Apply the `HDel` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hdel(&self.key, &self.fields) {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hdel".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for field in self.fields {
+            frame.push_bulk(field);
+        }
+        frame
+    }
+}
+
+impl HGetAll {
+    /// Create a new `HGetAll` command for `key`.
+    pub fn new(key: impl ToString) -> HGetAll {
+        HGetAll {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HGetAll` instance from a received frame.
+    ///
+    /// ```text
+    /// HGETALL key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HGetAll> {
+        let key = parse.next_string()?;
+
+        Ok(HGetAll { key })
+    }
+
+    /// Apply the `HGetAll` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hgetall(&self.key) {
+            Ok(fields) => Frame::Map(
+                fields
+                    .into_iter()
+                    .map(|(field, value)| (Frame::Bulk(field), Frame::Bulk(value)))
+                    .collect(),
+            ),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hgetall".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl HMGet {
+    /// Create a new `HMGet` command fetching `fields` from the hash at `key`.
+    pub fn new(key: impl ToString, fields: Vec<Bytes>) -> HMGet {
+        HMGet {
+            key: key.to_string(),
+            fields,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HMGet` instance from a received frame.
+    ///
+    /// ```text
+    /// HMGET key field [field ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HMGet> {
+        let key = parse.next_string()?;
+        let fields = parse.remaining_bytes()?;
+
+        Ok(HMGet { key, fields })
+    }
+
+    /// Apply the `HMGet` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hmget(&self.key, &self.fields) {
+            Ok(values) => Frame::Array(
+                values
+                    .into_iter()
+                    .map(|value| value.map_or(Frame::Null, Frame::Bulk))
+                    .collect(),
+            ),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hmget".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for field in self.fields {
+            frame.push_bulk(field);
+        }
+        frame
+    }
+}
+
+impl HIncrBy {
+    /// Create a new `HIncrBy` command adding `increment` to `field`.
+    pub fn new(key: impl ToString, field: Bytes, increment: i64) -> HIncrBy {
+        HIncrBy {
+            key: key.to_string(),
+            field,
+            increment,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HIncrBy` instance from a received frame.
+    ///
+    /// ```text
+    /// HINCRBY key field increment
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HIncrBy> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+        let increment = parse.next_int()?;
+
+        Ok(HIncrBy {
+            key,
+            field,
+            increment,
+        })
+    }
+
+    /// Apply the `HIncrBy` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hincrby(self.key, self.field, self.increment) {
+            Ok(value) => Frame::Integer(value),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hincrby".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.field);
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+}
+
+impl HKeys {
+    /// Create a new `HKeys` command for `key`.
+    pub fn new(key: impl ToString) -> HKeys {
+        HKeys {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HKeys` instance from a received frame.
+    ///
+    /// ```text
+    /// HKEYS key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HKeys> {
+        let key = parse.next_string()?;
+
+        Ok(HKeys { key })
+    }
+
+    /// Apply the `HKeys` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hgetall(&self.key) {
+            Ok(fields) => {
+                let mut response = Frame::array();
+                for (field, _) in fields {
+                    response.push_bulk(field);
+                }
+                response
+            }
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hkeys".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl HVals {
+    /// Create a new `HVals` command for `key`.
+    pub fn new(key: impl ToString) -> HVals {
+        HVals {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HVals` instance from a received frame.
+    ///
+    /// ```text
+    /// HVALS key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HVals> {
+        let key = parse.next_string()?;
+
+        Ok(HVals { key })
+    }
+
+    /// Apply the `HVals` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hgetall(&self.key) {
+            Ok(fields) => {
+                let mut response = Frame::array();
+                for (_, value) in fields {
+                    response.push_bulk(value);
+                }
+                response
+            }
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hvals".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl HLen {
+    /// Create a new `HLen` command for `key`.
+    pub fn new(key: impl ToString) -> HLen {
+        HLen {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HLen` instance from a received frame.
+    ///
+    /// ```text
+    /// HLEN key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HLen> {
+        let key = parse.next_string()?;
+
+        Ok(HLen { key })
+    }
+
+    /// Apply the `HLen` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hlen(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hlen".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl HExists {
+    /// Create a new `HExists` command checking `field` in the hash at `key`.
+    pub fn new(key: impl ToString, field: Bytes) -> HExists {
+        HExists {
+            key: key.to_string(),
+            field,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HExists` instance from a received frame.
+    ///
+    /// ```text
+    /// HEXISTS key field
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HExists> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+
+        Ok(HExists { key, field })
+    }
+
+    /// Apply the `HExists` command to the specified `Db` instance.
+    ///
+    /// Replies `1` if the field exists, `0` otherwise.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.hget(&self.key, &self.field) {
+            Ok(value) => Frame::Integer(value.is_some() as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hexists".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.field);
+        frame
+    }
+}
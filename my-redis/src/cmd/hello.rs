@@ -0,0 +1,163 @@
+use crate::replication::Role;
+use crate::{Connection, Db, Frame, Parse, ParseError, Protocol};
+
+use bytes::Bytes;
+use std::fmt;
+use tracing::{debug, instrument};
+
+/// Switch the protocol spoken on the connection.
+///
+/// Without argument, the protocol is left unchanged. The reply is a map
+/// describing the server, encoded with the protocol selected by the command.
+///
+/// With `AUTH`, the connection is authenticated first, as by the `AUTH`
+/// command, and the protocol is only switched if that succeeds. Without
+/// `AUTH`, the connection must already be authenticated.
+#[derive(Clone, Default)]
+pub struct Hello {
+    protover: Option<i64>,
+
+    /// The username and password to authenticate with.
+    auth: Option<(String, String)>,
+}
+
+impl Hello {
+    /// Create a new `Hello` command switching to `protocol`, if any.
+    pub fn new(protocol: Option<Protocol>) -> Hello {
+        Hello {
+            protover: protocol.map(Protocol::version),
+            auth: None,
+        }
+    }
+
+    /// Parse a `Hello` instance from a received frame.
+    ///
+    /// The `HELLO` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// HELLO [protover [AUTH username password]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Hello> {
+        let protover = match parse.next_int() {
+            Ok(protover) => Some(protover),
+            Err(ParseError::EndOfStream) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        let auth = match parse.next_string() {
+            Ok(option) if option.eq_ignore_ascii_case("AUTH") => {
+                Some((parse.next_string()?, parse.next_string()?))
+            }
+            Ok(option) => {
+                return Err(format!("ERR Syntax error in HELLO option '{}'", option).into())
+            }
+            Err(ParseError::EndOfStream) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Hello { protover, auth })
+    }
+
+    /// Apply the `Hello` command to the connection, authenticating it
+    /// against the specified `Db` instance if requested.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        if let Some((username, password)) = &self.auth {
+            match db.authenticate(Some(username), password) {
+                Ok(user) => dst.set_user(Some(user)),
+                Err(err) => {
+                    let response = Frame::Error(err.to_string());
+                    debug!(?response);
+                    dst.write_frame(&response).await?;
+                    return Ok(());
+                }
+            }
+        } else if dst.user().is_none() {
+            let response = Frame::Error(
+                "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".to_string(),
+            );
+            debug!(?response);
+            dst.write_frame(&response).await?;
+            return Ok(());
+        }
+
+        let protocol = match self.protover {
+            None => Some(dst.protocol()),
+            Some(2) => Some(Protocol::Resp2),
+            Some(3) => Some(Protocol::Resp3),
+            Some(_) => None,
+        };
+
+        let response = match protocol {
+            Some(protocol) => {
+                // The reply already uses the new protocol.
+                dst.set_protocol(protocol);
+                server_info(protocol, db)
+            }
+            None => Frame::Error("NOPROTO unsupported protocol version".to_string()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Hello` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hello".as_bytes()));
+        if let Some(protover) = self.protover {
+            frame.push_bulk(Bytes::from(protover.to_string()));
+        }
+        if let Some((username, password)) = self.auth {
+            frame.push_bulk(Bytes::from("auth".as_bytes()));
+            frame.push_bulk(Bytes::from(username.into_bytes()));
+            frame.push_bulk(Bytes::from(password.into_bytes()));
+        }
+        frame
+    }
+}
+
+// The password is kept out of the logs.
+impl fmt::Debug for Hello {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Hello")
+            .field("protover", &self.protover)
+            .field("username", &self.auth.as_ref().map(|(username, _)| username))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Describe the server in reply to `HELLO`.
+///
+/// The mode is `cluster` in cluster mode, and the role `replica` while
+/// following a leader.
+fn server_info(protocol: Protocol, db: &Db) -> Frame {
+    let field = |name: &'static str| Frame::Bulk(Bytes::from_static(name.as_bytes()));
+
+    let mode = if db.is_cluster() {
+        "cluster"
+    } else {
+        "standalone"
+    };
+    let role = match db.role() {
+        Role::Leader { .. } => "master",
+        Role::Follower { .. } => "replica",
+    };
+
+    Frame::Map(vec![
+        (field("server"), field("redis")),
+        (field("version"), field(env!("CARGO_PKG_VERSION"))),
+        (field("proto"), Frame::Integer(protocol.version())),
+        (field("mode"), field(mode)),
+        (field("role"), field(role)),
+        (field("modules"), Frame::array()),
+    ])
+}
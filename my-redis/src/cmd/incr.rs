@@ -0,0 +1,268 @@
+use crate::db::DbError;
+use crate::{Db, Frame, Parse};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Increments the number stored at key by one.
+///
+/// If the key does not exist, it is set to 0 before performing the operation.
+/// An error is returned if the key holds a value that can not be represented
+/// as a 64 bit signed integer. The time to live of the key is preserved.
+#[derive(Debug, Clone)]
+pub struct Incr {
+    key: String,
+}
+
+/// Decrements the number stored at key by one. Works like `INCR`.
+#[derive(Debug, Clone)]
+pub struct Decr {
+    key: String,
+}
+
+/// Increments the number stored at key by increment. Works like `INCR`.
+#[derive(Debug, Clone)]
+pub struct IncrBy {
+    key: String,
+    increment: i64,
+}
+
+/// Decrements the number stored at key by decrement. Works like `INCR`.
+#[derive(Debug, Clone)]
+pub struct DecrBy {
+    key: String,
+    decrement: i64,
+}
+
+/// Increments the floating point number stored at key by increment.
+///
+/// The increment may be negative. Works like `INCR` otherwise, the result
+/// being replied as a bulk string.
+#[derive(Debug, Clone)]
+pub struct IncrByFloat {
+    key: String,
+    increment: f64,
+}
+
+impl Incr {
+    /// Create a new `Incr` command incrementing `key`.
+    pub fn new(key: impl ToString) -> Incr {
+        Incr {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `Incr` instance from a received frame.
+    ///
+    /// ```text
+    /// INCR key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Incr> {
+        let key = parse.next_string()?;
+
+        Ok(Incr { key })
+    }
+
+    /// Apply the `Incr` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        counter_frame(db.incr_by(self.key, 1))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("incr".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl Decr {
+    /// Create a new `Decr` command decrementing `key`.
+    pub fn new(key: impl ToString) -> Decr {
+        Decr {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Decr` instance from a received frame.
+    ///
+    /// ```text
+    /// DECR key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Decr> {
+        let key = parse.next_string()?;
+
+        Ok(Decr { key })
+    }
+
+    /// Apply the `Decr` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        counter_frame(db.incr_by(self.key, -1))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("decr".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl IncrBy {
+    /// Create a new `IncrBy` command adding `increment` to `key`.
+    pub fn new(key: impl ToString, increment: i64) -> IncrBy {
+        IncrBy {
+            key: key.to_string(),
+            increment,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `IncrBy` instance from a received frame.
+    ///
+    /// ```text
+    /// INCRBY key increment
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<IncrBy> {
+        let key = parse.next_string()?;
+        let increment = parse.next_int()?;
+
+        Ok(IncrBy { key, increment })
+    }
+
+    /// Apply the `IncrBy` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        counter_frame(db.incr_by(self.key, self.increment))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("incrby".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+}
+
+impl DecrBy {
+    /// Create a new `DecrBy` command subtracting `decrement` from `key`.
+    pub fn new(key: impl ToString, decrement: i64) -> DecrBy {
+        DecrBy {
+            key: key.to_string(),
+            decrement,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `DecrBy` instance from a received frame.
+    ///
+    /// ```text
+    /// DECRBY key decrement
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<DecrBy> {
+        let key = parse.next_string()?;
+        let decrement = parse.next_int()?;
+
+        Ok(DecrBy { key, decrement })
+    }
+
+    /// Apply the `DecrBy` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        // `i64::MIN` has no positive counterpart.
+        match self.decrement.checked_neg() {
+            Some(delta) => counter_frame(db.incr_by(self.key, delta)),
+            None => Frame::Error(DbError::Overflow.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("decrby".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.decrement.to_string()));
+        frame
+    }
+}
+
+impl IncrByFloat {
+    /// Create a new `IncrByFloat` command adding `increment` to `key`.
+    pub fn new(key: impl ToString, increment: f64) -> IncrByFloat {
+        IncrByFloat {
+            key: key.to_string(),
+            increment,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `IncrByFloat` instance from a received frame.
+    ///
+    /// ```text
+    /// INCRBYFLOAT key increment
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<IncrByFloat> {
+        let key = parse.next_string()?;
+        let increment = parse
+            .next_string()?
+            .parse::<f64>()
+            .ok()
+            .filter(|increment| increment.is_finite())
+            .ok_or("ERR value is not a valid float")?;
+
+        Ok(IncrByFloat { key, increment })
+    }
+
+    /// Apply the `IncrByFloat` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.incr_by_float(self.key, self.increment) {
+            Ok(value) => Frame::Bulk(Bytes::from(value.to_string())),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("incrbyfloat".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+}
+
+fn counter_frame(value: Result<i64, DbError>) -> Frame {
+    match value {
+        Ok(value) => Frame::Integer(value),
+        Err(err) => Frame::Error(err.to_string()),
+    }
+}
@@ -0,0 +1,460 @@
+use crate::{Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Removes the specified keys. A key is ignored if it does not exist.
+///
+/// Replies the number of keys that were removed.
+#[derive(Debug, Clone)]
+pub struct Del {
+    keys: Vec<String>,
+}
+
+/// Removes the specified keys like `DEL`, but the memory of their values is
+/// reclaimed in the background.
+#[derive(Debug, Clone)]
+pub struct Unlink {
+    keys: Vec<String>,
+}
+
+/// Returns the number of the specified keys that exist.
+///
+/// A key mentioned several times is counted several times.
+#[derive(Debug, Clone)]
+pub struct Exists {
+    keys: Vec<String>,
+}
+
+/// Alters the last access time of the specified keys. Replies the number of
+/// keys that exist, like `EXISTS`.
+///
+/// Access times are not tracked, touching a key has no other effect.
+#[derive(Debug, Clone)]
+pub struct Touch {
+    keys: Vec<String>,
+}
+
+/// Returns the type of the value stored at key: `string`, `list`, `hash`,
+/// `set`, `zset` or `stream`, and `none` if the key does not exist.
+#[derive(Debug, Clone)]
+pub struct Type {
+    key: String,
+}
+
+/// Renames key to newkey, overwriting any value stored at newkey.
+///
+/// The time to live of key moves along with its value. An error is replied
+/// if key does not exist.
+#[derive(Debug, Clone)]
+pub struct Rename {
+    key: String,
+    new_key: String,
+}
+
+/// Renames key to newkey if newkey does not exist yet. Works like `RENAME`.
+///
+/// Replies `1` if key was renamed and `0` if newkey already exists.
+#[derive(Debug, Clone)]
+pub struct RenameNx {
+    key: String,
+    new_key: String,
+}
+
+/// Returns all keys matching pattern.
+///
+/// The whole key space is walked while holding the lock. Prefer `SCAN` on
+/// large databases.
+#[derive(Debug, Clone)]
+pub struct Keys {
+    pattern: Bytes,
+}
+
+/// Copies the value stored at the source key to the destination key, along
+/// with its time to live.
+///
+/// Unless `REPLACE` is given, nothing is copied if the destination exists.
+/// Replies `1` if the value was copied and `0` otherwise.
+#[derive(Debug, Clone)]
+pub struct Copy {
+    source: String,
+    destination: String,
+    replace: bool,
+}
+
+impl Del {
+    /// Create a new `Del` command removing `keys`.
+    pub fn new(keys: Vec<String>) -> Del {
+        Del { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `Del` instance from a received frame.
+    ///
+    /// ```text
+    /// DEL key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Del> {
+        let keys = parse.remaining_strings()?;
+
+        Ok(Del { keys })
+    }
+
+    /// Apply the `Del` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        Frame::Integer(db.del(&self.keys) as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("del", self.keys)
+    }
+}
+
+impl Unlink {
+    /// Create a new `Unlink` command removing `keys`.
+    pub fn new(keys: Vec<String>) -> Unlink {
+        Unlink { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `Unlink` instance from a received frame.
+    ///
+    /// ```text
+    /// UNLINK key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Unlink> {
+        let keys = parse.remaining_strings()?;
+
+        Ok(Unlink { keys })
+    }
+
+    /// Apply the `Unlink` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        Frame::Integer(db.unlink(&self.keys) as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("unlink", self.keys)
+    }
+}
+
+impl Exists {
+    /// Create a new `Exists` command counting the existing `keys`.
+    pub fn new(keys: Vec<String>) -> Exists {
+        Exists { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `Exists` instance from a received frame.
+    ///
+    /// ```text
+    /// EXISTS key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Exists> {
+        let keys = parse.remaining_strings()?;
+
+        Ok(Exists { keys })
+    }
+
+    /// Apply the `Exists` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        Frame::Integer(db.exists(&self.keys) as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("exists", self.keys)
+    }
+}
+
+impl Touch {
+    /// Create a new `Touch` command touching `keys`.
+    pub fn new(keys: Vec<String>) -> Touch {
+        Touch { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `Touch` instance from a received frame.
+    ///
+    /// ```text
+    /// TOUCH key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Touch> {
+        let keys = parse.remaining_strings()?;
+
+        Ok(Touch { keys })
+    }
+
+    /// Apply the `Touch` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        Frame::Integer(db.exists(&self.keys) as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("touch", self.keys)
+    }
+}
+
+impl Type {
+    /// Create a new `Type` command reading the type of `key`.
+    pub fn new(key: impl ToString) -> Type {
+        Type {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Type` instance from a received frame.
+    ///
+    /// ```text
+    /// TYPE key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Type> {
+        let key = parse.next_string()?;
+
+        Ok(Type { key })
+    }
+
+    /// Apply the `Type` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        Frame::Simple(db.key_type(&self.key).to_string())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("type".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl Rename {
+    /// Create a new `Rename` command renaming `key` to `new_key`.
+    pub fn new(key: impl ToString, new_key: impl ToString) -> Rename {
+        Rename {
+            key: key.to_string(),
+            new_key: new_key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Get the new name of the key
+    pub fn new_key(&self) -> &str {
+        &self.new_key
+    }
+
+    /// Parse a `Rename` instance from a received frame.
+    ///
+    /// ```text
+    /// RENAME key newkey
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Rename> {
+        let key = parse.next_string()?;
+        let new_key = parse.next_string()?;
+
+        Ok(Rename { key, new_key })
+    }
+
+    /// Apply the `Rename` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.rename(&self.key, &self.new_key, false) {
+            Ok(_) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("rename", vec![self.key, self.new_key])
+    }
+}
+
+impl RenameNx {
+    /// Create a new `RenameNx` command renaming `key` to `new_key`.
+    pub fn new(key: impl ToString, new_key: impl ToString) -> RenameNx {
+        RenameNx {
+            key: key.to_string(),
+            new_key: new_key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Get the new name of the key
+    pub fn new_key(&self) -> &str {
+        &self.new_key
+    }
+
+    /// Parse a `RenameNx` instance from a received frame.
+    ///
+    /// ```text
+    /// RENAMENX key newkey
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<RenameNx> {
+        let key = parse.next_string()?;
+        let new_key = parse.next_string()?;
+
+        Ok(RenameNx { key, new_key })
+    }
+
+    /// Apply the `RenameNx` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.rename(&self.key, &self.new_key, true) {
+            Ok(renamed) => Frame::Integer(renamed as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("renamenx", vec![self.key, self.new_key])
+    }
+}
+
+impl Copy {
+    /// Create a new `Copy` command copying `source` to `destination`.
+    pub fn new(source: impl ToString, destination: impl ToString, replace: bool) -> Copy {
+        Copy {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            replace,
+        }
+    }
+
+    /// Get the source key
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Get the destination key
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Parse a `Copy` instance from a received frame.
+    ///
+    /// ```text
+    /// COPY source destination [REPLACE]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Copy> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+
+        let replace = match parse.next_string() {
+            Ok(s) if s.to_uppercase() == "REPLACE" => true,
+            Ok(_) => return Err("ERR syntax error".into()),
+            Err(ParseError::EndOfStream) => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Copy {
+            source,
+            destination,
+            replace,
+        })
+    }
+
+    /// Apply the `Copy` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        if self.source == self.destination {
+            Frame::Error("ERR source and destination objects are the same".to_string())
+        } else {
+            let copied = db.copy(&self.source, &self.destination, self.replace);
+            Frame::Integer(copied as i64)
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = keys_frame("copy", vec![self.source, self.destination]);
+        if self.replace {
+            frame.push_bulk(Bytes::from_static(b"REPLACE"));
+        }
+        frame
+    }
+}
+
+impl Keys {
+    /// Create a new `Keys` command listing the keys matching `pattern`.
+    pub fn new(pattern: Bytes) -> Keys {
+        Keys { pattern }
+    }
+
+    /// Parse a `Keys` instance from a received frame.
+    ///
+    /// ```text
+    /// KEYS pattern
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Keys> {
+        let pattern = parse.next_bytes()?;
+
+        Ok(Keys { pattern })
+    }
+
+    /// Apply the `Keys` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let mut response = Frame::array();
+        for key in db.keys(&self.pattern) {
+            response.push_bulk(Bytes::from(key));
+        }
+        response
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("keys".as_bytes()));
+        frame.push_bulk(self.pattern);
+        frame
+    }
+}
+
+/// Encodes a command taking only keys as arguments.
+fn keys_frame(name: &'static str, keys: Vec<String>) -> Frame {
+    let mut frame = Frame::array();
+    frame.push_bulk(Bytes::from(name.as_bytes()));
+    for key in keys {
+        frame.push_bulk(Bytes::from(key.into_bytes()));
+    }
+    frame
+}
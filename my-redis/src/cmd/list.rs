@@ -0,0 +1,551 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::ListEnd;
+use crate::{Db, Frame};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Insert all the specified values at the head of the list stored at key.
+///
+/// If key does not exist, it is created as an empty list before performing the
+/// push operation. Elements are inserted one after the other, so `LPUSH key a
+/// b c` results in a list containing `c b a`.
+#[derive(Debug, Clone)]
+pub struct LPush {
+    key: String,
+    values: Vec<Bytes>,
+}
+
+/// Insert all the specified values at the tail of the list stored at key.
+///
+/// If key does not exist, it is created as an empty list before performing the
+/// push operation.
+#[derive(Debug, Clone)]
+pub struct RPush {
+    key: String,
+    values: Vec<Bytes>,
+}
+
+/// Removes and returns the first elements of the list stored at key.
+///
+/// Without the `count` argument a single element is popped and returned as a
+/// bulk string. With `count`, up to `count` elements are returned as an array.
+#[derive(Debug, Clone)]
+pub struct LPop {
+    key: String,
+    count: Option<u64>,
+}
+
+/// Removes and returns the last elements of the list stored at key.
+///
+/// Same reply shapes as `LPOP`.
+#[derive(Debug, Clone)]
+pub struct RPop {
+    key: String,
+    count: Option<u64>,
+}
+
+/// Returns the specified elements of the list stored at key.
+///
+/// `start` and `stop` are zero-based inclusive indices. Negative indices count
+/// from the tail of the list, `-1` being the last element.
+#[derive(Debug, Clone)]
+pub struct LRange {
+    key: String,
+    start: i64,
+    stop: i64,
+}
+
+/// Returns the length of the list stored at key, `0` if the key does not exist.
+#[derive(Debug, Clone)]
+pub struct LLen {
+    key: String,
+}
+
+/// Returns the element at `index` in the list stored at key, or nil when the
+/// index is out of range.
+#[derive(Debug, Clone)]
+pub struct LIndex {
+    key: String,
+    index: i64,
+}
+
+/// Sets the list element at `index` to `value`.
+///
+/// An error is returned for out of range indexes or when the key does not
+/// exist.
+#[derive(Debug, Clone)]
+pub struct LSet {
+    key: String,
+    index: i64,
+    value: Bytes,
+}
+
+/// Trim an existing list so that it will contain only the specified range of
+/// elements.
+#[derive(Debug, Clone)]
+pub struct LTrim {
+    key: String,
+    start: i64,
+    stop: i64,
+}
+
+impl LPush {
+    /// Create a new `LPush` command which pushes `values` onto `key`.
+    pub fn new(key: impl ToString, values: Vec<Bytes>) -> LPush {
+        LPush {
+            key: key.to_string(),
+            values,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `LPush` instance from a received frame.
+    ///
+    /// ```text
+    /// LPUSH key element [element ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<LPush> {
+        let key = parse.next_string()?;
+        let values = parse.remaining_bytes()?;
+
+        Ok(LPush { key, values })
+    }
+
+    /// Apply the `LPush` command to the specified `Db` instance.
+    ///
+    /// The new length of the list is replied.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        push(db, self.key, ListEnd::Left, self.values)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("lpush".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for value in self.values {
+            frame.push_bulk(value);
+        }
+        frame
+    }
+}
+
+impl RPush {
+    /// Create a new `RPush` command which pushes `values` onto `key`.
+    pub fn new(key: impl ToString, values: Vec<Bytes>) -> RPush {
+        RPush {
+            key: key.to_string(),
+            values,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `RPush` instance from a received frame.
+    ///
+    /// ```text
+    /// RPUSH key element [element ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<RPush> {
+        let key = parse.next_string()?;
+        let values = parse.remaining_bytes()?;
+
+        Ok(RPush { key, values })
+    }
+
+    /// Apply the `RPush` command to the specified `Db` instance.
+    ///
+    /// The new length of the list is replied.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        push(db, self.key, ListEnd::Right, self.values)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("rpush".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for value in self.values {
+            frame.push_bulk(value);
+        }
+        frame
+    }
+}
+
+impl LPop {
+    /// Create a new `LPop` command. When `count` is `None` a single element is
+    /// popped.
+    pub fn new(key: impl ToString, count: Option<u64>) -> LPop {
+        LPop {
+            key: key.to_string(),
+            count,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `LPop` instance from a received frame.
+    ///
+    /// ```text
+    /// LPOP key [count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<LPop> {
+        let key = parse.next_string()?;
+        let count = parse_count(parse)?;
+
+        Ok(LPop { key, count })
+    }
+
+    /// Apply the `LPop` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        pop(db, &self.key, ListEnd::Left, self.count)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("lpop".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        if let Some(count) = self.count {
+            frame.push_int(count as i64);
+        }
+        frame
+    }
+}
+
+impl RPop {
+    /// Create a new `RPop` command. When `count` is `None` a single element is
+    /// popped.
+    pub fn new(key: impl ToString, count: Option<u64>) -> RPop {
+        RPop {
+            key: key.to_string(),
+            count,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `RPop` instance from a received frame.
+    ///
+    /// ```text
+    /// RPOP key [count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<RPop> {
+        let key = parse.next_string()?;
+        let count = parse_count(parse)?;
+
+        Ok(RPop { key, count })
+    }
+
+    /// Apply the `RPop` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        pop(db, &self.key, ListEnd::Right, self.count)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("rpop".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        if let Some(count) = self.count {
+            frame.push_int(count as i64);
+        }
+        frame
+    }
+}
+
+impl LRange {
+    /// Create a new `LRange` command fetching `start..=stop` from `key`.
+    pub fn new(key: impl ToString, start: i64, stop: i64) -> LRange {
+        LRange {
+            key: key.to_string(),
+            start,
+            stop,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `LRange` instance from a received frame.
+    ///
+    /// ```text
+    /// LRANGE key start stop
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<LRange> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let stop = parse.next_int()?;
+
+        Ok(LRange { key, start, stop })
+    }
+
+    /// Apply the `LRange` command to the specified `Db` instance.
+    ///
+    /// The elements are replied as an array of bulk strings.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.lrange(&self.key, self.start, self.stop) {
+            Ok(values) => {
+                let mut response = Frame::array();
+                for value in values {
+                    response.push_bulk(value);
+                }
+                response
+            }
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("lrange".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.start.to_string()));
+        frame.push_bulk(Bytes::from(self.stop.to_string()));
+        frame
+    }
+}
+
+impl LLen {
+    /// Create a new `LLen` command for `key`.
+    pub fn new(key: impl ToString) -> LLen {
+        LLen {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `LLen` instance from a received frame.
+    ///
+    /// ```text
+    /// LLEN key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<LLen> {
+        let key = parse.next_string()?;
+
+        Ok(LLen { key })
+    }
+
+    /// Apply the `LLen` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.llen(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("llen".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl LIndex {
+    /// Create a new `LIndex` command fetching the element at `index`.
+    pub fn new(key: impl ToString, index: i64) -> LIndex {
+        LIndex {
+            key: key.to_string(),
+            index,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `LIndex` instance from a received frame.
+    ///
+    /// ```text
+    /// LINDEX key index
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<LIndex> {
+        let key = parse.next_string()?;
+        let index = parse.next_int()?;
+
+        Ok(LIndex { key, index })
+    }
+
+    /// Apply the `LIndex` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.lindex(&self.key, self.index) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("lindex".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.index.to_string()));
+        frame
+    }
+}
+
+impl LSet {
+    /// Create a new `LSet` command replacing the element at `index`.
+    pub fn new(key: impl ToString, index: i64, value: Bytes) -> LSet {
+        LSet {
+            key: key.to_string(),
+            index,
+            value,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `LSet` instance from a received frame.
+    ///
+    /// ```text
+    /// LSET key index element
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<LSet> {
+        let key = parse.next_string()?;
+        let index = parse.next_int()?;
+        let value = parse.next_bytes()?;
+
+        Ok(LSet { key, index, value })
+    }
+
+    /// Apply the `LSet` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.lset(&self.key, self.index, self.value) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("lset".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.index.to_string()));
+        frame.push_bulk(self.value);
+        frame
+    }
+}
+
+impl LTrim {
+    /// Create a new `LTrim` command keeping only `start..=stop`.
+    pub fn new(key: impl ToString, start: i64, stop: i64) -> LTrim {
+        LTrim {
+            key: key.to_string(),
+            start,
+            stop,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `LTrim` instance from a received frame.
+    ///
+    /// ```text
+    /// LTRIM key start stop
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<LTrim> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let stop = parse.next_int()?;
+
+        Ok(LTrim { key, start, stop })
+    }
+
+    /// Apply the `LTrim` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.ltrim(&self.key, self.start, self.stop) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("ltrim".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.start.to_string()));
+        frame.push_bulk(Bytes::from(self.stop.to_string()));
+        frame
+    }
+}
+
+/// Parse the optional `count` argument of the pop commands.
+fn parse_count(parse: &mut Parse) -> crate::Result<Option<u64>> {
+    match parse.next_uint() {
+        Ok(count) => Ok(Some(count)),
+        Err(ParseError::EndOfStream) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Shared by `LPUSH` and `RPUSH`. Builds the reply frame.
+fn push(db: &Db, key: String, end: ListEnd, values: Vec<Bytes>) -> Frame {
+    match db.push(key, end, values) {
+        Ok(len) => Frame::Integer(len as i64),
+        Err(err) => Frame::Error(err.to_string()),
+    }
+}
+
+/// Shared by `LPOP` and `RPOP`. Builds the reply frame.
+///
+/// Without a `count` the single popped element is replied as a bulk string,
+/// otherwise the popped elements are replied as an array.
+fn pop(db: &Db, key: &str, end: ListEnd, count: Option<u64>) -> Frame {
+    match db.pop(key, end, count.unwrap_or(1) as usize) {
+        Ok(Some(values)) => match count {
+            None => values.into_iter().next().map_or(Frame::Null, Frame::Bulk),
+            Some(_) => {
+                let mut response = Frame::array();
+                for value in values {
+                    response.push_bulk(value);
+                }
+                response
+            }
+        },
+        Ok(None) => Frame::Null,
+        Err(err) => Frame::Error(err.to_string()),
+    }
+}
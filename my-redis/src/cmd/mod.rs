@@ -13,13 +13,68 @@ pub use subscribe::{Subscribe, Unsubscribe};
 mod ping;
 pub use ping::Ping;
 
+mod list;
+pub use list::{LIndex, LLen, LPop, LPush, LRange, LSet, LTrim, RPop, RPush};
+
+mod hash;
+pub use hash::{HDel, HExists, HGet, HGetAll, HIncrBy, HKeys, HLen, HMGet, HSet, HVals};
+
+mod sets;
+pub use sets::{SAdd, SCard, SDiff, SDiffStore, SInter, SInterStore, SIsMember, SMembers, SRem, SUnion, SUnionStore};
+
+mod sorted_set;
+pub use sorted_set::{ZAdd, ZCard, ZIncrBy, ZPopMin, ZRange, ZRangeByScore, ZRank, ZRem};
+
+mod stream;
+pub use stream::{XAck, XAdd, XClaim, XGroupCreate, XLen, XPending, XRange, XRead, XReadGroup, XRevRange, XTrim};
+
+mod incr;
+pub use incr::{Decr, DecrBy, Incr, IncrBy, IncrByFloat};
+
+mod hello;
+pub use hello::Hello;
+
+mod expire;
+pub use expire::{Expire, ExpireAt, PExpire, PExpireAt, PTtl, Persist, Ttl};
+
+mod keyspace;
+pub use keyspace::{Copy, Del, Exists, Keys, Rename, RenameNx, Touch, Type, Unlink};
+
+mod scan;
+pub use scan::{HScan, SScan, Scan, ZScan};
+
+mod transaction;
+pub use transaction::{Discard, Exec, Multi, Unwatch, Watch};
+pub(crate) use transaction::TransactionState;
+
+mod eval;
+pub use eval::{Eval, EvalSha, Script};
+
+mod save;
+pub use save::{BgRewriteAof, BgSave, Save};
+
+mod replication;
+pub use replication::{PSync, ReplConf, ReplicaOf, Role};
+
+mod cluster;
+pub use cluster::{Asking, Cluster};
+
+mod auth;
+pub use auth::Auth;
+
+mod acl;
+pub use acl::Acl;
+
 mod unknown;
 pub use unknown::Unknown;
 
 use crate::{Connection, Db, Frame, Parse, ParseError, Shutdown};
 
+use tracing::debug;
 
-#[derive(Debug)]
+
+
+#[derive(Debug, Clone)]
 pub enum Command{
     Get(Get),
     Publish(Publish),
@@ -27,39 +82,248 @@ pub enum Command{
     Subscribe(Subscribe),
     Unsubscribe(Unsubscribe),
     Ping(Ping),
+    LPush(LPush),
+    RPush(RPush),
+    LPop(LPop),
+    RPop(RPop),
+    LRange(LRange),
+    LLen(LLen),
+    LIndex(LIndex),
+    LSet(LSet),
+    LTrim(LTrim),
+    HSet(HSet),
+    HGet(HGet),
+    HDel(HDel),
+    HGetAll(HGetAll),
+    HMGet(HMGet),
+    HIncrBy(HIncrBy),
+    HKeys(HKeys),
+    HVals(HVals),
+    HLen(HLen),
+    HExists(HExists),
+    SAdd(SAdd),
+    SRem(SRem),
+    SMembers(SMembers),
+    SIsMember(SIsMember),
+    SCard(SCard),
+    SInter(SInter),
+    SUnion(SUnion),
+    SDiff(SDiff),
+    SInterStore(SInterStore),
+    SUnionStore(SUnionStore),
+    SDiffStore(SDiffStore),
+    ZAdd(ZAdd),
+    ZRange(ZRange),
+    ZRangeByScore(ZRangeByScore),
+    ZRank(ZRank),
+    ZIncrBy(ZIncrBy),
+    ZRem(ZRem),
+    ZCard(ZCard),
+    ZPopMin(ZPopMin),
+    XAdd(XAdd),
+    XRange(XRange),
+    XRevRange(XRevRange),
+    XLen(XLen),
+    XTrim(XTrim),
+    XRead(XRead),
+    XGroupCreate(XGroupCreate),
+    XReadGroup(XReadGroup),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    Incr(Incr),
+    Decr(Decr),
+    IncrBy(IncrBy),
+    DecrBy(DecrBy),
+    IncrByFloat(IncrByFloat),
+    Hello(Hello),
+    Expire(Expire),
+    PExpire(PExpire),
+    ExpireAt(ExpireAt),
+    PExpireAt(PExpireAt),
+    Ttl(Ttl),
+    PTtl(PTtl),
+    Persist(Persist),
+    Del(Del),
+    Unlink(Unlink),
+    Exists(Exists),
+    Touch(Touch),
+    Type(Type),
+    Rename(Rename),
+    RenameNx(RenameNx),
+    Copy(Copy),
+    Keys(Keys),
+    Scan(Scan),
+    HScan(HScan),
+    SScan(SScan),
+    ZScan(ZScan),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Eval(Eval),
+    EvalSha(EvalSha),
+    Script(Script),
+    Save(Save),
+    BgSave(BgSave),
+    BgRewriteAof(BgRewriteAof),
+    ReplicaOf(ReplicaOf),
+    PSync(PSync),
+    ReplConf(ReplConf),
+    Role(Role),
+    Cluster(Cluster),
+    Asking(Asking),
+    Auth(Auth),
+    Acl(Acl),
     Unknown(Unknown),
 }
 
 impl Command {
+    /// Parse a command from a received frame.
+    ///
+    /// Invalid arguments are reported with an `ERR` error, replied to the
+    /// client. Other errors are protocol errors.
     pub fn from_frame(frame: Frame) -> crate::Result<Command>{
         let mut parse = Parse::new(frame)?;
         // set nskdo 1
         // *3\r\n$3\r\nSET\r\n$4\r\nskdo\r\n$1\r\n1\r\n
         let command_name = parse.next_string()?.to_lowercase();
-        
-        let command = match &command_name[..] {
-            "get" => Command::Get(Get::parse_frames(&mut parse)?),
-            "publish" => Command::Publish(Publish::parse_frames(&mut parse)?),
-            "set" => Command::Set(Set::parse_frames(&mut parse)?),
-            "subscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parse)?),
-            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parse)?),
-            "ping" => Command::Ping(Ping::parse_frames(&mut parse)?),
+
+        // Missing or extra arguments are reported like Redis does.
+        let arity = || format!("ERR wrong number of arguments for '{}' command", command_name);
+        match Command::parse_frames(&command_name, &mut parse){
+            Err(err) if matches!(err.downcast_ref(), Some(ParseError::EndOfStream)) => Err(arity().into()),
+            Err(err) => Err(err),
+            // `Unknown` is returned before the arguments are parsed, most
+            // likely leaving some unconsumed in the `Parse` instance.
+            Ok(command) if matches!(command, Command::Unknown(_)) => Ok(command),
+            Ok(command) => match parse.finish(){
+                Ok(()) => Ok(command),
+                Err(_) => Err(arity().into()),
+            },
+        }
+    }
+
+    /// Parse the arguments of the command named `command_name`.
+    fn parse_frames(command_name: &str, parse: &mut Parse) -> crate::Result<Command>{
+        let command = match command_name {
+            "get" => Command::Get(Get::parse_frames(parse)?),
+            "publish" => Command::Publish(Publish::parse_frames(parse)?),
+            "set" => Command::Set(Set::parse_frames(parse)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(parse)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(parse)?),
+            "ping" => Command::Ping(Ping::parse_frames(parse)?),
+            "lpush" => Command::LPush(LPush::parse_frames(parse)?),
+            "rpush" => Command::RPush(RPush::parse_frames(parse)?),
+            "lpop" => Command::LPop(LPop::parse_frames(parse)?),
+            "rpop" => Command::RPop(RPop::parse_frames(parse)?),
+            "lrange" => Command::LRange(LRange::parse_frames(parse)?),
+            "llen" => Command::LLen(LLen::parse_frames(parse)?),
+            "lindex" => Command::LIndex(LIndex::parse_frames(parse)?),
+            "lset" => Command::LSet(LSet::parse_frames(parse)?),
+            "ltrim" => Command::LTrim(LTrim::parse_frames(parse)?),
+            "hset" => Command::HSet(HSet::parse_frames(parse)?),
+            "hget" => Command::HGet(HGet::parse_frames(parse)?),
+            "hdel" => Command::HDel(HDel::parse_frames(parse)?),
+            "hgetall" => Command::HGetAll(HGetAll::parse_frames(parse)?),
+            "hmget" => Command::HMGet(HMGet::parse_frames(parse)?),
+            "hincrby" => Command::HIncrBy(HIncrBy::parse_frames(parse)?),
+            "hkeys" => Command::HKeys(HKeys::parse_frames(parse)?),
+            "hvals" => Command::HVals(HVals::parse_frames(parse)?),
+            "hlen" => Command::HLen(HLen::parse_frames(parse)?),
+            "hexists" => Command::HExists(HExists::parse_frames(parse)?),
+            "sadd" => Command::SAdd(SAdd::parse_frames(parse)?),
+            "srem" => Command::SRem(SRem::parse_frames(parse)?),
+            "smembers" => Command::SMembers(SMembers::parse_frames(parse)?),
+            "sismember" => Command::SIsMember(SIsMember::parse_frames(parse)?),
+            "scard" => Command::SCard(SCard::parse_frames(parse)?),
+            "sinter" => Command::SInter(SInter::parse_frames(parse)?),
+            "sunion" => Command::SUnion(SUnion::parse_frames(parse)?),
+            "sdiff" => Command::SDiff(SDiff::parse_frames(parse)?),
+            "sinterstore" => Command::SInterStore(SInterStore::parse_frames(parse)?),
+            "sunionstore" => Command::SUnionStore(SUnionStore::parse_frames(parse)?),
+            "sdiffstore" => Command::SDiffStore(SDiffStore::parse_frames(parse)?),
+            "zadd" => Command::ZAdd(ZAdd::parse_frames(parse)?),
+            "zrange" => Command::ZRange(ZRange::parse_frames(parse)?),
+            "zrangebyscore" => Command::ZRangeByScore(ZRangeByScore::parse_frames(parse)?),
+            "zrank" => Command::ZRank(ZRank::parse_frames(parse)?),
+            "zincrby" => Command::ZIncrBy(ZIncrBy::parse_frames(parse)?),
+            "zrem" => Command::ZRem(ZRem::parse_frames(parse)?),
+            "zcard" => Command::ZCard(ZCard::parse_frames(parse)?),
+            "zpopmin" => Command::ZPopMin(ZPopMin::parse_frames(parse)?),
+            "xadd" => Command::XAdd(XAdd::parse_frames(parse)?),
+            "xrange" => Command::XRange(XRange::parse_frames(parse)?),
+            "xrevrange" => Command::XRevRange(XRevRange::parse_frames(parse)?),
+            "xlen" => Command::XLen(XLen::parse_frames(parse)?),
+            "xtrim" => Command::XTrim(XTrim::parse_frames(parse)?),
+            "xread" => Command::XRead(XRead::parse_frames(parse)?),
+            "xgroup" => Command::XGroupCreate(XGroupCreate::parse_frames(parse)?),
+            "xreadgroup" => Command::XReadGroup(XReadGroup::parse_frames(parse)?),
+            "xack" => Command::XAck(XAck::parse_frames(parse)?),
+            "xpending" => Command::XPending(XPending::parse_frames(parse)?),
+            "xclaim" => Command::XClaim(XClaim::parse_frames(parse)?),
+            "incr" => Command::Incr(Incr::parse_frames(parse)?),
+            "decr" => Command::Decr(Decr::parse_frames(parse)?),
+            "incrby" => Command::IncrBy(IncrBy::parse_frames(parse)?),
+            "decrby" => Command::DecrBy(DecrBy::parse_frames(parse)?),
+            "incrbyfloat" => Command::IncrByFloat(IncrByFloat::parse_frames(parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(parse)?),
+            "expire" => Command::Expire(Expire::parse_frames(parse)?),
+            "pexpire" => Command::PExpire(PExpire::parse_frames(parse)?),
+            "expireat" => Command::ExpireAt(ExpireAt::parse_frames(parse)?),
+            "pexpireat" => Command::PExpireAt(PExpireAt::parse_frames(parse)?),
+            "ttl" => Command::Ttl(Ttl::parse_frames(parse)?),
+            "pttl" => Command::PTtl(PTtl::parse_frames(parse)?),
+            "persist" => Command::Persist(Persist::parse_frames(parse)?),
+            "del" => Command::Del(Del::parse_frames(parse)?),
+            "unlink" => Command::Unlink(Unlink::parse_frames(parse)?),
+            "exists" => Command::Exists(Exists::parse_frames(parse)?),
+            "touch" => Command::Touch(Touch::parse_frames(parse)?),
+            "type" => Command::Type(Type::parse_frames(parse)?),
+            "rename" => Command::Rename(Rename::parse_frames(parse)?),
+            "renamenx" => Command::RenameNx(RenameNx::parse_frames(parse)?),
+            "copy" => Command::Copy(Copy::parse_frames(parse)?),
+            "keys" => Command::Keys(Keys::parse_frames(parse)?),
+            "scan" => Command::Scan(Scan::parse_frames(parse)?),
+            "hscan" => Command::HScan(HScan::parse_frames(parse)?),
+            "sscan" => Command::SScan(SScan::parse_frames(parse)?),
+            "zscan" => Command::ZScan(ZScan::parse_frames(parse)?),
+            "multi" => Command::Multi(Multi::parse_frames(parse)?),
+            "exec" => Command::Exec(Exec::parse_frames(parse)?),
+            "discard" => Command::Discard(Discard::parse_frames(parse)?),
+            "watch" => Command::Watch(Watch::parse_frames(parse)?),
+            "unwatch" => Command::Unwatch(Unwatch::parse_frames(parse)?),
+            "eval" => Command::Eval(Eval::parse_frames(parse)?),
+            "evalsha" => Command::EvalSha(EvalSha::parse_frames(parse)?),
+            "script" => Command::Script(Script::parse_frames(parse)?),
+            "save" => Command::Save(Save::parse_frames(parse)?),
+            "bgsave" => Command::BgSave(BgSave::parse_frames(parse)?),
+            "bgrewriteaof" => Command::BgRewriteAof(BgRewriteAof::parse_frames(parse)?),
+            "replicaof" | "slaveof" => Command::ReplicaOf(ReplicaOf::parse_frames(parse)?),
+            "psync" => Command::PSync(PSync::parse_frames(parse)?),
+            "replconf" => Command::ReplConf(ReplConf::parse_frames(parse)?),
+            "role" => Command::Role(Role::parse_frames(parse)?),
+            "cluster" => Command::Cluster(Cluster::parse_frames(parse)?),
+            "asking" => Command::Asking(Asking::parse_frames(parse)?),
+            "auth" => Command::Auth(Auth::parse_frames(parse)?),
+            "acl" => Command::Acl(Acl::parse_frames(parse)?),
             _ => {
                 // The command is not recognized and an Unknown command is
                 // returned.
-                //
-                // `return` is called here to skip the `finish()` call below. As
-                // the command is not recognized, there is most likely
-                // unconsumed fields remaining in the `Parse` instance.
-                return Ok(Command::Unknown(Unknown::new(command_name)));
+                Command::Unknown(Unknown::new(command_name))
             }
         };
 
-        parse.finish()?;
-
-        Ok(command) 
+        Ok(command)
     }
 
+    /// Apply the command to the specified `Db` instance, writing its reply to
+    /// `dst`.
+    ///
+    /// Commands waiting for other clients or on the connection, such as
+    /// `SUBSCRIBE` or `XREAD` with `BLOCK`, are applied on their own. Every
+    /// other command is run by `execute`.
     pub(crate) async fn apply(
         self,
         db: &Db,
@@ -68,29 +332,464 @@ impl Command {
     )->crate::Result<()>{
         use Command::*;
 
+        // Commands are refused unless granted to the user of the connection.
+        if let Err(err) = db.acl_check(dst.user(), &self) {
+            dst.write_frame(&Frame::Error(err.to_string())).await?;
+            return Ok(());
+        }
+
         match self {
-            Get(cmd) => cmd.apply(db, dst).await,
-            Publish(cmd) => cmd.apply(db, dst).await,
-            Set(cmd) => cmd.apply(db, dst).await,
             Subscribe(cmd) => cmd.apply(db, dst, shutdown).await,
-            Ping(cmd) => cmd.apply(dst).await,
-            Unknown(cmd) => cmd.apply(dst).await,
+            XRead(cmd) => cmd.apply(db, dst, shutdown).await,
+            XReadGroup(cmd) => cmd.apply(db, dst, shutdown).await,
+            PSync(cmd) => cmd.apply(db, dst, shutdown).await,
+            ReplConf(cmd) => match cmd.execute() {
+                Some(response) => {
+                    debug!(?response);
+                    dst.write_frame(&response).await?;
+                    Ok(())
+                }
+                None => Ok(()),
+            },
             // `Unsubscribe` cannot be applied. It may only be received from the
             // context of a `Subscribe` command.
             Unsubscribe(_) => Err("`Unsubscribe` is unsupported in this context".into()),
+            // The transaction and authentication commands act on the state of
+            // the connection and are applied by the connection handler.
+            Multi(_) | Exec(_) | Discard(_) | Watch(_) | Unwatch(_) | Hello(_) | Auth(_) => {
+                Err(format!("`{}` is unsupported in this context", self.get_name()).into())
+            }
+            cmd => {
+                let response = cmd.execute(db, dst);
+                debug!(?response);
+                dst.write_frame(&response).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Apply the command without yielding, returning its reply.
+    ///
+    /// Used by `apply`, and by `EXEC` and scripts which hold the lock on the
+    /// database while running commands. Blocking commands, such as `XREAD`
+    /// with `BLOCK`, reply right away as if their timeout had elapsed.
+    /// Commands which only make sense on their own connection reply an
+    /// error.
+    ///
+    /// `dst` is only used for its user, commands do not write to it.
+    pub(crate) fn execute(self, db: &Db, dst: &mut Connection) -> Frame {
+        use Command::*;
+
+        match self {
+            Get(cmd) => cmd.execute(db),
+            Publish(cmd) => cmd.execute(db),
+            Set(cmd) => cmd.execute(db),
+            Ping(cmd) => cmd.execute(),
+            LPush(cmd) => cmd.execute(db),
+            RPush(cmd) => cmd.execute(db),
+            LPop(cmd) => cmd.execute(db),
+            RPop(cmd) => cmd.execute(db),
+            LRange(cmd) => cmd.execute(db),
+            LLen(cmd) => cmd.execute(db),
+            LIndex(cmd) => cmd.execute(db),
+            LSet(cmd) => cmd.execute(db),
+            LTrim(cmd) => cmd.execute(db),
+            HSet(cmd) => cmd.execute(db),
+            HGet(cmd) => cmd.execute(db),
+            HDel(cmd) => cmd.execute(db),
+            HGetAll(cmd) => cmd.execute(db),
+            HMGet(cmd) => cmd.execute(db),
+            HIncrBy(cmd) => cmd.execute(db),
+            HKeys(cmd) => cmd.execute(db),
+            HVals(cmd) => cmd.execute(db),
+            HLen(cmd) => cmd.execute(db),
+            HExists(cmd) => cmd.execute(db),
+            SAdd(cmd) => cmd.execute(db),
+            SRem(cmd) => cmd.execute(db),
+            SMembers(cmd) => cmd.execute(db),
+            SIsMember(cmd) => cmd.execute(db),
+            SCard(cmd) => cmd.execute(db),
+            SInter(cmd) => cmd.execute(db),
+            SUnion(cmd) => cmd.execute(db),
+            SDiff(cmd) => cmd.execute(db),
+            SInterStore(cmd) => cmd.execute(db),
+            SUnionStore(cmd) => cmd.execute(db),
+            SDiffStore(cmd) => cmd.execute(db),
+            ZAdd(cmd) => cmd.execute(db),
+            ZRange(cmd) => cmd.execute(db),
+            ZRangeByScore(cmd) => cmd.execute(db),
+            ZRank(cmd) => cmd.execute(db),
+            ZIncrBy(cmd) => cmd.execute(db),
+            ZRem(cmd) => cmd.execute(db),
+            ZCard(cmd) => cmd.execute(db),
+            ZPopMin(cmd) => cmd.execute(db),
+            XAdd(cmd) => cmd.execute(db),
+            XRange(cmd) => cmd.execute(db),
+            XRevRange(cmd) => cmd.execute(db),
+            XLen(cmd) => cmd.execute(db),
+            XTrim(cmd) => cmd.execute(db),
+            XRead(cmd) => cmd.execute(db),
+            XGroupCreate(cmd) => cmd.execute(db),
+            XReadGroup(cmd) => cmd.execute(db),
+            XAck(cmd) => cmd.execute(db),
+            XPending(cmd) => cmd.execute(db),
+            XClaim(cmd) => cmd.execute(db),
+            Incr(cmd) => cmd.execute(db),
+            Decr(cmd) => cmd.execute(db),
+            IncrBy(cmd) => cmd.execute(db),
+            DecrBy(cmd) => cmd.execute(db),
+            IncrByFloat(cmd) => cmd.execute(db),
+            Expire(cmd) => cmd.execute(db),
+            PExpire(cmd) => cmd.execute(db),
+            ExpireAt(cmd) => cmd.execute(db),
+            PExpireAt(cmd) => cmd.execute(db),
+            Ttl(cmd) => cmd.execute(db),
+            PTtl(cmd) => cmd.execute(db),
+            Persist(cmd) => cmd.execute(db),
+            Del(cmd) => cmd.execute(db),
+            Unlink(cmd) => cmd.execute(db),
+            Exists(cmd) => cmd.execute(db),
+            Touch(cmd) => cmd.execute(db),
+            Type(cmd) => cmd.execute(db),
+            Rename(cmd) => cmd.execute(db),
+            RenameNx(cmd) => cmd.execute(db),
+            Copy(cmd) => cmd.execute(db),
+            Keys(cmd) => cmd.execute(db),
+            Scan(cmd) => cmd.execute(db),
+            HScan(cmd) => cmd.execute(db),
+            SScan(cmd) => cmd.execute(db),
+            ZScan(cmd) => cmd.execute(db),
+            Eval(cmd) => cmd.execute(db, dst),
+            EvalSha(cmd) => cmd.execute(db, dst),
+            Script(cmd) => cmd.execute(db),
+            Save(cmd) => cmd.execute(db),
+            BgSave(cmd) => cmd.execute(db),
+            BgRewriteAof(cmd) => cmd.execute(db),
+            ReplicaOf(cmd) => cmd.execute(db),
+            Role(cmd) => cmd.execute(db),
+            Cluster(cmd) => cmd.execute(db),
+            Asking(cmd) => cmd.execute(),
+            Acl(cmd) => cmd.execute(db, dst.user()),
+            Unknown(cmd) => cmd.execute(),
+            ReplConf(cmd) => cmd.execute().unwrap_or(Frame::Null),
+            Subscribe(_) | Unsubscribe(_) | PSync(_) | Multi(_) | Exec(_) | Discard(_)
+            | Watch(_) | Unwatch(_) | Hello(_) | Auth(_) => {
+                Frame::Error(format!("`{}` is unsupported in this context", self.get_name()))
+            }
+        }
+    }
+
+    /// Apply the command like `execute`, checking it against the ACL of the
+    /// user of `dst` first.
+    ///
+    /// A write changing the key space is propagated to the append only file,
+    /// see `propagated`. Failed writes and writes changing nothing, such as
+    /// `LPOP` on a missing key, leave `Db::writes` as is and are not
+    /// propagated.
+    pub(crate) fn apply_now(self, db: &Db, dst: &mut Connection) -> Frame {
+        if let Err(err) = db.acl_check(dst.user(), &self) {
+            return Frame::Error(err.to_string());
+        }
+
+        let write = self.is_write().then(|| (self.clone(), db.writes()));
+        let reply = self.execute(db, dst);
+
+        if let Some((cmd, writes)) = write {
+            if db.writes() != writes {
+                db.propagate(cmd.propagated(&reply));
+            }
         }
-        
+        reply
     }
+
+    /// Returns `true` if the command may write to the key space.
+    pub(crate) fn is_write(&self) -> bool {
+        use Command::*;
+
+        matches!(
+            self,
+            Set(_) | LPush(_) | RPush(_) | LPop(_) | RPop(_) | LSet(_) | LTrim(_)
+                | HSet(_) | HDel(_) | HIncrBy(_)
+                | SAdd(_) | SRem(_) | SInterStore(_) | SUnionStore(_) | SDiffStore(_)
+                | ZAdd(_) | ZIncrBy(_) | ZRem(_) | ZPopMin(_)
+                | XAdd(_) | XTrim(_) | XGroupCreate(_) | XReadGroup(_) | XAck(_) | XClaim(_)
+                | Incr(_) | Decr(_) | IncrBy(_) | DecrBy(_) | IncrByFloat(_)
+                | Expire(_) | PExpire(_) | ExpireAt(_) | PExpireAt(_) | Persist(_)
+                | Del(_) | Unlink(_) | Rename(_) | RenameNx(_) | Copy(_)
+                | Eval(_) | EvalSha(_)
+        )
+    }
+
+    /// Returns the keys the command operates on.
+    ///
+    /// In cluster mode, the keys must hash to the same slot, which decides
+    /// the node serving the command.
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        use Command::*;
+
+        match self {
+            Get(cmd) => vec![cmd.key()],
+            Set(cmd) => vec![cmd.key()],
+            LPush(cmd) => vec![cmd.key()],
+            RPush(cmd) => vec![cmd.key()],
+            LPop(cmd) => vec![cmd.key()],
+            RPop(cmd) => vec![cmd.key()],
+            LRange(cmd) => vec![cmd.key()],
+            LLen(cmd) => vec![cmd.key()],
+            LIndex(cmd) => vec![cmd.key()],
+            LSet(cmd) => vec![cmd.key()],
+            LTrim(cmd) => vec![cmd.key()],
+            HSet(cmd) => vec![cmd.key()],
+            HGet(cmd) => vec![cmd.key()],
+            HDel(cmd) => vec![cmd.key()],
+            HGetAll(cmd) => vec![cmd.key()],
+            HMGet(cmd) => vec![cmd.key()],
+            HIncrBy(cmd) => vec![cmd.key()],
+            HKeys(cmd) => vec![cmd.key()],
+            HVals(cmd) => vec![cmd.key()],
+            HLen(cmd) => vec![cmd.key()],
+            HExists(cmd) => vec![cmd.key()],
+            SAdd(cmd) => vec![cmd.key()],
+            SRem(cmd) => vec![cmd.key()],
+            SMembers(cmd) => vec![cmd.key()],
+            SIsMember(cmd) => vec![cmd.key()],
+            SCard(cmd) => vec![cmd.key()],
+            ZAdd(cmd) => vec![cmd.key()],
+            ZRange(cmd) => vec![cmd.key()],
+            ZRangeByScore(cmd) => vec![cmd.key()],
+            ZRank(cmd) => vec![cmd.key()],
+            ZIncrBy(cmd) => vec![cmd.key()],
+            ZRem(cmd) => vec![cmd.key()],
+            ZCard(cmd) => vec![cmd.key()],
+            ZPopMin(cmd) => vec![cmd.key()],
+            XAdd(cmd) => vec![cmd.key()],
+            XRange(cmd) => vec![cmd.key()],
+            XRevRange(cmd) => vec![cmd.key()],
+            XLen(cmd) => vec![cmd.key()],
+            XTrim(cmd) => vec![cmd.key()],
+            XGroupCreate(cmd) => vec![cmd.key()],
+            XAck(cmd) => vec![cmd.key()],
+            XPending(cmd) => vec![cmd.key()],
+            XClaim(cmd) => vec![cmd.key()],
+            Incr(cmd) => vec![cmd.key()],
+            Decr(cmd) => vec![cmd.key()],
+            IncrBy(cmd) => vec![cmd.key()],
+            DecrBy(cmd) => vec![cmd.key()],
+            IncrByFloat(cmd) => vec![cmd.key()],
+            Expire(cmd) => vec![cmd.key()],
+            PExpire(cmd) => vec![cmd.key()],
+            ExpireAt(cmd) => vec![cmd.key()],
+            PExpireAt(cmd) => vec![cmd.key()],
+            Ttl(cmd) => vec![cmd.key()],
+            PTtl(cmd) => vec![cmd.key()],
+            Persist(cmd) => vec![cmd.key()],
+            Type(cmd) => vec![cmd.key()],
+            HScan(cmd) => vec![cmd.key()],
+            SScan(cmd) => vec![cmd.key()],
+            ZScan(cmd) => vec![cmd.key()],
+            Del(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Unlink(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Exists(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Touch(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            SInter(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            SUnion(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            SDiff(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Watch(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Eval(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            EvalSha(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            SInterStore(cmd) => keys_with(cmd.destination(), cmd.keys()),
+            SUnionStore(cmd) => keys_with(cmd.destination(), cmd.keys()),
+            SDiffStore(cmd) => keys_with(cmd.destination(), cmd.keys()),
+            Rename(cmd) => vec![cmd.key(), cmd.new_key()],
+            RenameNx(cmd) => vec![cmd.key(), cmd.new_key()],
+            Copy(cmd) => vec![cmd.source(), cmd.destination()],
+            XRead(cmd) => cmd.keys().collect(),
+            XReadGroup(cmd) => cmd.keys().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the commands reproducing the write made by applying the
+    /// command, given its reply.
+    ///
+    /// Most commands reproduce their own write. Those depending on when they
+    /// are applied are rewritten: relative expirations become absolute, and
+    /// generated stream identifiers explicit. Scripts and `XREADGROUP`
+    /// propagate their writes themselves and return nothing.
+    pub(crate) fn propagated(self, reply: &Frame) -> Vec<Frame> {
+        use Command::*;
+
+        match self {
+            Set(cmd) => match cmd.expire() {
+                Some(expire) => {
+                    let expire = self::PExpireAt::after(cmd.key(), expire);
+                    let set = self::Set::new(cmd.key(), cmd.value().clone(), None);
+                    vec![set.into_frame(), expire.into_frame()]
+                }
+                None => vec![cmd.into_frame()],
+            },
+            LPush(cmd) => vec![cmd.into_frame()],
+            RPush(cmd) => vec![cmd.into_frame()],
+            LPop(cmd) => vec![cmd.into_frame()],
+            RPop(cmd) => vec![cmd.into_frame()],
+            LSet(cmd) => vec![cmd.into_frame()],
+            LTrim(cmd) => vec![cmd.into_frame()],
+            HSet(cmd) => vec![cmd.into_frame()],
+            HDel(cmd) => vec![cmd.into_frame()],
+            HIncrBy(cmd) => vec![cmd.into_frame()],
+            SAdd(cmd) => vec![cmd.into_frame()],
+            SRem(cmd) => vec![cmd.into_frame()],
+            SInterStore(cmd) => vec![cmd.into_frame()],
+            SUnionStore(cmd) => vec![cmd.into_frame()],
+            SDiffStore(cmd) => vec![cmd.into_frame()],
+            ZAdd(cmd) => vec![cmd.into_frame()],
+            ZIncrBy(cmd) => vec![cmd.into_frame()],
+            ZRem(cmd) => vec![cmd.into_frame()],
+            ZPopMin(cmd) => vec![cmd.into_frame()],
+            XAdd(cmd) => vec![cmd.with_generated_id(reply).into_frame()],
+            XTrim(cmd) => vec![cmd.into_frame()],
+            XGroupCreate(cmd) => vec![cmd.into_frame()],
+            XAck(cmd) => vec![cmd.into_frame()],
+            XClaim(cmd) => cmd.with_claimed(reply).into_iter().map(self::XClaim::into_frame).collect(),
+            Incr(cmd) => vec![cmd.into_frame()],
+            Decr(cmd) => vec![cmd.into_frame()],
+            IncrBy(cmd) => vec![cmd.into_frame()],
+            DecrBy(cmd) => vec![cmd.into_frame()],
+            IncrByFloat(cmd) => vec![cmd.into_frame()],
+            Expire(cmd) => vec![cmd.into_pexpireat().into_frame()],
+            PExpire(cmd) => vec![cmd.into_pexpireat().into_frame()],
+            ExpireAt(cmd) => vec![cmd.into_frame()],
+            PExpireAt(cmd) => vec![cmd.into_frame()],
+            Persist(cmd) => vec![cmd.into_frame()],
+            Del(cmd) => vec![cmd.into_frame()],
+            Unlink(cmd) => vec![cmd.into_frame()],
+            Rename(cmd) => vec![cmd.into_frame()],
+            RenameNx(cmd) => vec![cmd.into_frame()],
+            Copy(cmd) => vec![cmd.into_frame()],
+            _ => vec![],
+        }
+    }
+
+    /// Returns the pub/sub channels the command operates on.
+    pub(crate) fn channels(&self) -> Vec<&str> {
+        match self {
+            Command::Publish(cmd) => vec![cmd.channel()],
+            Command::Subscribe(cmd) => cmd.channels().iter().map(String::as_str).collect(),
+            _ => vec![],
+        }
+    }
+
     /// Returns the command name
     pub(crate) fn get_name(&self) -> &str {
         match self {
             Command::Get(_) => "get",
-            Command::Publish(_) => "pub",
+            Command::Publish(_) => "publish",
             Command::Set(_) => "set",
             Command::Subscribe(_) => "subscribe",
             Command::Unsubscribe(_) => "unsubscribe",
             Command::Ping(_) => "ping",
+            Command::LPush(_) => "lpush",
+            Command::RPush(_) => "rpush",
+            Command::LPop(_) => "lpop",
+            Command::RPop(_) => "rpop",
+            Command::LRange(_) => "lrange",
+            Command::LLen(_) => "llen",
+            Command::LIndex(_) => "lindex",
+            Command::LSet(_) => "lset",
+            Command::LTrim(_) => "ltrim",
+            Command::HSet(_) => "hset",
+            Command::HGet(_) => "hget",
+            Command::HDel(_) => "hdel",
+            Command::HGetAll(_) => "hgetall",
+            Command::HMGet(_) => "hmget",
+            Command::HIncrBy(_) => "hincrby",
+            Command::HKeys(_) => "hkeys",
+            Command::HVals(_) => "hvals",
+            Command::HLen(_) => "hlen",
+            Command::HExists(_) => "hexists",
+            Command::SAdd(_) => "sadd",
+            Command::SRem(_) => "srem",
+            Command::SMembers(_) => "smembers",
+            Command::SIsMember(_) => "sismember",
+            Command::SCard(_) => "scard",
+            Command::SInter(_) => "sinter",
+            Command::SUnion(_) => "sunion",
+            Command::SDiff(_) => "sdiff",
+            Command::SInterStore(_) => "sinterstore",
+            Command::SUnionStore(_) => "sunionstore",
+            Command::SDiffStore(_) => "sdiffstore",
+            Command::ZAdd(_) => "zadd",
+            Command::ZRange(_) => "zrange",
+            Command::ZRangeByScore(_) => "zrangebyscore",
+            Command::ZRank(_) => "zrank",
+            Command::ZIncrBy(_) => "zincrby",
+            Command::ZRem(_) => "zrem",
+            Command::ZCard(_) => "zcard",
+            Command::ZPopMin(_) => "zpopmin",
+            Command::XAdd(_) => "xadd",
+            Command::XRange(_) => "xrange",
+            Command::XRevRange(_) => "xrevrange",
+            Command::XLen(_) => "xlen",
+            Command::XTrim(_) => "xtrim",
+            Command::XRead(_) => "xread",
+            Command::XGroupCreate(_) => "xgroup",
+            Command::XReadGroup(_) => "xreadgroup",
+            Command::XAck(_) => "xack",
+            Command::XPending(_) => "xpending",
+            Command::XClaim(_) => "xclaim",
+            Command::Incr(_) => "incr",
+            Command::Decr(_) => "decr",
+            Command::IncrBy(_) => "incrby",
+            Command::DecrBy(_) => "decrby",
+            Command::IncrByFloat(_) => "incrbyfloat",
+            Command::Hello(_) => "hello",
+            Command::Expire(_) => "expire",
+            Command::PExpire(_) => "pexpire",
+            Command::ExpireAt(_) => "expireat",
+            Command::PExpireAt(_) => "pexpireat",
+            Command::Ttl(_) => "ttl",
+            Command::PTtl(_) => "pttl",
+            Command::Persist(_) => "persist",
+            Command::Del(_) => "del",
+            Command::Unlink(_) => "unlink",
+            Command::Exists(_) => "exists",
+            Command::Touch(_) => "touch",
+            Command::Type(_) => "type",
+            Command::Rename(_) => "rename",
+            Command::RenameNx(_) => "renamenx",
+            Command::Copy(_) => "copy",
+            Command::Keys(_) => "keys",
+            Command::Scan(_) => "scan",
+            Command::HScan(_) => "hscan",
+            Command::SScan(_) => "sscan",
+            Command::ZScan(_) => "zscan",
+            Command::Multi(_) => "multi",
+            Command::Exec(_) => "exec",
+            Command::Discard(_) => "discard",
+            Command::Watch(_) => "watch",
+            Command::Unwatch(_) => "unwatch",
+            Command::Eval(_) => "eval",
+            Command::EvalSha(_) => "evalsha",
+            Command::Script(_) => "script",
+            Command::Save(_) => "save",
+            Command::BgSave(_) => "bgsave",
+            Command::BgRewriteAof(_) => "bgrewriteaof",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::PSync(_) => "psync",
+            Command::ReplConf(_) => "replconf",
+            Command::Role(_) => "role",
+            Command::Cluster(_) => "cluster",
+            Command::Asking(_) => "asking",
+            Command::Auth(_) => "auth",
+            Command::Acl(_) => "acl",
             Command::Unknown(cmd) => cmd.get_name(),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Returns `first` followed by `keys`.
+fn keys_with<'a>(first: &'a str, keys: &'a [String]) -> Vec<&'a str> {
+    std::iter::once(first)
+        .chain(keys.iter().map(String::as_str))
+        .collect()
+}
@@ -1,13 +1,13 @@
-use crate::{Connection, Frame, Parse, ParseError};
+use crate::{Frame, Parse, ParseError};
 use bytes::Bytes;
-use tracing::{debug, instrument};
+use tracing::instrument;
 
 /// Returns PONG if no argument is provided, otherwise
 /// return a copy of the argument as a bulk.
 ///
 /// This command is often used to test if a connection
 /// is still alive, or to measure latency.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Ping {
     /// optional message to be returned
     msg: Option<Bytes>,
@@ -26,19 +26,12 @@ impl Ping{
         }
     }
 
-    #[instrument(skip(self, dst))]
-    pub(crate) async fn apply(self, dst: &mut Connection)->crate::Result<()>{
-        let response = match self.msg {
+    #[instrument(skip(self))]
+    pub(crate) fn execute(self) -> Frame {
+        match self.msg {
             None => Frame::Simple("PONG".to_string()),
             Some(msg) => Frame::Bulk(msg),
-        };
-
-        debug!(?response);
-
-        // Write the response back to the client
-        dst.write_frame(&response).await?;
-
-        Ok(())
+        }
     }
     
     pub(crate) fn into_frame(self) -> Frame {
@@ -1,9 +1,9 @@
-use crate::{Connection, Db, Frame, Parse};
+use crate::{Db, Frame, Parse};
 
 use bytes::Bytes;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Publish {
     /// Name of the channel on which the message should be published.
     channel: String,
@@ -20,6 +20,12 @@ impl Publish {
             message,
         }
     }
+
+    /// Get the channel
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
     pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Publish> {
         // The `PUBLISH` string has already been consumed. Extract the `channel`
         // and `message` values from the frame.
@@ -33,7 +39,7 @@ impl Publish {
         Ok(Publish { channel, message })
     }
 
-    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+    pub(crate) fn execute(self, db: &Db) -> Frame {
         // The shared state contains the `tokio::sync::broadcast::Sender` for
         // all active channels. Calling `db.publish` dispatches the message into
         // the appropriate channel.
@@ -47,12 +53,7 @@ impl Publish {
 
         // The number of subscribers is returned as the response to the publish
         // request.
-        let response = Frame::Integer(num_subscribers as u64);
-
-        // Write the frame to the client.
-        dst.write_frame(&response).await?;
-
-        Ok(())
+        Frame::Integer(num_subscribers as i64)
     }
 
     pub(crate) fn into_frame(self) -> Frame {
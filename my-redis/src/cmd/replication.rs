@@ -0,0 +1,322 @@
+use crate::replication::{PSyncStart, Role as ServerRole};
+use crate::{Connection, Db, Frame, Parse, Shutdown};
+
+use bytes::Bytes;
+use tokio::select;
+use tracing::{info, instrument};
+
+/// Makes the server follow another one, or stop following with `NO ONE`.
+///
+/// A follower replaces its key space with the one of its leader, then applies
+/// the writes the leader streams to it. `SLAVEOF` is an alias.
+#[derive(Debug, Clone)]
+pub struct ReplicaOf {
+    /// Host and port of the leader, `None` for `NO ONE`.
+    leader: Option<(String, u16)>,
+}
+
+/// Sent by a follower to its leader to start receiving its writes, continuing
+/// from the offset it reached in the history `replid` when possible.
+///
+/// The connection then carries the write stream until closed.
+#[derive(Debug, Clone)]
+pub struct PSync {
+    replid: String,
+    offset: i64,
+}
+
+/// Configures the replication link, sent by a follower to its leader.
+///
+/// `REPLCONF ACK <offset>` acknowledges the offset reached, and is not
+/// replied. Other options are accepted and ignored.
+#[derive(Debug, Clone)]
+pub struct ReplConf {
+    /// The offset acknowledged, for `ACK`.
+    ack: Option<u64>,
+
+    /// The other options, along with their value.
+    options: Vec<(String, String)>,
+}
+
+/// Returns the replication role of the server.
+///
+/// A leader replies `master`, its offset and the identifier and acknowledged
+/// offset of each follower. A follower replies `slave`, the host and port of
+/// its leader, the state of the link and its offset.
+#[derive(Debug, Clone, Default)]
+pub struct Role;
+
+impl ReplicaOf {
+    /// Create a new `ReplicaOf` command following the server at `host:port`.
+    pub fn new(host: impl ToString, port: u16) -> ReplicaOf {
+        ReplicaOf {
+            leader: Some((host.to_string(), port)),
+        }
+    }
+
+    /// Create a new `ReplicaOf` command to stop following, `REPLICAOF NO ONE`.
+    pub fn no_one() -> ReplicaOf {
+        ReplicaOf { leader: None }
+    }
+
+    /// Parse a `ReplicaOf` instance from a received frame.
+    ///
+    /// ```text
+    /// REPLICAOF host port | NO ONE
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ReplicaOf> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?;
+
+        if host.eq_ignore_ascii_case("NO") && port.eq_ignore_ascii_case("ONE") {
+            return Ok(ReplicaOf::no_one());
+        }
+        match port.parse() {
+            Ok(port) => Ok(ReplicaOf::new(host, port)),
+            Err(_) => Err("ERR Invalid master port".into()),
+        }
+    }
+
+    /// Apply the `ReplicaOf` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        db.replicaof(self.leader);
+        Frame::Simple("OK".to_string())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("replicaof".as_bytes()));
+        match self.leader {
+            Some((host, port)) => {
+                frame.push_bulk(Bytes::from(host.into_bytes()));
+                frame.push_bulk(Bytes::from(port.to_string().into_bytes()));
+            }
+            None => {
+                frame.push_bulk(Bytes::from("no".as_bytes()));
+                frame.push_bulk(Bytes::from("one".as_bytes()));
+            }
+        }
+        frame
+    }
+}
+
+impl PSync {
+    /// Create a new `PSync` command. `offset` is the first byte of the write
+    /// stream wanted, one past the offset reached.
+    pub fn new(replid: impl ToString, offset: i64) -> PSync {
+        PSync {
+            replid: replid.to_string(),
+            offset,
+        }
+    }
+
+    /// Parse a `PSync` instance from a received frame.
+    ///
+    /// ```text
+    /// PSYNC replicationid offset
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<PSync> {
+        let replid = parse.next_string()?;
+        let offset = parse.next_int()?;
+
+        Ok(PSync { replid, offset })
+    }
+
+    /// Apply the `PSync` command to the specified `Db` instance.
+    ///
+    /// Replies `+CONTINUE <replid>` followed by the writes the follower
+    /// missed, or `+FULLRESYNC <replid> <offset>` followed by a snapshot of
+    /// the key space as a bulk string. The writes are then streamed until
+    /// the follower disconnects, falls too far behind, or the server shuts
+    /// down.
+    #[instrument(skip(self, db, dst, shutdown))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        let (start, mut stream) = db.psync(&self.replid, self.offset).await?;
+
+        match start {
+            PSyncStart::Continue { replid, backlog } => {
+                info!(offset = self.offset, "follower continues from the backlog");
+                dst.write_frame(&Frame::Simple(format!("CONTINUE {}", replid)))
+                    .await?;
+                dst.write_raw(&backlog).await?;
+            }
+            PSyncStart::FullResync {
+                replid,
+                offset,
+                snapshot,
+            } => {
+                info!(offset, "full resynchronization of a follower");
+                dst.write_frame(&Frame::Simple(format!("FULLRESYNC {} {}", replid, offset)))
+                    .await?;
+                dst.write_frame(&Frame::Bulk(Bytes::from(snapshot))).await?;
+            }
+        }
+
+        loop {
+            select! {
+                data = stream.recv() => match data {
+                    Some(data) => dst.write_raw(&data).await?,
+                    None => return Ok(()),
+                },
+                frame = dst.read_frame() => match frame? {
+                    Some(frame) => {
+                        if let Ok(ack) = ReplConf::parse_ack(frame) {
+                            db.running_script().completed().await;
+                            stream.ack(ack);
+                        }
+                    }
+                    None => return Ok(()),
+                },
+                _ = shutdown.recv() => return Ok(()),
+            }
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("psync".as_bytes()));
+        frame.push_bulk(Bytes::from(self.replid.into_bytes()));
+        frame.push_int(self.offset);
+        frame
+    }
+}
+
+impl ReplConf {
+    /// Create a new `ReplConf` command acknowledging `offset`.
+    pub fn ack(offset: u64) -> ReplConf {
+        ReplConf {
+            ack: Some(offset),
+            options: vec![],
+        }
+    }
+
+    /// Parse a `ReplConf` instance from a received frame.
+    ///
+    /// ```text
+    /// REPLCONF option value [option value ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ReplConf> {
+        let mut ack = None;
+        let mut options = vec![];
+
+        let args = parse.remaining_strings()?;
+        if args.is_empty() || args.len() % 2 != 0 {
+            return Err("ERR syntax error".into());
+        }
+        for pair in args.chunks(2) {
+            if pair[0].eq_ignore_ascii_case("ACK") {
+                ack = Some(pair[1].parse().map_err(|_| "ERR invalid offset")?);
+            } else {
+                options.push((pair[0].clone(), pair[1].clone()));
+            }
+        }
+
+        Ok(ReplConf { ack, options })
+    }
+
+    /// Returns the offset acknowledged by `frame`, if it holds
+    /// `REPLCONF ACK`.
+    fn parse_ack(frame: Frame) -> crate::Result<u64> {
+        let mut parse = Parse::new(frame)?;
+        if !parse.next_string()?.eq_ignore_ascii_case("replconf") {
+            return Err("not REPLCONF".into());
+        }
+        ReplConf::parse_frames(&mut parse)?
+            .ack
+            .ok_or_else(|| "not REPLCONF ACK".into())
+    }
+
+    /// Apply the `ReplConf` command.
+    ///
+    /// Acknowledgements are only meaningful on the connection streaming the
+    /// writes, see `PSync::apply`, and are ignored elsewhere without reply.
+    /// `None` is returned for them.
+    #[instrument(skip(self))]
+    pub(crate) fn execute(self) -> Option<Frame> {
+        match self.ack {
+            Some(_) => None,
+            None => Some(Frame::Simple("OK".to_string())),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("replconf".as_bytes()));
+        for (option, value) in self.options {
+            frame.push_bulk(Bytes::from(option.into_bytes()));
+            frame.push_bulk(Bytes::from(value.into_bytes()));
+        }
+        if let Some(ack) = self.ack {
+            frame.push_bulk(Bytes::from("ack".as_bytes()));
+            frame.push_bulk(Bytes::from(ack.to_string().into_bytes()));
+        }
+        frame
+    }
+}
+
+impl Role {
+    /// Create a new `Role` command.
+    pub fn new() -> Role {
+        Role
+    }
+
+    /// Parse a `Role` instance from a received frame.
+    ///
+    /// ```text
+    /// ROLE
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Role> {
+        Ok(Role)
+    }
+
+    /// Apply the `Role` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.role() {
+            ServerRole::Leader { offset, replicas } => {
+                let followers = replicas
+                    .into_iter()
+                    .map(|(id, acked)| {
+                        Frame::Array(vec![
+                            Frame::Integer(id as i64),
+                            Frame::Integer(acked as i64),
+                        ])
+                    })
+                    .collect();
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from("master".as_bytes())),
+                    Frame::Integer(offset as i64),
+                    Frame::Array(followers),
+                ])
+            }
+            ServerRole::Follower {
+                host,
+                port,
+                state,
+                offset,
+            } => Frame::Array(vec![
+                Frame::Bulk(Bytes::from("slave".as_bytes())),
+                Frame::Bulk(Bytes::from(host.into_bytes())),
+                Frame::Integer(port as i64),
+                Frame::Bulk(Bytes::from(state.as_str().as_bytes())),
+                Frame::Integer(offset as i64),
+            ]),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("role".as_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{Db, Frame, Parse};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Writes a snapshot of the key space to the RDB file.
+///
+/// The snapshot is taken synchronously: no other client is served until the
+/// file is written. Replies `OK` once done.
+#[derive(Debug, Clone, Default)]
+pub struct Save;
+
+/// Writes a snapshot of the key space to the RDB file in the background.
+///
+/// Replies as soon as the key space is copied, other clients being served
+/// while the snapshot is written. Copying the key space still holds them up
+/// for a time growing with its size.
+#[derive(Debug, Clone, Default)]
+pub struct BgSave;
+
+/// Rewrites the append only file in the background, compacting it to the
+/// current key space.
+///
+/// Replies as soon as the rewrite is started. Writes applied meanwhile are
+/// still logged, and kept in the rewritten file.
+#[derive(Debug, Clone, Default)]
+pub struct BgRewriteAof;
+
+impl Save {
+    /// Create a new `Save` command.
+    pub fn new() -> Save {
+        Save
+    }
+
+    /// Parse a `Save` instance from a received frame.
+    ///
+    /// ```text
+    /// SAVE
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Save> {
+        Ok(Save)
+    }
+
+    /// Apply the `Save` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.save() {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("save".as_bytes()));
+        frame
+    }
+}
+
+impl BgSave {
+    /// Create a new `BgSave` command.
+    pub fn new() -> BgSave {
+        BgSave
+    }
+
+    /// Parse a `BgSave` instance from a received frame.
+    ///
+    /// ```text
+    /// BGSAVE
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<BgSave> {
+        Ok(BgSave)
+    }
+
+    /// Apply the `BgSave` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.bgsave() {
+            Ok(()) => Frame::Simple("Background saving started".to_string()),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("bgsave".as_bytes()));
+        frame
+    }
+}
+
+impl BgRewriteAof {
+    /// Create a new `BgRewriteAof` command.
+    pub fn new() -> BgRewriteAof {
+        BgRewriteAof
+    }
+
+    /// Parse a `BgRewriteAof` instance from a received frame.
+    ///
+    /// ```text
+    /// BGREWRITEAOF
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<BgRewriteAof> {
+        Ok(BgRewriteAof)
+    }
+
+    /// Apply the `BgRewriteAof` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.bgrewriteaof() {
+            Ok(()) => Frame::Simple("Background append only file rewriting started".to_string()),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("bgrewriteaof".as_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,370 @@
+use crate::db::DbError;
+use crate::{Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Number of elements a page holds when `COUNT` is not given.
+const DEFAULT_COUNT: u64 = 10;
+
+/// Incrementally iterate over the keys.
+///
+/// A call replies with the cursor to pass to the next call and a page of keys.
+/// The iteration starts and ends with the cursor `0`. A key present during the
+/// whole iteration is returned at least once.
+///
+/// `COUNT` hints at the number of keys to visit, `MATCH` and `TYPE` filter the
+/// visited keys. A page may therefore be empty before the end of the
+/// iteration.
+#[derive(Debug, Clone)]
+pub struct Scan {
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: Option<u64>,
+    type_name: Option<String>,
+}
+
+/// Incrementally iterate over the fields and values of a hash. Works like
+/// `SCAN`, the page alternating fields and values.
+#[derive(Debug, Clone)]
+pub struct HScan {
+    key: String,
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: Option<u64>,
+}
+
+/// Incrementally iterate over the members of a set. Works like `SCAN`.
+#[derive(Debug, Clone)]
+pub struct SScan {
+    key: String,
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: Option<u64>,
+}
+
+/// Incrementally iterate over the members of a sorted set. Works like `SCAN`,
+/// each member being followed by its score.
+#[derive(Debug, Clone)]
+pub struct ZScan {
+    key: String,
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: Option<u64>,
+}
+
+impl Scan {
+    /// Create a new `Scan` command resuming the iteration at `cursor`.
+    pub fn new(
+        cursor: u64,
+        pattern: Option<Bytes>,
+        count: Option<u64>,
+        type_name: Option<String>,
+    ) -> Scan {
+        Scan {
+            cursor,
+            pattern,
+            count,
+            type_name,
+        }
+    }
+
+    /// Parse a `Scan` instance from a received frame.
+    ///
+    /// ```text
+    /// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Scan> {
+        let cursor = parse.next_uint()?;
+        let mut scan = Scan::new(cursor, None, None, None);
+
+        loop {
+            match parse.next_string() {
+                Ok(s) if s.to_uppercase() == "TYPE" => scan.type_name = Some(parse.next_string()?),
+                Ok(s) => parse_option(parse, &s, &mut scan.pattern, &mut scan.count)?,
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(scan)
+    }
+
+    /// Apply the `Scan` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let (cursor, keys) = db.scan(
+            self.cursor,
+            self.count.unwrap_or(DEFAULT_COUNT) as usize,
+            self.pattern.as_deref(),
+            self.type_name.as_deref(),
+        );
+
+        let mut page = Frame::array();
+        for key in keys {
+            page.push_bulk(Bytes::from(key));
+        }
+        page_frame(cursor, page)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("scan".as_bytes()));
+        frame.push_bulk(Bytes::from(self.cursor.to_string()));
+        push_options(&mut frame, self.pattern, self.count);
+        if let Some(type_name) = self.type_name {
+            frame.push_bulk(Bytes::from_static(b"TYPE"));
+            frame.push_bulk(Bytes::from(type_name));
+        }
+        frame
+    }
+}
+
+impl HScan {
+    /// Create a new `HScan` command resuming the iteration of the hash stored
+    /// at `key` at `cursor`.
+    pub fn new(
+        key: impl ToString,
+        cursor: u64,
+        pattern: Option<Bytes>,
+        count: Option<u64>,
+    ) -> HScan {
+        HScan {
+            key: key.to_string(),
+            cursor,
+            pattern,
+            count,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `HScan` instance from a received frame.
+    ///
+    /// ```text
+    /// HSCAN key cursor [MATCH pattern] [COUNT count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HScan> {
+        let key = parse.next_string()?;
+        let cursor = parse.next_uint()?;
+        let (pattern, count) = parse_options(parse)?;
+
+        Ok(HScan::new(key, cursor, pattern, count))
+    }
+
+    /// Apply the `HScan` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let count = self.count.unwrap_or(DEFAULT_COUNT) as usize;
+        let result = db.hscan(&self.key, self.cursor, count, self.pattern.as_deref());
+
+        scan_frame(result, |page, fields| {
+            for (field, value) in fields {
+                page.push_bulk(field);
+                page.push_bulk(value);
+            }
+        })
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hscan".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.cursor.to_string()));
+        push_options(&mut frame, self.pattern, self.count);
+        frame
+    }
+}
+
+impl SScan {
+    /// Create a new `SScan` command resuming the iteration of the set stored
+    /// at `key` at `cursor`.
+    pub fn new(
+        key: impl ToString,
+        cursor: u64,
+        pattern: Option<Bytes>,
+        count: Option<u64>,
+    ) -> SScan {
+        SScan {
+            key: key.to_string(),
+            cursor,
+            pattern,
+            count,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `SScan` instance from a received frame.
+    ///
+    /// ```text
+    /// SSCAN key cursor [MATCH pattern] [COUNT count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SScan> {
+        let key = parse.next_string()?;
+        let cursor = parse.next_uint()?;
+        let (pattern, count) = parse_options(parse)?;
+
+        Ok(SScan::new(key, cursor, pattern, count))
+    }
+
+    /// Apply the `SScan` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let count = self.count.unwrap_or(DEFAULT_COUNT) as usize;
+        let result = db.sscan(&self.key, self.cursor, count, self.pattern.as_deref());
+
+        scan_frame(result, |page, members| {
+            for member in members {
+                page.push_bulk(member);
+            }
+        })
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("sscan".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.cursor.to_string()));
+        push_options(&mut frame, self.pattern, self.count);
+        frame
+    }
+}
+
+impl ZScan {
+    /// Create a new `ZScan` command resuming the iteration of the sorted set
+    /// stored at `key` at `cursor`.
+    pub fn new(
+        key: impl ToString,
+        cursor: u64,
+        pattern: Option<Bytes>,
+        count: Option<u64>,
+    ) -> ZScan {
+        ZScan {
+            key: key.to_string(),
+            cursor,
+            pattern,
+            count,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZScan` instance from a received frame.
+    ///
+    /// ```text
+    /// ZSCAN key cursor [MATCH pattern] [COUNT count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZScan> {
+        let key = parse.next_string()?;
+        let cursor = parse.next_uint()?;
+        let (pattern, count) = parse_options(parse)?;
+
+        Ok(ZScan::new(key, cursor, pattern, count))
+    }
+
+    /// Apply the `ZScan` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let count = self.count.unwrap_or(DEFAULT_COUNT) as usize;
+        let result = db.zscan(&self.key, self.cursor, count, self.pattern.as_deref());
+
+        scan_frame(result, |page, members| {
+            for (member, score) in members {
+                page.push_bulk(member);
+                page.push_bulk(Bytes::from(score.to_string()));
+            }
+        })
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zscan".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.cursor.to_string()));
+        push_options(&mut frame, self.pattern, self.count);
+        frame
+    }
+}
+
+/// Parse the `MATCH` and `COUNT` options shared by all scan commands.
+fn parse_options(parse: &mut Parse) -> crate::Result<(Option<Bytes>, Option<u64>)> {
+    let mut pattern = None;
+    let mut count = None;
+
+    loop {
+        match parse.next_string() {
+            Ok(s) => parse_option(parse, &s, &mut pattern, &mut count)?,
+            Err(ParseError::EndOfStream) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok((pattern, count))
+}
+
+/// Parse the value of the `option` just read, which must be `MATCH` or
+/// `COUNT`.
+fn parse_option(
+    parse: &mut Parse,
+    option: &str,
+    pattern: &mut Option<Bytes>,
+    count: &mut Option<u64>,
+) -> crate::Result<()> {
+    match &option.to_uppercase()[..] {
+        "MATCH" => *pattern = Some(parse.next_bytes()?),
+        "COUNT" => match parse.next_uint()? {
+            0 => return Err("ERR syntax error".into()),
+            value => *count = Some(value),
+        },
+        _ => return Err("ERR syntax error".into()),
+    }
+
+    Ok(())
+}
+
+fn push_options(frame: &mut Frame, pattern: Option<Bytes>, count: Option<u64>) {
+    if let Some(pattern) = pattern {
+        frame.push_bulk(Bytes::from_static(b"MATCH"));
+        frame.push_bulk(pattern);
+    }
+    if let Some(count) = count {
+        frame.push_bulk(Bytes::from_static(b"COUNT"));
+        frame.push_bulk(Bytes::from(count.to_string()));
+    }
+}
+
+/// Builds the reply to a scan command: the next cursor, as a bulk string, and
+/// the page.
+fn page_frame(cursor: u64, page: Frame) -> Frame {
+    Frame::Array(vec![Frame::Bulk(Bytes::from(cursor.to_string())), page])
+}
+
+/// Builds the reply to the scan commands iterating over a key, `fill`
+/// encoding the elements of the page.
+fn scan_frame<T>(
+    result: Result<(u64, Vec<T>), DbError>,
+    fill: impl FnOnce(&mut Frame, Vec<T>),
+) -> Frame {
+    match result {
+        Ok((cursor, elements)) => {
+            let mut page = Frame::array();
+            fill(&mut page, elements);
+            page_frame(cursor, page)
+        }
+        Err(err) => Frame::Error(err.to_string()),
+    }
+}
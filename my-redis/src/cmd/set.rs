@@ -1,12 +1,12 @@
 use crate::cmd::{Parse, ParseError};
-use crate::{Connection, Db, Frame};
+use crate::{Db, Frame};
 
 use bytes::Bytes;
 use std::time::Duration;
-use tracing::{debug, instrument};
+use tracing::instrument;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Set{
     key: String,
     value: Bytes,
@@ -42,13 +42,13 @@ impl Set{
 
         match parse.next_string(){
             Ok(s) if s.to_uppercase() == "EX" => {
-                let secs = parse.next_int()?;
+                let secs = parse.next_uint()?;
                 expire = Some(Duration::from_secs(secs));
             }
             Ok(s) if s.to_uppercase() == "PX" => {
                 // An expiration is specified in milliseconds. The next value is
                 // an integer.
-                let ms = parse.next_int()?;
+                let ms = parse.next_uint()?;
                 expire = Some(Duration::from_millis(ms));
             }
             // Currently, mini-redis does not support any of the other SET
@@ -67,14 +67,11 @@ impl Set{
         Ok(Set{key, value, expire})
     }
 
-    #[instrument(skip(self, db, dst))]
-    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
         db.set(self.key, self.value, self.expire);
         
-        let response = Frame::Simple("OK".to_string());
-        debug!(?response);
-        dst.write_frame(&response).await?;
-        Ok(())
+        Frame::Simple("OK".to_string())
     }
 
 
@@ -95,7 +92,7 @@ impl Set{
             // src/bin/cli.rs parses the expiration argument as milliseconds
             // in duration_from_ms_str()
             frame.push_bulk(Bytes::from("px".as_bytes()));
-            frame.push_int(ms.as_millis() as u64);
+            frame.push_int(ms.as_millis() as i64);
         }
         frame
     }
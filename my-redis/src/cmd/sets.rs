@@ -0,0 +1,583 @@
+use crate::cmd::Parse;
+use crate::db::{DbError, SetOp};
+use crate::{Db, Frame};
+
+use bytes::Bytes;
+use std::collections::HashSet;
+use tracing::instrument;
+
+/// Add the specified members to the set stored at key.
+///
+/// Members already in the set are ignored. If key does not exist, a new set is
+/// created. Replies with the number of members that were added.
+#[derive(Debug, Clone)]
+pub struct SAdd {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+/// Remove the specified members from the set stored at key.
+///
+/// Replies with the number of members that were removed.
+#[derive(Debug, Clone)]
+pub struct SRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+/// Returns all the members of the set stored at key.
+#[derive(Debug, Clone)]
+pub struct SMembers {
+    key: String,
+}
+
+/// Returns if member is a member of the set stored at key.
+#[derive(Debug, Clone)]
+pub struct SIsMember {
+    key: String,
+    member: Bytes,
+}
+
+/// Returns the number of members of the set stored at key.
+#[derive(Debug, Clone)]
+pub struct SCard {
+    key: String,
+}
+
+/// Returns the members of the set resulting from the intersection of all the
+/// given sets.
+#[derive(Debug, Clone)]
+pub struct SInter {
+    keys: Vec<String>,
+}
+
+/// Returns the members of the set resulting from the union of all the given
+/// sets.
+#[derive(Debug, Clone)]
+pub struct SUnion {
+    keys: Vec<String>,
+}
+
+/// Returns the members of the set resulting from the difference between the
+/// first set and all the successive sets.
+#[derive(Debug, Clone)]
+pub struct SDiff {
+    keys: Vec<String>,
+}
+
+/// Like `SINTER`, but the result is stored in destination.
+///
+/// Replies with the number of members in the resulting set.
+#[derive(Debug, Clone)]
+pub struct SInterStore {
+    destination: String,
+    keys: Vec<String>,
+}
+
+/// Like `SUNION`, but the result is stored in destination.
+///
+/// Replies with the number of members in the resulting set.
+#[derive(Debug, Clone)]
+pub struct SUnionStore {
+    destination: String,
+    keys: Vec<String>,
+}
+
+/// Like `SDIFF`, but the result is stored in destination.
+///
+/// Replies with the number of members in the resulting set.
+#[derive(Debug, Clone)]
+pub struct SDiffStore {
+    destination: String,
+    keys: Vec<String>,
+}
+
+impl SAdd {
+    /// Create a new `SAdd` command adding `members`.
+    pub fn new(key: impl ToString, members: Vec<Bytes>) -> SAdd {
+        SAdd {
+            key: key.to_string(),
+            members,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `SAdd` instance from a received frame.
+    ///
+    /// ```text
+    /// SADD key member [member ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SAdd> {
+        let key = parse.next_string()?;
+        let members = parse.remaining_bytes()?;
+
+        Ok(SAdd { key, members })
+    }
+
+    /// Apply the `SAdd` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.sadd(self.key, self.members) {
+            Ok(count) => Frame::Integer(count as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("sadd".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+}
+
+impl SRem {
+    /// Create a new `SRem` command removing `members`.
+    pub fn new(key: impl ToString, members: Vec<Bytes>) -> SRem {
+        SRem {
+            key: key.to_string(),
+            members,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `SRem` instance from a received frame.
+    ///
+    /// ```text
+    /// SREM key member [member ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SRem> {
+        let key = parse.next_string()?;
+        let members = parse.remaining_bytes()?;
+
+        Ok(SRem { key, members })
+    }
+
+    /// Apply the `SRem` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.srem(&self.key, &self.members) {
+            Ok(count) => Frame::Integer(count as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("srem".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+}
+
+impl SMembers {
+    /// Create a new `SMembers` command for `key`.
+    pub fn new(key: impl ToString) -> SMembers {
+        SMembers {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `SMembers` instance from a received frame.
+    ///
+    /// ```text
+    /// SMEMBERS key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SMembers> {
+        let key = parse.next_string()?;
+
+        Ok(SMembers { key })
+    }
+
+    /// Apply the `SMembers` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        members_frame(db.smembers(&self.key))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("smembers".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl SIsMember {
+    /// Create a new `SIsMember` command checking `member` in the set at `key`.
+    pub fn new(key: impl ToString, member: Bytes) -> SIsMember {
+        SIsMember {
+            key: key.to_string(),
+            member,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `SIsMember` instance from a received frame.
+    ///
+    /// ```text
+    /// SISMEMBER key member
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SIsMember> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+
+        Ok(SIsMember { key, member })
+    }
+
+    /// Apply the `SIsMember` command to the specified `Db` instance.
+    ///
+    /// Replies `1` if the member belongs to the set, `0` otherwise.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.sismember(&self.key, &self.member) {
+            Ok(found) => Frame::Integer(found as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("sismember".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.member);
+        frame
+    }
+}
+
+impl SCard {
+    /// Create a new `SCard` command for `key`.
+    pub fn new(key: impl ToString) -> SCard {
+        SCard {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `SCard` instance from a received frame.
+    ///
+    /// ```text
+    /// SCARD key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SCard> {
+        let key = parse.next_string()?;
+
+        Ok(SCard { key })
+    }
+
+    /// Apply the `SCard` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.scard(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("scard".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl SInter {
+    /// Create a new `SInter` command combining the sets stored at `keys`.
+    pub fn new(keys: Vec<String>) -> SInter {
+        SInter { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `SInter` instance from a received frame.
+    ///
+    /// ```text
+    /// SINTER key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SInter> {
+        let keys = parse.remaining_strings()?;
+
+        Ok(SInter { keys })
+    }
+
+    /// Apply the `SInter` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        members_frame(db.set_combine(&self.keys, SetOp::Inter))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("sinter", None, self.keys)
+    }
+}
+
+impl SUnion {
+    /// Create a new `SUnion` command combining the sets stored at `keys`.
+    pub fn new(keys: Vec<String>) -> SUnion {
+        SUnion { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `SUnion` instance from a received frame.
+    ///
+    /// ```text
+    /// SUNION key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SUnion> {
+        let keys = parse.remaining_strings()?;
+
+        Ok(SUnion { keys })
+    }
+
+    /// Apply the `SUnion` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        members_frame(db.set_combine(&self.keys, SetOp::Union))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("sunion", None, self.keys)
+    }
+}
+
+impl SDiff {
+    /// Create a new `SDiff` command combining the sets stored at `keys`.
+    pub fn new(keys: Vec<String>) -> SDiff {
+        SDiff { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `SDiff` instance from a received frame.
+    ///
+    /// ```text
+    /// SDIFF key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SDiff> {
+        let keys = parse.remaining_strings()?;
+
+        Ok(SDiff { keys })
+    }
+
+    /// Apply the `SDiff` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        members_frame(db.set_combine(&self.keys, SetOp::Diff))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("sdiff", None, self.keys)
+    }
+}
+
+impl SInterStore {
+    /// Create a new `SInterStore` command storing the combination of the sets at
+    /// `keys` into `destination`.
+    pub fn new(destination: impl ToString, keys: Vec<String>) -> SInterStore {
+        SInterStore {
+            destination: destination.to_string(),
+            keys,
+        }
+    }
+
+    /// Get the destination key
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `SInterStore` instance from a received frame.
+    ///
+    /// ```text
+    /// SINTERSTORE destination key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SInterStore> {
+        let destination = parse.next_string()?;
+        let keys = parse.remaining_strings()?;
+
+        Ok(SInterStore { destination, keys })
+    }
+
+    /// Apply the `SInterStore` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.set_combine_store(self.destination, &self.keys, SetOp::Inter) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("sinterstore", Some(self.destination), self.keys)
+    }
+}
+
+impl SUnionStore {
+    /// Create a new `SUnionStore` command storing the combination of the sets at
+    /// `keys` into `destination`.
+    pub fn new(destination: impl ToString, keys: Vec<String>) -> SUnionStore {
+        SUnionStore {
+            destination: destination.to_string(),
+            keys,
+        }
+    }
+
+    /// Get the destination key
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `SUnionStore` instance from a received frame.
+    ///
+    /// ```text
+    /// SUNIONSTORE destination key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SUnionStore> {
+        let destination = parse.next_string()?;
+        let keys = parse.remaining_strings()?;
+
+        Ok(SUnionStore { destination, keys })
+    }
+
+    /// Apply the `SUnionStore` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.set_combine_store(self.destination, &self.keys, SetOp::Union) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("sunionstore", Some(self.destination), self.keys)
+    }
+}
+
+impl SDiffStore {
+    /// Create a new `SDiffStore` command storing the combination of the sets at
+    /// `keys` into `destination`.
+    pub fn new(destination: impl ToString, keys: Vec<String>) -> SDiffStore {
+        SDiffStore {
+            destination: destination.to_string(),
+            keys,
+        }
+    }
+
+    /// Get the destination key
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `SDiffStore` instance from a received frame.
+    ///
+    /// ```text
+    /// SDIFFSTORE destination key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SDiffStore> {
+        let destination = parse.next_string()?;
+        let keys = parse.remaining_strings()?;
+
+        Ok(SDiffStore { destination, keys })
+    }
+
+    /// Apply the `SDiffStore` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.set_combine_store(self.destination, &self.keys, SetOp::Diff) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        keys_frame("sdiffstore", Some(self.destination), self.keys)
+    }
+}
+
+/// Builds the reply to the commands returning set members, a RESP3 set.
+fn members_frame(members: Result<HashSet<Bytes>, DbError>) -> Frame {
+    match members {
+        Ok(members) => {
+            let mut response = Frame::Set(vec![]);
+            for member in members {
+                response.push_bulk(member);
+            }
+            response
+        }
+        Err(err) => Frame::Error(err.to_string()),
+    }
+}
+
+/// Encodes the multi-key set commands, with an optional leading destination
+/// key for the `STORE` variants.
+fn keys_frame(name: &'static str, destination: Option<String>, keys: Vec<String>) -> Frame {
+    let mut frame = Frame::array();
+    frame.push_bulk(Bytes::from(name.as_bytes()));
+    for key in destination.into_iter().chain(keys) {
+        frame.push_bulk(Bytes::from(key.into_bytes()));
+    }
+    frame
+}
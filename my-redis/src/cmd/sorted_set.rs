@@ -0,0 +1,760 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::{DbError, LexBound, RangeBy, ScoreBound, ZAddOptions};
+use crate::{Db, Frame};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Adds all the specified members with the specified scores to the sorted set
+/// stored at key.
+///
+/// The `NX`, `XX`, `GT` and `LT` options restrict which members are added or
+/// updated. `CH` makes the reply count changed members too, and `INCR` turns
+/// the command into `ZINCRBY`.
+#[derive(Debug, Clone)]
+pub struct ZAdd {
+    key: String,
+    options: ZAddOptions,
+    members: Vec<(f64, Bytes)>,
+}
+
+/// Returns the specified range of members in the sorted set stored at key.
+///
+/// Ranges are by rank unless `BYSCORE` or `BYLEX` is given. `REV` reverses the
+/// ordering, `LIMIT` pages through score and lex ranges and `WITHSCORES`
+/// includes the scores in the reply.
+#[derive(Debug, Clone)]
+pub struct ZRange {
+    key: String,
+    by: RangeBy,
+    rev: bool,
+    limit: Option<(u64, i64)>,
+    with_scores: bool,
+}
+
+/// Returns all the members in the sorted set at key with a score between min
+/// and max, ordered from low to high scores.
+#[derive(Debug, Clone)]
+pub struct ZRangeByScore {
+    key: String,
+    min: ScoreBound,
+    max: ScoreBound,
+    limit: Option<(u64, i64)>,
+    with_scores: bool,
+}
+
+/// Returns the rank of member in the sorted set stored at key, with the scores
+/// ordered from low to high.
+#[derive(Debug, Clone)]
+pub struct ZRank {
+    key: String,
+    member: Bytes,
+}
+
+/// Increments the score of member in the sorted set stored at key by
+/// increment. Replies with the new score.
+#[derive(Debug, Clone)]
+pub struct ZIncrBy {
+    key: String,
+    increment: f64,
+    member: Bytes,
+}
+
+/// Removes the specified members from the sorted set stored at key.
+#[derive(Debug, Clone)]
+pub struct ZRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+/// Returns the number of members of the sorted set stored at key.
+#[derive(Debug, Clone)]
+pub struct ZCard {
+    key: String,
+}
+
+/// Removes and returns up to count members with the lowest scores in the
+/// sorted set stored at key.
+#[derive(Debug, Clone)]
+pub struct ZPopMin {
+    key: String,
+    count: Option<u64>,
+}
+
+impl ZAdd {
+    /// Create a new `ZAdd` command adding `members`, given as `(score,
+    /// member)` pairs, to the sorted set at `key`.
+    pub fn new(key: impl ToString, members: Vec<(f64, Bytes)>) -> ZAdd {
+        ZAdd {
+            key: key.to_string(),
+            options: ZAddOptions::default(),
+            members,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZAdd` instance from a received frame.
+    ///
+    /// ```text
+    /// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZAdd> {
+        let key = parse.next_string()?;
+        let mut options = ZAddOptions::default();
+
+        // Options come first. The first argument which is not an option is the
+        // score of the first member.
+        let first = loop {
+            let arg = parse.next_bytes()?;
+            match &arg.to_ascii_uppercase()[..] {
+                b"NX" => options.nx = true,
+                b"XX" => options.xx = true,
+                b"GT" => options.gt = true,
+                b"LT" => options.lt = true,
+                b"CH" => options.ch = true,
+                b"INCR" => options.incr = true,
+                _ => break arg,
+            }
+        };
+
+        if options.nx && options.xx {
+            return Err("ERR XX and NX options at the same time are not compatible".into());
+        }
+        if (options.gt && options.lt) || (options.nx && (options.gt || options.lt)) {
+            return Err("ERR GT, LT, and/or NX options at the same time are not compatible".into());
+        }
+
+        let mut args = vec![first];
+        args.extend(parse.remaining_bytes()?);
+
+        if args.len() % 2 != 0 {
+            return Err("protocol error; `ZADD` expects score member pairs".into());
+        }
+        if options.incr && args.len() != 2 {
+            return Err("ERR INCR option supports a single increment-element pair".into());
+        }
+
+        let members = args
+            .chunks_exact(2)
+            .map(|pair| Ok((parse_score(&pair[0])?, pair[1].clone())))
+            .collect::<crate::Result<_>>()?;
+
+        Ok(ZAdd {
+            key,
+            options,
+            members,
+        })
+    }
+
+    /// Apply the `ZAdd` command to the specified `Db` instance.
+    ///
+    /// With `INCR` the new score is replied, or nil when the options prevented
+    /// the update. Otherwise the number of added (or changed) members is
+    /// replied.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        if self.options.incr {
+            let (increment, member) = self.members.into_iter().next().unwrap();
+            match db.zincrby(self.key, self.options, member, increment) {
+                Ok(Some(score)) => Frame::Double(score),
+                Ok(None) => Frame::Null,
+                Err(err) => Frame::Error(err.to_string()),
+            }
+        } else {
+            match db.zadd(self.key, self.options, self.members) {
+                Ok(count) => Frame::Integer(count as i64),
+                Err(err) => Frame::Error(err.to_string()),
+            }
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zadd".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+
+        let flags = [
+            (self.options.nx, "nx"),
+            (self.options.xx, "xx"),
+            (self.options.gt, "gt"),
+            (self.options.lt, "lt"),
+            (self.options.ch, "ch"),
+            (self.options.incr, "incr"),
+        ];
+        for (_, flag) in flags.iter().filter(|(set, _)| *set) {
+            frame.push_bulk(Bytes::from(flag.as_bytes()));
+        }
+
+        for (score, member) in self.members {
+            frame.push_bulk(score_to_bytes(score));
+            frame.push_bulk(member);
+        }
+        frame
+    }
+}
+
+impl ZRange {
+    /// Create a new `ZRange` command fetching the members ranked between
+    /// `start` and `stop`, both inclusive.
+    pub fn new(key: impl ToString, start: i64, stop: i64, with_scores: bool) -> ZRange {
+        ZRange {
+            key: key.to_string(),
+            by: RangeBy::Rank(start, stop),
+            rev: false,
+            limit: None,
+            with_scores,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRange` instance from a received frame.
+    ///
+    /// ```text
+    /// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZRange> {
+        use ParseError::EndOfStream;
+
+        let key = parse.next_string()?;
+        let start = parse.next_bytes()?;
+        let stop = parse.next_bytes()?;
+
+        let mut by_score = false;
+        let mut by_lex = false;
+        let mut rev = false;
+        let mut limit = None;
+        let mut with_scores = false;
+
+        loop {
+            match parse.next_string() {
+                Ok(s) => match &s.to_uppercase()[..] {
+                    "BYSCORE" => by_score = true,
+                    "BYLEX" => by_lex = true,
+                    "REV" => rev = true,
+                    "LIMIT" => limit = Some(parse_limit(parse)?),
+                    "WITHSCORES" => with_scores = true,
+                    _ => return Err("ERR syntax error".into()),
+                },
+                Err(EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if limit.is_some() && !(by_score || by_lex) {
+            return Err("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX".into());
+        }
+
+        // With `REV`, score and lex ranges are given from the highest bound to
+        // the lowest one.
+        let (min, max) = if rev {
+            (&stop, &start)
+        } else {
+            (&start, &stop)
+        };
+
+        let by = match (by_score, by_lex) {
+            (true, true) => return Err("ERR syntax error".into()),
+            (true, false) => RangeBy::Score(parse_score_bound(min)?, parse_score_bound(max)?),
+            (false, true) => RangeBy::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?),
+            (false, false) => RangeBy::Rank(parse_rank(&start)?, parse_rank(&stop)?),
+        };
+
+        Ok(ZRange {
+            key,
+            by,
+            rev,
+            limit,
+            with_scores,
+        })
+    }
+
+    /// Apply the `ZRange` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let (offset, count) = limit_bounds(self.limit);
+        let members = db.zrange(&self.key, &self.by, self.rev, offset, count);
+        members_frame(members, self.with_scores)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrange".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+
+        let (start, stop, by) = match self.by {
+            RangeBy::Rank(start, stop) => (
+                Bytes::from(start.to_string()),
+                Bytes::from(stop.to_string()),
+                None,
+            ),
+            RangeBy::Score(min, max) => (
+                score_bound_to_bytes(min),
+                score_bound_to_bytes(max),
+                Some("byscore"),
+            ),
+            RangeBy::Lex(min, max) => (
+                lex_bound_to_bytes(min),
+                lex_bound_to_bytes(max),
+                Some("bylex"),
+            ),
+        };
+
+        // Score and lex bounds are stored lowest first, undo the swap done by
+        // `parse_frames` for `REV`.
+        let (start, stop) = match by {
+            Some(_) if self.rev => (stop, start),
+            _ => (start, stop),
+        };
+        frame.push_bulk(start);
+        frame.push_bulk(stop);
+
+        if let Some(by) = by {
+            frame.push_bulk(Bytes::from(by.as_bytes()));
+        }
+        if self.rev {
+            frame.push_bulk(Bytes::from("rev".as_bytes()));
+        }
+        push_limit(&mut frame, self.limit);
+        if self.with_scores {
+            frame.push_bulk(Bytes::from("withscores".as_bytes()));
+        }
+        frame
+    }
+}
+
+impl ZRangeByScore {
+    /// Create a new `ZRangeByScore` command fetching the members with a score
+    /// between `min` and `max`, both inclusive.
+    pub fn new(key: impl ToString, min: f64, max: f64, with_scores: bool) -> ZRangeByScore {
+        ZRangeByScore {
+            key: key.to_string(),
+            min: ScoreBound::Inclusive(min),
+            max: ScoreBound::Inclusive(max),
+            limit: None,
+            with_scores,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRangeByScore` instance from a received frame.
+    ///
+    /// ```text
+    /// ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZRangeByScore> {
+        use ParseError::EndOfStream;
+
+        let key = parse.next_string()?;
+        let min = parse_score_bound(&parse.next_bytes()?)?;
+        let max = parse_score_bound(&parse.next_bytes()?)?;
+
+        let mut limit = None;
+        let mut with_scores = false;
+
+        loop {
+            match parse.next_string() {
+                Ok(s) => match &s.to_uppercase()[..] {
+                    "LIMIT" => limit = Some(parse_limit(parse)?),
+                    "WITHSCORES" => with_scores = true,
+                    _ => return Err("ERR syntax error".into()),
+                },
+                Err(EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(ZRangeByScore {
+            key,
+            min,
+            max,
+            limit,
+            with_scores,
+        })
+    }
+
+    /// Apply the `ZRangeByScore` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let (offset, count) = limit_bounds(self.limit);
+        let by = RangeBy::Score(self.min, self.max);
+        let members = db.zrange(&self.key, &by, false, offset, count);
+        members_frame(members, self.with_scores)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrangebyscore".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(score_bound_to_bytes(self.min));
+        frame.push_bulk(score_bound_to_bytes(self.max));
+        if self.with_scores {
+            frame.push_bulk(Bytes::from("withscores".as_bytes()));
+        }
+        push_limit(&mut frame, self.limit);
+        frame
+    }
+}
+
+impl ZRank {
+    /// Create a new `ZRank` command for `member` of the sorted set at `key`.
+    pub fn new(key: impl ToString, member: Bytes) -> ZRank {
+        ZRank {
+            key: key.to_string(),
+            member,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRank` instance from a received frame.
+    ///
+    /// ```text
+    /// ZRANK key member
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZRank> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+
+        Ok(ZRank { key, member })
+    }
+
+    /// Apply the `ZRank` command to the specified `Db` instance.
+    ///
+    /// Nil is replied if the key or the member does not exist.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.zrank(&self.key, &self.member) {
+            Ok(Some(rank)) => Frame::Integer(rank as i64),
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrank".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.member);
+        frame
+    }
+}
+
+impl ZIncrBy {
+    /// Create a new `ZIncrBy` command adding `increment` to the score of
+    /// `member`.
+    pub fn new(key: impl ToString, increment: f64, member: Bytes) -> ZIncrBy {
+        ZIncrBy {
+            key: key.to_string(),
+            increment,
+            member,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZIncrBy` instance from a received frame.
+    ///
+    /// ```text
+    /// ZINCRBY key increment member
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZIncrBy> {
+        let key = parse.next_string()?;
+        let increment = parse_score(&parse.next_bytes()?)?;
+        let member = parse.next_bytes()?;
+
+        Ok(ZIncrBy {
+            key,
+            increment,
+            member,
+        })
+    }
+
+    /// Apply the `ZIncrBy` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let options = ZAddOptions::default();
+        match db.zincrby(self.key, options, self.member, self.increment) {
+            Ok(Some(score)) => Frame::Double(score),
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zincrby".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(score_to_bytes(self.increment));
+        frame.push_bulk(self.member);
+        frame
+    }
+}
+
+impl ZRem {
+    /// Create a new `ZRem` command removing `members` from the sorted set at
+    /// `key`.
+    pub fn new(key: impl ToString, members: Vec<Bytes>) -> ZRem {
+        ZRem {
+            key: key.to_string(),
+            members,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRem` instance from a received frame.
+    ///
+    /// ```text
+    /// ZREM key member [member ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZRem> {
+        let key = parse.next_string()?;
+        let members = parse.remaining_bytes()?;
+
+        Ok(ZRem { key, members })
+    }
+
+    /// Apply the `ZRem` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.zrem(&self.key, &self.members) {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrem".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+}
+
+impl ZCard {
+    /// Create a new `ZCard` command for `key`.
+    pub fn new(key: impl ToString) -> ZCard {
+        ZCard {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZCard` instance from a received frame.
+    ///
+    /// ```text
+    /// ZCARD key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZCard> {
+        let key = parse.next_string()?;
+
+        Ok(ZCard { key })
+    }
+
+    /// Apply the `ZCard` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.zcard(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zcard".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl ZPopMin {
+    /// Create a new `ZPopMin` command. When `count` is `None` a single member
+    /// is popped.
+    pub fn new(key: impl ToString, count: Option<u64>) -> ZPopMin {
+        ZPopMin {
+            key: key.to_string(),
+            count,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZPopMin` instance from a received frame.
+    ///
+    /// ```text
+    /// ZPOPMIN key [count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZPopMin> {
+        let key = parse.next_string()?;
+        let count = match parse.next_uint() {
+            Ok(count) => Some(count),
+            Err(ParseError::EndOfStream) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(ZPopMin { key, count })
+    }
+
+    /// Apply the `ZPopMin` command to the specified `Db` instance.
+    ///
+    /// The popped members are replied along with their scores.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let members = db.zpopmin(&self.key, self.count.unwrap_or(1) as usize);
+        members_frame(members, true)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zpopmin".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        if let Some(count) = self.count {
+            frame.push_int(count as i64);
+        }
+        frame
+    }
+}
+
+/// Builds the reply of the commands returning sorted set members. With
+/// `with_scores`, each member is followed by its score.
+fn members_frame(members: Result<Vec<(Bytes, f64)>, DbError>, with_scores: bool) -> Frame {
+    match members {
+        Ok(members) => {
+            let mut response = Frame::array();
+            for (member, score) in members {
+                response.push_bulk(member);
+                if with_scores {
+                    response.push_bulk(score_to_bytes(score));
+                }
+            }
+            response
+        }
+        Err(err) => Frame::Error(err.to_string()),
+    }
+}
+
+/// Parse a score. Accepts the `inf`, `+inf` and `-inf` spellings used by
+/// Redis, but not `NaN`.
+fn parse_score(src: &[u8]) -> crate::Result<f64> {
+    std::str::from_utf8(src)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|score| !score.is_nan())
+        .ok_or_else(|| "ERR value is not a valid float".into())
+}
+
+/// Parse a score range bound, `(` marking an exclusive bound.
+fn parse_score_bound(src: &[u8]) -> crate::Result<ScoreBound> {
+    match src.strip_prefix(b"(") {
+        Some(score) => Ok(ScoreBound::Exclusive(parse_score(score)?)),
+        None => Ok(ScoreBound::Inclusive(parse_score(src)?)),
+    }
+}
+
+/// Parse a lex range bound: `-`, `+`, `[member` or `(member`.
+fn parse_lex_bound(src: &Bytes) -> crate::Result<LexBound> {
+    match src.first() {
+        Some(b'-') if src.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if src.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(src.slice(1..))),
+        Some(b'(') => Ok(LexBound::Exclusive(src.slice(1..))),
+        _ => Err("ERR min or max not valid string range item".into()),
+    }
+}
+
+fn parse_rank(src: &[u8]) -> crate::Result<i64> {
+    std::str::from_utf8(src)
+        .ok()
+        .and_then(|src| src.parse::<i64>().ok())
+        .ok_or_else(|| "ERR value is not an integer or out of range".into())
+}
+
+/// Parse the `offset count` arguments following `LIMIT`.
+fn parse_limit(parse: &mut Parse) -> crate::Result<(u64, i64)> {
+    let offset = parse.next_uint()?;
+    let count = parse.next_int()?;
+    Ok((offset, count))
+}
+
+/// Resolves a `LIMIT` into an offset and an optional count, a negative count
+/// meaning all the remaining members.
+fn limit_bounds(limit: Option<(u64, i64)>) -> (usize, Option<usize>) {
+    match limit {
+        Some((offset, count)) => (offset as usize, usize::try_from(count).ok()),
+        None => (0, None),
+    }
+}
+
+fn push_limit(frame: &mut Frame, limit: Option<(u64, i64)>) {
+    if let Some((offset, count)) = limit {
+        frame.push_bulk(Bytes::from("limit".as_bytes()));
+        frame.push_bulk(Bytes::from(offset.to_string()));
+        frame.push_bulk(Bytes::from(count.to_string()));
+    }
+}
+
+/// Formats a score the way Redis does, e.g. `1`, `1.5`, `inf` or `-inf`.
+fn score_to_bytes(score: f64) -> Bytes {
+    Bytes::from(score.to_string())
+}
+
+fn score_bound_to_bytes(bound: ScoreBound) -> Bytes {
+    match bound {
+        ScoreBound::Inclusive(score) => score_to_bytes(score),
+        ScoreBound::Exclusive(score) => Bytes::from(format!("({}", score)),
+    }
+}
+
+fn lex_bound_to_bytes(bound: LexBound) -> Bytes {
+    let (prefix, member) = match bound {
+        LexBound::Min => return Bytes::from_static(b"-"),
+        LexBound::Max => return Bytes::from_static(b"+"),
+        LexBound::Inclusive(member) => (b'[', member),
+        LexBound::Exclusive(member) => (b'(', member),
+    };
+
+    let mut bytes = Vec::with_capacity(member.len() + 1);
+    bytes.push(prefix);
+    bytes.extend_from_slice(&member);
+    Bytes::from(bytes)
+}
@@ -0,0 +1,1388 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::{DbError, NewId, StreamEntry, StreamTrim};
+use crate::{Connection, Db, Frame, Shutdown, StreamId};
+
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::select;
+use tokio::time::{self, Instant};
+use tracing::{debug, instrument};
+
+/// Appends a new entry to the stream stored at key, creating the stream if
+/// needed.
+///
+/// The entry identifier is generated from the current time unless given
+/// explicitly. `MAXLEN` or `MINID` trim the stream once the entry is added.
+#[derive(Debug, Clone)]
+pub struct XAdd {
+    key: String,
+    id: NewId,
+    trim: Option<StreamTrim>,
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+/// Returns the entries of the stream stored at key with an identifier within
+/// the given range.
+#[derive(Debug, Clone)]
+pub struct XRange {
+    key: String,
+    start: StreamId,
+    end: StreamId,
+    count: Option<u64>,
+}
+
+/// Like `XRANGE`, but returns the entries in reverse order. The range is given
+/// from the end to the start.
+#[derive(Debug, Clone)]
+pub struct XRevRange {
+    key: String,
+    end: StreamId,
+    start: StreamId,
+    count: Option<u64>,
+}
+
+/// Returns the number of entries of the stream stored at key.
+#[derive(Debug, Clone)]
+pub struct XLen {
+    key: String,
+}
+
+/// Trims the stream stored at key, evicting its oldest entries.
+#[derive(Debug, Clone)]
+pub struct XTrim {
+    key: String,
+    trim: StreamTrim,
+}
+
+/// Reads the entries added to one or more streams after the given
+/// identifiers.
+///
+/// With `BLOCK`, the client waits for new entries when there are none yet.
+#[derive(Debug, Clone)]
+pub struct XRead {
+    count: Option<u64>,
+    block: Option<Duration>,
+
+    /// The streams to read and the identifier to read after. `None` stands
+    /// for `$`, the last entry of the stream when the command is received.
+    streams: Vec<(String, Option<StreamId>)>,
+}
+
+/// Creates a consumer group, `XGROUP CREATE`.
+#[derive(Debug, Clone)]
+pub struct XGroupCreate {
+    key: String,
+    group: String,
+
+    /// The group delivers the entries added after this identifier. `None`
+    /// stands for `$`, the last entry of the stream.
+    id: Option<StreamId>,
+    mkstream: bool,
+}
+
+/// Reads entries from one or more streams on behalf of a consumer of a
+/// consumer group.
+///
+/// Entries delivered to the consumer are added to its pending entries list
+/// until acknowledged with `XACK`.
+#[derive(Debug, Clone)]
+pub struct XReadGroup {
+    group: String,
+    consumer: String,
+    count: Option<u64>,
+    block: Option<Duration>,
+    noack: bool,
+
+    /// The streams to read and the identifier to read after. `None` stands
+    /// for `>`, the entries never delivered to the group; an identifier reads
+    /// the pending entries of the consumer instead.
+    streams: Vec<(String, Option<StreamId>)>,
+}
+
+/// Acknowledges entries delivered by a consumer group, removing them from
+/// the pending entries list.
+#[derive(Debug, Clone)]
+pub struct XAck {
+    key: String,
+    group: String,
+    ids: Vec<StreamId>,
+}
+
+/// Inspects the entries delivered by a consumer group and not acknowledged
+/// yet.
+///
+/// Without a range a summary is returned, otherwise the pending entries
+/// within the range are listed.
+#[derive(Debug, Clone)]
+pub struct XPending {
+    key: String,
+    group: String,
+    min_idle: Option<Duration>,
+    range: Option<(StreamId, StreamId, u64)>,
+    consumer: Option<String>,
+}
+
+/// Transfers the ownership of pending entries to another consumer of the
+/// group.
+#[derive(Debug, Clone)]
+pub struct XClaim {
+    key: String,
+    group: String,
+    consumer: String,
+    min_idle: Duration,
+    ids: Vec<StreamId>,
+    justid: bool,
+}
+
+impl XAdd {
+    /// Create a new `XAdd` command appending an entry made of `fields` to the
+    /// stream at `key`, with a generated identifier.
+    pub fn new(key: impl ToString, fields: Vec<(Bytes, Bytes)>) -> XAdd {
+        XAdd {
+            key: key.to_string(),
+            id: NewId::Auto,
+            trim: None,
+            fields,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XAdd` instance from a received frame.
+    ///
+    /// ```text
+    /// XADD key [MAXLEN | MINID [= | ~] threshold] <* | id> field value [field value ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XAdd> {
+        let key = parse.next_string()?;
+        let mut trim = None;
+
+        let id = loop {
+            let arg = parse.next_bytes()?;
+            match &arg.to_ascii_uppercase()[..] {
+                b"MAXLEN" | b"MINID" => trim = Some(parse_trim(&arg, parse)?),
+                b"*" => break NewId::Auto,
+                _ => match arg.strip_suffix(b"-*") {
+                    Some(ms) => match StreamId::parse(ms, 0) {
+                        Some(id) => break NewId::Partial(id.ms()),
+                        None => return Err(invalid_id()),
+                    },
+                    None => break NewId::Explicit(parse_id(&arg)?),
+                },
+            }
+        };
+
+        let args = parse.remaining_bytes()?;
+        if args.len() % 2 != 0 {
+            return Err("ERR wrong number of arguments for 'xadd' command".into());
+        }
+
+        let fields = args
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+
+        Ok(XAdd {
+            key,
+            id,
+            trim,
+            fields,
+        })
+    }
+
+    /// Apply the `XAdd` command to the specified `Db` instance.
+    ///
+    /// The identifier of the new entry is replied.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.xadd(self.key, self.id, self.fields, self.trim) {
+            Ok(id) => Frame::Bulk(id_to_bytes(id)),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xadd".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        if let Some(trim) = self.trim {
+            push_trim(&mut frame, trim);
+        }
+        frame.push_bulk(match self.id {
+            NewId::Auto => Bytes::from_static(b"*"),
+            NewId::Partial(ms) => Bytes::from(format!("{}-*", ms)),
+            NewId::Explicit(id) => id_to_bytes(id),
+        });
+        for (field, value) in self.fields {
+            frame.push_bulk(field);
+            frame.push_bulk(value);
+        }
+        frame
+    }
+
+    /// Returns the command with the identifier replied by its application,
+    /// `reply`, given explicitly, so that it adds the same entry when applied
+    /// again.
+    pub(crate) fn with_generated_id(mut self, reply: &Frame) -> XAdd {
+        if let Frame::Bulk(id) = reply {
+            if let Some(id) = StreamId::parse(id, 0) {
+                self.id = NewId::Explicit(id);
+            }
+        }
+        self
+    }
+}
+
+impl XRange {
+    /// Create a new `XRange` command fetching the entries with an identifier
+    /// between `start` and `end`, both inclusive.
+    pub fn new(key: impl ToString, start: StreamId, end: StreamId) -> XRange {
+        XRange {
+            key: key.to_string(),
+            start,
+            end,
+            count: None,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XRange` instance from a received frame.
+    ///
+    /// ```text
+    /// XRANGE key start end [COUNT count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XRange> {
+        let key = parse.next_string()?;
+        let start = parse_range_start(&parse.next_bytes()?)?;
+        let end = parse_range_end(&parse.next_bytes()?)?;
+        let count = parse_range_count(parse)?;
+
+        Ok(XRange {
+            key,
+            start,
+            end,
+            count,
+        })
+    }
+
+    /// Apply the `XRange` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let count = self.count.map(|count| count as usize);
+        match db.xrange(&self.key, self.start, self.end, false, count) {
+            Ok(entries) => entries_frame(entries),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xrange".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(id_to_bytes(self.start));
+        frame.push_bulk(id_to_bytes(self.end));
+        push_count(&mut frame, self.count);
+        frame
+    }
+}
+
+impl XRevRange {
+    /// Create a new `XRevRange` command fetching the entries with an
+    /// identifier between `end` and `start`, both inclusive, newest first.
+    pub fn new(key: impl ToString, end: StreamId, start: StreamId) -> XRevRange {
+        XRevRange {
+            key: key.to_string(),
+            end,
+            start,
+            count: None,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XRevRange` instance from a received frame.
+    ///
+    /// ```text
+    /// XREVRANGE key end start [COUNT count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XRevRange> {
+        let key = parse.next_string()?;
+        let end = parse_range_end(&parse.next_bytes()?)?;
+        let start = parse_range_start(&parse.next_bytes()?)?;
+        let count = parse_range_count(parse)?;
+
+        Ok(XRevRange {
+            key,
+            end,
+            start,
+            count,
+        })
+    }
+
+    /// Apply the `XRevRange` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let count = self.count.map(|count| count as usize);
+        match db.xrange(&self.key, self.start, self.end, true, count) {
+            Ok(entries) => entries_frame(entries),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xrevrange".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(id_to_bytes(self.end));
+        frame.push_bulk(id_to_bytes(self.start));
+        push_count(&mut frame, self.count);
+        frame
+    }
+}
+
+impl XLen {
+    /// Create a new `XLen` command for `key`.
+    pub fn new(key: impl ToString) -> XLen {
+        XLen {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XLen` instance from a received frame.
+    ///
+    /// ```text
+    /// XLEN key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XLen> {
+        let key = parse.next_string()?;
+
+        Ok(XLen { key })
+    }
+
+    /// Apply the `XLen` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.xlen(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xlen".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl XTrim {
+    /// Create a new `XTrim` command keeping at most `max_len` entries in the
+    /// stream at `key`.
+    pub fn new(key: impl ToString, max_len: u64) -> XTrim {
+        XTrim {
+            key: key.to_string(),
+            trim: StreamTrim::MaxLen(max_len),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XTrim` instance from a received frame.
+    ///
+    /// ```text
+    /// XTRIM key MAXLEN | MINID [= | ~] threshold
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XTrim> {
+        let key = parse.next_string()?;
+        let strategy = parse.next_bytes()?;
+
+        let trim = match &strategy.to_ascii_uppercase()[..] {
+            b"MAXLEN" | b"MINID" => parse_trim(&strategy, parse)?,
+            _ => return Err("ERR syntax error".into()),
+        };
+
+        Ok(XTrim { key, trim })
+    }
+
+    /// Apply the `XTrim` command to the specified `Db` instance.
+    ///
+    /// The number of evicted entries is replied.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.xtrim(&self.key, self.trim) {
+            Ok(evicted) => Frame::Integer(evicted as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xtrim".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        push_trim(&mut frame, self.trim);
+        frame
+    }
+}
+
+impl XRead {
+    /// Create a new `XRead` command reading the entries added to each stream
+    /// after the associated identifier.
+    ///
+    /// When `block` is set and no such entry exists yet, the server waits up
+    /// to `block` for one to be added. A zero duration waits forever.
+    pub fn new(streams: Vec<(String, StreamId)>, block: Option<Duration>) -> XRead {
+        XRead {
+            count: None,
+            block,
+            streams: streams
+                .into_iter()
+                .map(|(key, id)| (key, Some(id)))
+                .collect(),
+        }
+    }
+
+    /// Get the keys of the streams read
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.streams.iter().map(|(key, _)| key.as_str())
+    }
+
+    /// Parse a `XRead` instance from a received frame.
+    ///
+    /// ```text
+    /// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XRead> {
+        let mut count = None;
+        let mut block = None;
+
+        loop {
+            match &parse.next_string()?.to_uppercase()[..] {
+                "COUNT" => count = Some(parse.next_uint()?),
+                "BLOCK" => block = Some(Duration::from_millis(parse.next_uint()?)),
+                "STREAMS" => break,
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+
+        let streams = parse_streams(parse, "xread", |id| match id {
+            b"$" => Ok(None),
+            id => Ok(Some(parse_id(id)?)),
+        })?;
+
+        Ok(XRead {
+            count,
+            block,
+            streams,
+        })
+    }
+
+    /// Apply the `XRead` command to the specified `Db` instance.
+    ///
+    /// Nil is replied if no stream has new entries, once the `BLOCK` timeout
+    /// elapsed if any.
+    #[instrument(skip(self, db, dst, shutdown))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        // `$` is resolved once, when the command is received. Entries added
+        // while blocked are then read as they come after it.
+        let streams = match resolve_last_ids(db, self.streams) {
+            Ok(streams) => streams,
+            Err(err) => {
+                dst.write_frame(&Frame::Error(err.to_string())).await?;
+                return Ok(());
+            }
+        };
+
+        let count = self.count.map(|count| count as usize);
+        let read = || read_streams(db, &streams, count);
+
+        let response = match blocking_read(db, self.block, shutdown, read).await {
+            Some(response) => response,
+            // The server is shutting down.
+            None => return Ok(()),
+        };
+
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Apply the `XRead` command without blocking, as within a transaction
+    /// or a script: nil is replied right away if no stream has new entries.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let count = self.count.map(|count| count as usize);
+        match resolve_last_ids(db, self.streams) {
+            Ok(streams) => read_streams(db, &streams, count).unwrap_or(Frame::Null),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xread".as_bytes()));
+        push_count(&mut frame, self.count);
+        push_block(&mut frame, self.block);
+        push_streams(&mut frame, self.streams, b"$");
+        frame
+    }
+}
+
+impl XGroupCreate {
+    /// Create a new `XGroupCreate` command creating the consumer group
+    /// `group` of the stream at `key`. The group delivers the entries added
+    /// after `id`.
+    pub fn new(key: impl ToString, group: impl ToString, id: StreamId) -> XGroupCreate {
+        XGroupCreate {
+            key: key.to_string(),
+            group: group.to_string(),
+            id: Some(id),
+            mkstream: false,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XGroupCreate` instance from a received frame.
+    ///
+    /// The `XGROUP` string has already been consumed. `CREATE` is the only
+    /// supported subcommand.
+    ///
+    /// ```text
+    /// XGROUP CREATE key group <id | $> [MKSTREAM]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XGroupCreate> {
+        let subcommand = parse.next_string()?;
+        if subcommand.to_uppercase() != "CREATE" {
+            return Err(format!("ERR unknown subcommand '{}'", subcommand).into());
+        }
+
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let id = match &parse.next_bytes()?[..] {
+            b"$" => None,
+            id => Some(parse_id(id)?),
+        };
+
+        let mkstream = match parse.next_string() {
+            Ok(s) if s.to_uppercase() == "MKSTREAM" => true,
+            Ok(_) => return Err("ERR syntax error".into()),
+            Err(ParseError::EndOfStream) => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(XGroupCreate {
+            key,
+            group,
+            id,
+            mkstream,
+        })
+    }
+
+    /// Apply the `XGroupCreate` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.xgroup_create(self.key, self.group, self.id, self.mkstream) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xgroup".as_bytes()));
+        frame.push_bulk(Bytes::from("create".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+        frame.push_bulk(match self.id {
+            Some(id) => id_to_bytes(id),
+            None => Bytes::from_static(b"$"),
+        });
+        if self.mkstream {
+            frame.push_bulk(Bytes::from("mkstream".as_bytes()));
+        }
+        frame
+    }
+}
+
+impl XReadGroup {
+    /// Create a new `XReadGroup` command reading, on behalf of `consumer`,
+    /// the entries of the streams at `keys` never delivered to `group`.
+    ///
+    /// When `block` is set and no such entry exists yet, the server waits up
+    /// to `block` for one to be added. A zero duration waits forever.
+    pub fn new(
+        group: impl ToString,
+        consumer: impl ToString,
+        keys: Vec<String>,
+        block: Option<Duration>,
+    ) -> XReadGroup {
+        XReadGroup {
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            count: None,
+            block,
+            noack: false,
+            streams: keys.into_iter().map(|key| (key, None)).collect(),
+        }
+    }
+
+    /// Get the keys of the streams read
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.streams.iter().map(|(key, _)| key.as_str())
+    }
+
+    /// Parse a `XReadGroup` instance from a received frame.
+    ///
+    /// ```text
+    /// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...] id [id ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XReadGroup> {
+        if parse.next_string()?.to_uppercase() != "GROUP" {
+            return Err("ERR syntax error".into());
+        }
+
+        let group = parse.next_string()?;
+        let consumer = parse.next_string()?;
+        let mut count = None;
+        let mut block = None;
+        let mut noack = false;
+
+        loop {
+            match &parse.next_string()?.to_uppercase()[..] {
+                "COUNT" => count = Some(parse.next_uint()?),
+                "BLOCK" => block = Some(Duration::from_millis(parse.next_uint()?)),
+                "NOACK" => noack = true,
+                "STREAMS" => break,
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+
+        let streams = parse_streams(parse, "xreadgroup", |id| match id {
+            b">" => Ok(None),
+            id => Ok(Some(parse_id(id)?)),
+        })?;
+
+        Ok(XReadGroup {
+            group,
+            consumer,
+            count,
+            block,
+            noack,
+            streams,
+        })
+    }
+
+    /// Apply the `XReadGroup` command to the specified `Db` instance.
+    ///
+    /// Nil is replied if no stream has entries for the consumer, once the
+    /// `BLOCK` timeout elapsed if any. Pending entries deleted from the stream
+    /// since their delivery are replied with nil fields.
+    #[instrument(skip(self, db, dst, shutdown))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        let response = match blocking_read(db, self.block, shutdown, || self.read(db)).await {
+            Some(response) => response,
+            // The server is shutting down.
+            None => return Ok(()),
+        };
+
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Apply the `XReadGroup` command without blocking, as within a
+    /// transaction or a script: nil is replied right away if no stream has
+    /// entries for the consumer.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        self.read(db).unwrap_or(Frame::Null)
+    }
+
+    /// Read the streams once, `None` if no stream has entries for the
+    /// consumer.
+    fn read(&self, db: &Db) -> Option<Frame> {
+        let count = self.count.map(|count| count as usize);
+
+        // Delivering entries changes the consumer group, the read is
+        // propagated without `BLOCK` once it delivered something.
+        let result = db
+            .atomically(&[], || {
+                let result = db.xreadgroup(
+                    &self.group,
+                    &self.consumer,
+                    &self.streams,
+                    count,
+                    self.noack,
+                );
+                if matches!(&result, Ok(result) if !result.is_empty()) {
+                    let propagated = XReadGroup {
+                        block: None,
+                        ..self.clone()
+                    };
+                    db.propagate(vec![propagated.into_frame()]);
+                }
+                result
+            })
+            .expect("no watched key");
+        match result {
+            Ok(result) if result.is_empty() => None,
+            Ok(result) => Some(streams_frame(result.into_iter().map(|(key, entries)| {
+                let entries = entries
+                    .into_iter()
+                    .map(|(id, fields)| entry_frame(id, fields))
+                    .collect();
+                (key, Frame::Array(entries))
+            }))),
+            Err(err) => Some(Frame::Error(err.to_string())),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xreadgroup".as_bytes()));
+        frame.push_bulk(Bytes::from("group".as_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+        frame.push_bulk(Bytes::from(self.consumer.into_bytes()));
+        push_count(&mut frame, self.count);
+        push_block(&mut frame, self.block);
+        if self.noack {
+            frame.push_bulk(Bytes::from("noack".as_bytes()));
+        }
+        push_streams(&mut frame, self.streams, b">");
+        frame
+    }
+}
+
+impl XAck {
+    /// Create a new `XAck` command acknowledging the entries `ids` delivered
+    /// by `group`.
+    pub fn new(key: impl ToString, group: impl ToString, ids: Vec<StreamId>) -> XAck {
+        XAck {
+            key: key.to_string(),
+            group: group.to_string(),
+            ids,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XAck` instance from a received frame.
+    ///
+    /// ```text
+    /// XACK key group id [id ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XAck> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let ids = parse
+            .remaining_bytes()?
+            .iter()
+            .map(|id| parse_id(id))
+            .collect::<crate::Result<_>>()?;
+
+        Ok(XAck { key, group, ids })
+    }
+
+    /// Apply the `XAck` command to the specified `Db` instance.
+    ///
+    /// The number of acknowledged entries is replied.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match db.xack(&self.key, &self.group, &self.ids) {
+            Ok(acked) => Frame::Integer(acked as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xack".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+        for id in self.ids {
+            frame.push_bulk(id_to_bytes(id));
+        }
+        frame
+    }
+}
+
+impl XPending {
+    /// Create a new `XPending` command summarizing the pending entries of
+    /// `group`.
+    pub fn new(key: impl ToString, group: impl ToString) -> XPending {
+        XPending {
+            key: key.to_string(),
+            group: group.to_string(),
+            min_idle: None,
+            range: None,
+            consumer: None,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XPending` instance from a received frame.
+    ///
+    /// ```text
+    /// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XPending> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let mut pending = XPending::new(key, group);
+
+        let mut arg = match parse.next_bytes() {
+            Ok(arg) => arg,
+            Err(ParseError::EndOfStream) => return Ok(pending),
+            Err(err) => return Err(err.into()),
+        };
+
+        if arg.eq_ignore_ascii_case(b"IDLE") {
+            pending.min_idle = Some(Duration::from_millis(parse.next_uint()?));
+            arg = parse.next_bytes()?;
+        }
+
+        let start = parse_range_start(&arg)?;
+        let end = parse_range_end(&parse.next_bytes()?)?;
+        let count = parse.next_uint()?;
+        pending.range = Some((start, end, count));
+
+        pending.consumer = match parse.next_string() {
+            Ok(consumer) => Some(consumer),
+            Err(ParseError::EndOfStream) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(pending)
+    }
+
+    /// Apply the `XPending` command to the specified `Db` instance.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        match self.range {
+            None => match db.xpending_summary(&self.key, &self.group) {
+                Ok(summary) => {
+                    let mut response = Frame::array();
+                    response.push_int(summary.count as i64);
+
+                    match summary.bounds {
+                        Some((min, max)) => {
+                            response.push_bulk(id_to_bytes(min));
+                            response.push_bulk(id_to_bytes(max));
+                        }
+                        None => {
+                            push_frame(&mut response, Frame::Null);
+                            push_frame(&mut response, Frame::Null);
+                        }
+                    }
+
+                    let consumers = match summary.consumers.is_empty() {
+                        true => Frame::Null,
+                        false => Frame::Array(
+                            summary
+                                .consumers
+                                .into_iter()
+                                .map(|(consumer, count)| {
+                                    let mut frame = Frame::array();
+                                    frame.push_bulk(Bytes::from(consumer.into_bytes()));
+                                    frame.push_bulk(Bytes::from(count.to_string()));
+                                    frame
+                                })
+                                .collect(),
+                        ),
+                    };
+                    push_frame(&mut response, consumers);
+                    response
+                }
+                Err(err) => Frame::Error(err.to_string()),
+            },
+            Some((start, end, count)) => {
+                let consumer = self.consumer.as_deref();
+                match db.xpending(
+                    &self.key,
+                    &self.group,
+                    start,
+                    end,
+                    count as usize,
+                    consumer,
+                    self.min_idle,
+                ) {
+                    Ok(pending) => Frame::Array(
+                        pending
+                            .into_iter()
+                            .map(|info| {
+                                let mut frame = Frame::array();
+                                frame.push_bulk(id_to_bytes(info.id));
+                                frame.push_bulk(Bytes::from(info.consumer.into_bytes()));
+                                frame.push_int(info.idle.as_millis() as i64);
+                                frame.push_int(info.deliveries as i64);
+                                frame
+                            })
+                            .collect(),
+                    ),
+                    Err(err) => Frame::Error(err.to_string()),
+                }
+            }
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xpending".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+        if let Some((start, end, count)) = self.range {
+            if let Some(min_idle) = self.min_idle {
+                frame.push_bulk(Bytes::from("idle".as_bytes()));
+                frame.push_int(min_idle.as_millis() as i64);
+            }
+            frame.push_bulk(id_to_bytes(start));
+            frame.push_bulk(id_to_bytes(end));
+            frame.push_int(count as i64);
+            if let Some(consumer) = self.consumer {
+                frame.push_bulk(Bytes::from(consumer.into_bytes()));
+            }
+        }
+        frame
+    }
+}
+
+impl XClaim {
+    /// Create a new `XClaim` command transferring the pending entries `ids`
+    /// of `group` idle for at least `min_idle` to `consumer`.
+    pub fn new(
+        key: impl ToString,
+        group: impl ToString,
+        consumer: impl ToString,
+        min_idle: Duration,
+        ids: Vec<StreamId>,
+    ) -> XClaim {
+        XClaim {
+            key: key.to_string(),
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            min_idle,
+            ids,
+            justid: false,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XClaim` instance from a received frame.
+    ///
+    /// ```text
+    /// XCLAIM key group consumer min-idle-time id [id ...] [JUSTID]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XClaim> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let consumer = parse.next_string()?;
+        let min_idle = Duration::from_millis(parse.next_uint()?);
+
+        let mut ids = vec![];
+        let mut justid = false;
+
+        for arg in parse.remaining_bytes()? {
+            if arg.eq_ignore_ascii_case(b"JUSTID") {
+                justid = true;
+            } else if justid {
+                return Err("ERR syntax error".into());
+            } else {
+                ids.push(parse_id(&arg)?);
+            }
+        }
+
+        Ok(XClaim {
+            key,
+            group,
+            consumer,
+            min_idle,
+            ids,
+            justid,
+        })
+    }
+
+    /// Apply the `XClaim` command to the specified `Db` instance.
+    ///
+    /// The claimed entries are replied, or only their identifiers with
+    /// `JUSTID`.
+    #[instrument(skip(self, db))]
+    pub(crate) fn execute(self, db: &Db) -> Frame {
+        let claimed = db.xclaim(
+            &self.key,
+            &self.group,
+            &self.consumer,
+            self.min_idle,
+            &self.ids,
+            self.justid,
+        );
+        match claimed {
+            Ok(claimed) if self.justid => {
+                let mut response = Frame::array();
+                for (id, _) in claimed {
+                    response.push_bulk(id_to_bytes(id));
+                }
+                response
+            }
+            Ok(claimed) => entries_frame(claimed),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xclaim".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+        frame.push_bulk(Bytes::from(self.consumer.into_bytes()));
+        frame.push_int(self.min_idle.as_millis() as i64);
+        for id in self.ids {
+            frame.push_bulk(id_to_bytes(id));
+        }
+        if self.justid {
+            frame.push_bulk(Bytes::from("justid".as_bytes()));
+        }
+        frame
+    }
+
+    /// Returns the command claiming exactly the entries its application
+    /// claimed according to `reply`, whatever their idle time when applied
+    /// again. `None` is returned if no entry was claimed.
+    pub(crate) fn with_claimed(mut self, reply: &Frame) -> Option<XClaim> {
+        let claimed = match reply {
+            Frame::Array(claimed) => claimed,
+            _ => return None,
+        };
+        self.ids = claimed
+            .iter()
+            .filter_map(|claimed| match claimed {
+                Frame::Bulk(id) => StreamId::parse(id, 0),
+                Frame::Array(entry) => match entry.first() {
+                    Some(Frame::Bulk(id)) => StreamId::parse(id, 0),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        self.min_idle = Duration::ZERO;
+
+        if self.ids.is_empty() {
+            return None;
+        }
+        Some(self)
+    }
+}
+
+/// Calls `read` until it returns a reply.
+///
+/// Without `block`, `read` is called once and nil is replied if it has nothing
+/// to return. Otherwise the task sleeps until an entry is appended to a stream
+/// and tries again, until `block` elapses. A zero `block` waits forever.
+///
+/// `None` is returned if the server shuts down while waiting.
+async fn blocking_read(
+    db: &Db,
+    block: Option<Duration>,
+    shutdown: &mut Shutdown,
+    mut read: impl FnMut() -> Option<Frame>,
+) -> Option<Frame> {
+    let deadline = block
+        .filter(|block| !block.is_zero())
+        .map(|block| Instant::now() + block);
+
+    loop {
+        // Register for notifications before reading, otherwise an entry
+        // appended right after the read would not wake the task up.
+        let appended = db.stream_appended();
+        tokio::pin!(appended);
+        appended.as_mut().enable();
+
+        db.running_script().completed().await;
+        if let Some(response) = read() {
+            return Some(response);
+        }
+
+        if block.is_none() {
+            return Some(Frame::Null);
+        }
+
+        select! {
+            _ = appended => {}
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                return Some(Frame::Null);
+            }
+            _ = shutdown.recv() => return None,
+        }
+    }
+}
+
+/// Resolve the `$` identifiers of `streams`, `None`, to the identifier of
+/// the last entry of their stream.
+fn resolve_last_ids(
+    db: &Db,
+    streams: Vec<(String, Option<StreamId>)>,
+) -> Result<Vec<(String, StreamId)>, DbError> {
+    streams
+        .into_iter()
+        .map(|(key, id)| match id {
+            Some(id) => Ok((key, id)),
+            None => db.xlast_id(&key).map(|id| (key, id)),
+        })
+        .collect()
+}
+
+/// Read the entries of `streams` following their identifier, `None` if
+/// there are none.
+fn read_streams(db: &Db, streams: &[(String, StreamId)], count: Option<usize>) -> Option<Frame> {
+    match db.xread(streams, count) {
+        Ok(result) if result.is_empty() => None,
+        Ok(result) => Some(streams_frame(
+            result
+                .into_iter()
+                .map(|(key, entries)| (key, entries_frame(entries))),
+        )),
+        Err(err) => Some(Frame::Error(err.to_string())),
+    }
+}
+
+/// Parse the keys and identifiers following `STREAMS`, the identifiers being
+/// parsed with `parse_id`.
+fn parse_streams(
+    parse: &mut Parse,
+    name: &str,
+    parse_id: impl Fn(&[u8]) -> crate::Result<Option<StreamId>>,
+) -> crate::Result<Vec<(String, Option<StreamId>)>> {
+    let args = parse.remaining_bytes()?;
+    if args.len() % 2 != 0 {
+        return Err(format!(
+            "ERR Unbalanced '{}' list of streams: for each stream key an ID must be specified.",
+            name
+        )
+        .into());
+    }
+
+    let (keys, ids) = args.split_at(args.len() / 2);
+    keys.iter()
+        .zip(ids)
+        .map(|(key, id)| {
+            let key = String::from_utf8(key.to_vec())?;
+            Ok((key, parse_id(id)?))
+        })
+        .collect()
+}
+
+/// Parse the strategy and threshold of `MAXLEN` or `MINID`, `strategy` being
+/// the already consumed argument.
+fn parse_trim(strategy: &[u8], parse: &mut Parse) -> crate::Result<StreamTrim> {
+    let mut threshold = parse.next_bytes()?;
+
+    // Approximate trimming is allowed to keep more entries than asked for.
+    // Trimming exactly is always a valid way of implementing it.
+    if &threshold[..] == b"=" || &threshold[..] == b"~" {
+        threshold = parse.next_bytes()?;
+    }
+
+    if strategy.eq_ignore_ascii_case(b"MAXLEN") {
+        match std::str::from_utf8(&threshold)
+            .ok()
+            .and_then(|threshold| threshold.parse::<u64>().ok())
+        {
+            Some(max_len) => Ok(StreamTrim::MaxLen(max_len)),
+            None => Err("ERR value is not an integer or out of range".into()),
+        }
+    } else {
+        Ok(StreamTrim::MinId(parse_id(&threshold)?))
+    }
+}
+
+/// Parse the optional `COUNT` argument of `XRANGE` and `XREVRANGE`.
+fn parse_range_count(parse: &mut Parse) -> crate::Result<Option<u64>> {
+    match parse.next_string() {
+        Ok(s) if s.to_uppercase() == "COUNT" => Ok(Some(parse.next_uint()?)),
+        Ok(_) => Err("ERR syntax error".into()),
+        Err(ParseError::EndOfStream) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Parse an entry identifier, `<ms>-<seq>` or `<ms>` alone standing for
+/// `<ms>-0`.
+fn parse_id(src: &[u8]) -> crate::Result<StreamId> {
+    StreamId::parse(src, 0).ok_or_else(invalid_id)
+}
+
+/// Parse the start of a range: `-`, an identifier or `(` followed by an
+/// exclusive identifier.
+fn parse_range_start(src: &[u8]) -> crate::Result<StreamId> {
+    match src {
+        b"-" => Ok(StreamId::MIN),
+        [b'(', id @ ..] => parse_id(id)?
+            .next()
+            .ok_or_else(|| "ERR invalid start ID for the interval".into()),
+        id => parse_id(id),
+    }
+}
+
+/// Parse the end of a range: `+`, an identifier or `(` followed by an
+/// exclusive identifier. `<ms>` alone stands for `<ms>` with the greatest
+/// sequence number.
+fn parse_range_end(src: &[u8]) -> crate::Result<StreamId> {
+    match src {
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => parse_id(id)?
+            .prev()
+            .ok_or_else(|| "ERR invalid end ID for the interval".into()),
+        id => StreamId::parse(id, u64::MAX).ok_or_else(invalid_id),
+    }
+}
+
+fn invalid_id() -> crate::Error {
+    "ERR Invalid stream ID specified as stream command argument".into()
+}
+
+fn id_to_bytes(id: StreamId) -> Bytes {
+    Bytes::from(id.to_string())
+}
+
+/// Builds the reply for an entry: its identifier followed by the flattened
+/// field/value pairs, or nil for entries deleted from the stream.
+fn entry_frame(id: StreamId, fields: Option<Vec<(Bytes, Bytes)>>) -> Frame {
+    let fields = match fields {
+        Some(fields) => {
+            let mut frame = Frame::array();
+            for (field, value) in fields {
+                frame.push_bulk(field);
+                frame.push_bulk(value);
+            }
+            frame
+        }
+        None => Frame::Null,
+    };
+
+    Frame::Array(vec![Frame::Bulk(id_to_bytes(id)), fields])
+}
+
+fn entries_frame(entries: Vec<StreamEntry>) -> Frame {
+    Frame::Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| entry_frame(id, Some(fields)))
+            .collect(),
+    )
+}
+
+/// Builds the reply of `XREAD` and `XREADGROUP`: the entries read from every
+/// stream, preceded by the stream key.
+fn streams_frame(streams: impl Iterator<Item = (String, Frame)>) -> Frame {
+    Frame::Array(
+        streams
+            .map(|(key, entries)| {
+                Frame::Array(vec![Frame::Bulk(Bytes::from(key.into_bytes())), entries])
+            })
+            .collect(),
+    )
+}
+
+fn push_frame(frame: &mut Frame, entry: Frame) {
+    match frame {
+        Frame::Array(vec) => vec.push(entry),
+        _ => panic!("not an array frame"),
+    }
+}
+
+fn push_trim(frame: &mut Frame, trim: StreamTrim) {
+    match trim {
+        StreamTrim::MaxLen(max_len) => {
+            frame.push_bulk(Bytes::from("maxlen".as_bytes()));
+            frame.push_bulk(Bytes::from(max_len.to_string()));
+        }
+        StreamTrim::MinId(id) => {
+            frame.push_bulk(Bytes::from("minid".as_bytes()));
+            frame.push_bulk(id_to_bytes(id));
+        }
+    }
+}
+
+fn push_count(frame: &mut Frame, count: Option<u64>) {
+    if let Some(count) = count {
+        frame.push_bulk(Bytes::from("count".as_bytes()));
+        frame.push_int(count as i64);
+    }
+}
+
+fn push_block(frame: &mut Frame, block: Option<Duration>) {
+    if let Some(block) = block {
+        frame.push_bulk(Bytes::from("block".as_bytes()));
+        frame.push_int(block.as_millis() as i64);
+    }
+}
+
+/// Push the `STREAMS` argument: the keys, then the identifiers, `none`
+/// standing for a `None` identifier.
+fn push_streams(frame: &mut Frame, streams: Vec<(String, Option<StreamId>)>, none: &'static [u8]) {
+    frame.push_bulk(Bytes::from("streams".as_bytes()));
+
+    let (keys, ids): (Vec<_>, Vec<_>) = streams.into_iter().unzip();
+    for key in keys {
+        frame.push_bulk(Bytes::from(key.into_bytes()));
+    }
+    for id in ids {
+        frame.push_bulk(match id {
+            Some(id) => id_to_bytes(id),
+            None => Bytes::from_static(none),
+        });
+    }
+}
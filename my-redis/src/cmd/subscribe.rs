@@ -12,7 +12,7 @@ use tokio_stream::{Stream, StreamExt, StreamMap};
 /// Once the client enters the subscribed state, it is not supposed to issue any
 /// other commands, except for additional SUBSCRIBE, PSUBSCRIBE, UNSUBSCRIBE,
 /// PUNSUBSCRIBE, PING and QUIT commands.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Subscribe {
     channels: Vec<String>,
 }
@@ -33,6 +33,10 @@ impl Subscribe {
         Subscribe { channels }
     }
 
+    /// Get the channels
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
 
     pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Subscribe> {
         use ParseError::EndOfStream;
@@ -94,10 +98,12 @@ impl Subscribe {
                         None => return Ok(())
                     };
 
+                    db.running_script().completed().await;
                     handle_command(
                         frame,
                         &mut self.channels,
                         &mut subscriptions,
+                        db,
                         dst,
                     ).await?;
                 }
@@ -148,13 +154,19 @@ async fn handle_command(
     frame: Frame,
     subscribe_to: &mut Vec<String>,
     subscriptions: &mut StreamMap<String, Messages>,
+    db: &Db,
     dst: &mut Connection,
 ) -> crate::Result<()> {
     match Command::from_frame(frame)? {
-        Command::Subscribe(subscribe) => {
-            // The `apply` method will subscribe to the channels we add to this
-            // vector.
-            subscribe_to.extend(subscribe.channels.into_iter());
+        cmd @ Command::Subscribe(_) => {
+            // The channels must be granted to the user, see `Command::apply`.
+            if let Err(err) = db.acl_check(dst.user(), &cmd) {
+                dst.write_frame(&Frame::Error(err.to_string())).await?;
+            } else if let Command::Subscribe(subscribe) = cmd {
+                // The `apply` method will subscribe to the channels we add to
+                // this vector.
+                subscribe_to.extend(subscribe.channels);
+            }
         }
         Command::Unsubscribe(mut unsubscribe) => {
             if unsubscribe.channels.is_empty() {
@@ -171,8 +183,8 @@ async fn handle_command(
             }
         }
         command => {
-            let cmd = Unknown::new(command.get_name());
-            cmd.apply(dst).await?;
+            let response = Unknown::new(command.get_name()).execute();
+            dst.write_frame(&response).await?;
         }
     }
     Ok(())
@@ -185,25 +197,25 @@ async fn handle_command(
 /// taking a `&str` would require copying the data. This allows the caller to
 /// decide whether to clone the channel name or not.
 fn make_subscribe_frame(channel_name: String, num_subs: usize) -> Frame {
-    let mut response = Frame::array();
+    let mut response = Frame::Push(vec![]);
     response.push_bulk(Bytes::from_static(b"subscribe"));
     response.push_bulk(Bytes::from(channel_name));
-    response.push_int(num_subs as u64);
+    response.push_int(num_subs as i64);
     response
 }
 
 fn make_unsubscribe_frame(channel_name: String, num_subs: usize) -> Frame {
-    let mut response = Frame::array();
+    let mut response = Frame::Push(vec![]);
     response.push_bulk(Bytes::from_static(b"unsubscribe"));
     response.push_bulk(Bytes::from(channel_name));
-    response.push_int(num_subs as u64);
+    response.push_int(num_subs as i64);
     response
 }
 
 /// Creates a message informing the client about a new message on a channel that
 /// the client subscribes to.
 fn make_message_frame(channel_name: String, msg: Bytes) -> Frame {
-    let mut response = Frame::array();
+    let mut response = Frame::Push(vec![]);
     response.push_bulk(Bytes::from_static(b"message"));
     response.push_bulk(Bytes::from(channel_name));
     response.push_bulk(msg);
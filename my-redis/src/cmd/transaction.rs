@@ -0,0 +1,388 @@
+use crate::{scripting, Command, Connection, Db, Frame, Parse};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Marks the start of a transaction block.
+///
+/// The following commands are queued, then executed atomically by `EXEC` or
+/// thrown away by `DISCARD`.
+#[derive(Debug, Clone, Default)]
+pub struct Multi;
+
+/// Executes the commands queued since `MULTI`.
+///
+/// The commands run one after the other while holding the lock on the
+/// database, no other client observes an intermediate state. Replies an array
+/// holding the reply of each command, or nil if a watched key was modified,
+/// in which case nothing is executed.
+#[derive(Debug, Clone, Default)]
+pub struct Exec;
+
+/// Throws away the commands queued since `MULTI` and unwatches all keys.
+#[derive(Debug, Clone, Default)]
+pub struct Discard;
+
+/// Marks keys to watch for the next transaction.
+///
+/// `EXEC` does not execute anything if one of the keys was modified after
+/// being watched, which allows optimistic locking: read, `WATCH`, compute,
+/// then write in a transaction, retrying if it replied nil.
+#[derive(Debug, Clone)]
+pub struct Watch {
+    keys: Vec<String>,
+}
+
+/// Forgets the keys watched by the connection.
+#[derive(Debug, Clone, Default)]
+pub struct Unwatch;
+
+/// Transaction state of a connection.
+///
+/// Owned by the connection handler, transactions being local to the
+/// connection issuing them.
+#[derive(Debug, Default)]
+pub(crate) struct TransactionState {
+    /// Commands queued since `MULTI`, `None` outside of a transaction.
+    queued: Option<Vec<Command>>,
+
+    /// Set when a command could not be queued, `EXEC` then discards the
+    /// transaction.
+    aborted: bool,
+
+    /// Watched keys along with the version they had when watched, `None`
+    /// standing for a missing key.
+    watched: Vec<(String, Option<u64>)>,
+}
+
+impl TransactionState {
+    /// Returns `true` between `MULTI` and `EXEC` or `DISCARD`.
+    pub(crate) fn is_open(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// Make the next `EXEC` discard the transaction, after a command was
+    /// refused. Does nothing outside of a transaction.
+    pub(crate) fn abort(&mut self) {
+        if self.is_open() {
+            self.aborted = true;
+        }
+    }
+
+    /// Queue `cmd` for the next `EXEC`, replying `QUEUED`.
+    ///
+    /// Commands which cannot run in a transaction, or which the user is not
+    /// granted in the specified `Db` instance, are refused, and the whole
+    /// transaction is then discarded by `EXEC`.
+    pub(crate) async fn queue(
+        &mut self,
+        cmd: Command,
+        db: &Db,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        if let Err(err) = db.acl_check(dst.user(), &cmd) {
+            self.aborted = true;
+            let response = Frame::Error(err.to_string());
+            debug!(?response);
+            dst.write_frame(&response).await?;
+            return Ok(());
+        }
+
+        let response = match cmd {
+            Command::Unknown(cmd) => {
+                self.aborted = true;
+                cmd.execute()
+            }
+            Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PSync(_)
+            | Command::Hello(_)
+            | Command::Auth(_) => {
+                self.aborted = true;
+                Frame::Error(format!(
+                    "ERR Command not allowed inside a transaction: '{}'",
+                    cmd.get_name()
+                ))
+            }
+            cmd => {
+                self.queued.get_or_insert_with(Vec::new).push(cmd);
+                Frame::Simple("QUEUED".to_string())
+            }
+        };
+
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+}
+
+impl Multi {
+    /// Create a new `Multi` command.
+    pub fn new() -> Multi {
+        Multi
+    }
+
+    /// Parse a `Multi` instance from a received frame.
+    ///
+    /// ```text
+    /// MULTI
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Multi> {
+        Ok(Multi)
+    }
+
+    /// Open a transaction on the connection.
+    #[instrument(skip(self, state, dst))]
+    pub(crate) async fn apply(
+        self,
+        state: &mut TransactionState,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = if state.is_open() {
+            Frame::Error("ERR MULTI calls can not be nested".to_string())
+        } else {
+            state.queued = Some(vec![]);
+            Frame::Simple("OK".to_string())
+        };
+
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("multi".as_bytes()));
+        frame
+    }
+}
+
+impl Exec {
+    /// Create a new `Exec` command.
+    pub fn new() -> Exec {
+        Exec
+    }
+
+    /// Parse an `Exec` instance from a received frame.
+    ///
+    /// ```text
+    /// EXEC
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Exec> {
+        Ok(Exec)
+    }
+
+    /// Execute the queued commands against the specified `Db` instance.
+    ///
+    /// The transaction is closed and all keys are unwatched, whatever the
+    /// outcome.
+    #[instrument(skip(self, state, db, dst))]
+    pub(crate) async fn apply(
+        self,
+        state: &mut TransactionState,
+        db: &Db,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = match state.queued.take() {
+            None => Frame::Error("ERR EXEC without MULTI".to_string()),
+            Some(_) if std::mem::take(&mut state.aborted) => {
+                state.watched.clear();
+                Frame::Error(
+                    "EXECABORT Transaction discarded because of previous errors.".to_string(),
+                )
+            }
+            Some(queued) => {
+                let watched = std::mem::take(&mut state.watched);
+                let scripts = queued
+                    .iter()
+                    .any(|cmd| matches!(cmd, Command::Eval(_) | Command::EvalSha(_)));
+
+                let exec = move |db: &Db, dst: &mut Connection| {
+                    let replies = db.atomically(&watched, || execute(queued, db, dst));
+                    replies.map_or(Frame::NullArray, Frame::Array)
+                };
+
+                // Scripts hold the lock until they complete, see
+                // `scripting::spawn`.
+                if scripts {
+                    scripting::spawn(db, dst, exec).await?
+                } else {
+                    exec(db, dst)
+                }
+            }
+        };
+
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("exec".as_bytes()));
+        frame
+    }
+}
+
+impl Discard {
+    /// Create a new `Discard` command.
+    pub fn new() -> Discard {
+        Discard
+    }
+
+    /// Parse a `Discard` instance from a received frame.
+    ///
+    /// ```text
+    /// DISCARD
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Discard> {
+        Ok(Discard)
+    }
+
+    /// Close the transaction of the connection without executing it.
+    #[instrument(skip(self, state, dst))]
+    pub(crate) async fn apply(
+        self,
+        state: &mut TransactionState,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = if state.is_open() {
+            *state = TransactionState::default();
+            Frame::Simple("OK".to_string())
+        } else {
+            Frame::Error("ERR DISCARD without MULTI".to_string())
+        };
+
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("discard".as_bytes()));
+        frame
+    }
+}
+
+impl Watch {
+    /// Create a new `Watch` command watching `keys`.
+    pub fn new(keys: Vec<String>) -> Watch {
+        Watch { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `Watch` instance from a received frame.
+    ///
+    /// ```text
+    /// WATCH key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Watch> {
+        let keys = parse.remaining_strings()?;
+
+        Ok(Watch { keys })
+    }
+
+    /// Record the current version of the keys in the specified `Db` instance.
+    ///
+    /// A key watched several times keeps the version it had the first time.
+    #[instrument(skip(self, state, db, dst))]
+    pub(crate) async fn apply(
+        self,
+        state: &mut TransactionState,
+        db: &Db,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = if state.is_open() {
+            Frame::Error("ERR WATCH inside MULTI is not allowed".to_string())
+        } else {
+            for key in self.keys {
+                if !state.watched.iter().any(|(watched, _)| *watched == key) {
+                    let version = db.version(&key);
+                    state.watched.push((key, version));
+                }
+            }
+            Frame::Simple("OK".to_string())
+        };
+
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("watch".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl Unwatch {
+    /// Create a new `Unwatch` command.
+    pub fn new() -> Unwatch {
+        Unwatch
+    }
+
+    /// Parse an `Unwatch` instance from a received frame.
+    ///
+    /// ```text
+    /// UNWATCH
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Unwatch> {
+        Ok(Unwatch)
+    }
+
+    /// Forget the keys watched by the connection.
+    ///
+    /// Inside a transaction, the command is queued like any other one. The
+    /// watched keys are checked before the queued commands run, so it is a
+    /// no-op by then.
+    #[instrument(skip(self, state, dst))]
+    pub(crate) async fn apply(
+        self,
+        state: &mut TransactionState,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        state.watched.clear();
+
+        let response = Frame::Simple("OK".to_string());
+
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("unwatch".as_bytes()));
+        frame
+    }
+}
+
+/// Run the queued commands, collecting their replies.
+///
+/// The caller holds the lock on the database, so the commands are applied
+/// without yielding, see `Command::apply_now`.
+fn execute(queued: Vec<Command>, db: &Db, dst: &mut Connection) -> Vec<Frame> {
+    queued
+        .into_iter()
+        .map(|cmd| match cmd {
+            // Keys were checked before running the transaction, nothing is
+            // left to unwatch.
+            Command::Unwatch(_) => Frame::Simple("OK".to_string()),
+            cmd => cmd.apply_now(db, dst),
+        })
+        .collect()
+}
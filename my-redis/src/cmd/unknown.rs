@@ -1,9 +1,9 @@
-use crate::{Connection, Frame};
+use crate::Frame;
 
-use tracing::{debug, instrument};
+use tracing::instrument;
 
 /// Represents an "unknown" command. This is not a real `Redis` command.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Unknown {
     command_name: String,
 }
@@ -18,13 +18,8 @@ impl Unknown{
         &self.command_name
     }
 
-    #[instrument(skip(self, dst))]
-    pub(crate) async fn apply(self, dst: &mut Connection) -> crate::Result<()> {
-        let response = Frame::Error(format!("ERR unknown command '{}'", self.command_name));
-
-        debug!(?response);
-
-        dst.write_frame(&response).await?;
-        Ok(())
+    #[instrument(skip(self))]
+    pub(crate) fn execute(self) -> Frame {
+        Frame::Error(format!("ERR unknown command '{}'", self.command_name))
     }
 }
\ No newline at end of file
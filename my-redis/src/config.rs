@@ -0,0 +1,471 @@
+//! Server settings.
+//!
+//! `ServerConfig` gathers the settings the server is started with. It is
+//! passed to `server::run` and hands each part of the server the values it
+//! needs.
+//!
+//! Settings may be read from a `redis.conf`-style file, see `Directive`.
+
+use crate::{TlsConfig, DEFAULT_PORT};
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::level_filters::LevelFilter;
+
+/// Settings of a server instance.
+///
+/// `ServerConfig::default()` provides the values used when nothing else is
+/// specified.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Addresses the server binary listens on. `run` accepts connections
+    /// from the listener it is given instead.
+    pub bind: Vec<String>,
+
+    /// TCP port the server binary listens on, 0 to only listen on the Unix
+    /// socket.
+    pub port: u16,
+
+    /// Unix socket the server binary listens on as well.
+    pub unixsocket: Option<PathBuf>,
+
+    /// Permissions of the Unix socket. `None` leaves the default ones.
+    pub unixsocketperm: Option<u32>,
+
+    /// Maximum number of concurrent connections.
+    ///
+    /// When this limit is reached, the server stops accepting connections
+    /// until an active connection terminates.
+    pub maxclients: usize,
+
+    /// Close the connection of a client idle for this long. A zero duration
+    /// never closes them.
+    ///
+    /// Only clients waiting to send a command count as idle, not subscribers
+    /// nor clients blocked on a list or stream.
+    pub timeout: Duration,
+
+    /// Number of messages buffered per pub/sub channel. A subscriber falling
+    /// further behind misses the oldest messages.
+    pub pubsub_capacity: usize,
+
+    /// Most verbose events logged by the server binary. `None` leaves the
+    /// filter to the `RUST_LOG` environment variable.
+    pub loglevel: Option<LevelFilter>,
+
+    /// Time a Lua script runs before other clients are replied `BUSY`, and
+    /// may abort it with `SCRIPT KILL`.
+    ///
+    /// Scripts run atomically, so a script stuck in a loop would otherwise
+    /// block every other client forever. A script reaching the limit is also
+    /// aborted with an error, unless it wrote to the key space: it then runs
+    /// to completion. A zero duration replies `BUSY` right away and never
+    /// aborts scripts.
+    pub script_timeout: Duration,
+
+    /// File snapshots are written to by `SAVE` and `BGSAVE`, and loaded from
+    /// when the server starts.
+    pub rdb_path: PathBuf,
+
+    /// Conditions under which a snapshot is taken in the background. No
+    /// snapshot is taken automatically if empty.
+    ///
+    /// When the server shuts down gracefully with rules configured, a final
+    /// snapshot is taken.
+    pub save_rules: Vec<SaveRule>,
+
+    /// Whether the writes are logged to the append only file. When enabled,
+    /// the key space is loaded from that file instead of the RDB file when
+    /// the server starts.
+    pub appendonly: bool,
+
+    /// Append only file the writes are logged to.
+    pub aof_path: PathBuf,
+
+    /// When the append only file is flushed to disk.
+    pub appendfsync: AppendFsync,
+
+    /// Leader to follow from startup, as a host and port. `None` starts the
+    /// server as a leader, `REPLICAOF` changes it later on.
+    pub replicaof: Option<(String, u16)>,
+
+    /// Whether writes sent by clients are refused while following a leader.
+    /// Writes accepted otherwise are not streamed to the followers of the
+    /// server, and are overwritten by the next full resynchronization.
+    pub replica_read_only: bool,
+
+    /// Config file holding the topology of the cluster, created if missing.
+    /// `None` disables cluster mode.
+    pub cluster_config: Option<PathBuf>,
+
+    /// Password clients must send with `AUTH` before issuing any other
+    /// command, as the `default` user. `None` lets every client in.
+    pub requirepass: Option<String>,
+
+    /// ACL file the users are loaded from on startup, and saved to by
+    /// `ACL SAVE`. `None` leaves the `default` user alone.
+    pub aclfile: Option<PathBuf>,
+
+    /// Password sent with `AUTH` to the leader when following one which
+    /// requires it.
+    pub masterauth: Option<String>,
+
+    /// Accept TLS connections only, with these settings. `None` accepts
+    /// plain TCP connections. Unix socket connections are plaintext either
+    /// way.
+    pub tls: Option<TlsConfig>,
+}
+
+/// Take a snapshot once `changes` writes happened and `seconds` elapsed since
+/// the last snapshot, like the `save <seconds> <changes>` directive of Redis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+/// Policy flushing the append only file to disk, like the `appendfsync`
+/// directive of Redis.
+///
+/// Writes are handed to the operating system as they are applied, so they
+/// survive a crash of the server. The policy controls what survives a crash
+/// of the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AppendFsync {
+    /// Flush after every write, before it is acknowledged to the client.
+    Always,
+
+    /// Flush once per second, losing at most a second of writes.
+    #[default]
+    EverySec,
+
+    /// Leave flushing to the operating system.
+    No,
+}
+
+/// A setting as written in a `redis.conf`-style file: its name followed by
+/// its arguments, e.g. `save 60 1000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    /// Name of the setting, in lowercase.
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            bind: vec!["127.0.0.1".to_string()],
+            port: DEFAULT_PORT,
+            unixsocket: None,
+            unixsocketperm: None,
+            // Set pretty low to discourage using this in production.
+            maxclients: 250,
+            timeout: Duration::ZERO,
+            pubsub_capacity: 1024,
+            loglevel: None,
+            script_timeout: Duration::from_secs(5),
+            rdb_path: PathBuf::from("dump.rdb"),
+            // The rules Redis applies when none are configured.
+            save_rules: vec![
+                SaveRule {
+                    seconds: 3600,
+                    changes: 1,
+                },
+                SaveRule {
+                    seconds: 300,
+                    changes: 100,
+                },
+                SaveRule {
+                    seconds: 60,
+                    changes: 10000,
+                },
+            ],
+            appendonly: false,
+            aof_path: PathBuf::from("appendonly.aof"),
+            appendfsync: AppendFsync::default(),
+            replicaof: None,
+            replica_read_only: true,
+            cluster_config: None,
+            requirepass: None,
+            aclfile: None,
+            masterauth: None,
+            tls: None,
+        }
+    }
+}
+
+impl FromStr for SaveRule {
+    type Err = crate::Error;
+
+    /// Parse a rule written `<seconds> <changes>`.
+    fn from_str(src: &str) -> crate::Result<SaveRule> {
+        let mut parts = src.split_whitespace().map(str::parse);
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(seconds)), Some(Ok(changes)), None) => Ok(SaveRule { seconds, changes }),
+            _ => Err(format!("invalid save rule '{}'", src).into()),
+        }
+    }
+}
+
+impl FromStr for AppendFsync {
+    type Err = crate::Error;
+
+    /// Parse a policy written `always`, `everysec` or `no`.
+    fn from_str(src: &str) -> crate::Result<AppendFsync> {
+        match &src.to_lowercase()[..] {
+            "always" => Ok(AppendFsync::Always),
+            "everysec" => Ok(AppendFsync::EverySec),
+            "no" => Ok(AppendFsync::No),
+            _ => Err(format!("invalid appendfsync policy '{}'", src).into()),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Build the settings from `directives`, applied in order over the
+    /// defaults so that a setting given twice keeps its last value.
+    ///
+    /// As in Redis, the first `save` directive replaces the default rules
+    /// instead of adding to them, and `save ""` removes every rule. The RDB,
+    /// append only and cluster config files are looked up in `dir`, wherever
+    /// it appears.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use my_redis::{Directive, ServerConfig};
+    /// use std::time::Duration;
+    ///
+    /// let mut directives = Directive::parse_file("port 7000\ntimeout 300\n").unwrap();
+    /// // Settings given after the file override it.
+    /// directives.push(Directive::new("port", vec!["7001".to_string()]));
+    ///
+    /// let config = ServerConfig::from_directives(&directives).unwrap();
+    /// assert_eq!(config.port, 7001);
+    /// assert_eq!(config.timeout, Duration::from_secs(300));
+    /// ```
+    pub fn from_directives(directives: &[Directive]) -> crate::Result<ServerConfig> {
+        let mut config = ServerConfig::default();
+        let mut dir = None;
+        let mut save_rules = None;
+        let (mut tls_cert_file, mut tls_key_file, mut tls_ca_cert_file) = (None, None, None);
+
+        for directive in directives {
+            match &directive.name[..] {
+                "bind" if !directive.args.is_empty() => config.bind = directive.args.clone(),
+                "port" => config.port = directive.parse()?,
+                "unixsocket" => config.unixsocket = Some(directive.parse()?),
+                "unixsocketperm" => {
+                    let perm = directive.single()?;
+                    let perm = u32::from_str_radix(perm, 8).map_err(|_| directive.invalid(perm))?;
+                    config.unixsocketperm = Some(perm);
+                }
+                "maxclients" => {
+                    config.maxclients = directive.parse()?;
+                    if config.maxclients == 0 {
+                        return Err(directive.invalid("0"));
+                    }
+                }
+                "timeout" => config.timeout = Duration::from_secs(directive.parse()?),
+                "pubsub-buffer-size" => {
+                    config.pubsub_capacity = directive.parse()?;
+                    if config.pubsub_capacity == 0 {
+                        return Err(directive.invalid("0"));
+                    }
+                }
+                "loglevel" => {
+                    let level = directive.single()?;
+                    config.loglevel =
+                        Some(parse_level(level).ok_or_else(|| directive.invalid(level))?);
+                }
+                "lua-time-limit" => {
+                    config.script_timeout = Duration::from_millis(directive.parse()?)
+                }
+                "dir" => dir = Some(directive.parse::<PathBuf>()?),
+                "dbfilename" => config.rdb_path = directive.parse()?,
+                "save" => {
+                    let rules = save_rules.get_or_insert_with(Vec::new);
+                    match &directive.args[..] {
+                        [rule] if rule.is_empty() => rules.clear(),
+                        args if !args.is_empty() && args.len() % 2 == 0 => {
+                            for pair in args.chunks(2) {
+                                rules.push(pair.join(" ").parse::<SaveRule>()?);
+                            }
+                        }
+                        _ => return Err(directive.arity()),
+                    }
+                }
+                "appendonly" => config.appendonly = directive.yes_no()?,
+                "appendfilename" => config.aof_path = directive.parse()?,
+                "appendfsync" => config.appendfsync = directive.single()?.parse()?,
+                "replicaof" => {
+                    config.replicaof = match &directive.args[..] {
+                        [no, one]
+                            if no.eq_ignore_ascii_case("no") && one.eq_ignore_ascii_case("one") =>
+                        {
+                            None
+                        }
+                        [host, port] => {
+                            let port = port.parse().map_err(|_| directive.invalid(port))?;
+                            Some((host.clone(), port))
+                        }
+                        _ => return Err(directive.arity()),
+                    }
+                }
+                "replica-read-only" => config.replica_read_only = directive.yes_no()?,
+                "cluster-config-file" => config.cluster_config = Some(directive.parse()?),
+                "requirepass" => config.requirepass = Some(directive.single()?.to_string()),
+                "masterauth" => config.masterauth = Some(directive.single()?.to_string()),
+                "aclfile" => config.aclfile = Some(directive.parse()?),
+                "tls-cert-file" => tls_cert_file = Some(directive.parse()?),
+                "tls-key-file" => tls_key_file = Some(directive.parse()?),
+                "tls-ca-cert-file" => tls_ca_cert_file = Some(directive.parse()?),
+                "bind" => return Err(directive.arity()),
+                name => return Err(format!("unknown directive '{}'", name).into()),
+            }
+        }
+
+        if let Some(rules) = save_rules {
+            config.save_rules = rules;
+        }
+        if let Some(dir) = dir {
+            config.rdb_path = dir.join(config.rdb_path);
+            config.aof_path = dir.join(config.aof_path);
+            config.cluster_config = config.cluster_config.map(|path| dir.join(path));
+        }
+        config.tls = match (tls_cert_file, tls_key_file) {
+            (Some(cert_file), Some(key_file)) => Some(TlsConfig {
+                cert_file,
+                key_file,
+                ca_cert_file: tls_ca_cert_file,
+            }),
+            (None, None) if tls_ca_cert_file.is_none() => None,
+            _ => return Err("TLS requires both 'tls-cert-file' and 'tls-key-file'".into()),
+        };
+
+        Ok(config)
+    }
+}
+
+impl Directive {
+    /// Create a new directive setting `name` to `args`.
+    pub fn new(name: &str, args: Vec<String>) -> Directive {
+        Directive {
+            name: name.to_lowercase(),
+            args,
+        }
+    }
+
+    /// Parse the directives of a `redis.conf`-style file, one per line.
+    ///
+    /// Blank lines and lines starting with `#` are ignored. Arguments are
+    /// separated by spaces, and may be quoted to hold spaces or be empty:
+    /// `"..."` understands the `\n`, `\r`, `\t`, `\"` and `\\` escapes, `'...'`
+    /// only `\'`.
+    pub fn parse_file(src: &str) -> crate::Result<Vec<Directive>> {
+        let mut directives = vec![];
+
+        for (i, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut args = split_args(line)
+                .ok_or_else(|| format!("unbalanced quotes on line {}", i + 1))?
+                .into_iter();
+            // A line holding only spaces between quotes has no name.
+            if let Some(name) = args.next() {
+                directives.push(Directive::new(&name, args.collect()));
+            }
+        }
+
+        Ok(directives)
+    }
+
+    /// Returns the only argument of the directive.
+    fn single(&self) -> crate::Result<&str> {
+        match &self.args[..] {
+            [arg] => Ok(arg),
+            _ => Err(self.arity()),
+        }
+    }
+
+    /// Parse the only argument of the directive.
+    fn parse<T: FromStr>(&self) -> crate::Result<T> {
+        let arg = self.single()?;
+        arg.parse().map_err(|_| self.invalid(arg))
+    }
+
+    /// Parse the only argument of the directive, written `yes` or `no`.
+    fn yes_no(&self) -> crate::Result<bool> {
+        match &self.single()?.to_lowercase()[..] {
+            "yes" => Ok(true),
+            "no" => Ok(false),
+            arg => Err(self.invalid(arg)),
+        }
+    }
+
+    fn arity(&self) -> crate::Error {
+        format!("wrong number of arguments for '{}'", self.name).into()
+    }
+
+    fn invalid(&self, arg: &str) -> crate::Error {
+        format!("invalid argument '{}' for '{}'", arg, self.name).into()
+    }
+}
+
+/// Parse a log level, written as in Redis or as a `tracing` level.
+fn parse_level(src: &str) -> Option<LevelFilter> {
+    match &src.to_lowercase()[..] {
+        "debug" | "verbose" => Some(LevelFilter::DEBUG),
+        "notice" => Some(LevelFilter::INFO),
+        "warning" => Some(LevelFilter::WARN),
+        "nothing" => Some(LevelFilter::OFF),
+        level => level.parse().ok(),
+    }
+}
+
+/// Split a config line into its arguments, `None` if a quote is not closed.
+fn split_args(line: &str) -> Option<Vec<String>> {
+    let mut args = vec![];
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let quote = match chars.peek() {
+            None => return Some(args),
+            Some(&c) if c == '"' || c == '\'' => chars.next(),
+            Some(_) => None,
+        };
+
+        let mut arg = String::new();
+        loop {
+            match (quote, chars.next()) {
+                (None, Some(c)) if c.is_whitespace() => break,
+                (None, Some(c)) => arg.push(c),
+                (None, None) => break,
+                (Some(_), None) => return None,
+                (Some(q), Some(c)) if c == q => {
+                    // The closing quote must end the argument.
+                    if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                        return None;
+                    }
+                    break;
+                }
+                (Some('"'), Some('\\')) => match chars.next()? {
+                    'n' => arg.push('\n'),
+                    'r' => arg.push('\r'),
+                    't' => arg.push('\t'),
+                    c => arg.push(c),
+                },
+                (Some(_), Some('\\')) if chars.peek() == Some(&'\'') => arg.push(chars.next()?),
+                (Some(_), Some(c)) => arg.push(c),
+            }
+        }
+        args.push(arg);
+    }
+}
@@ -1,15 +1,16 @@
 use crate::frame::{self, Frame};
 
 use bytes::{Buf, BytesMut};
+use std::fmt;
 use std::io::{self, Cursor};
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
 
 /// Send and receive `Frame` values from a remote peer.
 ///
 /// When implementing networking protocols, a message on that protocol is
 /// often composed of several smaller messages known as frames. The purpose of
-/// `Connection` is to read and write frames on the underlying `TcpStream`.
+/// `Connection` is to read and write frames on the underlying transport, such
+/// as a `TcpStream` or a TLS stream over one.
 ///
 /// To read frames, the `Connection` uses an internal buffer, which is filled
 /// up until there are enough bytes to create a full frame. Once this happens,
@@ -19,29 +20,126 @@ use tokio::net::TcpStream;
 /// The contents of the write buffer are then written to the socket.
 #[derive(Debug)]
 pub struct Connection {
-    // The `TcpStream`. It is decorated with a `BufWriter`, which provides write
+    // The stream. It is decorated with a `BufWriter`, which provides write
     // level buffering. The `BufWriter` implementation provided by Tokio is
-    // sufficient for our needs.
-    stream: BufWriter<TcpStream>,
+    // sufficient for our needs. `None` for a detached connection.
+    stream: Option<BufWriter<Box<dyn Transport>>>,
 
     // The buffer for reading frames.
     buffer: BytesMut,
+
+    // Protocol version used to encode frames, negotiated with `HELLO`.
+    protocol: Protocol,
+
+    // User the connection is authenticated as, whose ACL commands are checked
+    // against. `None` before authenticating, and for the connections the
+    // server applies commands on its own behalf with, which are not checked.
+    user: Option<String>,
+}
+
+/// A byte stream a `Connection` reads and writes frames on.
+///
+/// Implemented for every type implementing the traits below, which includes
+/// `TcpStream`, `UnixStream` and the in-memory `DuplexStream` of Tokio.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+impl fmt::Debug for dyn Transport {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("Transport")
+    }
+}
+
+/// Version of the Redis serialization protocol spoken on a connection.
+///
+/// Connections start with RESP2 and switch to RESP3 when the client sends
+/// `HELLO 3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    /// Returns the version number used by `HELLO`.
+    pub fn version(self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
 }
 
 impl Connection {
     /// Create a new `Connection`, backed by `socket`. Read and write buffers
     /// are initialized.
-    pub fn new(socket: TcpStream) -> Connection {
+    ///
+    /// # Examples
+    ///
+    /// Frames may be exchanged over an in-memory pipe.
+    ///
+    /// ```
+    /// use my_redis::{Connection, Frame};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (client, server) = tokio::io::duplex(1024);
+    ///     let mut client = Connection::new(client);
+    ///     let mut server = Connection::new(server);
+    ///
+    ///     client.write_frame(&Frame::Simple("PING".to_string())).await.unwrap();
+    ///     let frame = server.read_frame().await.unwrap();
+    ///     assert!(matches!(frame, Some(Frame::Simple(s)) if s == "PING"));
+    /// }
+    /// ```
+    pub fn new<T: Transport + 'static>(socket: T) -> Connection {
         Connection {
-            stream: BufWriter::new(socket),
+            stream: Some(BufWriter::new(Box::new(socket))),
             // Default to a 4KB read buffer. For the use case of mini redis,
             // this is fine. However, real applications will want to tune this
             // value to their specific use case. There is a high likelihood that
             // a larger read buffer will work better.
             buffer: BytesMut::with_capacity(4 * 1024),
+            protocol: Protocol::default(),
+            user: None,
         }
     }
 
+    /// Create a `Connection` backed by no socket, used by the server to apply
+    /// commands on its own behalf, e.g. when loading the append only file.
+    ///
+    /// Reading or writing frames fails.
+    pub(crate) fn detached() -> Connection {
+        Connection {
+            stream: None,
+            buffer: BytesMut::new(),
+            protocol: Protocol::default(),
+            user: None,
+        }
+    }
+
+    /// Returns the protocol version frames are written with.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Switch the protocol version frames are written with.
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    /// Returns the user the connection is authenticated as.
+    pub(crate) fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Set the user the connection is authenticated as.
+    pub(crate) fn set_user(&mut self, user: Option<String>) {
+        self.user = user;
+    }
+
     /// Read a single `Frame` value from the underlying stream.
     ///
     /// The function waits until it has retrieved enough data to parse a frame.
@@ -66,7 +164,8 @@ impl Connection {
             //
             // On success, the number of bytes is returned. `0` indicates "end
             // of stream".
-            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+            let stream = self.stream.as_mut().ok_or_else(not_connected)?;
+            if 0 == stream.read_buf(&mut self.buffer).await? {
                 // The remote closed the connection. For this to be a clean
                 // shutdown, there should be no data in the read buffer. If
                 // there is, this means that the peer closed the socket while
@@ -147,6 +246,10 @@ impl Connection {
 
     /// Write a single `Frame` value to the underlying stream.
     ///
+    /// RESP3 frames written to a RESP2 connection are downgraded: maps and
+    /// sets become arrays, doubles and big numbers bulk strings, booleans
+    /// integers, and attributes are dropped.
+    ///
     /// The `Frame` value is written to the socket using the various `write_*`
     /// functions provided by `AsyncWrite`. Calling these functions directly on
     /// a `TcpStream` is **not** advised, as this will result in a large number of
@@ -154,72 +257,167 @@ impl Connection {
     /// write stream. The data will be written to the buffer. Once the buffer is
     /// full, it is flushed to the underlying socket.
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        // Arrays are encoded by encoding each entry. All other frame types are
-        // considered literals. For now, mini-redis is not able to encode
-        // recursive frame structures. See below for more details.
-        match frame {
-            Frame::Array(val) => {
-                // Encode the frame type prefix. For an array, it is `*`.
-                self.stream.write_u8(b'*').await?;
-
-                // Encode the length of the array.
-                self.write_decimal(val.len() as u64).await?;
-
-                // Iterate and encode each entry in the array.
-                for entry in &**val {
-                    self.write_value(entry).await?;
-                }
-            }
-            // The frame type is a literal. Encode the value directly.
-            _ => self.write_value(frame).await?,
-        }
+        self.write_value(frame).await?;
 
         // Ensure the encoded frame is written to the socket. The calls above
         // are to the buffered stream and writes. Calling `flush` writes the
         // remaining contents of the buffer to the socket.
-        self.stream.flush().await
+        self.stream()?.flush().await
+    }
+
+    /// Write `data`, frames already encoded, to the underlying stream.
+    ///
+    /// A leader streams its writes to a follower encoded once, the way they
+    /// are kept in the backlog.
+    pub(crate) async fn write_raw(&mut self, data: &[u8]) -> io::Result<()> {
+        let stream = self.stream()?;
+        stream.write_all(data).await?;
+        stream.flush().await
+    }
+
+    /// Returns the underlying stream, failing for a detached connection.
+    fn stream(&mut self) -> io::Result<&mut BufWriter<Box<dyn Transport>>> {
+        self.stream.as_mut().ok_or_else(not_connected)
     }
 
-    /// Write a frame literal to the stream
+    /// Write a frame to the stream, encoding arrays recursively.
     async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
         match frame {
             Frame::Simple(val) => {
-                self.stream.write_u8(b'+').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
+                self.stream()?.write_u8(b'+').await?;
+                self.stream()?.write_all(val.as_bytes()).await?;
+                self.stream()?.write_all(b"\r\n").await?;
             }
             Frame::Error(val) => {
-                self.stream.write_u8(b'-').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
+                self.stream()?.write_u8(b'-').await?;
+                self.stream()?.write_all(val.as_bytes()).await?;
+                self.stream()?.write_all(b"\r\n").await?;
             }
             Frame::Integer(val) => {
-                self.stream.write_u8(b':').await?;
+                self.stream()?.write_u8(b':').await?;
                 self.write_decimal(*val).await?;
             }
-            Frame::Null => {
-                self.stream.write_all(b"$-1\r\n").await?;
+            Frame::Null => match self.protocol {
+                Protocol::Resp2 => self.stream()?.write_all(b"$-1\r\n").await?,
+                Protocol::Resp3 => self.stream()?.write_all(b"_\r\n").await?,
+            },
+            Frame::NullArray => match self.protocol {
+                Protocol::Resp2 => self.stream()?.write_all(b"*-1\r\n").await?,
+                Protocol::Resp3 => self.stream()?.write_all(b"_\r\n").await?,
+            },
+            Frame::Bulk(val) => self.write_bulk(val).await?,
+            Frame::Array(val) => {
+                // Encode the frame type prefix. For an array, it is `*`.
+                self.stream()?.write_u8(b'*').await?;
+
+                // Encode the length of the array.
+                self.write_decimal(val.len() as i64).await?;
+
+                // Iterate and encode each entry in the array. Entries may be
+                // arrays themselves, async recursion requires boxing the
+                // future.
+                for entry in &**val {
+                    Box::pin(self.write_value(entry)).await?;
+                }
+            }
+            Frame::Set(val) | Frame::Push(val) => {
+                let prefix = match (self.protocol, frame) {
+                    (Protocol::Resp2, _) => b'*',
+                    (Protocol::Resp3, Frame::Set(_)) => b'~',
+                    (Protocol::Resp3, _) => b'>',
+                };
+
+                self.stream()?.write_u8(prefix).await?;
+                self.write_decimal(val.len() as i64).await?;
+
+                for entry in &**val {
+                    Box::pin(self.write_value(entry)).await?;
+                }
+            }
+            // RESP2 has no way to carry an attribute, it is left out.
+            Frame::Attribute(_) if self.protocol == Protocol::Resp2 => {}
+            Frame::Map(val) | Frame::Attribute(val) => {
+                match (self.protocol, frame) {
+                    // A RESP2 map is flattened into an array of keys and
+                    // values.
+                    (Protocol::Resp2, _) => {
+                        self.stream()?.write_u8(b'*').await?;
+                        self.write_decimal(val.len() as i64 * 2).await?;
+                    }
+                    (Protocol::Resp3, Frame::Map(_)) => {
+                        self.stream()?.write_u8(b'%').await?;
+                        self.write_decimal(val.len() as i64).await?;
+                    }
+                    (Protocol::Resp3, _) => {
+                        self.stream()?.write_u8(b'|').await?;
+                        self.write_decimal(val.len() as i64).await?;
+                    }
+                }
+
+                for (key, value) in &**val {
+                    Box::pin(self.write_value(key)).await?;
+                    Box::pin(self.write_value(value)).await?;
+                }
             }
-            Frame::Bulk(val) => {
-                let len = val.len();
+            Frame::Double(val) => {
+                let val = frame::format_double(*val);
 
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(len as u64).await?;
-                self.stream.write_all(val).await?;
-                self.stream.write_all(b"\r\n").await?;
+                match self.protocol {
+                    Protocol::Resp2 => self.write_bulk(val.as_bytes()).await?,
+                    Protocol::Resp3 => {
+                        self.stream()?.write_u8(b',').await?;
+                        self.stream()?.write_all(val.as_bytes()).await?;
+                        self.stream()?.write_all(b"\r\n").await?;
+                    }
+                }
             }
-            // Encoding an `Array` from within a value cannot be done using a
-            // recursive strategy. In general, async fns do not support
-            // recursion. Mini-redis has not needed to encode nested arrays yet,
-            // so for now it is skipped.
-            Frame::Array(_val) => unreachable!(),
+            Frame::Boolean(val) => match self.protocol {
+                Protocol::Resp2 => {
+                    self.stream()?.write_u8(b':').await?;
+                    self.write_decimal(*val as i64).await?;
+                }
+                Protocol::Resp3 => {
+                    let val: &[u8] = if *val { b"#t\r\n" } else { b"#f\r\n" };
+                    self.stream()?.write_all(val).await?;
+                }
+            },
+            Frame::BigNumber(val) => match self.protocol {
+                Protocol::Resp2 => self.write_bulk(val.as_bytes()).await?,
+                Protocol::Resp3 => {
+                    self.stream()?.write_u8(b'(').await?;
+                    self.stream()?.write_all(val.as_bytes()).await?;
+                    self.stream()?.write_all(b"\r\n").await?;
+                }
+            },
+            Frame::Verbatim(format, val) => match self.protocol {
+                Protocol::Resp2 => self.write_bulk(val).await?,
+                Protocol::Resp3 => {
+                    self.stream()?.write_u8(b'=').await?;
+                    self.write_decimal((format.len() + 1 + val.len()) as i64)
+                        .await?;
+                    self.stream()?.write_all(format.as_bytes()).await?;
+                    self.stream()?.write_u8(b':').await?;
+                    self.stream()?.write_all(val).await?;
+                    self.stream()?.write_all(b"\r\n").await?;
+                }
+            },
         }
 
         Ok(())
     }
 
+    /// Write a bulk string to the stream.
+    async fn write_bulk(&mut self, val: &[u8]) -> io::Result<()> {
+        self.stream()?.write_u8(b'$').await?;
+        self.write_decimal(val.len() as i64).await?;
+        self.stream()?.write_all(val).await?;
+        self.stream()?.write_all(b"\r\n").await?;
+
+        Ok(())
+    }
+
     /// Write a decimal frame to the stream
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
+    async fn write_decimal(&mut self, val: i64) -> io::Result<()> {
         use std::io::Write;
 
         // Convert the value to a string
@@ -228,9 +426,13 @@ impl Connection {
         write!(&mut buf, "{}", val)?;
 
         let pos = buf.position() as usize;
-        self.stream.write_all(&buf.get_ref()[..pos]).await?;
-        self.stream.write_all(b"\r\n").await?;
+        self.stream()?.write_all(&buf.get_ref()[..pos]).await?;
+        self.stream()?.write_all(b"\r\n").await?;
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn not_connected() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "connection is detached")
+}
@@ -1,10 +1,43 @@
+use tokio::sync::futures::Notified;
 use tokio::sync::{broadcast, Notify};
 use tokio::time::{self, Duration, Instant};
 
+use crate::acl::{AclError, AclState};
+use crate::aof::{self, Aof, Replay};
+use crate::cluster::{ClusterState, Redirect};
+use crate::replication::{self, LinkState, PSyncStart, ReplicaStream, Replication, Role};
+use crate::scripting;
+use crate::{AppendFsync, Command, Frame, SaveRule, ServerConfig};
+
 use bytes::Bytes;
-use std::collections::{BTreeSet, HashMap};
-use std::sync::{Arc, Mutex};
-use tracing::debug;
+use parking_lot::ReentrantMutex;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+
+mod sorted_set;
+pub(crate) use sorted_set::{LexBound, RangeBy, ScoreBound, ZAddOptions};
+use sorted_set::SortedSet;
+
+mod scan;
+pub(crate) use scan::glob_match;
+use scan::{ScanMap, ScanSet};
+
+mod stream;
+pub use stream::{StreamEntry, StreamId};
+pub(crate) use stream::{GroupEntry, NewId, PendingInfo, PendingSummary, StreamTrim};
+use stream::Stream;
+
+mod rdb;
+
+/// Delay before a snapshot which failed is attempted again by the save rules.
+const SAVE_RETRY_DELAY: Duration = Duration::from_secs(5);
 
 /// A wrapper around a "Db" instance. It allow us to orderly clean up of the db by signalling the background purge task 
 /// to shut down when this struct is dropped
@@ -35,29 +68,70 @@ pub(crate) struct Db{
 
 #[derive(Debug)]
 struct Shared{
-    /// The shared state is guarded by a mutex. This is a `std::sync::Mutex` and
+    /// The shared state is guarded by a mutex. This is a blocking mutex and
     /// not a Tokio mutex. This is because there are no asynchronous operations
     /// being performed while holding the mutex. Additionally, the critical
     /// sections are very small.
     ///
     /// A Tokio mutex is mostly intended to be used when locks need to be held
     /// across `.await` yield points. All other cases are **usually** best
-    /// served by a std mutex. If the critical section does not include any
+    /// served by a blocking mutex. If the critical section does not include any
     /// async operations but is long (CPU intensive or performing blocking
     /// operations), then the entire operation, including waiting for the mutex,
     /// is considered a "blocking" operation and `tokio::task::spawn_blocking`
     /// should be used.
-    state: Mutex<State>,
+    ///
+    /// The mutex is reentrant so that `EXEC` can hold it while the queued
+    /// commands lock it again, making the transaction atomic. The `RefCell`
+    /// hands out the mutable access a reentrant mutex cannot.
+    state: ReentrantMutex<RefCell<State>>,
 
     /// Notifies the background task handling entry expiration. The background
     /// task waits on this to be notified, then checks for expired values or the
     /// shutdown signal.
     background_task: Notify,
+
+    /// Notifies the clients blocked in `XREAD` or `XREADGROUP` that an entry
+    /// was appended to a stream. Woken clients check the streams they are
+    /// waiting on and go back to sleep if nothing new is there for them.
+    streams: Notify,
+
+    /// Time a Lua script runs before being aborted unless it wrote, zero
+    /// meaning no limit. See `ServerConfig::script_timeout`.
+    script_timeout: Duration,
+
+    /// Status of the script running, read by the other clients without the
+    /// lock the script holds.
+    script: scripting::Running,
+
+    /// File snapshots are written to and loaded from.
+    rdb_path: PathBuf,
+
+    /// Conditions under which the background task takes a snapshot.
+    save_rules: Vec<SaveRule>,
+
+    /// Whether the writes are logged to the append only file.
+    appendonly: bool,
+
+    /// Append only file the writes are logged to.
+    aof_path: PathBuf,
+
+    /// When the append only file is flushed to disk.
+    appendfsync: AppendFsync,
+
+    /// Whether clients are refused writes while following a leader.
+    replica_read_only: bool,
+
+    /// Password the follower authenticates with to its leader.
+    masterauth: Option<String>,
+
+    /// Number of messages buffered per pub/sub channel.
+    pubsub_capacity: usize,
 }
 
 #[derive(Debug)]
 struct State{
-    entries : HashMap<String, Entry>,
+    entries : ScanMap<String, Entry>,
     /// The pub/sub key-space. Redis uses a **separate** key space for key-value
     /// and pub/sub. `mini-redis` handles this by using a separate `HashMap`.
     pub_sub : HashMap<String, broadcast::Sender<Bytes>>,
@@ -73,23 +147,225 @@ struct State{
     /// not enough for the key. String is used to break these ties
     expirations: BTreeSet<(Instant, String)>,
 
+    /// Last version given to an entry. Incremented each time an entry is
+    /// written, so that a version is never reused, even across keys.
+    version: u64,
+
+    /// Lua scripts loaded by `EVAL` or `SCRIPT LOAD`, by the hexadecimal SHA1
+    /// digest of their source.
+    scripts: HashMap<String, Bytes>,
+
+    /// Number of writes made to the key space since the server started. Each
+    /// written entry and each removed key counts as one write.
+    writes: u64,
+
+    /// Progress of the key space since the last snapshot.
+    snapshots: Snapshots,
+
+    /// Append only file the writes are logged to, once loaded. `None` when
+    /// disabled.
+    aof: Option<Aof>,
+
+    /// Number of nested `Db::atomically` calls running.
+    atomic_depth: usize,
+
+    /// Commands propagated within `Db::atomically`, logged together once the
+    /// outermost call returns.
+    propagated: Vec<Frame>,
+
+    /// Replication state, streaming the propagated writes to the followers.
+    repl: Replication,
+
+    /// Topology of the cluster, `None` outside of cluster mode.
+    cluster: Option<ClusterState>,
+
+    /// Users clients authenticate as, and what they are granted.
+    acl: AclState,
+
     shutdown: bool
 }
 
+/// Tracks when the key space was last saved, to apply the save rules.
+#[derive(Debug)]
+struct Snapshots{
+    /// Value of `State::writes` when the last successful snapshot was taken.
+    writes: u64,
+
+    /// Instant of the last successful snapshot, or of the start of the server.
+    last_save: Instant,
+
+    /// Instant of the last failed snapshot, if it failed. Failed snapshots are
+    /// retried after a delay instead of on every tick of the background task.
+    last_failure: Option<Instant>,
+
+    /// Whether a `BGSAVE` is running.
+    in_progress: bool,
+}
+
 
 
 /// Entry in the key-value store
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Entry{
-    data: Bytes,
+    value: Value,
     
     /// Instant at which the entry expires and should be removed from the database
-    expires_at: Option<Instant>
+    expires_at: Option<Instant>,
+
+    /// Version of the entry, replaced by a new one each time the entry is
+    /// written. `WATCH` compares versions to detect modified keys.
+    version: u64,
+}
+
+/// The value stored at a key.
+///
+/// Redis keys are typed: a command only operates on the kinds of value it
+/// understands and replies with a `WRONGTYPE` error otherwise.
+#[derive(Debug, Clone)]
+enum Value{
+    /// Binary safe string, written by `SET`.
+    String(Bytes),
+
+    /// List of elements, pushed and popped at both ends.
+    List(VecDeque<Bytes>),
+
+    /// Map of fields to values.
+    Hash(ScanMap<Bytes, Bytes>),
+
+    /// Unordered collection of unique members.
+    Set(ScanSet<Bytes>),
+
+    /// Unique members ordered by score.
+    SortedSet(SortedSet),
+
+    /// Append-only log of entries, with consumer groups.
+    Stream(Stream),
+}
+
+/// Which end of a list an element is pushed to or popped from.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ListEnd{
+    Left,
+    Right,
+}
+
+/// Algebra applied by the multi-key set commands.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SetOp{
+    /// Members present in every set (`SINTER`).
+    Inter,
+
+    /// Members present in any set (`SUNION`).
+    Union,
+
+    /// Members of the first set not present in any of the others (`SDIFF`).
+    Diff,
+}
+
+/// Conditions of the expire commands.
+///
+/// A key without expiration is considered to live forever: `GT` never
+/// applies to it while `LT` always does.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ExpireOptions{
+    /// Only set an expiration if the key has none.
+    pub(crate) nx: bool,
+
+    /// Only set an expiration if the key already has one.
+    pub(crate) xx: bool,
+
+    /// Only set an expiration greater than the current one.
+    pub(crate) gt: bool,
+
+    /// Only set an expiration less than the current one.
+    pub(crate) lt: bool,
+}
+
+/// Error replied to the client when a command cannot be applied to the
+/// current state of the key space.
+///
+/// Unlike protocol errors, these do not terminate the connection. The command
+/// writes the message back to the client as an `Error` frame.
+#[derive(Debug)]
+pub(crate) enum DbError{
+    /// The key holds a value of a different type than the command expects.
+    WrongType,
+
+    /// The command requires the key to exist.
+    NoSuchKey,
+
+    /// A list index points outside of the list.
+    IndexOutOfRange,
+
+    /// `HINCRBY` was applied to a hash field not holding an integer.
+    HashValueNotInteger,
+
+    /// An increment would overflow a 64 bit signed integer.
+    Overflow,
+
+    /// The counter commands were applied to a string not holding an
+    /// integer.
+    NotInteger,
+
+    /// `INCRBYFLOAT` was applied to a string not holding a number.
+    NotFloat,
+
+    /// `INCRBYFLOAT` would store a `NaN` or infinite value.
+    NaNOrInfinity,
+
+    /// A sorted set score increment resulted in `NaN`.
+    ScoreNaN,
+
+    /// `XADD` was given `0-0` as the entry identifier.
+    StreamIdZero,
+
+    /// `XADD` was given an identifier not greater than the last one.
+    StreamIdTooSmall,
+
+    /// The stream or its consumer group does not exist.
+    NoGroup,
+
+    /// `XGROUP CREATE` was given the name of an existing group.
+    BusyGroup,
+
+    /// `XGROUP CREATE` was applied to a missing key without `MKSTREAM`.
+    NoStream,
+
+    /// `BGSAVE` was called while a snapshot is being taken.
+    SaveInProgress,
+
+    /// `BGREWRITEAOF` was called without the append only file enabled.
+    AofDisabled,
+
+    /// `BGREWRITEAOF` was called while the append only file is rewritten.
+    RewriteInProgress,
+
+    /// A client sent a write to a read only follower.
+    ReadOnly,
+
+    /// A client sent a command before authenticating.
+    NoAuth,
+
+    /// `AUTH` was given a wrong password or an unknown user.
+    WrongPass,
+
+    /// `AUTH` was given a password while none is required.
+    NoPassword,
+
+    /// A client sent a command while a script is running.
+    Busy,
+
+    /// `SCRIPT KILL` was called while no script is running.
+    NotBusy,
+
+    /// `SCRIPT KILL` was called on a script which already wrote to the key
+    /// space.
+    Unkillable,
 }
 
 impl DbDropGuard{
-    pub(crate) fn new() -> DbDropGuard{
-        DbDropGuard{db : Db::new()}
+    pub(crate) fn new(config: &ServerConfig) -> DbDropGuard{
+        DbDropGuard{db : Db::new(config)}
     }
     pub(crate) fn db(&self) -> Db{
         self.db.clone()
@@ -103,32 +379,67 @@ impl Drop for DbDropGuard {
 }
 
 impl Db{
-    pub(crate) fn new() ->Db {
+    pub(crate) fn new(config: &ServerConfig) ->Db {
         let shared = Arc::new(Shared{
-            state: Mutex::new(State { 
-                entries: HashMap::new(), 
+            state: ReentrantMutex::new(RefCell::new(State { 
+                entries: ScanMap::default(), 
                 pub_sub: HashMap::new(), 
                 expirations: BTreeSet::new(), 
+                version: 0,
+                scripts: HashMap::new(),
+                writes: 0,
+                snapshots: Snapshots{
+                    writes: 0,
+                    last_save: Instant::now(),
+                    last_failure: None,
+                    in_progress: false,
+                },
+                aof: None,
+                atomic_depth: 0,
+                propagated: vec![],
+                repl: Replication::new(),
+                cluster: None,
+                acl: AclState::new(config.requirepass.as_deref()),
                 shutdown: false,
-            }),
-            background_task : Notify::new()
+            })),
+            background_task : Notify::new(),
+            streams: Notify::new(),
+            script_timeout: config.script_timeout,
+            script: scripting::Running::new(config.script_timeout),
+            rdb_path: config.rdb_path.clone(),
+            save_rules: config.save_rules.clone(),
+            appendonly: config.appendonly,
+            aof_path: config.aof_path.clone(),
+            appendfsync: config.appendfsync,
+            replica_read_only: config.replica_read_only,
+            masterauth: config.masterauth.clone(),
+            pubsub_capacity: config.pubsub_capacity,
         });
 
         tokio::spawn(purge_expired_tasks(shared.clone()));
+        if !shared.save_rules.is_empty(){
+            tokio::spawn(save_snapshots_task(Db{shared: shared.clone()}));
+        }
         Db{shared}
     }
 
-    pub(crate) fn get(&self, key: &str)->Option<Bytes>{
+    pub(crate) fn get(&self, key: &str)->Result<Option<Bytes>, DbError>{
         // Acquire the lock, get the entry and clone the value
         
         //the clone is shallow clone
-        let state = self.shared.state.lock().unwrap();
-        state.entries.get(key).map(|entry| entry.data.clone())
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+        match state.entries.get(key).map(|entry| &entry.value){
+            Some(Value::String(data)) => Ok(Some(data.clone())),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
     }
 
     // if a value is already associated with a key, remove it
     pub(crate) fn set(&self, key: String, value: Bytes, expire: Option<Duration>){
-        let mut state = self.shared.state.lock().unwrap();
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
 
         let mut notify = false; 
         
@@ -150,9 +461,10 @@ impl Db{
         });
 
         // Insert the entry into the 'HashMap'
+        let version = state.next_version();
         let prev_key_pair = state.entries.insert(
             key.clone(),
-            Entry { data: value, expires_at: expires_at }
+            Entry { value: Value::String(value), expires_at, version }
         );
 
         //remove if the same key exist  
@@ -161,123 +473,2222 @@ impl Db{
                 state.expirations.remove(&(when, key.clone()));
             }
         }
+
+        // Track the expiration so the background task purges the key
+        if let Some(when) = expires_at{
+            state.expirations.insert((when, key));
+        }
         // release the mutex before notifying, because help to reduce contention
         // dropping needs to acquire a mutex, if we dont drop it, it will cause busy
         // loop
         drop(state);
+        drop(guard);
 
         if notify{
             self.shared.background_task.notify_one();
         }
     }
 
-    pub(crate) fn subscribe(&self, key: String) -> broadcast::Receiver<Bytes>{
-        use std::collections::hash_map::Entry;
+    /// Add `delta` to the integer stored as a string at `key`, a missing key
+    /// counting as `0`. The time to live of the key is left untouched.
+    ///
+    /// Returns the value after the increment.
+    pub(crate) fn incr_by(&self, key: String, delta: i64) -> Result<i64, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
 
-        let mut state = self.shared.state.lock().unwrap();
+        let entry = state.entry_or_insert(key.clone(), || Value::String(Bytes::from_static(b"0")));
 
-        // if there is no entry for the requested channel, then create a new
-        // broadcast channel and associate it with the key. If one already
-        // exists, return an associated receiver.
-        match state.pub_sub.entry(key){
-            Entry::Occupied(e) => e.get().subscribe(),
-            Entry::Vacant(e) => {
-                // A message would stored in the channel, until all subscribers 
-                // have seen it.
-                // This means that a slow subscriber could result in messages being 
-                // held indefinitely
-                //
-                // When the channel's capacity fills up, publishing will result
-                // in old messages being dropped. This prevents slow consumers
-                // from blocking the entire system.
-                let (tx, rx) = broadcast::channel(1024);
-                e.insert(tx);
-                rx
+        let current = match &entry.value{
+            Value::String(data) => std::str::from_utf8(data)
+                .ok()
+                .and_then(|data| data.parse::<i64>().ok())
+                .ok_or(DbError::NotInteger)?,
+            _ => return Err(DbError::WrongType),
+        };
+
+        let value = current.checked_add(delta).ok_or(DbError::Overflow)?;
+        entry.value = Value::String(Bytes::from(value.to_string()));
+
+        state.touch(&key);
+        Ok(value)
+    }
+
+    /// Floating point counterpart of `incr_by`.
+    pub(crate) fn incr_by_float(&self, key: String, delta: f64) -> Result<f64, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let entry = state.entry_or_insert(key.clone(), || Value::String(Bytes::from_static(b"0")));
+
+        let current = match &entry.value{
+            Value::String(data) => std::str::from_utf8(data)
+                .ok()
+                .and_then(|data| data.parse::<f64>().ok())
+                .filter(|current| current.is_finite())
+                .ok_or(DbError::NotFloat)?,
+            _ => return Err(DbError::WrongType),
+        };
+
+        let value = current + delta;
+        if !value.is_finite(){
+            return Err(DbError::NaNOrInfinity);
+        }
+        entry.value = Value::String(Bytes::from(value.to_string()));
+
+        state.touch(&key);
+        Ok(value)
+    }
+
+    /// Set the expiration of `key` to `when`, subject to `options`. A `when`
+    /// which is already past deletes the key.
+    ///
+    /// Returns `false` if the key does not exist or the options prevented the
+    /// update.
+    pub(crate) fn expire(&self, key: &str, when: Instant, options: ExpireOptions) -> bool{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let current = match state.entries.get(key){
+            Some(entry) => entry.expires_at,
+            None => return false,
+        };
+
+        // A key without expiration lives forever, which is greater than any
+        // expiration.
+        let allowed = match current{
+            Some(_) if options.nx => false,
+            None if options.xx || options.gt => false,
+            Some(current) if options.gt => when > current,
+            Some(current) if options.lt => when < current,
+            _ => true,
+        };
+
+        if !allowed{
+            return false;
+        }
+
+        if when <= Instant::now(){
+            state.remove(key);
+            return true;
+        }
+
+        let notify = state.set_expiration(key, Some(when));
+        drop(state);
+        drop(guard);
+
+        if notify{
+            self.shared.background_task.notify_one();
+        }
+        true
+    }
+
+    /// Returns the time to live of `key`, `Some(None)` standing for a key
+    /// without expiration and `None` for a missing key.
+    pub(crate) fn ttl(&self, key: &str) -> Option<Option<Duration>>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        let entry = state.entries.get(key)?;
+        Some(entry.expires_at.map(|when| when.saturating_duration_since(Instant::now())))
+    }
+
+    /// Remove the expiration of `key`, returning `false` if the key does not
+    /// exist or has no expiration.
+    pub(crate) fn persist(&self, key: &str) -> bool{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        match state.entries.get(key){
+            Some(entry) if entry.expires_at.is_some() => {
+                state.set_expiration(key, None);
+                true
             }
+            _ => false,
         }
     }
 
-    pub(crate) fn publish(&self, key: &str, value: Bytes) -> usize{
-        let state = self.shared.state.lock().unwrap();
+    /// Remove `keys`, returning the number of keys that existed.
+    pub(crate) fn del(&self, keys: &[String]) -> usize{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
 
-        state.pub_sub
-            .get(key)
-            // on a successful message send on the broadcast channel
-            // the number of subscribers is returned.
-            // Error means there are no receivers
-            .map(|tx| tx.send(value).unwrap_or(0))
-            .unwrap_or(0)
+        keys.iter().filter(|key| state.remove(key).is_some()).count()
     }
 
-    /// signals the purge background task to shut down
-    fn shutdown_purge_task(&self){
-        let mut state = self.shared.state.lock().unwrap();
-        state.shutdown = true;
+    /// Remove `keys` like `del`, but drop their values on a background task.
+    ///
+    /// Freeing a large aggregate takes time, which would otherwise be spent
+    /// holding the lock.
+    pub(crate) fn unlink(&self, keys: &[String]) -> usize{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let removed: Vec<Entry> = keys.iter().filter_map(|key| state.remove(key)).collect();
         drop(state);
-        self.shared.background_task.notify_one()
+        drop(guard);
+
+        let count = removed.len();
+        if count > 0{
+            tokio::task::spawn_blocking(move || drop(removed));
+        }
+        count
     }
-}
 
-impl Shared{
-    /// purge all expired keys and return the "Instant" at which the
-    /// next key will expire. the background task will sleep until this
-    /// instant
-    fn purge_expired_keys(&self) -> Option<Instant>{
-        let mut state = self.state.lock().unwrap();
+    /// Returns the number of `keys` that exist, a key given several times
+    /// being counted as many times.
+    pub(crate) fn exists(&self, keys: &[String]) -> usize{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
 
-        if state.shutdown{
-            // the database is shutting down.
-            // All handles to the share state have dropped
-            return None;
+        keys.iter().filter(|key| state.entries.contains_key(*key)).count()
+    }
+
+    /// Returns the name of the type of the value stored at `key`, `none` if
+    /// the key does not exist.
+    pub(crate) fn key_type(&self, key: &str) -> &'static str{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        state.entries.get(key).map_or("none", |entry| entry.value.type_name())
+    }
+
+    /// Move the value stored at `src` to `dst`, along with its expiration.
+    ///
+    /// With `nx`, nothing happens if `dst` exists. Returns whether the value
+    /// was moved.
+    pub(crate) fn rename(&self, src: &str, dst: &str, nx: bool) -> Result<bool, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        if !state.entries.contains_key(src){
+            return Err(DbError::NoSuchKey);
+        }
+        if nx && state.entries.contains_key(dst){
+            return Ok(false);
+        }
+        if src == dst{
+            // `RENAMENX` does not rename a key onto itself, as it exists.
+            return Ok(!nx);
         }
-        // This is needed to make the borrow checker happy. In short, `lock()`
-        // returns a `MutexGuard` and not a `&mut State`. The borrow checker is
-        // not able to see "through" the mutex guard and determine that it is
-        // safe to access both `state.expirations` and `state.entries` mutably,
-        // so we get a "real" mutable reference to `State` outside of the loop.
-        let state = &mut *state;
 
-        let now  = Instant::now();
+        let entry = state.remove(src).unwrap();
+        let stream = matches!(entry.value, Value::Stream(_));
+        state.remove(dst);
+        state.insert(dst.to_string(), entry);
+        drop(state);
+        drop(guard);
 
-        while let Some(&(when, ref key)) = state.expirations.iter().next(){
-            if when > now{
-                return Some(when);
+        // A stream showing up under a new name may unblock readers.
+        if stream{
+            self.shared.streams.notify_waiters();
+        }
+        Ok(true)
+    }
+
+    /// Copy the value stored at `src` to `dst`, along with its expiration.
+    ///
+    /// Unless `replace` is set, nothing happens if `dst` exists. Returns
+    /// whether the value was copied.
+    pub(crate) fn copy(&self, src: &str, dst: &str, replace: bool) -> bool{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let entry = match state.entries.get(src){
+            Some(entry) => Entry{
+                value: entry.value.clone(),
+                expires_at: entry.expires_at,
+                version: 0,
+            },
+            None => return false,
+        };
+
+        if state.entries.contains_key(dst){
+            if !replace{
+                return false;
             }
-            state.entries.remove(key);
-            state.expirations.remove(&(when, key.clone()));
+            state.remove(dst);
         }
-        None
+        let stream = matches!(entry.value, Value::Stream(_));
+        state.insert(dst.to_string(), entry);
+        drop(state);
+        drop(guard);
+
+        if stream{
+            self.shared.streams.notify_waiters();
+        }
+        true
     }
 
-    fn is_shutdown(&self) -> bool{
-        self.state.lock().unwrap().shutdown
+    /// Returns the version of the entry stored at `key`, `None` if the key does
+    /// not exist. The version changes each time the key is written.
+    pub(crate) fn version(&self, key: &str) -> Option<u64>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        state.entries.get(key).map(|entry| entry.version)
     }
-}
 
+    /// Call `f` while holding the lock on the shared state, unless one of the
+    /// `watched` keys no longer has the recorded version.
+    ///
+    /// The lock is reentrant: the calls `f` makes on the `Db` from the same
+    /// thread do not block, and no other connection observes the state until
+    /// `f` returns. `f` must therefore not yield to the runtime.
+    ///
+    /// Returns `None` if `f` was not called.
+    pub(crate) fn atomically<R>(&self, watched: &[(String, Option<u64>)], f: impl FnOnce() -> R) -> Option<R>{
+        let guard = self.shared.state.lock();
 
-impl State{
-    fn next_expiration(&self)-> Option<Instant>{
-        self.expirations
+        let state = guard.borrow();
+        let modified = watched
             .iter()
-            .next()
-            .map(|expiration| expiration.0)
+            .any(|(key, version)| state.entries.get(key).map(|entry| entry.version) != *version);
+        drop(state);
+
+        if modified{
+            return None;
+        }
+
+        guard.borrow_mut().atomic_depth += 1;
+        let result = f();
+
+        let mut state = guard.borrow_mut();
+        state.atomic_depth -= 1;
+        if state.atomic_depth == 0 && !state.propagated.is_empty(){
+            let propagated = std::mem::take(&mut state.propagated);
+            state.log(&propagated);
+        }
+        Some(result)
     }
-}
 
-async fn purge_expired_tasks(shared: Arc<Shared>){
-    while !shared.is_shutdown(){
-        if let Some(when) = shared.purge_expired_keys(){
-            // Wait until the next key expires **or** until the background task
-            // is notified. If the task is notified, then it must reload its
-            // state as new keys have been set to expire early. This is done by
-            // looping.
-            tokio::select!{
-                _ = time::sleep_until(when) => {}
-                _ = shared.background_task.notified() => {}
-            }
-        }else{
+    /// Returns the number of writes made to the key space since the server
+    /// started. Comparing the values before and after applying a command
+    /// tells whether it wrote anything.
+    pub(crate) fn writes(&self) -> u64{
+        self.shared.state.lock().borrow().writes
+    }
+
+    /// Propagate a write made to the key space, as the commands `frames`
+    /// reproducing it, to the append only file and the followers.
+    ///
+    /// Within `atomically`, the commands are held back until the outermost
+    /// call returns, then logged together as a transaction.
+    pub(crate) fn propagate(&self, frames: Vec<Frame>){
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
 
+        if frames.is_empty(){
+            return;
+        }
+        if state.atomic_depth > 0{
+            state.propagated.extend(frames);
+        } else {
+            state.log(&frames);
+        }
+    }
+
+    /// Returns the maximum time a Lua script may run, zero meaning no limit.
+    pub(crate) fn script_timeout(&self) -> Duration{
+        self.shared.script_timeout
+    }
+
+    /// Returns the status of the script running, if any.
+    pub(crate) fn running_script(&self) -> &scripting::Running{
+        &self.shared.script
+    }
+
+    /// Add `script` to the script cache, returning the SHA1 digest it can be
+    /// run with.
+    pub(crate) fn script_load(&self, script: Bytes) -> String{
+        let sha1 = sha1_smol::Sha1::from(&script).digest().to_string();
+
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        state.scripts.entry(sha1.clone()).or_insert(script);
+        sha1
+    }
+
+    /// Returns the cached script with the SHA1 digest `sha1`.
+    pub(crate) fn script(&self, sha1: &str) -> Option<Bytes>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        state.scripts.get(&sha1.to_lowercase()).cloned()
+    }
+
+    /// Returns, for each digest of `sha1s`, whether the script is cached.
+    pub(crate) fn scripts_exist(&self, sha1s: &[String]) -> Vec<bool>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        sha1s.iter().map(|sha1| state.scripts.contains_key(&sha1.to_lowercase())).collect()
+    }
+
+    /// Empty the script cache.
+    pub(crate) fn script_flush(&self){
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        state.scripts.clear();
+    }
+
+    /// Returns all the keys matching `pattern`.
+    pub(crate) fn keys(&self, pattern: &[u8]) -> Vec<String>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        state.entries
+            .keys()
+            .filter(|key| glob_match(pattern, key.as_bytes()))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns about `count` keys following `cursor`, along with the cursor to
+    /// pass to the next call, `0` once all keys were returned.
+    ///
+    /// Keys are filtered by `pattern` and `type_name` after being picked, so a
+    /// page may be empty even though the iteration is not complete.
+    pub(crate) fn scan(
+        &self,
+        cursor: u64,
+        count: usize,
+        pattern: Option<&[u8]>,
+        type_name: Option<&str>,
+    ) -> (u64, Vec<String>){
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        let (cursor, page) = state.entries.page(cursor, count);
+
+        let keys = page
+            .into_iter()
+            .filter(|(key, _)| pattern.is_none_or(|pattern| glob_match(pattern, key.as_bytes())))
+            .filter(|(_, entry)| type_name.is_none_or(|name| entry.value.type_name().eq_ignore_ascii_case(name)))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        (cursor, keys)
+    }
+
+    /// Iterate over the fields of the hash stored at `key` like `scan`.
+    pub(crate) fn hscan(
+        &self,
+        key: &str,
+        cursor: u64,
+        count: usize,
+        pattern: Option<&[u8]>,
+    ) -> Result<(u64, Vec<(Bytes, Bytes)>), DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        let hash = match state.entries.get(key){
+            Some(entry) => entry.value.as_hash()?,
+            None => return Ok((0, vec![])),
+        };
+
+        let (cursor, page) = hash.page(cursor, count);
+
+        let fields = page
+            .into_iter()
+            .filter(|(field, _)| pattern.is_none_or(|pattern| glob_match(pattern, field)))
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect();
+
+        Ok((cursor, fields))
+    }
+
+    /// Iterate over the members of the set stored at `key` like `scan`.
+    pub(crate) fn sscan(
+        &self,
+        key: &str,
+        cursor: u64,
+        count: usize,
+        pattern: Option<&[u8]>,
+    ) -> Result<(u64, Vec<Bytes>), DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        let set = match state.entries.get(key){
+            Some(entry) => entry.value.as_set()?,
+            None => return Ok((0, vec![])),
+        };
+
+        let (cursor, page) = set.page(cursor, count);
+
+        let members = page
+            .into_iter()
+            .filter(|member| pattern.is_none_or(|pattern| glob_match(pattern, member)))
+            .cloned()
+            .collect();
+
+        Ok((cursor, members))
+    }
+
+    /// Iterate over the members of the sorted set stored at `key` like
+    /// `scan`.
+    pub(crate) fn zscan(
+        &self,
+        key: &str,
+        cursor: u64,
+        count: usize,
+        pattern: Option<&[u8]>,
+    ) -> Result<(u64, Vec<(Bytes, f64)>), DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        let zset = match state.entries.get(key){
+            Some(entry) => entry.value.as_sorted_set()?,
+            None => return Ok((0, vec![])),
+        };
+
+        let (cursor, page) = zset.page(cursor, count);
+
+        let members = page
+            .into_iter()
+            .filter(|(member, _)| pattern.is_none_or(|pattern| glob_match(pattern, member)))
+            .map(|(member, score)| (member.clone(), score))
+            .collect();
+
+        Ok((cursor, members))
+    }
+
+    /// Push `values` one by one onto the given end of the list stored at
+    /// `key`, creating the list if needed. Returns the new length of the list.
+    pub(crate) fn push(&self, key: String, end: ListEnd, values: Vec<Bytes>) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let entry = state.entry_or_insert(key.clone(), || Value::List(VecDeque::new()));
+        let list = entry.value.as_list_mut()?;
+
+        for value in values{
+            match end{
+                ListEnd::Left => list.push_front(value),
+                ListEnd::Right => list.push_back(value),
+            }
+        }
+
+        let len = list.len();
+        state.touch(&key);
+        Ok(len)
+    }
+
+    /// Pop up to `count` elements from the given end of the list stored at
+    /// `key`. Returns `None` if the key does not exist.
+    pub(crate) fn pop(&self, key: &str, end: ListEnd, count: usize) -> Result<Option<Vec<Bytes>>, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let list = match state.get_mut(key){
+            Some(entry) => entry.value.as_list_mut()?,
+            None => return Ok(None),
+        };
+
+        let count = count.min(list.len());
+        let popped = match end{
+            ListEnd::Left => list.drain(..count).collect(),
+            ListEnd::Right => list.drain(list.len() - count..).rev().collect(),
+        };
+
+        if count > 0{
+            state.touch(key);
+        }
+        state.remove_if_empty(key);
+        Ok(Some(popped))
+    }
+
+    /// Returns the elements of the list stored at `key` between the `start`
+    /// and `stop` indices, both inclusive. Negative indices count from the
+    /// tail of the list.
+    pub(crate) fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<Bytes>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        let list = match state.entries.get(key){
+            Some(entry) => entry.value.as_list()?,
+            None => return Ok(vec![]),
+        };
+
+        Ok(match clamp_range(start, stop, list.len()){
+            Some((start, stop)) => list.range(start..=stop).cloned().collect(),
+            None => vec![],
+        })
+    }
+
+    /// Returns the length of the list stored at `key`, `0` if it does not
+    /// exist.
+    pub(crate) fn llen(&self, key: &str) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_list()?.len()),
+            None => Ok(0),
+        }
+    }
+
+    /// Returns the element at `index` in the list stored at `key`.
+    pub(crate) fn lindex(&self, key: &str, index: i64) -> Result<Option<Bytes>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        let list = match state.entries.get(key){
+            Some(entry) => entry.value.as_list()?,
+            None => return Ok(None),
+        };
+
+        Ok(list_index(index, list.len()).map(|index| list[index].clone()))
+    }
+
+    /// Replaces the element at `index` in the list stored at `key`.
+    pub(crate) fn lset(&self, key: &str, index: i64, value: Bytes) -> Result<(), DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let list = match state.get_mut(key){
+            Some(entry) => entry.value.as_list_mut()?,
+            None => return Err(DbError::NoSuchKey),
+        };
+
+        let index = list_index(index, list.len()).ok_or(DbError::IndexOutOfRange)?;
+        list[index] = value;
+        state.touch(key);
+        Ok(())
+    }
+
+    /// Trims the list stored at `key` so it only contains the elements between
+    /// `start` and `stop`, both inclusive.
+    pub(crate) fn ltrim(&self, key: &str, start: i64, stop: i64) -> Result<(), DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let list = match state.get_mut(key){
+            Some(entry) => entry.value.as_list_mut()?,
+            None => return Ok(()),
+        };
+
+        let len = list.len();
+        match clamp_range(start, stop, list.len()){
+            Some((start, stop)) => {
+                list.truncate(stop + 1);
+                list.drain(..start);
+            }
+            None => list.clear(),
+        }
+
+        if list.len() != len{
+            state.touch(key);
+        }
+        state.remove_if_empty(key);
+        Ok(())
+    }
+
+    /// Set the given `fields` of the hash stored at `key`, creating the hash
+    /// if needed. Returns the number of fields that were added, fields which
+    /// already existed and were only updated are not counted.
+    pub(crate) fn hset(&self, key: String, fields: Vec<(Bytes, Bytes)>) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let entry = state.entry_or_insert(key.clone(), || Value::Hash(ScanMap::default()));
+        let hash = entry.value.as_hash_mut()?;
+
+        let mut added = 0;
+        for (field, value) in fields{
+            if hash.insert(field, value).is_none(){
+                added += 1;
+            }
+        }
+
+        state.touch(&key);
+        Ok(added)
+    }
+
+    /// Returns the value of `field` in the hash stored at `key`.
+    pub(crate) fn hget(&self, key: &str, field: &Bytes) -> Result<Option<Bytes>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_hash()?.get(field).cloned()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the values of `fields` in the hash stored at `key`, in the same
+    /// order. Missing fields are returned as `None`.
+    pub(crate) fn hmget(&self, key: &str, fields: &[Bytes]) -> Result<Vec<Option<Bytes>>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        let hash = match state.entries.get(key){
+            Some(entry) => entry.value.as_hash()?,
+            None => return Ok(vec![None; fields.len()]),
+        };
+
+        Ok(fields.iter().map(|field| hash.get(field).cloned()).collect())
+    }
+
+    /// Remove `fields` from the hash stored at `key`. Returns the number of
+    /// fields that were removed.
+    pub(crate) fn hdel(&self, key: &str, fields: &[Bytes]) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let hash = match state.get_mut(key){
+            Some(entry) => entry.value.as_hash_mut()?,
+            None => return Ok(0),
+        };
+
+        let removed = fields.iter().filter(|field| hash.remove(*field).is_some()).count();
+
+        if removed > 0{
+            state.touch(key);
+        }
+        state.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Returns all the fields and values of the hash stored at `key`.
+    pub(crate) fn hgetall(&self, key: &str) -> Result<Vec<(Bytes, Bytes)>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry
+                .value
+                .as_hash()?
+                .iter()
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect()),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Returns the number of fields in the hash stored at `key`.
+    pub(crate) fn hlen(&self, key: &str) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_hash()?.len()),
+            None => Ok(0),
+        }
+    }
+
+    /// Increment the integer stored in `field` of the hash stored at `key` by
+    /// `delta`. Missing keys and fields start at `0`. Returns the value after
+    /// the increment.
+    pub(crate) fn hincrby(&self, key: String, field: Bytes, delta: i64) -> Result<i64, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let entry = state.entry_or_insert(key.clone(), || Value::Hash(ScanMap::default()));
+        let hash = entry.value.as_hash_mut()?;
+
+        let current = match hash.get(&field){
+            Some(value) => std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse::<i64>().ok())
+                .ok_or(DbError::HashValueNotInteger)?,
+            None => 0,
+        };
+        let value = current.checked_add(delta).ok_or(DbError::Overflow)?;
+
+        hash.insert(field, Bytes::from(value.to_string()));
+        state.touch(&key);
+        Ok(value)
+    }
+
+    /// Add `members` to the set stored at `key`, creating the set if needed.
+    /// Returns the number of members that were not already in the set.
+    pub(crate) fn sadd(&self, key: String, members: Vec<Bytes>) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let entry = state.entry_or_insert(key.clone(), || Value::Set(ScanSet::default()));
+        let set = entry.value.as_set_mut()?;
+
+        let mut added = 0;
+        for member in members{
+            if set.insert(member){
+                added += 1;
+            }
+        }
+
+        if added > 0{
+            state.touch(&key);
+        }
+        Ok(added)
+    }
+
+    /// Remove `members` from the set stored at `key`. Returns the number of
+    /// members that were removed.
+    pub(crate) fn srem(&self, key: &str, members: &[Bytes]) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let set = match state.get_mut(key){
+            Some(entry) => entry.value.as_set_mut()?,
+            None => return Ok(0),
+        };
+
+        let removed = members.iter().filter(|member| set.remove(*member)).count();
+
+        if removed > 0{
+            state.touch(key);
+        }
+        state.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Returns all the members of the set stored at `key`.
+    pub(crate) fn smembers(&self, key: &str) -> Result<HashSet<Bytes>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(HashSet::clone(entry.value.as_set()?)),
+            None => Ok(HashSet::new()),
+        }
+    }
+
+    /// Returns `true` if `member` belongs to the set stored at `key`.
+    pub(crate) fn sismember(&self, key: &str, member: &Bytes) -> Result<bool, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_set()?.contains(member)),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the number of members of the set stored at `key`.
+    pub(crate) fn scard(&self, key: &str) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_set()?.len()),
+            None => Ok(0),
+        }
+    }
+
+    /// Combine the sets stored at `keys` using `op`. Missing keys are treated
+    /// as empty sets.
+    pub(crate) fn set_combine(&self, keys: &[String], op: SetOp) -> Result<HashSet<Bytes>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+        state.set_combine(keys, op)
+    }
+
+    /// Combine the sets stored at `keys` using `op` and store the result at
+    /// `destination`, overwriting any previous value. Returns the number of
+    /// members of the resulting set.
+    ///
+    /// Reading the source sets and writing the destination happens while
+    /// holding the lock once, so no other client can observe or modify the
+    /// sets in between.
+    pub(crate) fn set_combine_store(&self, destination: String, keys: &[String], op: SetOp) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let result = state.set_combine(keys, op)?;
+        let len = result.len();
+
+        state.remove(&destination);
+        if !result.is_empty(){
+            let entry = Entry {
+                value: Value::Set(result.into_iter().collect()),
+                expires_at: None,
+                version: 0,
+            };
+            state.insert(destination, entry);
+        }
+
+        Ok(len)
+    }
+
+    /// Add `members` to the sorted set stored at `key` according to
+    /// `options`, creating the sorted set if needed.
+    ///
+    /// Returns the number of added members, or of changed members when the
+    /// `CH` option is set.
+    pub(crate) fn zadd(&self, key: String, options: ZAddOptions, members: Vec<(f64, Bytes)>) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let entry = state.entry_or_insert(key.clone(), || Value::SortedSet(SortedSet::default()));
+        let zset = entry.value.as_sorted_set_mut()?;
+
+        let mut count = 0;
+        let mut changed = false;
+        for (score, member) in members{
+            match zset.add(member, score, &options)?{
+                (Some(_), None) => {
+                    count += 1;
+                    changed = true;
+                }
+                (Some(score), Some(previous)) if score != previous => {
+                    if options.ch{
+                        count += 1;
+                    }
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+
+        if changed{
+            state.touch(&key);
+        }
+        // With `XX` nothing may have been added to a newly created key.
+        state.remove_if_empty(&key);
+        Ok(count)
+    }
+
+    /// Increment the score of `member` in the sorted set stored at `key` by
+    /// `increment`. Returns the new score, or `None` when `options` prevented
+    /// the update.
+    pub(crate) fn zincrby(&self, key: String, options: ZAddOptions, member: Bytes, increment: f64) -> Result<Option<f64>, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let entry = state.entry_or_insert(key.clone(), || Value::SortedSet(SortedSet::default()));
+        let zset = entry.value.as_sorted_set_mut()?;
+
+        let options = ZAddOptions { incr: true, ..options };
+        let (score, _) = zset.add(member, increment, &options)?;
+
+        if score.is_some(){
+            state.touch(&key);
+        }
+        state.remove_if_empty(&key);
+        Ok(score)
+    }
+
+    /// Returns the members of the sorted set stored at `key` selected by `by`,
+    /// along with their scores.
+    pub(crate) fn zrange(&self, key: &str, by: &RangeBy, rev: bool, offset: usize, count: Option<usize>) -> Result<Vec<(Bytes, f64)>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_sorted_set()?.range(by, rev, offset, count)),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Returns the rank of `member` in the sorted set stored at `key`, ordered
+    /// from the lowest to the highest score.
+    pub(crate) fn zrank(&self, key: &str, member: &Bytes) -> Result<Option<usize>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_sorted_set()?.rank(member)),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove `members` from the sorted set stored at `key`. Returns the
+    /// number of members that were removed.
+    pub(crate) fn zrem(&self, key: &str, members: &[Bytes]) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let zset = match state.get_mut(key){
+            Some(entry) => entry.value.as_sorted_set_mut()?,
+            None => return Ok(0),
+        };
+
+        let removed = members.iter().filter(|member| zset.remove(member)).count();
+
+        if removed > 0{
+            state.touch(key);
+        }
+        state.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Returns the number of members of the sorted set stored at `key`.
+    pub(crate) fn zcard(&self, key: &str) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_sorted_set()?.len()),
+            None => Ok(0),
+        }
+    }
+
+    /// Remove and return up to `count` members with the lowest scores from
+    /// the sorted set stored at `key`.
+    pub(crate) fn zpopmin(&self, key: &str, count: usize) -> Result<Vec<(Bytes, f64)>, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let zset = match state.get_mut(key){
+            Some(entry) => entry.value.as_sorted_set_mut()?,
+            None => return Ok(vec![]),
+        };
+
+        let popped = zset.pop_min(count);
+
+        if !popped.is_empty(){
+            state.touch(key);
+        }
+        state.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    /// Append an entry to the stream stored at `key`, creating the stream if
+    /// needed, then trim it according to `trim`. Returns the identifier of
+    /// the new entry.
+    pub(crate) fn xadd(&self, key: String, id: NewId, fields: Vec<(Bytes, Bytes)>, trim: Option<StreamTrim>) -> Result<StreamId, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let created = !state.entries.contains_key(&key);
+        let entry = state.entry_or_insert(key.clone(), || Value::Stream(Stream::default()));
+        let stream = entry.value.as_stream_mut()?;
+
+        let added = stream.add(id, fields);
+        match (&added, trim){
+            (Ok(_), Some(trim)) => {
+                stream.trim(trim);
+            }
+            // Do not keep a stream created for an invalid identifier.
+            (Err(_), _) if created => {
+                state.take(&key);
+            }
+            _ => {}
+        }
+        if added.is_ok(){
+            state.touch(&key);
+        }
+        drop(state);
+        drop(guard);
+
+        if added.is_ok(){
+            self.shared.streams.notify_waiters();
+        }
+        added
+    }
+
+    /// Returns the entries of the stream stored at `key` with an identifier
+    /// between `start` and `end`, in reverse order if `rev` is set.
+    pub(crate) fn xrange(&self, key: &str, start: StreamId, end: StreamId, rev: bool, count: Option<usize>) -> Result<Vec<StreamEntry>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_stream()?.range(start, end, rev, count)),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Returns the number of entries of the stream stored at `key`.
+    pub(crate) fn xlen(&self, key: &str) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_stream()?.len()),
+            None => Ok(0),
+        }
+    }
+
+    /// Trim the stream stored at `key`. Returns the number of evicted entries.
+    pub(crate) fn xtrim(&self, key: &str, trim: StreamTrim) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let evicted = match state.get_mut(key){
+            Some(entry) => entry.value.as_stream_mut()?.trim(trim),
+            None => return Ok(0),
+        };
+
+        if evicted > 0{
+            state.touch(key);
+        }
+        Ok(evicted)
+    }
+
+    /// Returns the identifier of the last entry added to the stream stored at
+    /// `key`, which is what `$` stands for in `XREAD` and `XGROUP CREATE`.
+    pub(crate) fn xlast_id(&self, key: &str) -> Result<StreamId, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => Ok(entry.value.as_stream()?.last_id()),
+            None => Ok(StreamId::MIN),
+        }
+    }
+
+    /// Returns, for every `(key, id)` pair, the entries of the stream stored
+    /// at `key` with an identifier greater than `id`. Streams without such
+    /// entries are left out.
+    pub(crate) fn xread(&self, streams: &[(String, StreamId)], count: Option<usize>) -> Result<Vec<(String, Vec<StreamEntry>)>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        let mut result = Vec::new();
+        for (key, id) in streams{
+            let entries = match state.entries.get(key){
+                Some(entry) => entry.value.as_stream()?.after(*id, count),
+                None => continue,
+            };
+
+            if !entries.is_empty(){
+                result.push((key.clone(), entries));
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Create the consumer group `group` of the stream stored at `key`. The
+    /// stream is created when missing if `mkstream` is set.
+    pub(crate) fn xgroup_create(&self, key: String, group: String, id: Option<StreamId>, mkstream: bool) -> Result<(), DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        if mkstream{
+            state.entry_or_insert(key.clone(), || Value::Stream(Stream::default()));
+        }
+
+        match state.get_mut(&key){
+            Some(entry) => entry.value.as_stream_mut()?.create_group(group, id)?,
+            None => return Err(DbError::NoStream),
+        }
+
+        state.touch(&key);
+        Ok(())
+    }
+
+    /// Read from each of `streams` on behalf of `consumer` of the consumer
+    /// group `group`. A `None` identifier requests the entries never
+    /// delivered to the group, see `Stream::read_group`.
+    ///
+    /// Streams without new entries are left out.
+    pub(crate) fn xreadgroup(
+        &self,
+        group: &str,
+        consumer: &str,
+        streams: &[(String, Option<StreamId>)],
+        count: Option<usize>,
+        noack: bool,
+    ) -> Result<Vec<(String, Vec<GroupEntry>)>, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        // Check every stream before delivering anything, so that an error
+        // does not leave the command half applied.
+        for (key, _) in streams{
+            match state.entries.get(key){
+                Some(entry) if entry.value.as_stream()?.has_group(group) => {}
+                _ => return Err(DbError::NoGroup),
+            }
+        }
+
+        let mut result = Vec::new();
+        for (key, id) in streams{
+            let stream = match state.get_mut(key){
+                Some(entry) => entry.value.as_stream_mut()?,
+                None => return Err(DbError::NoGroup),
+            };
+            let entries = stream.read_group(group, consumer, *id, count, noack)?;
+
+            // Delivering new entries changes the consumer group.
+            if id.is_none() && !entries.is_empty(){
+                state.touch(key);
+            }
+
+            // Reading the history of the consumer always replies, even with
+            // no entries.
+            if !entries.is_empty() || id.is_some(){
+                result.push((key.clone(), entries));
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Acknowledge the entries `ids` delivered by the consumer group `group`
+    /// of the stream stored at `key`. Returns the number of acknowledged
+    /// entries.
+    pub(crate) fn xack(&self, key: &str, group: &str, ids: &[StreamId]) -> Result<usize, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let acked = match state.get_mut(key){
+            Some(entry) => entry.value.as_stream_mut()?.ack(group, ids),
+            None => return Ok(0),
+        };
+
+        if acked > 0{
+            state.touch(key);
+        }
+        Ok(acked)
+    }
+
+    /// Summarizes the pending entries of the consumer group `group`.
+    pub(crate) fn xpending_summary(&self, key: &str, group: &str) -> Result<PendingSummary, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => entry.value.as_stream()?.pending_summary(group),
+            None => Err(DbError::NoGroup),
+        }
+    }
+
+    /// Lists the pending entries of the consumer group `group`, see
+    /// `Stream::pending`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn xpending(
+        &self,
+        key: &str,
+        group: &str,
+        start: StreamId,
+        end: StreamId,
+        count: usize,
+        consumer: Option<&str>,
+        min_idle: Option<Duration>,
+    ) -> Result<Vec<PendingInfo>, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match state.entries.get(key){
+            Some(entry) => entry.value.as_stream()?.pending(group, start, end, count, consumer, min_idle),
+            None => Err(DbError::NoGroup),
+        }
+    }
+
+    /// Transfer pending entries of the consumer group `group` to `consumer`,
+    /// see `Stream::claim`.
+    pub(crate) fn xclaim(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: Duration,
+        ids: &[StreamId],
+        justid: bool,
+    ) -> Result<Vec<StreamEntry>, DbError>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let claimed = match state.get_mut(key){
+            Some(entry) => entry.value.as_stream_mut()?.claim(group, consumer, min_idle, ids, justid)?,
+            None => return Err(DbError::NoGroup),
+        };
+
+        if !claimed.is_empty(){
+            state.touch(key);
+        }
+        Ok(claimed)
+    }
+
+    /// Returns a future completing the next time an entry is appended to any
+    /// stream.
+    ///
+    /// Blocking reads must create and enable this future **before** checking
+    /// the streams, so that an entry added in between is not missed.
+    pub(crate) fn stream_appended(&self) -> Notified<'_>{
+        self.shared.streams.notified()
+    }
+
+    pub(crate) fn subscribe(&self, key: String) -> broadcast::Receiver<Bytes>{
+        use std::collections::hash_map::Entry;
+
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        // if there is no entry for the requested channel, then create a new
+        // broadcast channel and associate it with the key. If one already
+        // exists, return an associated receiver.
+        match state.pub_sub.entry(key){
+            Entry::Occupied(e) => e.get().subscribe(),
+            Entry::Vacant(e) => {
+                // A message would stored in the channel, until all subscribers 
+                // have seen it.
+                // This means that a slow subscriber could result in messages being 
+                // held indefinitely
+                //
+                // When the channel's capacity fills up, publishing will result
+                // in old messages being dropped. This prevents slow consumers
+                // from blocking the entire system.
+                let (tx, rx) = broadcast::channel(self.shared.pubsub_capacity);
+                e.insert(tx);
+                rx
+            }
+        }
+    }
+
+    pub(crate) fn publish(&self, key: &str, value: Bytes) -> usize{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        state.pub_sub
+            .get(key)
+            // on a successful message send on the broadcast channel
+            // the number of subscribers is returned.
+            // Error means there are no receivers
+            .map(|tx| tx.send(value).unwrap_or(0))
+            .unwrap_or(0)
+    }
+
+    /// Enable cluster mode, loading the topology from the config file at
+    /// `path`. A new cluster is created if missing, holding this node alone,
+    /// reachable at `addr`.
+    pub(crate) fn load_cluster(&self, path: &Path, addr: SocketAddr) -> crate::Result<()>{
+        let cluster = ClusterState::load(path, addr)?;
+        self.shared.state.lock().borrow_mut().cluster = Some(cluster);
+        Ok(())
+    }
+
+    /// Returns `Ok` if this node serves a command operating on `keys`, see
+    /// `ClusterState::check`. Every command is served outside of cluster
+    /// mode.
+    pub(crate) fn cluster_check(&self, keys: &[&str], asking: bool) -> Result<(), Redirect>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        match &state.cluster{
+            Some(cluster) => cluster.check(keys, asking, |key| state.entries.contains_key(key)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns `true` in cluster mode.
+    pub(crate) fn is_cluster(&self) -> bool{
+        self.shared.state.lock().borrow().cluster.is_some()
+    }
+
+    /// Run `f` with the topology of the cluster, failing outside of cluster
+    /// mode.
+    pub(crate) fn with_cluster<R>(&self, f: impl FnOnce(&mut ClusterState) -> R) -> crate::Result<R>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        match &mut state.cluster{
+            Some(cluster) => Ok(f(cluster)),
+            None => Err("ERR This instance has cluster support disabled".into()),
+        }
+    }
+
+    /// Load the users from the ACL file at `path`.
+    pub(crate) fn load_acl(&self, path: &Path) -> crate::Result<()>{
+        self.shared.state.lock().borrow_mut().acl.load(path)
+    }
+
+    /// Apply `f` to the ACL, for the `ACL` command.
+    pub(crate) fn with_acl<R>(&self, f: impl FnOnce(&mut AclState) -> R) -> R{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+        f(&mut state.acl)
+    }
+
+    /// Returns the user new connections are authenticated as, `None` if they
+    /// must authenticate.
+    pub(crate) fn default_user(&self) -> Option<String>{
+        self.shared.state.lock().borrow().acl.default_user()
+    }
+
+    /// Check the credentials sent by `AUTH` or `HELLO`, returning the user
+    /// authenticated as.
+    ///
+    /// Without `username`, the password is the one of the `default` user,
+    /// which must require one.
+    pub(crate) fn authenticate(&self, username: Option<&str>, password: &str) -> Result<String, DbError>{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+
+        if username.is_none() && state.acl.default_user().is_some(){
+            return Err(DbError::NoPassword);
+        }
+        let username = username.unwrap_or("default");
+        if state.acl.authenticate(username, password){
+            Ok(username.to_string())
+        }else{
+            Err(DbError::WrongPass)
+        }
+    }
+
+    /// Returns `Ok` if `user` may run `cmd`, or why it may not. Commands
+    /// applied without a user are not checked.
+    pub(crate) fn acl_check(&self, user: Option<&str>, cmd: &Command) -> Result<(), AclError>{
+        match user{
+            Some(user) => self.shared.state.lock().borrow().acl.check(user, cmd),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the password to authenticate with to the leader, if any.
+    pub(crate) fn masterauth(&self) -> Option<&str>{
+        self.shared.masterauth.as_deref()
+    }
+
+    /// Follow the leader at `host:port`, or stop following and become a
+    /// leader with `None`.
+    ///
+    /// The key space is replaced by the one of the leader once connected, and
+    /// clients are refused writes while following unless configured
+    /// otherwise.
+    pub(crate) fn replicaof(&self, leader: Option<(String, u16)>){
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        match leader{
+            Some((host, port)) => {
+                info!(%host, port, "following a leader");
+                let task = tokio::spawn(replication::follow(self.clone(), host.clone(), port));
+                state.repl.follow(host, port, task);
+            }
+            None if state.repl.is_following() => {
+                info!("promoted to leader");
+                state.repl.promote();
+            }
+            None => {}
+        }
+    }
+
+    /// Returns `true` if clients are refused writes, following a leader.
+    pub(crate) fn is_read_only(&self) -> bool{
+        self.shared.replica_read_only && self.shared.state.lock().borrow().repl.is_following()
+    }
+
+    /// Returns the replication identifier and the offset reached.
+    pub(crate) fn replication_position(&self) -> (String, u64){
+        self.shared.state.lock().borrow().repl.position()
+    }
+
+    pub(crate) fn role(&self) -> Role{
+        self.shared.state.lock().borrow().repl.role()
+    }
+
+    /// Start streaming the writes to a follower which reached `offset` in the
+    /// history `replid`.
+    ///
+    /// The follower continues from `offset` if the backlog still holds the
+    /// writes following it. Otherwise the key space is copied for a full
+    /// resynchronization and encoded on a blocking thread, stalling other
+    /// clients while it is copied like `bgsave`. Either way the stream starts
+    /// where the returned state ends.
+    pub(crate) async fn psync(&self, replid: &str, offset: i64) -> crate::Result<(PSyncStart, ReplicaStream)>{
+        let (entries, replid, offset, stream) = {
+            let guard = self.shared.state.lock();
+            let mut state = guard.borrow_mut();
+
+            let (id, receiver) = state.repl.attach();
+            let stream = ReplicaStream::new(id, receiver, self.clone());
+            let backlog = u64::try_from(offset)
+                .ok()
+                .and_then(|offset| state.repl.backlog_from(replid, offset));
+            let (replid, offset) = state.repl.position();
+
+            if let Some(backlog) = backlog{
+                return Ok((PSyncStart::Continue{ replid, backlog }, stream));
+            }
+            (HashMap::clone(&state.entries), replid, offset, stream)
+        };
+
+        let snapshot = tokio::task::spawn_blocking(move || rdb::encode(entries.iter(), rdb::Clock::now())).await?;
+        Ok((PSyncStart::FullResync{ replid, offset, snapshot }, stream))
+    }
+
+    /// Record the offset acknowledged by the follower `id`.
+    pub(crate) fn replica_ack(&self, id: u64, offset: u64){
+        self.shared.state.lock().borrow_mut().repl.ack(id, offset);
+    }
+
+    /// Forget the follower `id`, once disconnected.
+    pub(crate) fn replica_detach(&self, id: u64){
+        self.shared.state.lock().borrow_mut().repl.detach(id);
+    }
+
+    pub(crate) fn set_link_state(&self, link: LinkState){
+        self.shared.state.lock().borrow_mut().repl.set_link_state(link);
+    }
+
+    /// Replace the key space with `snapshot`, sent by the leader for a full
+    /// resynchronization starting at `offset` in the history `replid`.
+    /// Returns the number of keys loaded.
+    ///
+    /// The append only file, if enabled, is replaced by the snapshot.
+    pub(crate) fn resync(&self, replid: String, offset: u64, snapshot: &[u8]) -> crate::Result<usize>{
+        let (entries, _) = rdb::decode(snapshot, rdb::Clock::now())?;
+        let len = entries.len();
+
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        let keys: Vec<_> = state.entries.keys().cloned().collect();
+        for key in keys{
+            state.remove(&key);
+        }
+        for (key, entry) in entries{
+            state.insert(key, entry);
+        }
+        state.repl.resynced(replid, offset);
+
+        if state.aof.is_some(){
+            write_snapshot(&self.shared.aof_path, snapshot)?;
+            state.aof = Some(Aof::open(&self.shared.aof_path, self.shared.appendfsync)?);
+        }
+        drop(state);
+        drop(guard);
+
+        // Loaded entries may expire before any other.
+        self.shared.background_task.notify_one();
+        Ok(len)
+    }
+
+    /// Continue the history `replid` of the leader, after a partial
+    /// resynchronization.
+    pub(crate) fn continue_sync(&self, replid: String){
+        self.shared.state.lock().borrow_mut().repl.continued(replid);
+    }
+
+    /// Apply the command `frame` streamed by the leader, encoded as `data`,
+    /// and stream it on to the followers of this server.
+    pub(crate) fn replicate(&self, replay: &mut Replay, frame: Frame, data: Bytes) -> crate::Result<()>{
+        self.atomically(&[], ||{
+            replay.apply(self, frame)?;
+
+            let guard = self.shared.state.lock();
+            guard.borrow_mut().repl.feed(data);
+            Ok(())
+        })
+        .expect("no watched key")
+    }
+
+    /// Write a snapshot of the key space to the RDB file, blocking every other
+    /// client until it is written.
+    pub(crate) fn save(&self) -> crate::Result<()>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        if state.snapshots.in_progress{
+            return Err(DbError::SaveInProgress.into());
+        }
+
+        let data = rdb::encode(state.entries.iter(), rdb::Clock::now());
+        let result = write_snapshot(&self.shared.rdb_path, &data);
+        let writes = state.writes;
+        state.snapshots.saved(result.is_ok(), writes);
+        result
+    }
+
+    /// Start writing a snapshot of the key space to the RDB file in the
+    /// background.
+    ///
+    /// The key space is copied while the lock is held, then encoded and
+    /// written on a blocking thread. The copy clones every entry, so other
+    /// clients are stalled for a time growing with the size of the key
+    /// space, only the encoding and writing being moved off the lock.
+    pub(crate) fn bgsave(&self) -> crate::Result<()>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        if state.snapshots.in_progress{
+            return Err(DbError::SaveInProgress.into());
+        }
+        state.snapshots.in_progress = true;
+
+        let entries = HashMap::clone(&state.entries);
+        // Writes made while the snapshot is taken are not part of it.
+        let writes = state.writes;
+        drop(state);
+        drop(guard);
+
+        let db = self.clone();
+        tokio::task::spawn_blocking(move ||{
+            let data = rdb::encode(entries.iter(), rdb::Clock::now());
+            let result = write_snapshot(&db.shared.rdb_path, &data);
+
+            match &result{
+                Ok(()) => info!(path = ?db.shared.rdb_path, "background saving terminated with success"),
+                Err(err) => error!(cause = %err, "background saving failed"),
+            }
+
+            let guard = db.shared.state.lock();
+            let mut state = guard.borrow_mut();
+            state.snapshots.in_progress = false;
+            state.snapshots.saved(result.is_ok(), writes);
+        });
+
+        Ok(())
+    }
+
+    /// Start rewriting the append only file in the background, compacting it
+    /// into a snapshot of the key space.
+    ///
+    /// The key space is copied while the lock is held, like `bgsave`. The
+    /// commands logged while the snapshot is written are appended to it, then
+    /// the rewritten file replaces the current one.
+    pub(crate) fn bgrewriteaof(&self) -> crate::Result<()>{
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+
+        match &mut state.aof{
+            None => return Err(DbError::AofDisabled.into()),
+            Some(aof) if aof.is_rewriting() => return Err(DbError::RewriteInProgress.into()),
+            Some(aof) => aof.start_rewrite(),
+        }
+        let entries = HashMap::clone(&state.entries);
+        drop(state);
+        drop(guard);
+
+        let db = self.clone();
+        tokio::task::spawn_blocking(move ||{
+            let preamble = rdb::encode(entries.iter(), rdb::Clock::now());
+
+            match db.finish_rewrite(&preamble){
+                Ok(()) => info!(path = ?db.shared.aof_path, "background append only file rewriting terminated with success"),
+                Err(err) => error!(cause = %err, "background append only file rewriting failed"),
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Write the rewritten append only file, starting with `preamble`, and
+    /// replace the current one with it.
+    fn finish_rewrite(&self, preamble: &[u8]) -> crate::Result<()>{
+        let path = &self.shared.aof_path;
+        let tmp = tmp_path(path, "rewrite");
+        let written = write_file(&tmp, preamble);
+
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+        let aof = state.aof.as_mut().expect("append only file enabled");
+
+        // A full resynchronization with a leader replaced the file meanwhile.
+        if !aof.is_rewriting(){
+            let _ = fs::remove_file(&tmp);
+            return Err("ERR Rewrite aborted, the file was replaced".into());
+        }
+        let result = written.and_then(|()| aof.finish_rewrite(&tmp, path));
+        if let Err(err) = result{
+            aof.abort_rewrite();
+            let _ = fs::remove_file(&tmp);
+            return Err(format!("ERR Failed rewriting {:?}: {}", path, err).into());
+        }
+        Ok(())
+    }
+
+    /// Flush the append only file to disk, if writes were logged since it was
+    /// last flushed.
+    pub(crate) fn sync_aof(&self) -> crate::Result<()>{
+        let file = match &mut self.shared.state.lock().borrow_mut().aof{
+            Some(aof) => aof.unsynced_file()?,
+            None => None,
+        };
+
+        // The lock is not held while flushing, the file being shared with
+        // the clone.
+        if let Some(file) = file{
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Load the key space from disk, returning the number of keys loaded.
+    ///
+    /// The key space is loaded from the append only file if enabled, and from
+    /// the RDB file otherwise. A missing file leaves the key space empty. An
+    /// enabled append only file which is missing is created from the key
+    /// space loaded from the RDB file, then the writes start being logged to
+    /// it.
+    ///
+    /// Called before the server accepts connections.
+    pub(crate) fn load(&self) -> crate::Result<usize>{
+        let len = if !self.shared.appendonly{
+            self.load_rdb()?
+        } else if let Some(len) = self.load_aof()?{
+            len
+        } else {
+            let len = self.load_rdb()?;
+
+            let guard = self.shared.state.lock();
+            let state = guard.borrow();
+            let preamble = rdb::encode(state.entries.iter(), rdb::Clock::now());
+            write_snapshot(&self.shared.aof_path, &preamble)?;
+            len
+        };
+
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+        state.snapshots.writes = state.writes;
+
+        if self.shared.appendonly{
+            state.aof = Some(Aof::open(&self.shared.aof_path, self.shared.appendfsync)?);
+            if self.shared.appendfsync == AppendFsync::EverySec{
+                tokio::spawn(sync_aof_task(self.clone()));
+            }
+        }
+        drop(state);
+        drop(guard);
+
+        // Loaded entries may expire before any other.
+        self.shared.background_task.notify_one();
+        Ok(len)
+    }
+
+    /// Load the key space from the RDB file.
+    fn load_rdb(&self) -> crate::Result<usize>{
+        let data = match fs::read(&self.shared.rdb_path){
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let (entries, _) = rdb::decode(&data, rdb::Clock::now())?;
+        let len = entries.len();
+
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+        for (key, entry) in entries{
+            state.remove(&key);
+            state.insert(key, entry);
+        }
+        Ok(len)
+    }
+
+    /// Load the key space from the append only file, `None` if missing.
+    ///
+    /// A file ending with an incomplete command, as left by a crash, is
+    /// truncated after the last complete one.
+    fn load_aof(&self) -> crate::Result<Option<usize>>{
+        let path = &self.shared.aof_path;
+        let data = match fs::read(path){
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        // A rewritten file starts with a snapshot in the RDB format.
+        let mut len = 0;
+        if data.starts_with(b"REDIS"){
+            let (entries, preamble) = rdb::decode(&data, rdb::Clock::now())?;
+
+            let guard = self.shared.state.lock();
+            let mut state = guard.borrow_mut();
+            for (key, entry) in entries{
+                state.remove(&key);
+                state.insert(key, entry);
+            }
+            len = preamble;
+        }
+        len += crate::aof::replay(self, &data[len..])?;
+
+        if len < data.len(){
+            warn!(?path, discarded = data.len() - len, "truncating the incomplete end of the append only file");
+            OpenOptions::new().write(true).open(path)?.set_len(len as u64)?;
+        }
+
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+        Ok(Some(state.entries.len()))
+    }
+
+    /// Returns `true` if one of the save rules asks for a snapshot.
+    fn should_save(&self) -> bool{
+        let guard = self.shared.state.lock();
+        let state = guard.borrow();
+        let snapshots = &state.snapshots;
+
+        if snapshots.in_progress{
+            return false;
+        }
+        if snapshots.last_failure.is_some_and(|when| when.elapsed() < SAVE_RETRY_DELAY){
+            return false;
+        }
+
+        let dirty = state.writes - snapshots.writes;
+        let elapsed = snapshots.last_save.elapsed();
+        self.shared.save_rules.iter().any(|rule| {
+            dirty >= rule.changes && elapsed >= Duration::from_secs(rule.seconds)
+        })
+    }
+
+    /// signals the purge background task to shut down
+    fn shutdown_purge_task(&self){
+        let guard = self.shared.state.lock();
+        let mut state = guard.borrow_mut();
+        state.shutdown = true;
+        state.repl.shutdown();
+        drop(state);
+        drop(guard);
+        self.shared.background_task.notify_one()
+    }
+}
+
+impl Shared{
+    /// purge all expired keys and return the "Instant" at which the
+    /// next key will expire. the background task will sleep until this
+    /// instant
+    fn purge_expired_keys(&self) -> Option<Instant>{
+        let guard = self.state.lock();
+        let mut state = guard.borrow_mut();
+
+        if state.shutdown{
+            // the database is shutting down.
+            // All handles to the share state have dropped
+            return None;
+        }
+        // This is needed to make the borrow checker happy. In short,
+        // `borrow_mut()` returns a `RefMut` and not a `&mut State`. The borrow
+        // checker is not able to see "through" the guard and determine that it is
+        // safe to access both `state.expirations` and `state.entries` mutably,
+        // so we get a "real" mutable reference to `State` outside of the loop.
+        let state = &mut *state;
+
+        let now  = Instant::now();
+
+        while let Some(&(when, ref key)) = state.expirations.iter().next(){
+            if when > now{
+                return Some(when);
+            }
+            state.entries.remove(key);
+            state.expirations.remove(&(when, key.clone()));
+            state.writes += 1;
+        }
+        None
+    }
+
+    fn is_shutdown(&self) -> bool{
+        self.state.lock().borrow().shutdown
+    }
+}
+
+
+impl State{
+    /// Log the commands `frames` to the append only file, and stream them to
+    /// the followers. A follower streams the writes of its leader instead,
+    /// see `Db::replicate`.
+    fn log(&mut self, frames: &[Frame]){
+        // Commands build their frames from bulk strings and integers only.
+        let data = match aof::encode(frames){
+            Ok(data) => data,
+            Err(err) => {
+                error!(cause = %err, "failed to propagate the write");
+                return;
+            }
+        };
+        if let Some(aof) = &mut self.aof{
+            aof.append(&data);
+        }
+        if !self.repl.is_following(){
+            self.repl.feed(Bytes::from(data));
+        }
+    }
+
+    fn next_expiration(&self)-> Option<Instant>{
+        self.expirations
+            .iter()
+            .next()
+            .map(|expiration| expiration.0)
+    }
+
+    /// Insert `entry` at the free `key`, tracking its expiration and giving
+    /// the entry a new version.
+    ///
+    /// The expiration of the entry, if any, must already be known to the
+    /// background task, as when the entry comes from another key.
+    fn insert(&mut self, key: String, mut entry: Entry){
+        if let Some(when) = entry.expires_at{
+            self.expirations.insert((when, key.clone()));
+        }
+
+        entry.version = self.next_version();
+        self.entries.insert(key, entry);
+    }
+
+    /// Returns the entry at `key` for writing. The caller runs `touch` once
+    /// the entry was actually changed.
+    fn get_mut(&mut self, key: &str) -> Option<&mut Entry>{
+        self.entries.get_mut(key)
+    }
+
+    /// Returns the entry at `key` for writing like `get_mut`, first inserting
+    /// an entry without expiration holding `default()` if the key is missing.
+    ///
+    /// An entry inserted but left unchanged must not be kept, see
+    /// `remove_if_empty`.
+    fn entry_or_insert(&mut self, key: String, default: impl FnOnce() -> Value) -> &mut Entry{
+        self.entries.get_or_insert_with(key, || Entry {
+            value: default(),
+            expires_at: None,
+            version: 0,
+        })
+    }
+
+    /// Give the entry at `key` a new version and count the write, once the
+    /// entry was changed through `get_mut` or `entry_or_insert`.
+    ///
+    /// Failed commands and commands changing nothing do not touch the entry,
+    /// so that `WATCH` does not see a modification and nothing is propagated.
+    fn touch(&mut self, key: &str){
+        let version = self.next_version();
+        if let Some(entry) = self.entries.get_mut(key){
+            entry.version = version;
+        }
+    }
+
+    /// Returns a new version for an entry being written, counting the write.
+    fn next_version(&mut self) -> u64{
+        self.version += 1;
+        self.writes += 1;
+        self.version
+    }
+
+    /// Replace the expiration of the existing `key` with `when`.
+    ///
+    /// Returns `true` if the key now expires before any other one, in which
+    /// case the background task must be notified to update its state.
+    fn set_expiration(&mut self, key: &str, when: Option<Instant>) -> bool{
+        let notify = when.is_some_and(|when| {
+            self.next_expiration()
+                .map(|expiration| expiration > when)
+                .unwrap_or(true)
+        });
+
+        let entry = match self.get_mut(key){
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        if let Some(previous) = std::mem::replace(&mut entry.expires_at, when){
+            self.expirations.remove(&(previous, key.to_string()));
+        }
+        if let Some(when) = when{
+            self.expirations.insert((when, key.to_string()));
+        }
+        self.touch(key);
+        notify
+    }
+
+    /// Remove `key` together with its pending expiration, if any.
+    fn remove(&mut self, key: &str) -> Option<Entry>{
+        let entry = self.take(key)?;
+        self.writes += 1;
+        Some(entry)
+    }
+
+    /// Remove `key` like `remove`, without counting a write.
+    fn take(&mut self, key: &str) -> Option<Entry>{
+        let entry = self.entries.remove(key)?;
+
+        if let Some(when) = entry.expires_at{
+            self.expirations.remove(&(when, key.to_string()));
+        }
+        Some(entry)
+    }
+
+    /// Implements the set algebra of `Db::set_combine`.
+    fn set_combine(&self, keys: &[String], op: SetOp) -> Result<HashSet<Bytes>, DbError>{
+        let empty = HashSet::new();
+        let mut sets = Vec::with_capacity(keys.len());
+
+        // Check the type of every key before computing anything, so that a
+        // wrong type is reported even if the result is already known to be
+        // empty.
+        for key in keys{
+            match self.entries.get(key){
+                Some(entry) => sets.push(&**entry.value.as_set()?),
+                None => sets.push(&empty),
+            }
+        }
+
+        let (first, rest) = match sets.split_first(){
+            Some(split) => split,
+            None => return Ok(HashSet::new()),
+        };
+
+        Ok(match op{
+            SetOp::Inter => first
+                .iter()
+                .filter(|member| rest.iter().all(|set| set.contains(*member)))
+                .cloned()
+                .collect(),
+            SetOp::Union => sets.iter().flat_map(|set| set.iter()).cloned().collect(),
+            SetOp::Diff => first
+                .iter()
+                .filter(|member| !rest.iter().any(|set| set.contains(*member)))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Redis never stores empty aggregate values: once the last element of a
+    /// collection is removed, the key itself is deleted.
+    ///
+    /// The write emptying the entry was already counted by `touch`, and an
+    /// entry inserted empty by `entry_or_insert` was never written, so the
+    /// removal is not counted.
+    fn remove_if_empty(&mut self, key: &str){
+        if self.entries.get(key).is_some_and(|entry| entry.value.is_empty()){
+            self.take(key);
+        }
+    }
+}
+
+impl Snapshots{
+    /// Record the outcome of a snapshot of the key space taken after `writes`
+    /// writes.
+    fn saved(&mut self, success: bool, writes: u64){
+        if success{
+            self.writes = writes;
+            self.last_save = Instant::now();
+            self.last_failure = None;
+        } else {
+            self.last_failure = Some(Instant::now());
+        }
+    }
+}
+
+impl Value{
+    /// Name of the type, as replied by `TYPE`.
+    fn type_name(&self) -> &'static str{
+        match self{
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    fn is_empty(&self) -> bool{
+        match self{
+            Value::String(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::SortedSet(zset) => zset.is_empty(),
+            // Unlike other aggregates, streams survive their last entry.
+            Value::Stream(_) => false,
+        }
+    }
+
+    fn as_list(&self) -> Result<&VecDeque<Bytes>, DbError>{
+        match self{
+            Value::List(list) => Ok(list),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn as_list_mut(&mut self) -> Result<&mut VecDeque<Bytes>, DbError>{
+        match self{
+            Value::List(list) => Ok(list),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn as_hash(&self) -> Result<&ScanMap<Bytes, Bytes>, DbError>{
+        match self{
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn as_hash_mut(&mut self) -> Result<&mut ScanMap<Bytes, Bytes>, DbError>{
+        match self{
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn as_set(&self) -> Result<&ScanSet<Bytes>, DbError>{
+        match self{
+            Value::Set(set) => Ok(set),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn as_set_mut(&mut self) -> Result<&mut ScanSet<Bytes>, DbError>{
+        match self{
+            Value::Set(set) => Ok(set),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn as_sorted_set(&self) -> Result<&SortedSet, DbError>{
+        match self{
+            Value::SortedSet(zset) => Ok(zset),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn as_sorted_set_mut(&mut self) -> Result<&mut SortedSet, DbError>{
+        match self{
+            Value::SortedSet(zset) => Ok(zset),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn as_stream(&self) -> Result<&Stream, DbError>{
+        match self{
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn as_stream_mut(&mut self) -> Result<&mut Stream, DbError>{
+        match self{
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(DbError::WrongType),
+        }
+    }
+}
+
+impl fmt::Display for DbError{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            DbError::WrongType => "WRONGTYPE Operation against a key holding the wrong kind of value".fmt(fmt),
+            DbError::NoSuchKey => "ERR no such key".fmt(fmt),
+            DbError::IndexOutOfRange => "ERR index out of range".fmt(fmt),
+            DbError::HashValueNotInteger => "ERR hash value is not an integer".fmt(fmt),
+            DbError::Overflow => "ERR increment or decrement would overflow".fmt(fmt),
+            DbError::NotInteger => "ERR value is not an integer or out of range".fmt(fmt),
+            DbError::NotFloat => "ERR value is not a valid float".fmt(fmt),
+            DbError::NaNOrInfinity => "ERR increment would produce NaN or Infinity".fmt(fmt),
+            DbError::ScoreNaN => "ERR resulting score is not a number (NaN)".fmt(fmt),
+            DbError::StreamIdZero => "ERR The ID specified in XADD must be greater than 0-0".fmt(fmt),
+            DbError::StreamIdTooSmall => "ERR The ID specified in XADD is equal or smaller than the target stream top item".fmt(fmt),
+            DbError::NoGroup => "NOGROUP No such key or consumer group".fmt(fmt),
+            DbError::BusyGroup => "BUSYGROUP Consumer Group name already exists".fmt(fmt),
+            DbError::NoStream => "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.".fmt(fmt),
+            DbError::SaveInProgress => "ERR Background save already in progress".fmt(fmt),
+            DbError::AofDisabled => "ERR Append only file is disabled".fmt(fmt),
+            DbError::RewriteInProgress => "ERR Background append only file rewriting already in progress".fmt(fmt),
+            DbError::ReadOnly => "READONLY You can't write against a read only replica.".fmt(fmt),
+            DbError::NoAuth => "NOAUTH Authentication required.".fmt(fmt),
+            DbError::WrongPass => "WRONGPASS invalid username-password pair or user is disabled.".fmt(fmt),
+            DbError::NoPassword => "ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".fmt(fmt),
+            DbError::Busy => "BUSY Redis is busy running a script. You can only call SCRIPT KILL.".fmt(fmt),
+            DbError::NotBusy => "NOTBUSY No scripts in execution right now.".fmt(fmt),
+            DbError::Unkillable => "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way.".fmt(fmt),
+        }
+    }
+}
+
+impl std::error::Error for DbError{}
+
+/// Resolve a possibly negative list `index` against a list of `len` elements.
+fn list_index(index: i64, len: usize) -> Option<usize>{
+    let index = if index < 0 { len as i64 + index } else { index };
+
+    if index < 0 || index >= len as i64{
+        None
+    } else {
+        Some(index as usize)
+    }
+}
+
+/// Resolve the inclusive `start..=stop` range used by `LRANGE`, `LTRIM` and
+/// `ZRANGE` against a list of `len` elements. Out of range indices are clamped, `None`
+/// is returned when the range is empty.
+fn clamp_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)>{
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+
+    if start > stop || start >= len{
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
+}
+
+async fn purge_expired_tasks(shared: Arc<Shared>){
+    while !shared.is_shutdown(){
+        shared.script.completed().await;
+
+        if let Some(when) = shared.purge_expired_keys(){
+            // Wait until the next key expires **or** until the background task
+            // is notified. If the task is notified, then it must reload its
+            // state as new keys have been set to expire early. This is done by
+            // looping.
+            tokio::select!{
+                _ = time::sleep_until(when) => {}
+                _ = shared.background_task.notified() => {}
+            }
+        } else {
+            // There are no keys expiring in the future. Wait until the task is
+            // notified.
+            shared.background_task.notified().await;
+        }
+    }
+    debug!("Purge background task shut down")
+}
+
+/// Write `data` to `path`, through a temporary file renamed once written, so
+/// that a crash never leaves a partial snapshot behind.
+fn write_snapshot(path: &Path, data: &[u8]) -> crate::Result<()>{
+    let tmp = tmp_path(path, "tmp");
+    let write = || -> io::Result<()>{
+        write_file(&tmp, data)?;
+        fs::rename(&tmp, path)
+    };
+
+    write().map_err(|err| format!("ERR Failed writing {:?}: {}", path, err).into())
+}
+
+/// Create the file at `path` holding `data`, flushed to disk.
+fn write_file(path: &Path, data: &[u8]) -> io::Result<()>{
+    let mut file = fs::File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Returns the path of a temporary file `path` is written through.
+fn tmp_path(path: &Path, suffix: &str) -> PathBuf{
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".");
+    tmp.push(suffix);
+    PathBuf::from(tmp)
+}
+
+/// Flush the append only file to disk every second. Runs until the database
+/// shuts down.
+async fn sync_aof_task(db: Db){
+    let mut interval = time::interval(Duration::from_secs(1));
+
+    while !db.shared.is_shutdown(){
+        interval.tick().await;
+        db.running_script().completed().await;
+
+        let synced = db.clone();
+        if let Ok(Err(err)) = tokio::task::spawn_blocking(move || synced.sync_aof()).await{
+            error!(cause = %err, "failed to flush the append only file");
+        }
+    }
+}
+
+/// Take a snapshot in the background whenever one of the save rules matches.
+/// Runs until the database shuts down.
+async fn save_snapshots_task(db: Db){
+    let mut interval = time::interval(Duration::from_secs(1));
+
+    while !db.shared.is_shutdown(){
+        interval.tick().await;
+        db.running_script().completed().await;
+
+        if db.should_save(){
+            info!("save rule matched, saving in the background");
+            if let Err(err) = db.bgsave(){
+                error!(cause = %err, "failed to start background saving");
+            }
         }
     }
-    debug!("Purge background task shut down")
 }
\ No newline at end of file
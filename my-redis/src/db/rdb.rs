@@ -0,0 +1,971 @@
+//! Snapshots in the Redis RDB format.
+//!
+//! Snapshots are written with RDB version 9, the format of Redis 5 to 6.2,
+//! which later Redis versions and the usual inspection tools read. Lists,
+//! sets and hashes are written as plain sequences of strings, sorted sets
+//! with binary scores, and streams as listpacks like Redis does.
+//!
+//! Loading also accepts the compact encodings of later versions, so that a
+//! dump taken by Redis can be loaded as long as it only uses the first
+//! database.
+
+use super::stream::{Consumer, ConsumerGroup, PendingEntry, Stream, StreamId};
+use super::scan::{ScanMap, ScanSet};
+use super::{Entry, SortedSet, Value, ZAddOptions};
+
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{Duration, Instant};
+
+/// Version of the format written.
+const VERSION: u32 = 9;
+
+/// Latest version of the format which can be loaded.
+const MAX_VERSION: u32 = 12;
+
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+/// Flags of a stream entry stored in a listpack.
+const STREAM_ITEM_DELETED: i64 = 1;
+const STREAM_ITEM_SAMEFIELDS: i64 = 2;
+
+/// Maximum number of entries of a stream listpack, the default of Redis.
+const STREAM_NODE_ENTRIES: usize = 100;
+
+/// Container of a quicklist node holding a single element.
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+
+/// Checksum closing the file.
+const CRC64: crc::Crc<u64> = crc::Crc::<u64>::new(&crc::CRC_64_REDIS);
+
+/// Converts between the `Instant`s the database works with and the absolute
+/// Unix times, in milliseconds, snapshots store.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Clock {
+    instant: Instant,
+    unix_ms: u64,
+}
+
+/// Element of a listpack. Listpacks store strings looking like integers as
+/// integers.
+#[derive(Debug)]
+enum ListpackEntry {
+    Int(i64),
+    String(Bytes),
+}
+
+/// Builds a listpack, the compact list encoding of Redis.
+#[derive(Debug, Default)]
+struct Listpack {
+    elements: Vec<u8>,
+    len: usize,
+}
+
+/// Reads an RDB file.
+struct Reader<'a> {
+    data: &'a [u8],
+    clock: Clock,
+}
+
+impl Clock {
+    pub(super) fn now() -> Clock {
+        let unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+
+        Clock {
+            instant: Instant::now(),
+            unix_ms,
+        }
+    }
+
+    fn to_unix_ms(self, when: Instant) -> u64 {
+        if when >= self.instant {
+            self.unix_ms + (when - self.instant).as_millis() as u64
+        } else {
+            self.unix_ms
+                .saturating_sub((self.instant - when).as_millis() as u64)
+        }
+    }
+
+    /// Returns `None` if the time cannot be represented, which only happens
+    /// with corrupted files.
+    fn to_instant(self, unix_ms: u64) -> Option<Instant> {
+        if unix_ms >= self.unix_ms {
+            self.instant
+                .checked_add(Duration::from_millis(unix_ms - self.unix_ms))
+        } else {
+            self.instant
+                .checked_sub(Duration::from_millis(self.unix_ms - unix_ms))
+        }
+    }
+}
+
+/// Encode the key space into an RDB file. Expired entries are left out.
+pub(super) fn encode<'a>(
+    entries: impl Iterator<Item = (&'a String, &'a Entry)>,
+    clock: Clock,
+) -> Vec<u8> {
+    let entries: Vec<_> = entries
+        .filter(|(_, entry)| entry.expires_at.is_none_or(|when| when > clock.instant))
+        .collect();
+    let expires = entries
+        .iter()
+        .filter(|(_, entry)| entry.expires_at.is_some())
+        .count();
+
+    let mut out = format!("REDIS{:04}", VERSION).into_bytes();
+
+    for (name, value) in [
+        ("redis-ver", env!("CARGO_PKG_VERSION").to_string()),
+        ("redis-bits", usize::BITS.to_string()),
+        ("ctime", (clock.unix_ms / 1000).to_string()),
+    ] {
+        out.push(OPCODE_AUX);
+        put_string(&mut out, name.as_bytes());
+        put_string(&mut out, value.as_bytes());
+    }
+
+    out.push(OPCODE_SELECTDB);
+    put_length(&mut out, 0);
+    out.push(OPCODE_RESIZEDB);
+    put_length(&mut out, entries.len() as u64);
+    put_length(&mut out, expires as u64);
+
+    for (key, entry) in entries {
+        if let Some(when) = entry.expires_at {
+            out.push(OPCODE_EXPIRETIME_MS);
+            out.extend_from_slice(&clock.to_unix_ms(when).to_le_bytes());
+        }
+        put_value(&mut out, key, &entry.value, clock);
+    }
+
+    out.push(OPCODE_EOF);
+    let checksum = CRC64.checksum(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+/// Decode an RDB file into entries of the key space. Expired entries are left
+/// out.
+///
+/// Data following the end of the file is ignored, the length of the file is
+/// returned along with the entries.
+pub(super) fn decode(data: &[u8], clock: Clock) -> crate::Result<(Vec<(String, Entry)>, usize)> {
+    let version = match data.get(..9) {
+        Some(header) if header.starts_with(b"REDIS") => std::str::from_utf8(&header[5..])
+            .ok()
+            .and_then(|version| version.parse::<u32>().ok()),
+        _ => None,
+    };
+    let version = match version {
+        Some(version) if version <= MAX_VERSION => version,
+        Some(version) => return Err(format!("unsupported RDB version {}", version).into()),
+        None => return Err("invalid RDB file: not an RDB file".into()),
+    };
+
+    let mut reader = Reader {
+        data: &data[9..],
+        clock,
+    };
+    let mut entries = vec![];
+    let mut expires_at = None;
+
+    loop {
+        match reader.u8()? {
+            OPCODE_EOF => break,
+            OPCODE_AUX => {
+                reader.string()?;
+                reader.string()?;
+            }
+            OPCODE_RESIZEDB => {
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_SELECTDB => {
+                if reader.length()? != 0 {
+                    return Err("invalid RDB file: only database 0 is supported".into());
+                }
+            }
+            OPCODE_EXPIRETIME_MS => expires_at = Some(reader.u64()?),
+            OPCODE_EXPIRETIME => expires_at = Some(reader.u32()? as u64 * 1000),
+            OPCODE_IDLE => {
+                reader.length()?;
+            }
+            OPCODE_FREQ => {
+                reader.u8()?;
+            }
+            value_type => {
+                let key = String::from_utf8(reader.string()?.to_vec())?;
+                let value = reader.value(value_type)?;
+
+                let expires_at = match expires_at.take() {
+                    Some(unix_ms) if unix_ms <= clock.unix_ms => continue,
+                    Some(unix_ms) => Some(clock.to_instant(unix_ms).ok_or_else(invalid)?),
+                    None => None,
+                };
+
+                entries.push((
+                    key,
+                    Entry {
+                        value,
+                        expires_at,
+                        version: 0,
+                    },
+                ));
+            }
+        }
+    }
+
+    // Files written by Redis 5 and later end with a checksum, 0 when
+    // checksums are disabled.
+    let mut len = data.len() - reader.data.len();
+    if version >= 5 {
+        let checksum = reader.u64()?;
+        if checksum != 0 && checksum != CRC64.checksum(&data[..len]) {
+            return Err("invalid RDB file: wrong checksum".into());
+        }
+        len += 8;
+    }
+
+    Ok((entries, len))
+}
+
+/// Write the type, the key and the value of an entry.
+fn put_value(out: &mut Vec<u8>, key: &str, value: &Value, clock: Clock) {
+    let value_type = match value {
+        Value::String(_) => TYPE_STRING,
+        Value::List(_) => TYPE_LIST,
+        Value::Set(_) => TYPE_SET,
+        Value::Hash(_) => TYPE_HASH,
+        Value::SortedSet(_) => TYPE_ZSET_2,
+        Value::Stream(_) => TYPE_STREAM_LISTPACKS,
+    };
+    out.push(value_type);
+    put_string(out, key.as_bytes());
+
+    match value {
+        Value::String(data) => put_string(out, data),
+        Value::List(list) => {
+            put_length(out, list.len() as u64);
+            for element in list {
+                put_string(out, element);
+            }
+        }
+        Value::Set(set) => {
+            put_length(out, set.len() as u64);
+            for member in set {
+                put_string(out, member);
+            }
+        }
+        Value::Hash(hash) => {
+            put_length(out, hash.len() as u64);
+            for (field, value) in hash {
+                put_string(out, field);
+                put_string(out, value);
+            }
+        }
+        Value::SortedSet(zset) => {
+            put_length(out, zset.len() as u64);
+            for (member, score) in zset.iter() {
+                put_string(out, member);
+                out.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+        Value::Stream(stream) => put_stream(out, stream, clock),
+    }
+}
+
+fn put_stream(out: &mut Vec<u8>, stream: &Stream, clock: Clock) {
+    let entries: Vec<_> = stream.entries.iter().collect();
+    let nodes = entries.chunks(STREAM_NODE_ENTRIES);
+
+    put_length(out, nodes.len() as u64);
+    for node in nodes {
+        let master = *node[0].0;
+        put_string(out, &stream_id_bytes(master));
+        put_string(out, &stream_node(master, node));
+    }
+
+    put_length(out, stream.entries.len() as u64);
+    put_length(out, stream.last_id.ms());
+    put_length(out, stream.last_id.seq());
+
+    put_length(out, stream.groups.len() as u64);
+    for (name, group) in &stream.groups {
+        put_string(out, name.as_bytes());
+        put_length(out, group.last_delivered.ms());
+        put_length(out, group.last_delivered.seq());
+
+        put_length(out, group.pending.len() as u64);
+        for (id, pending) in &group.pending {
+            out.extend_from_slice(&stream_id_bytes(*id));
+            out.extend_from_slice(&clock.to_unix_ms(pending.delivered_at).to_le_bytes());
+            put_length(out, pending.deliveries);
+        }
+
+        put_length(out, group.consumers.len() as u64);
+        for (name, consumer) in &group.consumers {
+            put_string(out, name.as_bytes());
+            // The time the consumer was last seen is not tracked.
+            out.extend_from_slice(&clock.unix_ms.to_le_bytes());
+            put_length(out, consumer.pending.len() as u64);
+            for id in &consumer.pending {
+                out.extend_from_slice(&stream_id_bytes(*id));
+            }
+        }
+    }
+}
+
+/// Encode stream entries as a listpack.
+///
+/// The listpack starts with a master entry holding the fields of the first
+/// entry. Entries with the same fields only store their values.
+fn stream_node(master: StreamId, entries: &[(&StreamId, &Vec<(Bytes, Bytes)>)]) -> Vec<u8> {
+    let master_fields: Vec<_> = entries[0].1.iter().map(|(field, _)| field).collect();
+
+    let mut listpack = Listpack::default();
+    listpack.int(entries.len() as i64);
+    listpack.int(0);
+    listpack.int(master_fields.len() as i64);
+    for field in &master_fields {
+        listpack.string(field);
+    }
+    listpack.int(0);
+
+    for (id, fields) in entries {
+        let same_fields = fields.len() == master_fields.len()
+            && fields
+                .iter()
+                .zip(&master_fields)
+                .all(|((field, _), master)| field == *master);
+
+        listpack.int(if same_fields {
+            STREAM_ITEM_SAMEFIELDS
+        } else {
+            0
+        });
+        listpack.int(id.ms().wrapping_sub(master.ms()) as i64);
+        listpack.int(id.seq().wrapping_sub(master.seq()) as i64);
+
+        if same_fields {
+            for (_, value) in fields.iter() {
+                listpack.string(value);
+            }
+            listpack.int(fields.len() as i64 + 3);
+        } else {
+            listpack.int(fields.len() as i64);
+            for (field, value) in fields.iter() {
+                listpack.string(field);
+                listpack.string(value);
+            }
+            listpack.int(fields.len() as i64 * 2 + 4);
+        }
+    }
+
+    listpack.finish()
+}
+
+/// Stream identifiers are stored as 128 bit big endian integers.
+fn stream_id_bytes(id: StreamId) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&id.ms().to_be_bytes());
+    bytes[8..].copy_from_slice(&id.seq().to_be_bytes());
+    bytes
+}
+
+fn put_length(out: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.extend_from_slice(&(len as u16 | 0x4000).to_be_bytes());
+    } else if len <= u32::MAX as u64 {
+        out.push(0x80);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, data: &[u8]) {
+    put_length(out, data.len() as u64);
+    out.extend_from_slice(data);
+}
+
+impl Listpack {
+    fn int(&mut self, value: i64) {
+        let start = self.elements.len();
+
+        match value {
+            0..=127 => self.elements.push(value as u8),
+            -4096..=4095 => {
+                let value = value as u16 & 0x1FFF;
+                self.elements.push(0xC0 | (value >> 8) as u8);
+                self.elements.push(value as u8);
+            }
+            -32768..=32767 => {
+                self.elements.push(0xF1);
+                self.elements
+                    .extend_from_slice(&(value as i16).to_le_bytes());
+            }
+            -8388608..=8388607 => {
+                self.elements.push(0xF2);
+                self.elements
+                    .extend_from_slice(&(value as i32).to_le_bytes()[..3]);
+            }
+            -2147483648..=2147483647 => {
+                self.elements.push(0xF3);
+                self.elements
+                    .extend_from_slice(&(value as i32).to_le_bytes());
+            }
+            _ => {
+                self.elements.push(0xF4);
+                self.elements.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        self.back_length(start);
+    }
+
+    fn string(&mut self, value: &[u8]) {
+        let start = self.elements.len();
+        let len = value.len();
+
+        if len < 64 {
+            self.elements.push(0x80 | len as u8);
+        } else if len < 4096 {
+            self.elements.push(0xE0 | (len >> 8) as u8);
+            self.elements.push(len as u8);
+        } else {
+            self.elements.push(0xF0);
+            self.elements.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+        self.elements.extend_from_slice(value);
+
+        self.back_length(start);
+    }
+
+    /// Terminate the element starting at `start` with its length, which lets
+    /// listpacks be walked backwards.
+    fn back_length(&mut self, start: usize) {
+        let len = self.elements.len() - start;
+        let bytes = back_length_size(len);
+
+        for i in (0..bytes).rev() {
+            let part = (len >> (7 * i)) as u8 & 127;
+            self.elements
+                .push(if i == bytes - 1 { part } else { part | 128 });
+        }
+        self.len += 1;
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let total = 6 + self.elements.len() + 1;
+
+        let mut out = Vec::with_capacity(total);
+        out.extend_from_slice(&(total as u32).to_le_bytes());
+        out.extend_from_slice(&(self.len.min(u16::MAX as usize) as u16).to_le_bytes());
+        out.extend_from_slice(&self.elements);
+        out.push(0xFF);
+        out
+    }
+}
+
+/// Number of bytes used to store the length of a listpack element.
+fn back_length_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+/// Decode the elements of a listpack.
+fn read_listpack(data: &[u8]) -> crate::Result<Vec<ListpackEntry>> {
+    let mut data = data.get(6..).ok_or_else(truncated)?;
+    let mut elements = vec![];
+
+    loop {
+        let encoding = *data.first().ok_or_else(truncated)?;
+        if encoding == 0xFF {
+            return Ok(elements);
+        }
+
+        let byte = |i: usize| data.get(i).copied().map(usize::from).ok_or_else(truncated);
+        let slice = |start: usize, len: usize| data.get(start..start + len).ok_or_else(truncated);
+
+        let (element, len) = match encoding {
+            0x00..=0x7F => (ListpackEntry::Int(encoding as i64), 1),
+            0x80..=0xBF => {
+                let len = encoding as usize & 0x3F;
+                (string_entry(slice(1, len)?), 1 + len)
+            }
+            0xC0..=0xDF => {
+                let value = (encoding as i64 & 0x1F) << 8 | byte(1)? as i64;
+                // Sign extend the 13 bits value.
+                let value = (value << 51) >> 51;
+                (ListpackEntry::Int(value), 2)
+            }
+            0xE0..=0xEF => {
+                let len = (encoding as usize & 0x0F) << 8 | byte(1)?;
+                (string_entry(slice(2, len)?), 2 + len)
+            }
+            0xF0 => {
+                let len = u32::from_le_bytes(slice(1, 4)?.try_into().unwrap()) as usize;
+                (string_entry(slice(5, len)?), 5 + len)
+            }
+            0xF1 => {
+                let value = i16::from_le_bytes(slice(1, 2)?.try_into().unwrap());
+                (ListpackEntry::Int(value as i64), 3)
+            }
+            0xF2 => {
+                let bytes = slice(1, 3)?;
+                let value = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+                (ListpackEntry::Int(value as i64), 4)
+            }
+            0xF3 => {
+                let value = i32::from_le_bytes(slice(1, 4)?.try_into().unwrap());
+                (ListpackEntry::Int(value as i64), 5)
+            }
+            0xF4 => {
+                let value = i64::from_le_bytes(slice(1, 8)?.try_into().unwrap());
+                (ListpackEntry::Int(value), 9)
+            }
+            _ => return Err(invalid()),
+        };
+
+        elements.push(element);
+        data = data
+            .get(len + back_length_size(len)..)
+            .ok_or_else(truncated)?;
+    }
+}
+
+fn string_entry(data: &[u8]) -> ListpackEntry {
+    ListpackEntry::String(Bytes::copy_from_slice(data))
+}
+
+impl ListpackEntry {
+    fn into_bytes(self) -> Bytes {
+        match self {
+            ListpackEntry::Int(value) => Bytes::from(value.to_string()),
+            ListpackEntry::String(data) => data,
+        }
+    }
+
+    fn to_int(&self) -> crate::Result<i64> {
+        match self {
+            ListpackEntry::Int(value) => Ok(*value),
+            ListpackEntry::String(data) => atoi::atoi(data).ok_or_else(invalid),
+        }
+    }
+
+    fn to_score(&self) -> crate::Result<f64> {
+        match self {
+            ListpackEntry::Int(value) => Ok(*value as f64),
+            ListpackEntry::String(data) => {
+                std::str::from_utf8(data)?.parse().map_err(|_| invalid())
+            }
+        }
+    }
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: u64) -> crate::Result<&'a [u8]> {
+        let len = usize::try_from(len)?;
+        if len > self.data.len() {
+            return Err(truncated());
+        }
+
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> crate::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> crate::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> crate::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> crate::Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn stream_id(&mut self) -> crate::Result<StreamId> {
+        let bytes = self.take(16)?;
+        let ms = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let seq = u64::from_be_bytes(bytes[8..].try_into().unwrap());
+        Ok(StreamId::new(ms, seq))
+    }
+
+    fn length(&mut self) -> crate::Result<u64> {
+        let first = self.u8()?;
+        self.length_from(first)
+    }
+
+    /// Decode a length whose first byte, `first`, was already read.
+    fn length_from(&mut self, first: u8) -> crate::Result<u64> {
+        match first >> 6 {
+            0 => Ok(first as u64 & 0x3F),
+            1 => Ok((first as u64 & 0x3F) << 8 | self.u8()? as u64),
+            2 if first == 0x80 => Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64),
+            2 if first == 0x81 => Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Read a string, which may be stored as an integer or compressed.
+    fn string(&mut self) -> crate::Result<Bytes> {
+        let first = self.u8()?;
+        if first >> 6 != 3 {
+            let len = self.length_from(first)?;
+            return Ok(Bytes::copy_from_slice(self.take(len)?));
+        }
+
+        match first & 0x3F {
+            0 => Ok(Bytes::from((self.u8()? as i8).to_string())),
+            1 => {
+                let value = i16::from_le_bytes(self.take(2)?.try_into().unwrap());
+                Ok(Bytes::from(value.to_string()))
+            }
+            2 => {
+                let value = i32::from_le_bytes(self.take(4)?.try_into().unwrap());
+                Ok(Bytes::from(value.to_string()))
+            }
+            3 => {
+                let compressed_len = self.length()?;
+                let len = self.length()?;
+                let compressed = self.take(compressed_len)?;
+                Ok(Bytes::from(lzf_decompress(
+                    compressed,
+                    usize::try_from(len)?,
+                )?))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    fn strings(&mut self) -> crate::Result<Vec<Bytes>> {
+        let len = self.length()?;
+        (0..len).map(|_| self.string()).collect()
+    }
+
+    fn listpack(&mut self) -> crate::Result<Vec<ListpackEntry>> {
+        read_listpack(&self.string()?)
+    }
+
+    fn value(&mut self, value_type: u8) -> crate::Result<Value> {
+        let value = match value_type {
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_LIST => Value::List(self.strings()?.into()),
+            TYPE_SET => Value::Set(self.strings()?.into_iter().collect()),
+            TYPE_HASH => {
+                let len = self.length()?;
+                let hash = (0..len)
+                    .map(|_| Ok((self.string()?, self.string()?)))
+                    .collect::<crate::Result<_>>()?;
+                Value::Hash(hash)
+            }
+            TYPE_ZSET_2 => {
+                let len = self.length()?;
+                let mut zset = SortedSet::default();
+                for _ in 0..len {
+                    let member = self.string()?;
+                    let score = self.f64()?;
+                    add_member(&mut zset, member, score)?;
+                }
+                Value::SortedSet(zset)
+            }
+            TYPE_SET_INTSET => Value::Set(read_intset(&self.string()?)?),
+            TYPE_HASH_LISTPACK => {
+                let mut elements = self.listpack()?.into_iter();
+                let mut hash = ScanMap::default();
+                while let (Some(field), Some(value)) = (elements.next(), elements.next()) {
+                    hash.insert(field.into_bytes(), value.into_bytes());
+                }
+                Value::Hash(hash)
+            }
+            TYPE_ZSET_LISTPACK => {
+                let mut elements = self.listpack()?.into_iter();
+                let mut zset = SortedSet::default();
+                while let (Some(member), Some(score)) = (elements.next(), elements.next()) {
+                    add_member(&mut zset, member.into_bytes(), score.to_score()?)?;
+                }
+                Value::SortedSet(zset)
+            }
+            TYPE_LIST_QUICKLIST_2 => {
+                let nodes = self.length()?;
+                let mut list = VecDeque::new();
+                for _ in 0..nodes {
+                    if self.length()? == QUICKLIST_NODE_PLAIN {
+                        list.push_back(self.string()?);
+                    } else {
+                        list.extend(self.listpack()?.into_iter().map(ListpackEntry::into_bytes));
+                    }
+                }
+                Value::List(list)
+            }
+            TYPE_SET_LISTPACK => Value::Set(
+                self.listpack()?
+                    .into_iter()
+                    .map(ListpackEntry::into_bytes)
+                    .collect(),
+            ),
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+                Value::Stream(self.stream(value_type)?)
+            }
+            _ => return Err(format!("unsupported RDB value type {}", value_type).into()),
+        };
+
+        Ok(value)
+    }
+
+    fn stream(&mut self, value_type: u8) -> crate::Result<Stream> {
+        let mut entries = BTreeMap::new();
+
+        let nodes = self.length()?;
+        for _ in 0..nodes {
+            let master = self.string()?;
+            if master.len() != 16 {
+                return Err(invalid());
+            }
+            let master = Reader {
+                data: &master,
+                clock: self.clock,
+            }
+            .stream_id()?;
+
+            read_stream_node(master, self.listpack()?, &mut entries)?;
+        }
+
+        // The number of entries, known from the nodes.
+        self.length()?;
+        let last_id = StreamId::new(self.length()?, self.length()?);
+        if value_type >= TYPE_STREAM_LISTPACKS_2 {
+            // The first and maximal deleted identifiers, and the number of
+            // entries ever added, are not tracked.
+            for _ in 0..5 {
+                self.length()?;
+            }
+        }
+
+        let mut groups = HashMap::new();
+        let len = self.length()?;
+        for _ in 0..len {
+            let name = String::from_utf8(self.string()?.to_vec())?;
+            let last_delivered = StreamId::new(self.length()?, self.length()?);
+            if value_type >= TYPE_STREAM_LISTPACKS_2 {
+                // The number of entries read by the group.
+                self.length()?;
+            }
+
+            let mut pending = BTreeMap::new();
+            let len = self.length()?;
+            for _ in 0..len {
+                let id = self.stream_id()?;
+                let delivered_at = self.u64()?;
+                let deliveries = self.length()?;
+                pending.insert(
+                    id,
+                    PendingEntry {
+                        consumer: String::new(),
+                        // Deliveries older than the instants the platform can
+                        // represent are made as old as possible.
+                        delivered_at: self
+                            .clock
+                            .to_instant(delivered_at)
+                            .unwrap_or(self.clock.instant),
+                        deliveries,
+                    },
+                );
+            }
+
+            let mut consumers = HashMap::new();
+            let len = self.length()?;
+            for _ in 0..len {
+                let name = String::from_utf8(self.string()?.to_vec())?;
+                // The time the consumer was last seen, and last active.
+                self.u64()?;
+                if value_type >= TYPE_STREAM_LISTPACKS_3 {
+                    self.u64()?;
+                }
+
+                let mut consumer = Consumer {
+                    pending: BTreeSet::new(),
+                };
+                let len = self.length()?;
+                for _ in 0..len {
+                    let id = self.stream_id()?;
+                    pending.get_mut(&id).ok_or_else(invalid)?.consumer = name.clone();
+                    consumer.pending.insert(id);
+                }
+                consumers.insert(name, consumer);
+            }
+
+            groups.insert(
+                name,
+                ConsumerGroup {
+                    last_delivered,
+                    pending,
+                    consumers,
+                },
+            );
+        }
+
+        Ok(Stream {
+            entries,
+            last_id,
+            groups,
+        })
+    }
+}
+
+/// Decode the entries stored in a stream listpack, see `stream_node`.
+fn read_stream_node(
+    master: StreamId,
+    listpack: Vec<ListpackEntry>,
+    entries: &mut BTreeMap<StreamId, Vec<(Bytes, Bytes)>>,
+) -> crate::Result<()> {
+    let mut elements = listpack.into_iter();
+    let mut next = || elements.next().ok_or_else(invalid);
+
+    let count = next()?.to_int()? + next()?.to_int()?;
+    let len = next()?.to_int()?;
+    let master_fields = (0..len)
+        .map(|_| Ok(next()?.into_bytes()))
+        .collect::<crate::Result<Vec<_>>>()?;
+    next()?;
+
+    for _ in 0..count {
+        let flags = next()?.to_int()?;
+        let ms = master.ms().wrapping_add(next()?.to_int()? as u64);
+        let seq = master.seq().wrapping_add(next()?.to_int()? as u64);
+
+        let fields = if flags & STREAM_ITEM_SAMEFIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Ok((field.clone(), next()?.into_bytes())))
+                .collect::<crate::Result<Vec<_>>>()?
+        } else {
+            let len = next()?.to_int()?;
+            (0..len)
+                .map(|_| Ok((next()?.into_bytes(), next()?.into_bytes())))
+                .collect::<crate::Result<Vec<_>>>()?
+        };
+        next()?;
+
+        if flags & STREAM_ITEM_DELETED == 0 {
+            entries.insert(StreamId::new(ms, seq), fields);
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode an intset: the size of the integers, their number, then the
+/// integers.
+fn read_intset(data: &[u8]) -> crate::Result<ScanSet<Bytes>> {
+    let header = data.get(..8).ok_or_else(truncated)?;
+    let size = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+
+    let integers = &data[8..];
+    if ![2, 4, 8].contains(&size) || integers.len() != size * len {
+        return Err(invalid());
+    }
+
+    Ok(integers
+        .chunks_exact(size)
+        .map(|bytes| {
+            let value = match size {
+                2 => i16::from_le_bytes(bytes.try_into().unwrap()) as i64,
+                4 => i32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+                _ => i64::from_le_bytes(bytes.try_into().unwrap()),
+            };
+            Bytes::from(value.to_string())
+        })
+        .collect())
+}
+
+fn add_member(zset: &mut SortedSet, member: Bytes, score: f64) -> crate::Result<()> {
+    zset.add(member, score, &ZAddOptions::default())?;
+    Ok(())
+}
+
+/// Decompress an LZF compressed string of `len` bytes.
+fn lzf_decompress(src: &[u8], len: usize) -> crate::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < src.len() {
+        let control = src[i] as usize;
+        i += 1;
+
+        if control < 32 {
+            // A run of literal bytes.
+            let literal = src.get(i..i + control + 1).ok_or_else(invalid)?;
+            out.extend_from_slice(literal);
+            i += control + 1;
+        } else {
+            // A back reference to bytes already decompressed.
+            let mut run = control >> 5;
+            if run == 7 {
+                run += *src.get(i).ok_or_else(invalid)? as usize;
+                i += 1;
+            }
+            let offset = ((control & 0x1F) << 8) + *src.get(i).ok_or_else(invalid)? as usize + 1;
+            i += 1;
+
+            let start = out.len().checked_sub(offset).ok_or_else(invalid)?;
+            for j in start..start + run + 2 {
+                out.push(out[j]);
+            }
+        }
+    }
+
+    if out.len() != len {
+        return Err(invalid());
+    }
+    Ok(out)
+}
+
+fn invalid() -> crate::Error {
+    "invalid RDB file".into()
+}
+
+fn truncated() -> crate::Error {
+    "invalid RDB file: unexpected end of file".into()
+}
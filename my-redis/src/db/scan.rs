@@ -0,0 +1,356 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::{self, DefaultHasher, Entry};
+use std::collections::{hash_set, BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+/// A `HashMap` kept along with the order `SCAN` and its variants walk it in.
+///
+/// `HashMap` does not expose its buckets, so items are visited in the order of
+/// a hash of their key computed with fixed seeds. The cursor is the hash of
+/// the next item to return, which guarantees that an item present during the
+/// whole iteration is returned, whatever the items added or removed between
+/// two calls. Keys are kept twice, so that a page is found in the order
+/// without walking every item.
+///
+/// Reads go through `Deref`, writes through the methods below which keep the
+/// order up to date.
+#[derive(Debug, Clone)]
+pub(crate) struct ScanMap<K, V> {
+    items: HashMap<K, V>,
+    order: ScanOrder<K>,
+}
+
+/// A `HashSet` kept along with the order `SCAN` and its variants walk it in,
+/// see `ScanMap`.
+#[derive(Debug, Clone)]
+pub(crate) struct ScanSet<K> {
+    items: HashSet<K>,
+    order: ScanOrder<K>,
+}
+
+/// Keys grouped by their cursor hash. Keys sharing a hash must land on the
+/// same page, as the cursor cannot tell them apart.
+#[derive(Debug, Clone)]
+struct ScanOrder<K> {
+    groups: BTreeMap<u64, Vec<K>>,
+}
+
+impl<K, V> ScanMap<K, V>
+where
+    K: Hash + Eq + Clone + AsRef<[u8]>,
+{
+    /// Insert `value` at `key`, returning the value it replaced.
+    pub(crate) fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.items.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                self.order.insert(entry.key().clone());
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Returns the value at `key` for writing, first inserting `default()`
+    /// if the key is missing.
+    pub(crate) fn get_or_insert_with(&mut self, key: K, default: impl FnOnce() -> V) -> &mut V {
+        match self.items.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.order.insert(entry.key().clone());
+                entry.insert(default())
+            }
+        }
+    }
+
+    pub(crate) fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.items.get_mut(key)
+    }
+
+    pub(crate) fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + AsRef<[u8]> + ?Sized,
+    {
+        let value = self.items.remove(key)?;
+        self.order.remove(key.as_ref());
+        Some(value)
+    }
+
+    /// Returns the page of about `count` items following `cursor`, along with
+    /// the cursor of the next page, `0` once the iteration is complete.
+    pub(crate) fn page(&self, cursor: u64, count: usize) -> (u64, Vec<(&K, &V)>) {
+        let (next, keys) = self.order.page(cursor, count);
+        let page = keys
+            .into_iter()
+            .map(|key| (key, &self.items[key]))
+            .collect();
+        (next, page)
+    }
+}
+
+impl<K> ScanSet<K>
+where
+    K: Hash + Eq + Clone + AsRef<[u8]>,
+{
+    /// Insert `key`, returning `true` if it was missing.
+    pub(crate) fn insert(&mut self, key: K) -> bool {
+        if self.items.contains(&key) {
+            return false;
+        }
+        self.order.insert(key.clone());
+        self.items.insert(key)
+    }
+
+    /// Remove `key`, returning `true` if it was present.
+    pub(crate) fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + AsRef<[u8]> + ?Sized,
+    {
+        let removed = self.items.remove(key);
+        if removed {
+            self.order.remove(key.as_ref());
+        }
+        removed
+    }
+
+    /// Returns the page of about `count` keys following `cursor` like
+    /// `ScanMap::page`.
+    pub(crate) fn page(&self, cursor: u64, count: usize) -> (u64, Vec<&K>) {
+        self.order.page(cursor, count)
+    }
+}
+
+impl<K: AsRef<[u8]>> ScanOrder<K> {
+    fn insert(&mut self, key: K) {
+        let hash = cursor_hash(key.as_ref());
+        self.groups.entry(hash).or_default().push(key);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        let hash = cursor_hash(key);
+        if let Some(group) = self.groups.get_mut(&hash) {
+            group.retain(|other| other.as_ref() != key);
+            if group.is_empty() {
+                self.groups.remove(&hash);
+            }
+        }
+    }
+
+    /// Walks the groups from `cursor` until `count` keys are gathered. The
+    /// page grows past `count` rather than splitting a group.
+    fn page(&self, cursor: u64, count: usize) -> (u64, Vec<&K>) {
+        let count = count.max(1);
+        let mut page = vec![];
+
+        for (&hash, group) in self.groups.range(cursor..) {
+            if page.len() >= count {
+                return (hash, page);
+            }
+            page.extend(group);
+        }
+        (0, page)
+    }
+}
+
+impl<K, V> Deref for ScanMap<K, V> {
+    type Target = HashMap<K, V>;
+
+    fn deref(&self) -> &HashMap<K, V> {
+        &self.items
+    }
+}
+
+impl<K> Deref for ScanSet<K> {
+    type Target = HashSet<K>;
+
+    fn deref(&self) -> &HashSet<K> {
+        &self.items
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a ScanMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = hash_map::Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<'a, K> IntoIterator for &'a ScanSet<K> {
+    type Item = &'a K;
+    type IntoIter = hash_set::Iter<'a, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for ScanMap<K, V>
+where
+    K: Hash + Eq + Clone + AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = ScanMap::default();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl<K> FromIterator<K> for ScanSet<K>
+where
+    K: Hash + Eq + Clone + AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut set = ScanSet::default();
+        for key in iter {
+            set.insert(key);
+        }
+        set
+    }
+}
+
+impl<K, V> Default for ScanMap<K, V> {
+    fn default() -> Self {
+        ScanMap {
+            items: HashMap::new(),
+            order: ScanOrder::default(),
+        }
+    }
+}
+
+impl<K> Default for ScanSet<K> {
+    fn default() -> Self {
+        ScanSet {
+            items: HashSet::new(),
+            order: ScanOrder::default(),
+        }
+    }
+}
+
+impl<K> Default for ScanOrder<K> {
+    fn default() -> Self {
+        ScanOrder {
+            groups: BTreeMap::new(),
+        }
+    }
+}
+
+fn cursor_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns `true` if `string` matches the glob-style `pattern`.
+///
+/// Supports the same syntax as Redis: `*` matches any sequence, `?` any
+/// character, `[abc]`, `[^abc]` and `[a-z]` a set of characters, and `\`
+/// escapes the next character.
+///
+/// When the pattern fails to match after a star, only the last star is
+/// given more of the string, like the early exit of Redis: an earlier star
+/// taking more cannot help the later one. Matching takes at most
+/// `pattern.len() * string.len()` steps, where trying every split of every
+/// star would be exponential.
+pub(crate) fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Pattern following the last star, and string position it last resumed at.
+    let mut star = None;
+
+    while s < string.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, s));
+                continue;
+            }
+            Some(_) => {
+                if let Some(len) = match_one(&pattern[p..], string[s]) {
+                    p += len;
+                    s += 1;
+                    continue;
+                }
+            }
+            None => {}
+        }
+
+        // Give one more character to the last star, if any.
+        match star {
+            Some((after, start)) => {
+                p = after;
+                s = start + 1;
+                star = Some((after, s));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Matches `c` against the element starting `pattern`, other than a star.
+///
+/// Returns the length of the element if it matched.
+fn match_one(pattern: &[u8], c: u8) -> Option<usize> {
+    match pattern {
+        [b'?', ..] => Some(1),
+        [b'[', class @ ..] => {
+            let (matched, rest) = match_class(class, c);
+            matched.then_some(pattern.len() - rest.len())
+        }
+        [b'\\', escaped, ..] => (*escaped == c).then_some(2),
+        [p, ..] => (*p == c).then_some(1),
+        [] => None,
+    }
+}
+
+/// Matches `c` against the character class starting after `[`.
+///
+/// Returns whether it matched, along with the pattern following the class. An
+/// unterminated class extends to the end of the pattern.
+fn match_class(mut pattern: &[u8], c: u8) -> (bool, &[u8]) {
+    let negate = pattern.first() == Some(&b'^');
+    if negate {
+        pattern = &pattern[1..];
+    }
+
+    let mut matched = false;
+    loop {
+        match pattern {
+            [] => break,
+            [b']', rest @ ..] => {
+                pattern = rest;
+                break;
+            }
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == c;
+                pattern = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (low, high) = if start <= end {
+                    (*start, *end)
+                } else {
+                    (*end, *start)
+                };
+                matched |= (low..=high).contains(&c);
+                pattern = rest;
+            }
+            [other, rest @ ..] => {
+                matched |= *other == c;
+                pattern = rest;
+            }
+        }
+    }
+
+    (matched != negate, pattern)
+}
@@ -0,0 +1,273 @@
+use super::scan::ScanMap;
+use super::{clamp_range, DbError};
+
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+/// Value of a sorted set key.
+///
+/// Members are kept twice: `members` maps each member to its score for O(1)
+/// lookups, while `index` keeps the `(score, member)` pairs ordered so range
+/// queries can walk the set in order. Both are always updated together.
+/// `members` is also walked by `ZSCAN`, see `ScanMap`.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SortedSet {
+    members: ScanMap<Bytes, f64>,
+    index: BTreeSet<(Score, Bytes)>,
+}
+
+/// `f64` wrapper providing the total ordering required by `BTreeSet`.
+///
+/// `NaN` scores are never stored, commands producing one are rejected.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+/// Options of the `ZADD` command.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ZAddOptions {
+    /// Only add new members, never update existing ones.
+    pub(crate) nx: bool,
+
+    /// Only update existing members, never add new ones.
+    pub(crate) xx: bool,
+
+    /// Only update existing members if the new score is greater.
+    pub(crate) gt: bool,
+
+    /// Only update existing members if the new score is less.
+    pub(crate) lt: bool,
+
+    /// Count changed members in the reply, not only added ones.
+    pub(crate) ch: bool,
+
+    /// Act like `ZINCRBY`: the score is an increment.
+    pub(crate) incr: bool,
+}
+
+/// Selects the members returned by a range query.
+#[derive(Debug, Clone)]
+pub(crate) enum RangeBy {
+    /// Zero-based inclusive ranks, negative ranks count from the end.
+    Rank(i64, i64),
+
+    /// Members whose score lies between the two bounds.
+    Score(ScoreBound, ScoreBound),
+
+    /// Members lexicographically between the two bounds. Only meaningful when
+    /// all members share the same score.
+    Lex(LexBound, LexBound),
+}
+
+/// Bound of a `BYSCORE` range, `(` prefixed scores being exclusive.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+/// Bound of a `BYLEX` range: `-`, `+`, `[member` or `(member`.
+#[derive(Debug, Clone)]
+pub(crate) enum LexBound {
+    Min,
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+impl SortedSet {
+    pub(crate) fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub(crate) fn score(&self, member: &Bytes) -> Option<f64> {
+        self.members.get(member).copied()
+    }
+
+    /// Iterate over the members and their scores, in no particular order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> {
+        self.members.iter().map(|(member, score)| (member, *score))
+    }
+
+    /// Returns the page of members following `cursor` for `ZSCAN`, see
+    /// `ScanMap::page`.
+    pub(crate) fn page(&self, cursor: u64, count: usize) -> (u64, Vec<(&Bytes, f64)>) {
+        let (next, page) = self.members.page(cursor, count);
+        let page = page
+            .into_iter()
+            .map(|(member, score)| (member, *score))
+            .collect();
+        (next, page)
+    }
+
+    /// Add `member` with `score` according to `options`.
+    ///
+    /// Returns the score of the member after the operation, `None` when the
+    /// options prevented the update, along with the previous score.
+    pub(crate) fn add(
+        &mut self,
+        member: Bytes,
+        score: f64,
+        options: &ZAddOptions,
+    ) -> Result<(Option<f64>, Option<f64>), DbError> {
+        let previous = self.score(&member);
+
+        let score = match previous {
+            Some(_) if options.nx => return Ok((None, previous)),
+            None if options.xx => return Ok((None, previous)),
+            Some(current) if options.incr => current + score,
+            _ => score,
+        };
+
+        if score.is_nan() {
+            return Err(DbError::ScoreNaN);
+        }
+
+        if let Some(current) = previous {
+            if (options.gt && score <= current) || (options.lt && score >= current) {
+                return Ok((None, previous));
+            }
+            self.index.remove(&(Score(current), member.clone()));
+        }
+
+        // Adding `0.0` normalizes `-0.0`, which would otherwise sort apart.
+        let score = score + 0.0;
+        self.index.insert((Score(score), member.clone()));
+        self.members.insert(member, score);
+
+        Ok((Some(score), previous))
+    }
+
+    pub(crate) fn remove(&mut self, member: &Bytes) -> bool {
+        match self.members.remove(member) {
+            Some(score) => {
+                self.index.remove(&(Score(score), member.clone()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Zero-based position of `member` ordered by ascending score.
+    pub(crate) fn rank(&self, member: &Bytes) -> Option<usize> {
+        let score = self.score(member)?;
+        self.index
+            .iter()
+            .position(|(s, m)| s.0 == score && m == member)
+    }
+
+    /// Remove and return up to `count` members with the lowest scores.
+    pub(crate) fn pop_min(&mut self, count: usize) -> Vec<(Bytes, f64)> {
+        let mut popped = Vec::with_capacity(count.min(self.len()));
+
+        while popped.len() < count {
+            match self.index.pop_first() {
+                Some((score, member)) => {
+                    self.members.remove(&member);
+                    popped.push((member, score.0));
+                }
+                None => break,
+            }
+        }
+
+        popped
+    }
+
+    /// Returns the members selected by `by`, in ascending order or descending
+    /// when `rev` is set. `offset` and `count` implement the `LIMIT` option of
+    /// score and lex ranges.
+    pub(crate) fn range(
+        &self,
+        by: &RangeBy,
+        rev: bool,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        let iter: Box<dyn Iterator<Item = &(Score, Bytes)>> = if rev {
+            Box::new(self.index.iter().rev())
+        } else {
+            Box::new(self.index.iter())
+        };
+
+        let selected: Box<dyn Iterator<Item = &(Score, Bytes)>> = match by {
+            RangeBy::Rank(start, stop) => match clamp_range(*start, *stop, self.len()) {
+                Some((start, stop)) => Box::new(iter.skip(start).take(stop - start + 1)),
+                None => return vec![],
+            },
+            RangeBy::Score(min, max) => {
+                // Members matching the range are contiguous. Skip the ones
+                // before the range, then stop at the first member after it.
+                let (before, after) = if rev { (max, min) } else { (min, max) };
+                Box::new(
+                    iter.skip_while(move |(score, _)| !before.admits(score.0, rev))
+                        .take_while(move |(score, _)| after.admits(score.0, !rev)),
+                )
+            }
+            RangeBy::Lex(min, max) => {
+                let (before, after) = if rev { (max, min) } else { (min, max) };
+                Box::new(
+                    iter.skip_while(move |(_, member)| !before.admits(member, rev))
+                        .take_while(move |(_, member)| after.admits(member, !rev)),
+                )
+            }
+        };
+
+        selected
+            .skip(offset)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(score, member)| (member.clone(), score.0))
+            .collect()
+    }
+}
+
+impl ScoreBound {
+    /// Returns `true` if `score` is on the inner side of the bound. `upper`
+    /// tells whether the bound is the upper end of the range.
+    fn admits(&self, score: f64, upper: bool) -> bool {
+        match (self, upper) {
+            (ScoreBound::Inclusive(bound), false) => score >= *bound,
+            (ScoreBound::Exclusive(bound), false) => score > *bound,
+            (ScoreBound::Inclusive(bound), true) => score <= *bound,
+            (ScoreBound::Exclusive(bound), true) => score < *bound,
+        }
+    }
+}
+
+impl LexBound {
+    /// Returns `true` if `member` is on the inner side of the bound. `upper`
+    /// tells whether the bound is the upper end of the range.
+    fn admits(&self, member: &Bytes, upper: bool) -> bool {
+        match (self, upper) {
+            (LexBound::Min, false) | (LexBound::Max, true) => true,
+            (LexBound::Min, true) | (LexBound::Max, false) => false,
+            (LexBound::Inclusive(bound), false) => member >= bound,
+            (LexBound::Exclusive(bound), false) => member > bound,
+            (LexBound::Inclusive(bound), true) => member <= bound,
+            (LexBound::Exclusive(bound), true) => member < bound,
+        }
+    }
+}
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Score) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Score) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Score) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
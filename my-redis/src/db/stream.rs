@@ -0,0 +1,527 @@
+use super::DbError;
+
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Bound;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{Duration, Instant};
+
+/// Identifier of a stream entry, formatted as `<ms>-<seq>`.
+///
+/// `ms` is a Unix time in milliseconds and `seq` orders the entries added
+/// within the same millisecond. Identifiers of a stream are strictly
+/// increasing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    ms: u64,
+    seq: u64,
+}
+
+/// A stream entry: its identifier and its field/value pairs.
+pub type StreamEntry = (StreamId, Vec<(Bytes, Bytes)>);
+
+/// An entry read through a consumer group. The fields are `None` for pending
+/// entries deleted from the stream since their delivery.
+pub(crate) type GroupEntry = (StreamId, Option<Vec<(Bytes, Bytes)>>);
+
+/// Identifier requested for an entry added by `XADD`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum NewId {
+    /// `*`: generated from the current time.
+    Auto,
+
+    /// `<ms>-*`: explicit time, generated sequence number.
+    Partial(u64),
+
+    /// `<ms>-<seq>`: fully explicit.
+    Explicit(StreamId),
+}
+
+/// Trimming strategy of `XADD` and `XTRIM`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum StreamTrim {
+    /// Keep at most this many entries, evicting the oldest ones.
+    MaxLen(u64),
+
+    /// Evict the entries with an identifier lower than this one.
+    MinId(StreamId),
+}
+
+/// Value of a stream key.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Stream {
+    pub(super) entries: BTreeMap<StreamId, Vec<(Bytes, Bytes)>>,
+
+    /// Identifier of the last entry ever added. Entries may have been trimmed
+    /// since, new identifiers must still be greater.
+    pub(super) last_id: StreamId,
+
+    pub(super) groups: HashMap<String, ConsumerGroup>,
+}
+
+/// A consumer group tracks which entries were delivered to its consumers and
+/// which deliveries were not acknowledged yet.
+#[derive(Debug, Clone)]
+pub(super) struct ConsumerGroup {
+    /// Entries up to this identifier were delivered to a consumer.
+    pub(super) last_delivered: StreamId,
+
+    /// The group pending entries list: delivered entries waiting for `XACK`.
+    pub(super) pending: BTreeMap<StreamId, PendingEntry>,
+
+    pub(super) consumers: HashMap<String, Consumer>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct PendingEntry {
+    /// Consumer currently owning the entry.
+    pub(super) consumer: String,
+    pub(super) delivered_at: Instant,
+    pub(super) deliveries: u64,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Consumer {
+    /// The consumer pending entries list, a subset of the group one.
+    pub(super) pending: BTreeSet<StreamId>,
+}
+
+/// Reply of the summary form of `XPENDING`.
+#[derive(Debug)]
+pub(crate) struct PendingSummary {
+    pub(crate) count: usize,
+
+    /// Lowest and highest pending identifiers, `None` if nothing is pending.
+    pub(crate) bounds: Option<(StreamId, StreamId)>,
+
+    /// Number of pending entries of every consumer owning at least one.
+    pub(crate) consumers: Vec<(String, usize)>,
+}
+
+/// A pending entry, as listed by the extended form of `XPENDING`.
+#[derive(Debug)]
+pub(crate) struct PendingInfo {
+    pub(crate) id: StreamId,
+    pub(crate) consumer: String,
+    pub(crate) idle: Duration,
+    pub(crate) deliveries: u64,
+}
+
+impl StreamId {
+    /// The lowest possible identifier, `0-0`.
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+
+    /// The greatest possible identifier.
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// Create a new identifier from its two parts.
+    pub fn new(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// The millisecond part of the identifier.
+    pub fn ms(&self) -> u64 {
+        self.ms
+    }
+
+    /// The sequence number part of the identifier.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Parse `<ms>-<seq>`, or `<ms>` alone in which case the sequence number
+    /// is `default_seq`.
+    pub(crate) fn parse(src: &[u8], default_seq: u64) -> Option<StreamId> {
+        let (ms, seq) = match src.iter().position(|b| *b == b'-') {
+            Some(pos) => (&src[..pos], Some(&src[pos + 1..])),
+            None => (src, None),
+        };
+
+        let ms = parse_u64(ms)?;
+        let seq = match seq {
+            Some(seq) => parse_u64(seq)?,
+            None => default_seq,
+        };
+
+        Some(StreamId { ms, seq })
+    }
+
+    /// The identifier immediately following this one.
+    pub(crate) fn next(&self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// The identifier immediately preceding this one.
+    pub(crate) fn prev(&self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+impl Stream {
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Append an entry, returning its identifier.
+    pub(crate) fn add(
+        &mut self,
+        id: NewId,
+        fields: Vec<(Bytes, Bytes)>,
+    ) -> Result<StreamId, DbError> {
+        let last = self.last_id;
+
+        let id = match id {
+            NewId::Auto => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_millis() as u64)
+                    .unwrap_or(0);
+
+                // The clock may go backwards, identifiers never do.
+                if now > last.ms {
+                    StreamId { ms: now, seq: 0 }
+                } else {
+                    last.next().ok_or(DbError::StreamIdTooSmall)?
+                }
+            }
+            NewId::Partial(ms) if ms == last.ms => last.next().ok_or(DbError::StreamIdTooSmall)?,
+            NewId::Partial(ms) => StreamId { ms, seq: 0 },
+            NewId::Explicit(id) => id,
+        };
+
+        if id == StreamId::MIN {
+            return Err(DbError::StreamIdZero);
+        }
+        if id <= last {
+            return Err(DbError::StreamIdTooSmall);
+        }
+
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    /// Evict entries according to `trim`, returning the number of evicted
+    /// entries.
+    pub(crate) fn trim(&mut self, trim: StreamTrim) -> usize {
+        let before = self.entries.len();
+
+        match trim {
+            StreamTrim::MaxLen(max) => {
+                while self.entries.len() as u64 > max {
+                    self.entries.pop_first();
+                }
+            }
+            StreamTrim::MinId(min) => {
+                self.entries = self.entries.split_off(&min);
+            }
+        }
+
+        before - self.entries.len()
+    }
+
+    /// Entries with an identifier between `start` and `end`, both inclusive.
+    pub(crate) fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        rev: bool,
+        count: Option<usize>,
+    ) -> Vec<StreamEntry> {
+        if start > end {
+            return vec![];
+        }
+
+        let range = self.entries.range(start..=end);
+        let count = count.unwrap_or(usize::MAX);
+
+        if rev {
+            range.rev().take(count).map(clone_entry).collect()
+        } else {
+            range.take(count).map(clone_entry).collect()
+        }
+    }
+
+    /// Entries with an identifier greater than `id`.
+    pub(crate) fn after(&self, id: StreamId, count: Option<usize>) -> Vec<StreamEntry> {
+        self.entries
+            .range((Bound::Excluded(id), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .map(clone_entry)
+            .collect()
+    }
+
+    /// Create the consumer group `name`, which will deliver the entries added
+    /// after `id`, or after the last entry if `None`.
+    pub(crate) fn create_group(
+        &mut self,
+        name: String,
+        id: Option<StreamId>,
+    ) -> Result<(), DbError> {
+        if self.groups.contains_key(&name) {
+            return Err(DbError::BusyGroup);
+        }
+
+        self.groups.insert(
+            name,
+            ConsumerGroup {
+                last_delivered: id.unwrap_or(self.last_id),
+                pending: BTreeMap::new(),
+                consumers: HashMap::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub(crate) fn has_group(&self, name: &str) -> bool {
+        self.groups.contains_key(name)
+    }
+
+    /// Read entries on behalf of `consumer` of the group `group`.
+    ///
+    /// With `after` set to `None`, the entries never delivered to the group
+    /// are returned and added to the consumer pending entries list, unless
+    /// `noack` is set. Otherwise the consumer pending entries with an
+    /// identifier greater than `after` are returned again, `None` standing
+    /// for the fields of entries deleted since.
+    pub(crate) fn read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        after: Option<StreamId>,
+        count: Option<usize>,
+        noack: bool,
+    ) -> Result<Vec<GroupEntry>, DbError> {
+        let now = Instant::now();
+        let count = count.unwrap_or(usize::MAX);
+
+        let group = self.groups.get_mut(group).ok_or(DbError::NoGroup)?;
+        let owner = group.consumer(consumer);
+
+        let after = match after {
+            Some(after) => {
+                return Ok(owner
+                    .pending
+                    .range((Bound::Excluded(after), Bound::Unbounded))
+                    .take(count)
+                    .map(|id| (*id, self.entries.get(id).cloned()))
+                    .collect());
+            }
+            None => group.last_delivered,
+        };
+
+        let mut delivered = Vec::new();
+        for (id, fields) in self
+            .entries
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .take(count)
+        {
+            if !noack {
+                group.deliver(*id, consumer, now);
+            }
+            group.last_delivered = *id;
+            delivered.push((*id, Some(fields.clone())));
+        }
+
+        Ok(delivered)
+    }
+
+    /// Acknowledge `ids`, removing them from the pending entries lists of
+    /// `group`. Returns the number of acknowledged entries.
+    pub(crate) fn ack(&mut self, group: &str, ids: &[StreamId]) -> usize {
+        let group = match self.groups.get_mut(group) {
+            Some(group) => group,
+            None => return 0,
+        };
+
+        ids.iter().filter(|id| group.remove_pending(id)).count()
+    }
+
+    pub(crate) fn pending_summary(&self, group: &str) -> Result<PendingSummary, DbError> {
+        let group = self.groups.get(group).ok_or(DbError::NoGroup)?;
+
+        let bounds = match (
+            group.pending.keys().next(),
+            group.pending.keys().next_back(),
+        ) {
+            (Some(min), Some(max)) => Some((*min, *max)),
+            _ => None,
+        };
+
+        let mut consumers: Vec<_> = group
+            .consumers
+            .iter()
+            .filter(|(_, consumer)| !consumer.pending.is_empty())
+            .map(|(name, consumer)| (name.clone(), consumer.pending.len()))
+            .collect();
+        consumers.sort();
+
+        Ok(PendingSummary {
+            count: group.pending.len(),
+            bounds,
+            consumers,
+        })
+    }
+
+    /// Pending entries of `group` between `start` and `end`, optionally
+    /// restricted to those owned by `consumer` or idle for at least
+    /// `min_idle`.
+    pub(crate) fn pending(
+        &self,
+        group: &str,
+        start: StreamId,
+        end: StreamId,
+        count: usize,
+        consumer: Option<&str>,
+        min_idle: Option<Duration>,
+    ) -> Result<Vec<PendingInfo>, DbError> {
+        let group = self.groups.get(group).ok_or(DbError::NoGroup)?;
+        if start > end {
+            return Ok(vec![]);
+        }
+
+        let now = Instant::now();
+        Ok(group
+            .pending
+            .range(start..=end)
+            .filter(|(_, entry)| consumer.is_none_or(|consumer| entry.consumer == consumer))
+            .filter(|(_, entry)| min_idle.is_none_or(|min| now - entry.delivered_at >= min))
+            .take(count)
+            .map(|(id, entry)| PendingInfo {
+                id: *id,
+                consumer: entry.consumer.clone(),
+                idle: now - entry.delivered_at,
+                deliveries: entry.deliveries,
+            })
+            .collect())
+    }
+
+    /// Transfer the ownership of the pending entries `ids` idle for at least
+    /// `min_idle` to `consumer`, returning the claimed entries.
+    ///
+    /// Unless `justid` is set, claiming counts as a new delivery. Pending
+    /// entries deleted from the stream are dropped from the pending entries
+    /// list instead of being claimed.
+    pub(crate) fn claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        min_idle: Duration,
+        ids: &[StreamId],
+        justid: bool,
+    ) -> Result<Vec<StreamEntry>, DbError> {
+        let now = Instant::now();
+
+        let group = self.groups.get_mut(group).ok_or(DbError::NoGroup)?;
+        group.consumer(consumer);
+
+        let mut claimed = Vec::new();
+        for id in ids {
+            let deliveries = match group.pending.get(id) {
+                Some(entry) if now - entry.delivered_at >= min_idle => entry.deliveries,
+                _ => continue,
+            };
+
+            let fields = match self.entries.get(id) {
+                Some(fields) => fields.clone(),
+                None => {
+                    group.remove_pending(id);
+                    continue;
+                }
+            };
+
+            group.remove_pending(id);
+            group.deliver(*id, consumer, now);
+            if let Some(entry) = group.pending.get_mut(id) {
+                entry.deliveries = if justid { deliveries } else { deliveries + 1 };
+            }
+
+            claimed.push((*id, fields));
+        }
+
+        Ok(claimed)
+    }
+}
+
+impl ConsumerGroup {
+    /// Returns the consumer `name`, creating it if needed.
+    fn consumer(&mut self, name: &str) -> &mut Consumer {
+        self.consumers
+            .entry(name.to_string())
+            .or_insert_with(|| Consumer {
+                pending: BTreeSet::new(),
+            })
+    }
+
+    /// Record the delivery of the entry `id` to `consumer`.
+    fn deliver(&mut self, id: StreamId, consumer: &str, now: Instant) {
+        self.consumer(consumer).pending.insert(id);
+        self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.to_string(),
+                delivered_at: now,
+                deliveries: 1,
+            },
+        );
+    }
+
+    /// Remove `id` from the group and consumer pending entries lists.
+    fn remove_pending(&mut self, id: &StreamId) -> bool {
+        match self.pending.remove(id) {
+            Some(entry) => {
+                if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+                    consumer.pending.remove(id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = crate::Error;
+
+    fn from_str(src: &str) -> crate::Result<StreamId> {
+        StreamId::parse(src.as_bytes(), 0).ok_or_else(|| "invalid stream ID".into())
+    }
+}
+
+fn clone_entry((id, fields): (&StreamId, &Vec<(Bytes, Bytes)>)) -> StreamEntry {
+    (*id, fields.clone())
+}
+
+fn parse_u64(src: &[u8]) -> Option<u64> {
+    if src.is_empty() || !src.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(src).ok()?.parse().ok()
+}
@@ -9,14 +9,32 @@ use std::num::TryFromIntError;
 use std::string::FromUtf8Error;
 
 /// A frame in the Redis protocol.
+///
+/// The variants following `Array` only exist in RESP3. When written to a
+/// connection still speaking RESP2 they are downgraded to the closest RESP2
+/// type, see `Connection::write_frame`.
 #[derive(Clone, Debug)]
 pub enum Frame {
     Simple(String),
     Error(String),
-    Integer(u64),
+    Integer(i64),
     Bulk(Bytes),
     Null,
+    /// The null array some replies use in RESP2, e.g. for an aborted
+    /// transaction, read back as `Null`. The same as `Null` in RESP3.
+    NullArray,
     Array(Vec<Frame>),
+    Map(Vec<(Frame, Frame)>),
+    Set(Vec<Frame>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    /// A bulk string along with its three letters format, e.g. `txt`.
+    Verbatim(String, Bytes),
+    /// Out of band data, such as pub/sub messages.
+    Push(Vec<Frame>),
+    /// Auxiliary data preceding a reply, which clients may ignore.
+    Attribute(Vec<(Frame, Frame)>),
 }
 
 #[derive(Debug)]
@@ -26,6 +44,10 @@ pub enum Error {
 
     /// Invalid message encoding
     Other(crate::Error),
+
+    /// Request refused with an error replied to the client before the
+    /// connection is closed, as Redis does.
+    Protocol(&'static str),
 }
 
 impl Frame {
@@ -34,28 +56,30 @@ impl Frame {
         Frame::Array(vec![])
     }
 
-    /// Push a "bulk" frame into the array. `self` must be an Array frame.
+    /// Push a "bulk" frame into the array. `self` must be an Array, Set or
+    /// Push frame.
     ///
     /// # Panics
     ///
     /// panics if `self` is not an array
     pub(crate) fn push_bulk(&mut self, bytes: Bytes) {
         match self {
-            Frame::Array(vec) => {
+            Frame::Array(vec) | Frame::Set(vec) | Frame::Push(vec) => {
                 vec.push(Frame::Bulk(bytes));
             }
             _ => panic!("not an array frame"),
         }
     }
 
-    /// Push an "integer" frame into the array. `self` must be an Array frame.
+    /// Push an "integer" frame into the array. `self` must be an Array, Set or
+    /// Push frame.
     ///
     /// # Panics
     ///
     /// panics if `self` is not an array
-    pub(crate) fn push_int(&mut self, value: u64) {
+    pub(crate) fn push_int(&mut self, value: i64) {
         match self {
-            Frame::Array(vec) => {
+            Frame::Array(vec) | Frame::Set(vec) | Frame::Push(vec) => {
                 vec.push(Frame::Integer(value));
             }
             _ => panic!("not an array frame"),
@@ -64,6 +88,11 @@ impl Frame {
 
     /// Checks if an entire message can be decoded from `src`
     pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+        if !is_type_byte(peek_u8(src)?) {
+            get_inline(src)?;
+            return Ok(());
+        }
+
         match get_u8(src)? {
             b'+' => {
                 get_line(src)?;
@@ -74,7 +103,7 @@ impl Frame {
                 Ok(())
             }
             b':' => {
-                let _ = get_decimal(src)?;
+                let _ = get_int(src)?;
                 Ok(())
             }
             b'$' => {
@@ -86,24 +115,69 @@ impl Frame {
                     let len: usize = get_decimal(src)?.try_into()?;
 
                     // skip that number of bytes + 2 (\r\n).
-                    skip(src, len + 2)
+                    skip(src, with_crlf(len)?)
                 }
             }
             b'*' => {
+                if b'-' == peek_u8(src)? {
+                    // Skip '-1\r\n', a null array
+                    return skip(src, 4);
+                }
+
+                let len = get_decimal(src)?;
+
+                for _ in 0..len {
+                    Frame::check(src)?;
+                }
+
+                Ok(())
+            }
+            b'~' | b'>' => {
+                let len = get_decimal(src)?;
+
+                for _ in 0..len {
+                    Frame::check(src)?;
+                }
+
+                Ok(())
+            }
+            b'%' | b'|' => {
+                // Maps and attributes are followed by `len` key/value pairs.
                 let len = get_decimal(src)?;
 
+                let len = len
+                    .checked_mul(2)
+                    .ok_or("protocol error; invalid frame format")?;
+
                 for _ in 0..len {
                     Frame::check(src)?;
                 }
 
                 Ok(())
             }
+            b'_' | b',' | b'#' | b'(' => {
+                get_line(src)?;
+                Ok(())
+            }
+            b'=' => {
+                let len: usize = get_decimal(src)?.try_into()?;
+
+                // skip that number of bytes + 2 (\r\n).
+                skip(src, with_crlf(len)?)
+            }
             actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
         }
     }
 
     /// The message has already been validated with `check`.
     pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+        if !is_type_byte(peek_u8(src)?) {
+            // An inline command, as typed in a telnet session, is parsed into
+            // the same array of bulk strings a RESP client would send.
+            let args = split_inline(get_inline(src)?)?;
+            return Ok(Frame::Array(args.into_iter().map(Frame::Bulk).collect()));
+        }
+
         match get_u8(src)? {
             b'+' => {
                 // Read the line and convert it to `Vec<u8>`
@@ -124,8 +198,8 @@ impl Frame {
                 Ok(Frame::Error(string))
             }
             b':' => {
-                let len = get_decimal(src)?;
-                Ok(Frame::Integer(len))
+                let value = get_int(src)?;
+                Ok(Frame::Integer(value))
             }
             b'$' => {
                 if b'-' == peek_u8(src)? {
@@ -139,7 +213,7 @@ impl Frame {
                 } else {
                     // Read the bulk string
                     let len = get_decimal(src)?.try_into()?;
-                    let n = len + 2;
+                    let n = with_crlf(len)?;
 
                     if src.remaining() < n {
                         return Err(Error::Incomplete);
@@ -154,6 +228,18 @@ impl Frame {
                 }
             }
             b'*' => {
+                if b'-' == peek_u8(src)? {
+                    let line = get_line(src)?;
+
+                    if line != b"-1" {
+                        return Err("protocol error; invalid frame format".into());
+                    }
+
+                    // A null array carries no more information than a null
+                    // bulk string.
+                    return Ok(Frame::Null);
+                }
+
                 let len = get_decimal(src)?.try_into()?;
                 let mut out = Vec::with_capacity(len);
 
@@ -163,7 +249,61 @@ impl Frame {
 
                 Ok(Frame::Array(out))
             }
-            _ => unimplemented!(),
+            b'~' => Ok(Frame::Set(parse_aggregate(src)?)),
+            b'>' => Ok(Frame::Push(parse_aggregate(src)?)),
+            b'%' => Ok(Frame::Map(parse_pairs(src)?)),
+            b'|' => Ok(Frame::Attribute(parse_pairs(src)?)),
+            b'_' => {
+                if !get_line(src)?.is_empty() {
+                    return Err("protocol error; invalid frame format".into());
+                }
+
+                Ok(Frame::Null)
+            }
+            b',' => {
+                let line = std::str::from_utf8(get_line(src)?)
+                    .map_err(|_| "protocol error; invalid frame format")?;
+
+                // Rust parses `inf`, `-inf` and `nan` the way Redis writes them.
+                let value = line
+                    .parse()
+                    .map_err(|_| "protocol error; invalid frame format")?;
+
+                Ok(Frame::Double(value))
+            }
+            b'#' => match get_line(src)? {
+                b"t" => Ok(Frame::Boolean(true)),
+                b"f" => Ok(Frame::Boolean(false)),
+                _ => Err("protocol error; invalid frame format".into()),
+            },
+            b'(' => {
+                let line = get_line(src)?.to_vec();
+                let string = String::from_utf8(line)?;
+
+                Ok(Frame::BigNumber(string))
+            }
+            b'=' => {
+                let len = get_decimal(src)?.try_into()?;
+                let n = with_crlf(len)?;
+
+                if src.remaining() < n {
+                    return Err(Error::Incomplete);
+                }
+
+                // The payload starts with the format followed by a colon.
+                let data = &src.chunk()[..len];
+                if len < 4 || data[3] != b':' {
+                    return Err("protocol error; invalid frame format".into());
+                }
+
+                let format = String::from_utf8(data[..3].to_vec())?;
+                let data = Bytes::copy_from_slice(&data[4..]);
+
+                skip(src, n)?;
+
+                Ok(Frame::Verbatim(format, data))
+            }
+            actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
         }
     }
 
@@ -195,8 +335,8 @@ impl fmt::Display for Frame {
                 Ok(string) => string.fmt(fmt),
                 Err(_) => write!(fmt, "{:?}", msg),
             },
-            Frame::Null => "(nil)".fmt(fmt),
-            Frame::Array(parts) => {
+            Frame::Null | Frame::NullArray => "(nil)".fmt(fmt),
+            Frame::Array(parts) | Frame::Set(parts) | Frame::Push(parts) => {
                 for (i, part) in parts.iter().enumerate() {
                     if i > 0 {
                         // use space as the array element display separator
@@ -208,10 +348,63 @@ impl fmt::Display for Frame {
 
                 Ok(())
             }
+            Frame::Map(pairs) | Frame::Attribute(pairs) => {
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+
+                    write!(fmt, "{} {}", key, value)?;
+                }
+
+                Ok(())
+            }
+            Frame::Double(num) => format_double(*num).fmt(fmt),
+            Frame::Boolean(value) => value.fmt(fmt),
+            Frame::BigNumber(num) => num.fmt(fmt),
+            Frame::Verbatim(_, msg) => match str::from_utf8(msg) {
+                Ok(string) => string.fmt(fmt),
+                Err(_) => write!(fmt, "{:?}", msg),
+            },
         }
     }
 }
 
+/// Format a double the way Redis does, `inf`, `-inf` and `nan` included.
+pub(crate) fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Parse the entries of an array-like aggregate: array, set or push.
+fn parse_aggregate(src: &mut Cursor<&[u8]>) -> Result<Vec<Frame>, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        out.push(Frame::parse(src)?);
+    }
+
+    Ok(out)
+}
+
+/// Parse the key/value pairs of a map or an attribute.
+fn parse_pairs(src: &mut Cursor<&[u8]>) -> Result<Vec<(Frame, Frame)>, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        let key = Frame::parse(src)?;
+        let value = Frame::parse(src)?;
+        out.push((key, value));
+    }
+
+    Ok(out)
+}
+
 fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
     if !src.has_remaining() {
         return Err(Error::Incomplete);
@@ -237,6 +430,143 @@ fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
     Ok(())
 }
 
+/// Returns `true` if `byte` starts a RESP2 or RESP3 frame. Any other byte
+/// starts an inline command.
+fn is_type_byte(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'+' | b'-'
+            | b':'
+            | b'$'
+            | b'*'
+            | b'~'
+            | b'>'
+            | b'%'
+            | b'|'
+            | b'_'
+            | b','
+            | b'#'
+            | b'('
+            | b'='
+    )
+}
+
+/// Longest inline command accepted, as in Redis. Without a limit, a client
+/// never sending a newline would have the server buffer without bound.
+const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// Read the line of an inline command. Unlike RESP lines, it may end with a
+/// bare `\n`.
+fn get_inline<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
+    let start = src.position() as usize;
+    let line = &src.get_ref()[start..];
+
+    match line.iter().position(|&b| b == b'\n') {
+        Some(end) if end > MAX_INLINE_LEN => {
+            Err(Error::Protocol("ERR Protocol error: too big inline request"))
+        }
+        None if line.len() > MAX_INLINE_LEN => {
+            Err(Error::Protocol("ERR Protocol error: too big inline request"))
+        }
+        Some(end) => {
+            src.set_position((start + end + 1) as u64);
+
+            Ok(line[..end].strip_suffix(b"\r").unwrap_or(&line[..end]))
+        }
+        None => Err(Error::Incomplete),
+    }
+}
+
+/// Split an inline command into its arguments, following the quoting rules
+/// of Redis.
+///
+/// Arguments are separated by whitespace. Double quoted arguments support the
+/// `\n`, `\r`, `\t`, `\b`, `\a` and `\xHH` escapes, single quoted ones only
+/// `\'`. A closing quote must be followed by whitespace or the end of line.
+fn split_inline(line: &[u8]) -> Result<Vec<Bytes>, Error> {
+    const UNBALANCED: &str = "protocol error; unbalanced quotes in request";
+
+    let mut args = vec![];
+    let mut chars = line.iter().copied().peekable();
+
+    loop {
+        // Skip the blanks before the next argument.
+        while chars.next_if(u8::is_ascii_whitespace).is_some() {}
+
+        let first = match chars.next() {
+            Some(first) => first,
+            None => return Ok(args),
+        };
+
+        let mut arg = vec![];
+
+        match first {
+            b'"' => loop {
+                match chars.next().ok_or(UNBALANCED)? {
+                    b'"' => break,
+                    b'\\' => {
+                        let escaped = chars.next().ok_or(UNBALANCED)?;
+                        arg.push(match escaped {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            // Not a valid `\xHH`, keep the `x` verbatim.
+                            b'x' => hex_escape(&mut chars).unwrap_or(b'x'),
+                            other => other,
+                        });
+                    }
+                    byte => arg.push(byte),
+                }
+            },
+            b'\'' => loop {
+                match chars.next().ok_or(UNBALANCED)? {
+                    b'\'' => break,
+                    b'\\' if chars.peek() == Some(&b'\'') => {
+                        chars.next();
+                        arg.push(b'\'');
+                    }
+                    byte => arg.push(byte),
+                }
+            },
+            byte => {
+                arg.push(byte);
+
+                while let Some(byte) = chars.next_if(|b| !b.is_ascii_whitespace()) {
+                    arg.push(byte);
+                }
+            }
+        }
+
+        if matches!(first, b'"' | b'\'') && chars.peek().is_some_and(|b| !b.is_ascii_whitespace()) {
+            return Err(UNBALANCED.into());
+        }
+
+        args.push(Bytes::from(arg));
+    }
+}
+
+/// Decode the two hex digits following `\x`, consuming them only if both are
+/// valid.
+fn hex_escape(chars: &mut std::iter::Peekable<impl Iterator<Item = u8> + Clone>) -> Option<u8> {
+    let mut lookahead = chars.clone();
+    let high = (lookahead.next()? as char).to_digit(16)?;
+    let low = (lookahead.next()? as char).to_digit(16)?;
+
+    chars.next();
+    chars.next();
+
+    Some((high * 16 + low) as u8)
+}
+
+/// Length of a payload followed by `\r\n`, the length being read from the
+/// client.
+fn with_crlf(len: usize) -> Result<usize, Error> {
+    len.checked_add(2)
+        .ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
 /// Read a new-line terminated decimal
 fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     use atoi::atoi;
@@ -246,6 +576,15 @@ fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     atoi::<u64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
 }
 
+/// Read a new-line terminated signed integer
+fn get_int(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    use atoi::atoi;
+
+    let line = get_line(src)?;
+
+    atoi::<i64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
 /// Find a line
 fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     // Scan the bytes directly
@@ -297,6 +636,7 @@ impl fmt::Display for Error {
         match self {
             Error::Incomplete => "stream ended early".fmt(fmt),
             Error::Other(err) => err.fmt(fmt),
+            Error::Protocol(msg) => msg.fmt(fmt),
         }
     }
-}
\ No newline at end of file
+}
@@ -26,24 +26,41 @@
 //!   representation.
 //! 
 pub mod clients;
-pub use clients::{BlockingClient, BufferedClient, Client};
+pub use clients::{BlockingClient, BufferedClient, Client, ClusterClient};
 
 pub mod cmd;
 pub use cmd::Command;
 
+mod config;
+pub use config::{AppendFsync, Directive, SaveRule, ServerConfig};
+
 mod connection;
-pub use connection::Connection;
+pub use connection::{Connection, Protocol, Transport};
 
 pub mod frame;
 pub use frame::Frame;
 
 mod db;
 use db::Db;
+pub use db::{StreamEntry, StreamId};
 use db::DbDropGuard;
 
 mod parse;
 use parse::{Parse, ParseError};
 
+mod aof;
+
+mod replication;
+
+mod cluster;
+
+mod acl;
+
+mod tls;
+pub use tls::{TlsClientConfig, TlsConfig};
+
+mod scripting;
+
 pub mod server;
 
 mod shutdown;
@@ -17,8 +17,9 @@ pub(crate) struct Parse {
 
 /// Error encountered while parsing a frame.
 ///
-/// Only `EndOfStream` errors are handled at runtime. All other errors result in
-/// the connection being terminated.
+/// Only `EndOfStream` errors are handled at runtime. Errors with an `ERR`
+/// message, such as an invalid number, are replied to the client. All other
+/// errors result in the connection being terminated.
 #[derive(Debug)]
 pub(crate) enum ParseError {
     /// Attempting to extract a value failed due to the frame being fully
@@ -92,6 +93,39 @@ impl Parse {
         }
     }
 
+    /// Returns the number of entries left.
+    pub(crate) fn remaining(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Return all remaining entries as raw bytes.
+    ///
+    /// Used by variadic commands. At least one entry must remain, otherwise
+    /// `EndOfStream` is returned.
+    pub(crate) fn remaining_bytes(&mut self) -> Result<Vec<Bytes>, ParseError> {
+        let mut values = vec![self.next_bytes()?];
+
+        while self.parts.len() > 0 {
+            values.push(self.next_bytes()?);
+        }
+
+        Ok(values)
+    }
+
+    /// Return all remaining entries as strings.
+    ///
+    /// Used by multi-key commands. At least one entry must remain, otherwise
+    /// `EndOfStream` is returned.
+    pub(crate) fn remaining_strings(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut values = vec![self.next_string()?];
+
+        while self.parts.len() > 0 {
+            values.push(self.next_string()?);
+        }
+
+        Ok(values)
+    }
+
     /// Return the next entry as an integer.
     ///
     /// This includes `Simple`, `Bulk`, and `Integer` frame types. `Simple` and
@@ -99,18 +133,32 @@ impl Parse {
     ///
     /// If the next entry cannot be represented as an integer, then an error is
     /// returned.
-    pub(crate) fn next_int(&mut self) -> Result<u64, ParseError> {
-        use atoi::atoi;
-
-        const MSG: &str = "protocol error; invalid number";
+    pub(crate) fn next_int(&mut self) -> Result<i64, ParseError> {
+        const MSG: &str = "ERR value is not an integer or out of range";
 
         match self.next()? {
             // An integer frame type is already stored as an integer.
             Frame::Integer(v) => Ok(v),
             // Simple and bulk frames must be parsed as integers. If the parsing
-            // fails, an error is returned.
-            Frame::Simple(data) => atoi::<u64>(data.as_bytes()).ok_or_else(|| MSG.into()),
-            Frame::Bulk(data) => atoi::<u64>(&data).ok_or_else(|| MSG.into()),
+            // fails, including on trailing bytes such as `5x`, an error is
+            // returned.
+            Frame::Simple(data) => parse_number(data.as_bytes()).ok_or_else(|| MSG.into()),
+            Frame::Bulk(data) => parse_number(&data).ok_or_else(|| MSG.into()),
+            frame => Err(format!("protocol error; expected int frame but got {:?}", frame).into()),
+        }
+    }
+
+    /// Return the next entry as an unsigned integer.
+    ///
+    /// Works like `next_int` but rejects negative values, as used by
+    /// durations and counts.
+    pub(crate) fn next_uint(&mut self) -> Result<u64, ParseError> {
+        const MSG: &str = "ERR value is not an integer or out of range";
+
+        match self.next()? {
+            Frame::Integer(v) => u64::try_from(v).map_err(|_| MSG.into()),
+            Frame::Simple(data) => parse_number(data.as_bytes()).ok_or_else(|| MSG.into()),
+            Frame::Bulk(data) => parse_number(&data).ok_or_else(|| MSG.into()),
             frame => Err(format!("protocol error; expected int frame but got {:?}", frame).into()),
         }
     }
@@ -125,6 +173,12 @@ impl Parse {
     }
 }
 
+/// Parse `src` as a number, the whole of it, unlike `atoi` which stops at the
+/// first byte that is not a digit.
+fn parse_number<T: std::str::FromStr>(src: &[u8]) -> Option<T> {
+    std::str::from_utf8(src).ok()?.parse().ok()
+}
+
 impl From<String> for ParseError {
     fn from(src: String) -> ParseError {
         ParseError::Other(src.into())
@@ -146,4 +200,4 @@ impl fmt::Display for ParseError {
     }
 }
 
-impl std::error::Error for ParseError {}
\ No newline at end of file
+impl std::error::Error for ParseError {}
@@ -0,0 +1,459 @@
+//! Leader/follower replication.
+//!
+//! A follower connects to its leader and sends `PSYNC` with the replication
+//! identifier and offset it reached. The leader either continues from there,
+//! sending the writes the follower missed from its backlog, or starts a full
+//! resynchronization: the key space is sent as an RDB snapshot, and replaces
+//! the one of the follower. Either way, the leader then streams every write
+//! it propagates, encoded like in the append only file, over the same
+//! connection.
+//!
+//! The replication identifier names a history of writes, and the offset
+//! counts the bytes streamed since that history started. A follower keeps
+//! the identifier and offset of its leader, so that after a short
+//! disconnection it continues where it stopped. A follower promoted with
+//! `REPLICAOF NO ONE` starts a new history, but remembers the previous one so
+//! that the other followers of its former leader can continue from it.
+
+use crate::aof::{self, Replay};
+use crate::cmd::{Auth, PSync, Ping, ReplConf};
+use crate::{Connection, Db, Frame};
+
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use tracing::{info, warn};
+
+/// Number of bytes of the write stream kept for followers to continue from.
+const BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// Number of writes buffered for a follower before it is disconnected, and
+/// has to continue from the backlog.
+const STREAM_CAPACITY: usize = 1024;
+
+/// Delay before a follower connects to its leader again.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Period at which a follower acknowledges the offset it reached.
+const ACK_PERIOD: Duration = Duration::from_secs(1);
+
+/// Replication state of the server, kept with the key space so that writes
+/// are streamed in the order they are applied.
+#[derive(Debug)]
+pub(crate) struct Replication {
+    /// Identifier of the history of writes.
+    replid: String,
+
+    /// Identifier of the previous history and the offset it ended at, for a
+    /// promoted follower.
+    replid2: Option<(String, u64)>,
+
+    /// Number of bytes streamed since the history started.
+    offset: u64,
+
+    /// Last bytes of the write stream, ending at `offset`.
+    backlog: VecDeque<u8>,
+
+    /// Sends the write stream to the connected followers.
+    stream: broadcast::Sender<Bytes>,
+
+    /// Offset acknowledged by each connected follower.
+    replicas: HashMap<u64, u64>,
+
+    /// Identifier given to the next follower to connect.
+    next_replica: u64,
+
+    /// The leader followed, `None` for a leader.
+    leader: Option<Leader>,
+}
+
+/// Leader followed by the server.
+#[derive(Debug)]
+struct Leader {
+    host: String,
+    port: u16,
+    state: LinkState,
+
+    /// Task receiving the writes of the leader.
+    task: JoinHandle<()>,
+}
+
+/// State of the link of a follower with its leader, as reported by `ROLE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LinkState {
+    /// Waiting to connect to the leader.
+    Connect,
+
+    /// Connecting and sending the handshake.
+    Connecting,
+
+    /// Receiving the snapshot of a full resynchronization.
+    Sync,
+
+    /// Receiving the write stream.
+    Connected,
+}
+
+/// Role of the server, replied by `ROLE`.
+#[derive(Debug)]
+pub(crate) enum Role {
+    Leader {
+        offset: u64,
+
+        /// The identifier and acknowledged offset of each follower.
+        replicas: Vec<(u64, u64)>,
+    },
+    Follower {
+        host: String,
+        port: u16,
+        state: LinkState,
+        offset: u64,
+    },
+}
+
+/// How the leader answers `PSYNC`.
+#[derive(Debug)]
+pub(crate) enum PSyncStart {
+    /// The follower continues from its offset. `backlog` holds the writes it
+    /// missed.
+    Continue { replid: String, backlog: Bytes },
+
+    /// The follower replaces its key space with `snapshot`, in the RDB
+    /// format, and continues from `offset`.
+    FullResync {
+        replid: String,
+        offset: u64,
+        snapshot: Vec<u8>,
+    },
+}
+
+/// Write stream sent to a connected follower. The follower is forgotten when
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct ReplicaStream {
+    id: u64,
+    receiver: broadcast::Receiver<Bytes>,
+    db: Db,
+}
+
+impl Replication {
+    pub(crate) fn new() -> Replication {
+        Replication {
+            replid: new_replid(),
+            replid2: None,
+            offset: 0,
+            backlog: VecDeque::new(),
+            stream: broadcast::channel(STREAM_CAPACITY).0,
+            replicas: HashMap::new(),
+            next_replica: 0,
+            leader: None,
+        }
+    }
+
+    /// Returns `true` when following a leader.
+    pub(crate) fn is_following(&self) -> bool {
+        self.leader.is_some()
+    }
+
+    /// Returns the identifier of the history and the offset reached.
+    pub(crate) fn position(&self) -> (String, u64) {
+        (self.replid.clone(), self.offset)
+    }
+
+    /// Append `data` to the write stream.
+    pub(crate) fn feed(&mut self, data: Bytes) {
+        self.offset += data.len() as u64;
+        self.backlog.extend(&data[..]);
+        let excess = self.backlog.len().saturating_sub(BACKLOG_SIZE);
+        self.backlog.drain(..excess);
+
+        // Fails when no follower is connected.
+        let _ = self.stream.send(data);
+    }
+
+    /// Returns the writes following `offset` in the history `replid`, if the
+    /// backlog still holds them.
+    ///
+    /// `offset` is the first byte wanted, one past the offset the follower
+    /// reached, like with Redis.
+    pub(crate) fn backlog_from(&self, replid: &str, offset: u64) -> Option<Bytes> {
+        let reached = offset.checked_sub(1)?;
+        let known = replid == self.replid
+            || matches!(&self.replid2, Some((replid2, end)) if replid == replid2 && reached <= *end);
+        let start = self.offset - self.backlog.len() as u64;
+
+        if !known || reached < start || reached > self.offset {
+            return None;
+        }
+        let skip = (reached - start) as usize;
+        Some(self.backlog.iter().skip(skip).copied().collect())
+    }
+
+    /// Register a follower, returning its identifier and the receiver of the
+    /// writes streamed from now on.
+    pub(crate) fn attach(&mut self) -> (u64, broadcast::Receiver<Bytes>) {
+        let id = self.next_replica;
+        self.next_replica += 1;
+        self.replicas.insert(id, 0);
+        (id, self.stream.subscribe())
+    }
+
+    /// Record the offset acknowledged by the follower `id`.
+    pub(crate) fn ack(&mut self, id: u64, offset: u64) {
+        if let Some(acked) = self.replicas.get_mut(&id) {
+            *acked = offset;
+        }
+    }
+
+    /// Forget the follower `id`.
+    pub(crate) fn detach(&mut self, id: u64) {
+        self.replicas.remove(&id);
+    }
+
+    /// Start following the leader at `host:port`, receiving its writes with
+    /// `task`. The task following the previous leader, if any, is stopped.
+    pub(crate) fn follow(&mut self, host: String, port: u16, task: JoinHandle<()>) {
+        if let Some(leader) = self.leader.take() {
+            leader.task.abort();
+        }
+        self.leader = Some(Leader {
+            host,
+            port,
+            state: LinkState::Connect,
+            task,
+        });
+    }
+
+    /// Stop following the leader, becoming a leader with a new history. The
+    /// followers of the previous leader may continue from the current offset.
+    pub(crate) fn promote(&mut self) {
+        let leader = match self.leader.take() {
+            Some(leader) => leader,
+            None => return,
+        };
+        leader.task.abort();
+
+        let replid = std::mem::replace(&mut self.replid, new_replid());
+        self.replid2 = Some((replid, self.offset));
+    }
+
+    pub(crate) fn set_link_state(&mut self, state: LinkState) {
+        if let Some(leader) = &mut self.leader {
+            leader.state = state;
+        }
+    }
+
+    /// Take over the history of the leader after a full resynchronization,
+    /// starting at `offset`.
+    ///
+    /// The followers of this server were streamed a history which no longer
+    /// applies, they are disconnected to resynchronize as well.
+    pub(crate) fn resynced(&mut self, replid: String, offset: u64) {
+        self.replid = replid;
+        self.replid2 = None;
+        self.offset = offset;
+        self.backlog.clear();
+        self.stream = broadcast::channel(STREAM_CAPACITY).0;
+        self.replicas.clear();
+    }
+
+    /// Take over the identifier of the leader after continuing from the
+    /// backlog. The leader may have been promoted since.
+    pub(crate) fn continued(&mut self, replid: String) {
+        if replid != self.replid {
+            let previous = std::mem::replace(&mut self.replid, replid);
+            self.replid2 = Some((previous, self.offset));
+        }
+    }
+
+    pub(crate) fn role(&self) -> Role {
+        match &self.leader {
+            None => {
+                let mut replicas: Vec<_> = self
+                    .replicas
+                    .iter()
+                    .map(|(&id, &acked)| (id, acked))
+                    .collect();
+                replicas.sort_unstable();
+                Role::Leader {
+                    offset: self.offset,
+                    replicas,
+                }
+            }
+            Some(leader) => Role::Follower {
+                host: leader.host.clone(),
+                port: leader.port,
+                state: leader.state,
+                offset: self.offset,
+            },
+        }
+    }
+
+    /// Stop following the leader, when the server shuts down.
+    pub(crate) fn shutdown(&mut self) {
+        if let Some(leader) = self.leader.take() {
+            leader.task.abort();
+        }
+    }
+}
+
+impl LinkState {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            LinkState::Connect => "connect",
+            LinkState::Connecting => "connecting",
+            LinkState::Sync => "sync",
+            LinkState::Connected => "connected",
+        }
+    }
+}
+
+impl ReplicaStream {
+    pub(crate) fn new(id: u64, receiver: broadcast::Receiver<Bytes>, db: Db) -> ReplicaStream {
+        ReplicaStream { id, receiver, db }
+    }
+
+    /// Receive the next writes to send to the follower. `None` is returned if
+    /// the follower fell too far behind, or the history changed, in which
+    /// case it must be disconnected.
+    pub(crate) async fn recv(&mut self) -> Option<Bytes> {
+        self.receiver.recv().await.ok()
+    }
+
+    /// Record the offset acknowledged by the follower.
+    pub(crate) fn ack(&self, offset: u64) {
+        self.db.replica_ack(self.id, offset);
+    }
+}
+
+impl Drop for ReplicaStream {
+    fn drop(&mut self) {
+        self.db.replica_detach(self.id);
+    }
+}
+
+/// Follow the leader at `host:port`, connecting again whenever the link
+/// breaks. Runs until stopped by `Replication::follow` or
+/// `Replication::promote`.
+pub(crate) async fn follow(db: Db, host: String, port: u16) {
+    loop {
+        match sync_with(&db, &host, port).await {
+            Ok(()) => info!(%host, port, "connection with the leader lost"),
+            Err(err) => warn!(cause = %err, %host, port, "replication from the leader failed"),
+        }
+        db.set_link_state(LinkState::Connect);
+        time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Synchronize with the leader at `host:port`, then apply the writes it
+/// streams until the connection is closed.
+///
+/// The lock on the database is only taken once no script runs, see
+/// `Running::completed`.
+async fn sync_with(db: &Db, host: &str, port: u16) -> crate::Result<()> {
+    db.running_script().completed().await;
+    db.set_link_state(LinkState::Connecting);
+    let socket = TcpStream::connect((host, port)).await?;
+    let mut leader = Connection::new(socket);
+
+    leader.write_frame(&Ping::new(None).into_frame()).await?;
+    match leader.read_frame().await? {
+        Some(Frame::Simple(_)) => {}
+        Some(frame) => return Err(frame.to_error()),
+        None => return Ok(()),
+    }
+
+    db.running_script().completed().await;
+    if let Some(password) = db.masterauth() {
+        leader.write_frame(&Auth::new(None, password).into_frame()).await?;
+        match leader.read_frame().await? {
+            Some(Frame::Simple(_)) => {}
+            Some(frame) => return Err(frame.to_error()),
+            None => return Ok(()),
+        }
+    }
+
+    db.running_script().completed().await;
+    let (replid, offset) = db.replication_position();
+    let psync = PSync::new(replid, offset as i64 + 1);
+    leader.write_frame(&psync.into_frame()).await?;
+
+    let reply = match leader.read_frame().await? {
+        Some(Frame::Simple(reply)) => reply,
+        Some(frame) => return Err(frame.to_error()),
+        None => return Ok(()),
+    };
+    db.running_script().completed().await;
+    let mut parts = reply.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("FULLRESYNC"), Some(replid), Some(offset)) => {
+            let offset = offset.parse().map_err(|_| invalid_reply(&reply))?;
+            db.set_link_state(LinkState::Sync);
+
+            let snapshot = match leader.read_frame().await? {
+                Some(Frame::Bulk(snapshot)) => snapshot,
+                Some(frame) => return Err(frame.to_error()),
+                None => return Ok(()),
+            };
+            db.running_script().completed().await;
+            let keys = db.resync(replid.to_string(), offset, &snapshot)?;
+            info!(%host, port, keys, "full resynchronization with the leader done");
+        }
+        (Some("CONTINUE"), Some(replid), None) => {
+            db.continue_sync(replid.to_string());
+            info!(%host, port, "partial resynchronization with the leader accepted");
+        }
+        _ => return Err(invalid_reply(&reply)),
+    }
+    db.set_link_state(LinkState::Connected);
+
+    let mut replay = Replay::new();
+    let mut ack = time::interval(ACK_PERIOD);
+
+    loop {
+        select! {
+            frame = leader.read_frame() => {
+                let frame = match frame? {
+                    Some(frame) => frame,
+                    None => return Ok(()),
+                };
+                let data = Bytes::from(aof::encode(std::slice::from_ref(&frame))?);
+                db.running_script().completed().await;
+                db.replicate(&mut replay, frame, data)?;
+            }
+            _ = ack.tick() => {
+                db.running_script().completed().await;
+                let (_, offset) = db.replication_position();
+                leader.write_frame(&ReplConf::ack(offset).into_frame()).await?;
+            }
+        }
+    }
+}
+
+/// Number of replication identifiers generated.
+static GENERATED: AtomicU64 = AtomicU64::new(0);
+
+/// Generates a replication identifier: 40 hexadecimal characters unlikely to
+/// be generated twice. Cluster node identifiers are generated alike.
+pub(crate) fn new_replid() -> String {
+    let mut seed = sha1_smol::Sha1::new();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    seed.update(&now.as_nanos().to_le_bytes());
+    seed.update(&std::process::id().to_le_bytes());
+    // Differs between two identifiers generated at the same instant.
+    seed.update(&GENERATED.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    seed.digest().to_string()
+}
+
+fn invalid_reply(reply: &str) -> crate::Error {
+    format!("protocol error; unexpected reply to PSYNC '{}'", reply).into()
+}
@@ -0,0 +1,507 @@
+//! Lua scripting.
+//!
+//! `EVAL` and `EVALSHA` run a script in a fresh Lua interpreter, with the
+//! `KEYS` and `ARGV` tables holding the arguments of the call. Scripts reach
+//! the key space through `redis.call` and `redis.pcall`, which parse their
+//! arguments into a `Command` and apply it like a client command. Replies are
+//! converted from RESP to Lua values and back following the rules of Redis.
+//!
+//! A script holds the lock on the database until it completes, so it runs on
+//! a blocking thread, see `spawn`. Other clients are replied `BUSY` meanwhile,
+//! except for `SCRIPT KILL`.
+
+use crate::db::DbError;
+use crate::{Command, Connection, Db, Frame};
+
+use bytes::Bytes;
+use mlua::{ChunkMode, HookTriggers, Lua, LuaOptions, MultiValue, StdLib, Table, Value};
+use parking_lot::Mutex;
+use std::cell::RefCell;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// Number of Lua instructions between two checks of the script deadline, and
+/// of `SCRIPT KILL`.
+const DEADLINE_CHECK_INTERVAL: u32 = 10_000;
+
+/// Error reply of a script aborted by `SCRIPT KILL`.
+const KILLED: &str = "ERR Script killed by user with SCRIPT KILL...";
+
+/// Error reply of a command called with `redis.call`, or of the deadline
+/// being reached.
+///
+/// Raised as a Lua error, it aborts the script unless caught, and is replied
+/// as is to the client.
+#[derive(Debug)]
+struct ReplyError(String);
+
+/// Status of the script running, if any.
+///
+/// Other clients read it without taking the lock on the database, which the
+/// script holds. They wait for the script to complete without holding up the
+/// runtime, see `wait`, and are replied `BUSY` once it ran for longer than
+/// the script timeout. Background tasks wait for it too, see `completed`.
+#[derive(Debug)]
+pub(crate) struct Running {
+    status: Mutex<Status>,
+
+    /// Time a script runs before other clients are replied `BUSY`.
+    timeout: Duration,
+
+    /// Notified when the script completes.
+    completed: Notify,
+}
+
+/// Status of the script running, along with the instant it started.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Status {
+    #[default]
+    Idle,
+
+    /// A script is running and did not call any write command.
+    Running(Instant),
+
+    /// The script called a write command, it may not be killed anymore.
+    Wrote(Instant),
+
+    /// `SCRIPT KILL` was called, the script is aborted at its next check.
+    Killed(Instant),
+}
+
+/// Marks a script as running until dropped.
+struct RunningGuard(Db);
+
+impl Running {
+    pub(crate) fn new(timeout: Duration) -> Running {
+        Running {
+            status: Mutex::new(Status::Idle),
+            timeout,
+            completed: Notify::new(),
+        }
+    }
+
+    /// Returns `true` if a script is running for longer than the script
+    /// timeout, right away if it is zero.
+    pub(crate) fn is_busy(&self) -> bool {
+        self.started()
+            .is_some_and(|started| started.elapsed() >= self.timeout)
+    }
+
+    /// Wait until no script runs, or the script running is busy, see
+    /// `is_busy`. Completes right away if no script runs.
+    pub(crate) async fn wait(&self) {
+        self.wait_until(true).await
+    }
+
+    /// Wait until no script runs, before a task running on the runtime takes
+    /// the lock on the database, rather than blocking its worker thread.
+    ///
+    /// A script may start right after, the task then waiting for the lock.
+    pub(crate) async fn completed(&self) {
+        self.wait_until(false).await
+    }
+
+    async fn wait_until(&self, busy: bool) {
+        loop {
+            // Register for the notification before checking, otherwise a
+            // script completing right after the check would be missed.
+            let completed = self.completed.notified();
+            tokio::pin!(completed);
+            completed.as_mut().enable();
+
+            let started = match self.started() {
+                Some(started) => started,
+                None => return,
+            };
+
+            if busy {
+                let busy_at = started + self.timeout;
+                tokio::select! {
+                    _ = completed => {}
+                    _ = time::sleep_until(busy_at.into()) => return,
+                }
+            } else {
+                completed.await;
+            }
+        }
+    }
+
+    /// Abort the script running.
+    ///
+    /// Fails if no script is running, or if it called a write command: the
+    /// script is atomic, it may not be stopped half way through its writes.
+    pub(crate) fn kill(&self) -> Result<(), DbError> {
+        let mut status = self.status.lock();
+        match *status {
+            Status::Idle => Err(DbError::NotBusy),
+            Status::Wrote(_) => Err(DbError::Unkillable),
+            Status::Running(started) | Status::Killed(started) => {
+                *status = Status::Killed(started);
+                Ok(())
+            }
+        }
+    }
+
+    fn start(db: &Db) -> RunningGuard {
+        *db.running_script().status.lock() = Status::Running(Instant::now());
+        RunningGuard(db.clone())
+    }
+
+    fn started(&self) -> Option<Instant> {
+        match *self.status.lock() {
+            Status::Idle => None,
+            Status::Running(started) | Status::Wrote(started) | Status::Killed(started) => {
+                Some(started)
+            }
+        }
+    }
+
+    /// Record that the script calls a write command, returning `false` if it
+    /// was killed instead.
+    ///
+    /// Scripts replayed from the append only file or the leader are not
+    /// marked as running, and always write.
+    fn write(&self) -> bool {
+        let mut status = self.status.lock();
+        match *status {
+            Status::Killed(_) => false,
+            Status::Running(started) => {
+                *status = Status::Wrote(started);
+                true
+            }
+            Status::Idle | Status::Wrote(_) => true,
+        }
+    }
+
+    fn is_killed(&self) -> bool {
+        matches!(*self.status.lock(), Status::Killed(_))
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        let running = self.0.running_script();
+        *running.status.lock() = Status::Idle;
+        running.completed.notify_waiters();
+    }
+}
+
+/// Apply `f`, a command running scripts, on a blocking thread, the returned
+/// handle completing with its reply.
+///
+/// The lock on the database is held as long as a script runs, which must not
+/// stall the other connections served by the same worker of the runtime. `f`
+/// is given a detached connection, calling commands on behalf of the user of
+/// `dst`.
+///
+/// The script is marked as running, see `Running`, before `f` is spawned:
+/// clients checking it afterwards wait for it without holding up the
+/// runtime. A client which checked it just before waits for the lock.
+pub(crate) fn spawn<F>(db: &Db, dst: &Connection, f: F) -> JoinHandle<Frame>
+where
+    F: FnOnce(&Db, &mut Connection) -> Frame + Send + 'static,
+{
+    let db = db.clone();
+    let mut detached = Connection::detached();
+    detached.set_protocol(dst.protocol());
+    detached.set_user(dst.user().map(str::to_string));
+
+    let running = Running::start(&db);
+    tokio::task::spawn_blocking(move || {
+        let reply = f(&db, &mut detached);
+        drop(running);
+        reply
+    })
+}
+
+/// Run `script` atomically against `db`.
+///
+/// The lock on the database is held during the whole script, no other client
+/// observes the intermediate states. A script raising an error is aborted, but
+/// the writes it made are kept, like Redis does.
+///
+/// Returns the reply of the script.
+pub(crate) fn eval(
+    script: &[u8],
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+    db: &Db,
+    dst: &mut Connection,
+) -> Frame {
+    db.atomically(&[], || match run(script, keys, args, db, dst) {
+        Ok(reply) => reply,
+        Err(err) => error_frame(err),
+    })
+    .expect("no watched key")
+}
+
+fn run(
+    script: &[u8],
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+    db: &Db,
+    dst: &mut Connection,
+) -> mlua::Result<Frame> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+
+    // A script running past the deadline is aborted, unless it changed the
+    // key space: its writes are atomic, so it runs to completion, the other
+    // clients being replied `BUSY` meanwhile.
+    let timeout = db.script_timeout();
+    let deadline = Instant::now() + timeout;
+    let writes = db.writes();
+    let triggers = HookTriggers::new().every_nth_instruction(DEADLINE_CHECK_INTERVAL);
+    let hook_db = db.clone();
+    lua.set_hook(triggers, move |_, _| {
+        if hook_db.running_script().is_killed() {
+            return Err(mlua::Error::external(ReplyError(KILLED.to_string())));
+        }
+        if timeout.is_zero() || Instant::now() < deadline || hook_db.writes() != writes {
+            return Ok(());
+        }
+        Err(mlua::Error::external(ReplyError(format!(
+            "ERR Script timed out after {} ms",
+            timeout.as_millis()
+        ))))
+    });
+
+    let globals = lua.globals();
+
+    // Scripts must not reach the file system nor load precompiled chunks.
+    for name in ["dofile", "loadfile", "load"] {
+        globals.set(name, Value::Nil)?;
+    }
+
+    globals.set("KEYS", lua.create_sequence_from(keys)?)?;
+    let args = args
+        .iter()
+        .map(|arg| lua.create_string(arg))
+        .collect::<mlua::Result<Vec<_>>>()?;
+    globals.set("ARGV", lua.create_sequence_from(args)?)?;
+
+    let redis = lua.create_table()?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, msg: String| reply_table(lua, "err", msg))?,
+    )?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, msg: String| reply_table(lua, "ok", msg))?,
+    )?;
+    redis.set(
+        "sha1hex",
+        lua.create_function(|_, data: mlua::String| {
+            Ok(sha1_smol::Sha1::from(data.as_bytes()).digest().to_string())
+        })?,
+    )?;
+    globals.set("redis", &redis)?;
+
+    // The connection is shared by `redis.call` and `redis.pcall`. Scripts
+    // cannot be nested, so it is never borrowed twice.
+    let ctx = RefCell::new(dst);
+
+    lua.scope(|scope| {
+        redis.set(
+            "call",
+            scope.create_function(|lua, args: MultiValue| match call(lua, args, db, &ctx) {
+                Frame::Error(msg) => Err(mlua::Error::external(ReplyError(msg))),
+                reply => to_lua(lua, reply),
+            })?,
+        )?;
+        redis.set(
+            "pcall",
+            scope
+                .create_function(|lua, args: MultiValue| to_lua(lua, call(lua, args, db, &ctx)))?,
+        )?;
+
+        let value: Value = lua
+            .load(script)
+            .set_name("@user_script")
+            .set_mode(ChunkMode::Text)
+            .call(())?;
+        Ok(from_lua(value))
+    })
+}
+
+/// Apply the command `redis.call` or `redis.pcall` was called with, returning
+/// its reply.
+fn call(lua: &Lua, args: MultiValue, db: &Db, ctx: &RefCell<&mut Connection>) -> Frame {
+    if args.is_empty() {
+        return Frame::Error(
+            "ERR Please specify at least one argument for this redis lib call".to_string(),
+        );
+    }
+
+    let mut frame = Frame::array();
+    for arg in args {
+        let arg = match arg {
+            Value::String(_) | Value::Integer(_) | Value::Number(_) => lua.coerce_string(arg),
+            _ => Ok(None),
+        };
+        match arg {
+            Ok(Some(arg)) => frame.push_bulk(Bytes::copy_from_slice(arg.as_bytes())),
+            _ => {
+                return Frame::Error(
+                    "ERR Lua redis lib command arguments must be strings or integers".to_string(),
+                )
+            }
+        }
+    }
+
+    let cmd = match Command::from_frame(frame) {
+        Ok(cmd) => cmd,
+        Err(err) => return Frame::Error(err.to_string()),
+    };
+
+    match cmd {
+        Command::Subscribe(_)
+        | Command::Unsubscribe(_)
+        | Command::Multi(_)
+        | Command::Exec(_)
+        | Command::Discard(_)
+        | Command::Watch(_)
+        | Command::Unwatch(_)
+        | Command::Eval(_)
+        | Command::EvalSha(_)
+        | Command::Script(_)
+        | Command::Save(_)
+        | Command::BgSave(_)
+        | Command::BgRewriteAof(_)
+        | Command::ReplicaOf(_)
+        | Command::PSync(_)
+        | Command::Hello(_)
+        | Command::Auth(_) => {
+            Frame::Error("ERR This Redis command is not allowed from script".to_string())
+        }
+        cmd if cmd.is_write() && db.is_read_only() => Frame::Error(DbError::ReadOnly.to_string()),
+        cmd if cmd.is_write() && !db.running_script().write() => Frame::Error(KILLED.to_string()),
+        cmd => cmd.apply_now(db, &mut ctx.borrow_mut()),
+    }
+}
+
+/// Returns the table `redis.error_reply` and `redis.status_reply` build.
+fn reply_table<'lua>(lua: &'lua Lua, field: &str, msg: String) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set(field, msg)?;
+    Ok(table)
+}
+
+/// Convert a command reply to a Lua value.
+///
+/// Replies are converted as RESP2 would encode them: maps and sets become
+/// arrays, doubles and big numbers strings, and booleans integers. Nil is
+/// `false`, status and error replies tables with an `ok` or `err` field.
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    let value = match frame {
+        Frame::Simple(msg) => Value::Table(reply_table(lua, "ok", msg)?),
+        Frame::Error(msg) => Value::Table(reply_table(lua, "err", msg)?),
+        Frame::Integer(value) => Value::Integer(value),
+        Frame::Bulk(data) | Frame::Verbatim(_, data) => Value::String(lua.create_string(&data)?),
+        Frame::Null | Frame::NullArray | Frame::Attribute(_) => Value::Boolean(false),
+        Frame::Array(frames) | Frame::Set(frames) | Frame::Push(frames) => {
+            let values = frames
+                .into_iter()
+                .map(|frame| to_lua(lua, frame))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            Value::Table(lua.create_sequence_from(values)?)
+        }
+        Frame::Map(pairs) => {
+            let mut values = Vec::with_capacity(pairs.len() * 2);
+            for (key, value) in pairs {
+                values.push(to_lua(lua, key)?);
+                values.push(to_lua(lua, value)?);
+            }
+            Value::Table(lua.create_sequence_from(values)?)
+        }
+        Frame::Double(value) => {
+            Value::String(lua.create_string(crate::frame::format_double(value))?)
+        }
+        Frame::BigNumber(value) => Value::String(lua.create_string(value)?),
+        Frame::Boolean(value) => Value::Integer(value as i64),
+    };
+
+    Ok(value)
+}
+
+/// Convert the value returned by a script to its reply.
+///
+/// Numbers are truncated to integers, `true` is `1`, and `false` and nil are
+/// nil. A table with an `err` or `ok` field is an error or status reply, other
+/// tables are arrays ending at their first nil.
+fn from_lua(value: Value) -> Frame {
+    match value {
+        Value::Integer(value) => Frame::Integer(value),
+        Value::Number(value) => Frame::Integer(value as i64),
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::String(data) => Frame::Bulk(Bytes::copy_from_slice(data.as_bytes())),
+        Value::Table(table) => {
+            if let Ok(Value::String(msg)) = table.raw_get("err") {
+                return Frame::Error(single_line(&msg.to_string_lossy()));
+            }
+            if let Ok(Value::String(msg)) = table.raw_get("ok") {
+                return Frame::Simple(single_line(&msg.to_string_lossy()));
+            }
+
+            let mut frames = vec![];
+            for i in 1.. {
+                match table.raw_get(i) {
+                    Ok(Value::Nil) | Err(_) => break,
+                    Ok(value) => frames.push(from_lua(value)),
+                }
+            }
+            Frame::Array(frames)
+        }
+        _ => Frame::Null,
+    }
+}
+
+/// Replaces the line breaks of a status or error reply returned by a script,
+/// which would otherwise end the reply early and let the rest through as
+/// other replies.
+fn single_line(msg: &str) -> String {
+    msg.replace(['\r', '\n'], " ")
+}
+
+/// Returns the reply of a script which raised `err`.
+fn error_frame(err: mlua::Error) -> Frame {
+    if let Some(ReplyError(msg)) = reply_error(&err) {
+        return Frame::Error(msg.clone());
+    }
+
+    let msg = match err {
+        mlua::Error::SyntaxError { message, .. } => {
+            format!("ERR Error compiling script: {}", message)
+        }
+        mlua::Error::RuntimeError(message) => format!("ERR Error running script: {}", message),
+        mlua::Error::CallbackError { cause, .. } => {
+            format!("ERR Error running script: {}", cause)
+        }
+        err => format!("ERR Error running script: {}", err),
+    };
+
+    // Error replies are a single line, the stack traceback is left out.
+    let msg = msg.lines().next().unwrap_or_default();
+    Frame::Error(msg.to_string())
+}
+
+/// Returns the `ReplyError` which caused `err`, if any.
+fn reply_error(err: &mlua::Error) -> Option<&ReplyError> {
+    match err {
+        mlua::Error::CallbackError { cause, .. } => reply_error(cause),
+        err => err.downcast_ref(),
+    }
+}
+
+impl fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for ReplyError {}
@@ -3,19 +3,195 @@
 //! Provides an async `run` function that listens for inbound connections,
 //! spawning a task per connection.
 
-use crate::{Command, Connection, Db, DbDropGuard, Shutdown};
-
-use std::future::Future;
+use crate::cmd::TransactionState;
+use crate::db::DbError;
+use crate::frame;
+use crate::scripting;
+use crate::{tls, Command, Connection, Db, DbDropGuard, Frame, ServerConfig, Shutdown, Transport};
+
+use std::future::{self, Future};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio_rustls::TlsAcceptor;
 use tokio::sync::{broadcast, mpsc, Semaphore};
 use tokio::time::{self, Duration};
 use tracing::{debug, error, info, instrument};
 
+/// A source of inbound connections the server accepts, such as a
+/// `TcpListener`. See `run_with`.
+pub trait Accept: Send {
+    /// Transport of the accepted connections.
+    type Stream: Transport + 'static;
+
+    /// Accept the next inbound connection.
+    fn accept(&mut self) -> impl Future<Output = io::Result<Self::Stream>> + Send;
+
+    /// Returns the address connections are accepted on, advertised to the
+    /// other nodes in cluster mode. `None` if there is no such address.
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Returns `true` if `stream` is local to the host, such as a Unix
+    /// socket. Local connections are served in plaintext even with TLS
+    /// configured, like Redis does.
+    fn is_local(_stream: &Self::Stream) -> bool {
+        false
+    }
+}
+
+impl Accept for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&mut self) -> io::Result<TcpStream> {
+        let (socket, _) = TcpListener::accept(self).await?;
+        Ok(socket)
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        TcpListener::local_addr(self).ok()
+    }
+}
+
+#[cfg(unix)]
+impl Accept for UnixListener {
+    type Stream = UnixStream;
+
+    async fn accept(&mut self) -> io::Result<UnixStream> {
+        let (socket, _) = UnixListener::accept(self).await?;
+        Ok(socket)
+    }
+
+    fn is_local(_stream: &UnixStream) -> bool {
+        true
+    }
+}
+
+/// Connections accepted from both sources, e.g. a `TcpListener` and a
+/// `UnixListener`. The address of the first source is advertised if it has
+/// one.
+impl<A: Accept, B: Accept> Accept for (A, B) {
+    type Stream = Either<A::Stream, B::Stream>;
+
+    async fn accept(&mut self) -> io::Result<Either<A::Stream, B::Stream>> {
+        tokio::select! {
+            res = self.0.accept() => Ok(Either::First(res?)),
+            res = self.1.accept() => Ok(Either::Second(res?)),
+        }
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.0.local_addr().or_else(|| self.1.local_addr())
+    }
+
+    fn is_local(stream: &Either<A::Stream, B::Stream>) -> bool {
+        match stream {
+            Either::First(stream) => A::is_local(stream),
+            Either::Second(stream) => B::is_local(stream),
+        }
+    }
+}
+
+/// A connection accepted from either source of a pair, see `Accept`.
+#[derive(Debug)]
+pub enum Either<A, B> {
+    First(A),
+    Second(B),
+}
+
+impl<A: Transport, B: Transport> AsyncRead for Either<A, B> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Either::First(stream) => Pin::new(stream).poll_read(cx, buf),
+            Either::Second(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<A: Transport, B: Transport> AsyncWrite for Either<A, B> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Either::First(stream) => Pin::new(stream).poll_write(cx, buf),
+            Either::Second(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Either::First(stream) => Pin::new(stream).poll_flush(cx),
+            Either::Second(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Either::First(stream) => Pin::new(stream).poll_shutdown(cx),
+            Either::Second(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Accepts connections from all the listeners, e.g. one per bound address.
+impl<A: Accept> Accept for Vec<A> {
+    type Stream = A::Stream;
+
+    async fn accept(&mut self) -> io::Result<A::Stream> {
+        let mut accepts: Vec<_> = self.iter_mut().map(|l| Box::pin(l.accept())).collect();
+
+        // Completes with the first connection accepted, never if empty.
+        future::poll_fn(|cx| {
+            accepts
+                .iter_mut()
+                .find_map(|accept| match accept.as_mut().poll(cx) {
+                    Poll::Ready(res) => Some(res),
+                    Poll::Pending => None,
+                })
+                .map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.iter().find_map(Accept::local_addr)
+    }
+
+    fn is_local(stream: &A::Stream) -> bool {
+        A::is_local(stream)
+    }
+}
+
+/// Connections handed over a channel, e.g. in-memory `DuplexStream` pipes.
+/// Once every sender is dropped, no more connections are accepted.
+impl<T: Transport + 'static> Accept for mpsc::Receiver<T> {
+    type Stream = T;
+
+    async fn accept(&mut self) -> io::Result<T> {
+        match self.recv().await {
+            Some(stream) => Ok(stream),
+            None => future::pending().await,
+        }
+    }
+}
+
 /// Server listener state. Created in the `run` call. It includes a `run` method
-/// which performs the TCP listening and initialization of per-connection state.
+/// which accepts the connections and initializes the per-connection state.
 #[derive(Debug)]
-struct Listener {
+struct Listener<L> {
     /// Shared database handle.
     ///
     /// Contains the key / value store as well as the broadcast channels for
@@ -25,10 +201,15 @@ struct Listener {
     /// retrieved and passed into the per connection state (`Handler`).
     db_holder: DbDropGuard,
 
-    /// TCP listener supplied by the `run` caller.
-    listener: TcpListener,
+    /// Source of connections supplied by the `run` caller.
+    listener: L,
+
+    /// TLS configuration connections are accepted with, `None` for plain TCP
+    /// connections. Local connections are accepted in plaintext regardless,
+    /// see `Accept::is_local`.
+    tls: Option<Arc<rustls::ServerConfig>>,
 
-    /// Limit the max number of connections.
+    /// Limit the max number of connections to `maxclients`.
     ///
     /// A `Semaphore` is used to limit the max number of connections. Before
     /// attempting to accept a new connection, a permit is acquired from the
@@ -38,6 +219,9 @@ struct Listener {
     /// to the semaphore.
     limit_connections: Arc<Semaphore>,
 
+    /// Idle time after which connections are closed, zero to keep them open.
+    timeout: Duration,
+
     /// Broadcasts a shutdown signal to all active connections.
     ///
     /// The initial `shutdown` trigger is provided by the `run` caller. The
@@ -74,10 +258,11 @@ struct Handler {
     /// will need to interact with `db` in order to complete the work.
     db: Db,
 
-    /// The TCP connection decorated with the redis protocol encoder / decoder
-    /// implemented using a buffered `TcpStream`.
+    /// The connection decorated with the redis protocol encoder / decoder
+    /// implemented using a buffered transport, e.g. a `TcpStream` or a TLS
+    /// stream over it.
     ///
-    /// When `Listener` receives an inbound connection, the `TcpStream` is
+    /// When `Listener` receives an inbound connection, the transport is
     /// passed to `Connection::new`, which initializes the associated buffers.
     /// `Connection` allows the handler to operate at the "frame" level and keep
     /// the byte level protocol parsing details encapsulated in `Connection`.
@@ -93,24 +278,26 @@ struct Handler {
     /// which point the connection is terminated.
     shutdown: Shutdown,
 
-    /// Not used directly. Instead, when `Handler` is dropped...?
+    /// Commands queued by `MULTI` and keys watched by `WATCH`.
+    ///
+    /// Transactions are local to a connection: while one is open, commands
+    /// are queued instead of being applied, until `EXEC` or `DISCARD`.
+    transaction: TransactionState,
+
+    /// Set by `ASKING`, letting the next command access the slots imported
+    /// by the node in cluster mode.
+    asking: bool,
+
+    /// Close the connection once no command was received for this long,
+    /// unless zero.
+    timeout: Duration,
+
+    /// Not used directly. Instead, when `Handler` is dropped, this sender is
+    /// dropped too. Once every handler is dropped, the receiver held by the
+    /// server completes, telling it all connections finished processing.
     _shutdown_complete: mpsc::Sender<()>,
 }
 
-/// Maximum number of concurrent connections the redis server will accept.
-///
-/// When this limit is reached, the server will stop accepting connections until
-/// an active connection terminates.
-///
-/// A real application will want to make this value configurable, but for this
-/// example, it is hard coded.
-///
-/// This is also set to a pretty low value to discourage using this in
-/// production (you'd think that all the disclaimers would make it obvious that
-/// this is not a serious project... but I thought that about mini-http as
-/// well).
-const MAX_CONNECTIONS: usize = 250;
-
 /// Run the mini-redis server.
 ///
 /// Accepts connections from the supplied listener. For each inbound connection,
@@ -120,7 +307,67 @@ const MAX_CONNECTIONS: usize = 250;
 ///
 /// `tokio::signal::ctrl_c()` can be used as the `shutdown` argument. This will
 /// listen for a SIGINT signal.
-pub async fn run(listener: TcpListener, shutdown: impl Future) {
+///
+/// `config` holds the settings of the server, `ServerConfig::default()`
+/// providing sensible values. Up to `maxclients` connections are handled at
+/// once, further ones waiting to be accepted.
+///
+/// The key space is loaded from the RDB file before accepting connections,
+/// an error being returned if the file cannot be loaded. When save rules are
+/// configured, a last snapshot is taken once the server shut down.
+///
+/// In cluster mode, the topology of the cluster is loaded from its config
+/// file as well, the address of `listener` being advertised if the file is
+/// created.
+///
+/// With TLS configured, connections are accepted over TLS only, an error
+/// being returned if the certificate or key cannot be loaded. This applies
+/// to every source given to `run_with`, except for Unix sockets: like Redis,
+/// local connections are served in plaintext.
+pub async fn run(
+    listener: TcpListener,
+    config: ServerConfig,
+    shutdown: impl Future,
+) -> crate::Result<()> {
+    run_with(listener, config, shutdown).await
+}
+
+/// Run the mini-redis server, accepting connections from any source such as
+/// a Unix socket or in-memory pipes. See `run`.
+///
+/// Cluster mode requires the source to have an address to advertise.
+///
+/// # Examples
+///
+/// ```
+/// use my_redis::{server, Connection, Frame, ServerConfig};
+/// use tokio::sync::mpsc;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (tx, rx) = mpsc::channel(1);
+///     let config = ServerConfig {
+///         rdb_path: std::env::temp_dir().join("run-with-example.rdb"),
+///         save_rules: vec![],
+///         ..ServerConfig::default()
+///     };
+///     tokio::spawn(server::run_with(rx, config, std::future::pending::<()>()));
+///
+///     let (client, server) = tokio::io::duplex(1024);
+///     tx.send(server).await.unwrap();
+///
+///     let mut client = Connection::new(client);
+///     let ping = Frame::Array(vec![Frame::Bulk("PING".into())]);
+///     client.write_frame(&ping).await.unwrap();
+///     let frame = client.read_frame().await.unwrap();
+///     assert!(matches!(frame, Some(Frame::Simple(s)) if s == "PONG"));
+/// }
+/// ```
+pub async fn run_with<L: Accept>(
+    listener: L,
+    config: ServerConfig,
+    shutdown: impl Future,
+) -> crate::Result<()> {
     // When the provided `shutdown` future completes, we must send a shutdown
     // message to all active connections. We use a broadcast channel for this
     // purpose. The call below ignores the receiver of the broadcast pair, and when
@@ -129,15 +376,40 @@ pub async fn run(listener: TcpListener, shutdown: impl Future) {
     let (notify_shutdown, _) = broadcast::channel(1);
     let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel(1);
 
+    // Advertised to the other nodes of a new cluster.
+    let addr = listener.local_addr();
+
     // Initialize the listener state
     let mut server = Listener {
         listener,
-        db_holder: DbDropGuard::new(),
-        limit_connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+        tls: config.tls.as_ref().map(tls::server_config).transpose()?,
+        db_holder: DbDropGuard::new(&config),
+        limit_connections: Arc::new(Semaphore::new(config.maxclients)),
+        timeout: config.timeout,
         notify_shutdown,
         shutdown_complete_tx,
     };
 
+    let loaded = server.db_holder.db().load()?;
+    if config.appendonly {
+        info!(keys = loaded, path = ?config.aof_path, "loaded the append only file");
+    } else {
+        info!(keys = loaded, path = ?config.rdb_path, "loaded the RDB file");
+    }
+
+    if let Some(path) = &config.cluster_config {
+        let addr = addr.ok_or("cluster mode requires connections accepted on an address")?;
+        server.db_holder.db().load_cluster(path, addr)?;
+        info!(?path, "cluster mode enabled");
+    }
+    if let Some(path) = &config.aclfile {
+        server.db_holder.db().load_acl(path)?;
+        info!(?path, "loaded the ACL file");
+    }
+    if let Some(leader) = config.replicaof.clone() {
+        server.db_holder.db().replicaof(Some(leader));
+    }
+
     // Concurrently run the server and listen for the `shutdown` signal. The
     // server task runs until an error is encountered, so under normal
     // circumstances, this `select!` statement runs until the `shutdown` signal
@@ -195,9 +467,23 @@ pub async fn run(listener: TcpListener, shutdown: impl Future) {
     // `Sender` instances are held by connection handler tasks. When those drop,
     // the `mpsc` channel will close and `recv()` will return `None`.
     let _ = shutdown_complete_rx.recv().await;
+
+    // No client is connected anymore, the snapshot holds every write.
+    if !config.save_rules.is_empty() {
+        if let Err(err) = server.db_holder.db().save() {
+            error!(cause = %err, "failed to save on shutdown");
+        }
+    }
+    if config.appendonly {
+        if let Err(err) = server.db_holder.db().sync_aof() {
+            error!(cause = %err, "failed to flush the append only file on shutdown");
+        }
+    }
+
+    Ok(())
 }
 
-impl Listener {
+impl<L: Accept> Listener<L> {
     /// Run the server
     ///
     /// Listen for inbound connections. For each inbound connection, spawn a
@@ -237,26 +523,54 @@ impl Listener {
             // error here is non-recoverable.
             let socket = self.accept().await?;
 
-            // Create the necessary per-connection handler state.
-            let mut handler = Handler {
-                // Get a handle to the shared database.
-                db: self.db_holder.db(),
-
-                // Initialize the connection state. This allocates read/write
-                // buffers to perform redis protocol frame parsing.
-                connection: Connection::new(socket),
-
-                // Receive shutdown notifications.
-                shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
-
-                // Notifies the receiver half once all clones are
-                // dropped.
-                _shutdown_complete: self.shutdown_complete_tx.clone(),
-            };
+            // Get a handle to the shared database.
+            let db = self.db_holder.db();
+            // Receive shutdown notifications.
+            let shutdown = Shutdown::new(self.notify_shutdown.subscribe());
+            // Notifies the receiver half once all clones are dropped.
+            let shutdown_complete = self.shutdown_complete_tx.clone();
+            let tls = self.tls.clone().filter(|_| !L::is_local(&socket));
+            let timeout = self.timeout;
 
             // Spawn a new task to process the connections. Tokio tasks are like
             // asynchronous green threads and are executed concurrently.
             tokio::spawn(async move {
+                // Initialize the connection state. This allocates read/write
+                // buffers to perform redis protocol frame parsing. The TLS
+                // handshake is done here, so that a slow client does not hold
+                // up accepting the others.
+                let connection = match tls {
+                    Some(tls) => match TlsAcceptor::from(tls).accept(socket).await {
+                        Ok(stream) => Connection::new(stream),
+                        Err(err) => {
+                            debug!(cause = %err, "TLS handshake failed");
+                            return;
+                        }
+                    },
+                    None => Connection::new(socket),
+                };
+
+                // Create the necessary per-connection handler state.
+                let mut handler = Handler {
+                    connection,
+                    db,
+                    shutdown,
+
+                    // Connections start outside of any transaction.
+                    transaction: TransactionState::default(),
+
+                    asking: false,
+
+                    timeout,
+
+                    _shutdown_complete: shutdown_complete,
+                };
+
+                // Clients need to authenticate unless the `default` user does
+                // not require a password.
+                let user = handler.db.default_user();
+                handler.connection.set_user(user);
+
                 // Process the connection. If an error is encountered, log it.
                 if let Err(err) = handler.run().await {
                     error!(cause = ?err, "connection error");
@@ -275,7 +589,7 @@ impl Listener {
     /// After the second failure, the task waits for 2 seconds. Each subsequent
     /// failure doubles the wait time. If accepting fails on the 6th try after
     /// waiting for 64 seconds, then this function returns with an error.
-    async fn accept(&mut self) -> crate::Result<TcpStream> {
+    async fn accept(&mut self) -> crate::Result<L::Stream> {
         let mut backoff = 1;
 
         // Try to accept a few times
@@ -283,7 +597,7 @@ impl Listener {
             // Perform the accept operation. If a socket is successfully
             // accepted, return it. Otherwise, save the error.
             match self.listener.accept().await {
-                Ok((socket, _)) => return Ok(socket),
+                Ok(socket) => return Ok(socket),
                 Err(err) => {
                     if backoff > 64 {
                         // Accept has failed too many times. Return the error.
@@ -320,14 +634,30 @@ impl Handler {
         // new request frame.
         while !self.shutdown.is_shutdown() {
             // While reading a request frame, also listen for the shutdown
-            // signal.
+            // signal and wait for the client to time out.
             let maybe_frame = tokio::select! {
-                res = self.connection.read_frame() => res?,
+                res = self.connection.read_frame() => match res {
+                    Ok(frame) => frame,
+                    Err(err) => {
+                        // Some invalid requests are replied an error, telling
+                        // the client why its connection is closed.
+                        if let Some(frame::Error::Protocol(msg)) = err.downcast_ref() {
+                            let response = Frame::Error(msg.to_string());
+                            debug!(?response);
+                            let _ = self.connection.write_frame(&response).await;
+                        }
+                        return Err(err);
+                    }
+                },
                 _ = self.shutdown.recv() => {
                     // If a shutdown signal is received, return from `run`.
                     // This will result in the task terminating.
                     return Ok(());
                 }
+                _ = idle(self.timeout) => {
+                    debug!(timeout = ?self.timeout, "closing idle connection");
+                    return Ok(());
+                }
             };
 
             // If `None` is returned from `read_frame()` then the peer closed
@@ -338,10 +668,29 @@ impl Handler {
                 None => return Ok(()),
             };
 
+            // A blank inline command, e.g. an empty line typed in a telnet
+            // session, is ignored.
+            if matches!(frame, Frame::Array(ref args) if args.is_empty()) {
+                continue;
+            }
+
             // Convert the redis frame into a command struct. This returns an
             // error if the frame is not a valid redis command or it is an
             // unsupported command.
-            let cmd = Command::from_frame(frame)?;
+            //
+            // Invalid arguments are replied an error, keeping the connection
+            // open. Other errors are protocol errors, closing it.
+            let cmd = match Command::from_frame(frame) {
+                Ok(cmd) => cmd,
+                Err(err) if err.to_string().starts_with("ERR ") => {
+                    self.transaction.abort();
+                    let response = Frame::Error(err.to_string());
+                    debug!(?response);
+                    self.connection.write_frame(&response).await?;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
 
             // Logs the `cmd` object. The syntax here is a shorthand provided by
             // the `tracing` crate. It can be thought of as similar to:
@@ -361,10 +710,145 @@ impl Handler {
             // command to write response frames directly to the connection. In
             // the case of pub/sub, multiple frames may be send back to the
             // peer.
-            cmd.apply(&self.db, &mut self.connection, &mut self.shutdown)
-                .await?;
+            //
+            // The transaction commands are applied to the state of the
+            // connection, and other commands are queued while a transaction
+            // is open.
+            // When a password is required, a client which did not
+            // authenticate may only do so or check the server is alive.
+            // `HELLO` is refused unless it authenticates, see `Hello::apply`.
+            if self.connection.user().is_none()
+                && !matches!(cmd, Command::Auth(_) | Command::Hello(_) | Command::Ping(_))
+            {
+                self.transaction.abort();
+                let response = Frame::Error(DbError::NoAuth.to_string());
+                debug!(?response);
+                self.connection.write_frame(&response).await?;
+                continue;
+            }
+
+            // While a script runs, it holds the lock on the database. Other
+            // commands wait for it without holding up the runtime, until it
+            // ran for longer than the script timeout. They are then refused,
+            // except for `SCRIPT KILL`. The ACL of the user cannot be checked
+            // without the lock, any user may kill the script.
+            self.db.running_script().wait().await;
+            if self.db.running_script().is_busy() {
+                let response = match cmd {
+                    Command::Script(cmd) if cmd.is_kill() => cmd.execute(&self.db),
+                    _ => {
+                        self.transaction.abort();
+                        Frame::Error(DbError::Busy.to_string())
+                    }
+                };
+                debug!(?response);
+                self.connection.write_frame(&response).await?;
+                continue;
+            }
+
+            // In cluster mode, commands whose keys hash to a slot served by
+            // another node are redirected to it.
+            let asking = std::mem::take(&mut self.asking);
+            if let Err(redirect) = self.db.cluster_check(&cmd.keys(), asking) {
+                self.transaction.abort();
+                let response = Frame::Error(redirect.to_string());
+                debug!(?response);
+                self.connection.write_frame(&response).await?;
+                continue;
+            }
+
+            // Commands are checked against the ACL of the user when applied,
+            // see `Command::apply`. Those applied by the handler are checked
+            // here.
+            if matches!(
+                cmd,
+                Command::Multi(_)
+                    | Command::Exec(_)
+                    | Command::Discard(_)
+                    | Command::Watch(_)
+                    | Command::Unwatch(_)
+                    | Command::Asking(_)
+            ) {
+                if let Err(err) = self.db.acl_check(self.connection.user(), &cmd) {
+                    self.transaction.abort();
+                    let response = Frame::Error(err.to_string());
+                    debug!(?response);
+                    self.connection.write_frame(&response).await?;
+                    continue;
+                }
+            }
+
+            let transaction = &mut self.transaction;
+            match cmd {
+                Command::Multi(cmd) => cmd.apply(transaction, &mut self.connection).await?,
+                Command::Exec(cmd) => {
+                    cmd.apply(transaction, &self.db, &mut self.connection)
+                        .await?
+                }
+                Command::Discard(cmd) => cmd.apply(transaction, &mut self.connection).await?,
+                Command::Watch(cmd) => {
+                    cmd.apply(transaction, &self.db, &mut self.connection)
+                        .await?
+                }
+                // Followers refuse writes from clients. Scripts may only read,
+                // each write they make being refused instead.
+                cmd if cmd.is_write()
+                    && !matches!(cmd, Command::Eval(_) | Command::EvalSha(_))
+                    && self.db.is_read_only() =>
+                {
+                    transaction.abort();
+                    let response = Frame::Error(DbError::ReadOnly.to_string());
+                    debug!(?response);
+                    self.connection.write_frame(&response).await?
+                }
+                cmd if transaction.is_open() => {
+                    transaction
+                        .queue(cmd, &self.db, &mut self.connection)
+                        .await?
+                }
+                Command::Unwatch(cmd) => cmd.apply(transaction, &mut self.connection).await?,
+                Command::Asking(cmd) => {
+                    self.asking = true;
+                    let response = cmd.execute();
+                    debug!(?response);
+                    self.connection.write_frame(&response).await?
+                }
+                Command::Auth(cmd) => cmd.apply(&self.db, &mut self.connection).await?,
+                Command::Hello(cmd) => cmd.apply(&self.db, &mut self.connection).await?,
+                // Scripts are writes too, run on a blocking thread.
+                cmd @ (Command::Eval(_) | Command::EvalSha(_)) => {
+                    let response = scripting::spawn(&self.db, &self.connection, |db, dst| {
+                        db.atomically(&[], || cmd.apply_now(db, dst))
+                            .expect("no watched key")
+                    })
+                    .await?;
+                    self.connection.write_frame(&response).await?
+                }
+                // Writes are applied with the lock on the database held, so
+                // that they are propagated in the order they are applied.
+                // `XREADGROUP` may block and propagates itself.
+                cmd if cmd.is_write() && !matches!(cmd, Command::XReadGroup(_)) => {
+                    let response = self
+                        .db
+                        .atomically(&[], || cmd.apply_now(&self.db, &mut self.connection))
+                        .expect("no watched key");
+                    self.connection.write_frame(&response).await?
+                }
+                cmd => {
+                    cmd.apply(&self.db, &mut self.connection, &mut self.shutdown)
+                        .await?
+                }
+            }
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+/// Completes once `timeout` elapsed, never if it is zero.
+async fn idle(timeout: Duration) {
+    if timeout.is_zero() {
+        future::pending().await
+    } else {
+        time::sleep(timeout).await
+    }
+}
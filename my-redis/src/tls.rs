@@ -0,0 +1,115 @@
+//! TLS support, using rustls.
+//!
+//! The server accepts TLS connections when given a certificate and its
+//! private key, see `TlsConfig`. Given a CA certificate as well, clients must
+//! present a certificate signed by it. Clients connect with
+//! `Client::connect_tls`, trusting the CA the certificate of the server is
+//! signed by, see `TlsClientConfig`.
+//!
+//! Certificates and keys are read from PEM files.
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// TLS settings of the server.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// Certificate chain presented to the clients.
+    pub cert_file: PathBuf,
+
+    /// Private key of the certificate.
+    pub key_file: PathBuf,
+
+    /// CA certificates the certificates of the clients must be signed by.
+    /// `None` does not ask clients for a certificate.
+    pub ca_cert_file: Option<PathBuf>,
+}
+
+/// TLS settings of a client.
+#[derive(Debug, Clone)]
+pub struct TlsClientConfig {
+    /// CA certificates the certificate of the server must be signed by.
+    pub ca_cert_file: PathBuf,
+
+    /// Certificate chain presented to the server, when it requires one.
+    pub cert_file: Option<PathBuf>,
+
+    /// Private key of `cert_file`.
+    pub key_file: Option<PathBuf>,
+}
+
+/// Returns the rustls configuration the server accepts connections with.
+pub(crate) fn server_config(config: &TlsConfig) -> crate::Result<Arc<rustls::ServerConfig>> {
+    let builder = rustls::ServerConfig::builder();
+    let builder = match &config.ca_cert_file {
+        Some(path) => {
+            let verifier = WebPkiClientVerifier::builder(Arc::new(load_roots(path)?)).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let server_config =
+        builder.with_single_cert(load_certs(&config.cert_file)?, load_key(&config.key_file)?)?;
+    Ok(Arc::new(server_config))
+}
+
+/// Connect to the server at `socket`, checking its certificate is issued for
+/// `server_name`.
+pub(crate) async fn connect(
+    socket: TcpStream,
+    server_name: &str,
+    config: &TlsClientConfig,
+) -> crate::Result<TlsStream<TcpStream>> {
+    let builder =
+        rustls::ClientConfig::builder().with_root_certificates(load_roots(&config.ca_cert_file)?);
+    let client_config = match (&config.cert_file, &config.key_file) {
+        (Some(cert_file), Some(key_file)) => {
+            builder.with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err("a client certificate requires its private key, and conversely".into()),
+    };
+
+    let server_name = ServerName::try_from(server_name.to_string())?;
+    let connector = TlsConnector::from(Arc::new(client_config));
+    Ok(connector.connect(server_name, socket).await?)
+}
+
+/// Load the certificates of the PEM file at `path`.
+fn load_certs(path: &Path) -> crate::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {:?}", path).into());
+    }
+    Ok(certs)
+}
+
+/// Load the first private key of the PEM file at `path`.
+fn load_key(path: &Path) -> crate::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| format!("no private key found in {:?}", path).into())
+}
+
+/// Load the CA certificates of the PEM file at `path`.
+fn load_roots(path: &Path) -> crate::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+fn open(path: &Path) -> crate::Result<File> {
+    File::open(path).map_err(|err| format!("cannot open {:?}: {}", path, err).into())
+}
@@ -0,0 +1,197 @@
+use my_redis::{server, Connection, Frame, ServerConfig};
+
+use tokio::io;
+use tokio::sync::mpsc;
+
+/// Keys outside of the patterns granted to a user are refused, for reading
+/// as for writing.
+#[tokio::test]
+async fn key_pattern_denied() {
+    let tx = start_server("key-pattern-denied");
+    let mut client = connect_as_alice(&tx).await;
+
+    let reply = request(&mut client, &["SET", "allowed:1", "value"]).await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "OK"));
+
+    for args in [&["SET", "other", "value"][..], &["GET", "other"]] {
+        let reply = request(&mut client, args).await;
+        assert!(
+            matches!(&reply, Frame::Error(msg) if msg == "NOPERM No permissions to access a key"),
+            "{:?} replied {:?}",
+            args,
+            reply
+        );
+    }
+}
+
+/// A command refused inside a transaction aborts it, none of the commands
+/// queued being run.
+#[tokio::test]
+async fn noperm_aborts_transaction() {
+    let tx = start_server("noperm-aborts-transaction");
+    let mut client = connect_as_alice(&tx).await;
+
+    let reply = request(&mut client, &["MULTI"]).await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "OK"));
+
+    let reply = request(&mut client, &["SET", "allowed:1", "value"]).await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "QUEUED"));
+
+    let reply = request(&mut client, &["SET", "other", "value"]).await;
+    assert!(matches!(reply, Frame::Error(msg) if msg == "NOPERM No permissions to access a key"));
+
+    let reply = request(&mut client, &["EXEC"]).await;
+    assert!(
+        matches!(&reply, Frame::Error(msg) if msg == "EXECABORT Transaction discarded because of previous errors."),
+        "{:?}",
+        reply
+    );
+
+    let reply = request(&mut client, &["GET", "allowed:1"]).await;
+    assert!(matches!(reply, Frame::Null), "{:?}", reply);
+}
+
+/// The commands a script calls are checked against the user running it.
+#[tokio::test]
+async fn script_calls_checked() {
+    let tx = start_server("script-calls-checked");
+    let mut client = connect_as_alice(&tx).await;
+
+    let script = "return redis.call('SET', KEYS[1], 'value')";
+    let reply = request(&mut client, &["EVAL", script, "1", "allowed:1"]).await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "OK"));
+
+    let script = "return redis.call('SET', 'other', 'value')";
+    let reply = request(&mut client, &["EVAL", script, "0"]).await;
+    assert!(
+        matches!(&reply, Frame::Error(msg) if msg == "NOPERM No permissions to access a key"),
+        "{:?}",
+        reply
+    );
+
+    let mut admin = connect(&tx).await;
+    let reply = request(&mut admin, &["GET", "other"]).await;
+    assert!(matches!(reply, Frame::Null), "{:?}", reply);
+}
+
+/// With `requirepass`, commands are refused until the client authenticates,
+/// except for those it may authenticate with or check the server is alive.
+#[tokio::test]
+async fn noauth_until_authenticated() {
+    let tx = start_server_with(
+        "noauth-until-authenticated",
+        ServerConfig {
+            requirepass: Some("secret".to_string()),
+            ..ServerConfig::default()
+        },
+    );
+    let mut client = connect(&tx).await;
+
+    for args in [&["GET", "key"][..], &["MULTI"], &["EVAL", "return 1", "0"]] {
+        let reply = request(&mut client, args).await;
+        assert!(
+            matches!(&reply, Frame::Error(msg) if msg == "NOAUTH Authentication required."),
+            "{:?} replied {:?}",
+            args,
+            reply
+        );
+    }
+
+    let reply = request(&mut client, &["PING"]).await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "PONG"));
+
+    let reply = request(&mut client, &["HELLO", "3"]).await;
+    assert!(
+        matches!(&reply, Frame::Error(msg) if msg.starts_with("NOAUTH ")),
+        "{:?}",
+        reply
+    );
+
+    let reply = request(&mut client, &["AUTH", "wrong"]).await;
+    assert!(
+        matches!(&reply, Frame::Error(msg) if msg.starts_with("WRONGPASS ")),
+        "{:?}",
+        reply
+    );
+
+    let reply = request(&mut client, &["AUTH", "secret"]).await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "OK"));
+
+    let reply = request(&mut client, &["GET", "key"]).await;
+    assert!(matches!(reply, Frame::Null), "{:?}", reply);
+}
+
+/// `HELLO` authenticates the client when given `AUTH`.
+#[tokio::test]
+async fn hello_authenticates() {
+    let tx = start_server_with(
+        "hello-authenticates",
+        ServerConfig {
+            requirepass: Some("secret".to_string()),
+            ..ServerConfig::default()
+        },
+    );
+    let mut client = connect(&tx).await;
+
+    let reply = request(&mut client, &["HELLO", "3", "AUTH", "default", "secret"]).await;
+    assert!(matches!(reply, Frame::Map(_)), "{:?}", reply);
+
+    let reply = request(&mut client, &["GET", "key"]).await;
+    assert!(matches!(reply, Frame::Null), "{:?}", reply);
+}
+
+fn start_server(name: &str) -> mpsc::Sender<io::DuplexStream> {
+    start_server_with(name, ServerConfig::default())
+}
+
+fn start_server_with(name: &str, config: ServerConfig) -> mpsc::Sender<io::DuplexStream> {
+    let (tx, rx) = mpsc::channel(1);
+    let config = ServerConfig {
+        rdb_path: std::env::temp_dir().join(format!("{}-{}.rdb", name, std::process::id())),
+        save_rules: vec![],
+        ..config
+    };
+    tokio::spawn(server::run_with(rx, config, std::future::pending::<()>()));
+    tx
+}
+
+async fn connect(tx: &mpsc::Sender<io::DuplexStream>) -> Connection {
+    let (client, server) = io::duplex(1024);
+    tx.send(server).await.unwrap();
+    Connection::new(client)
+}
+
+/// Creates the user `alice`, granted every command on the keys starting with
+/// `allowed:`, and connects as this user.
+async fn connect_as_alice(tx: &mpsc::Sender<io::DuplexStream>) -> Connection {
+    let mut admin = connect(tx).await;
+    let reply = request(
+        &mut admin,
+        &[
+            "ACL",
+            "SETUSER",
+            "alice",
+            "on",
+            ">secret",
+            "~allowed:*",
+            "+@all",
+        ],
+    )
+    .await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "OK"));
+
+    let mut client = connect(tx).await;
+    let reply = request(&mut client, &["AUTH", "alice", "secret"]).await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "OK"));
+    client
+}
+
+async fn request(connection: &mut Connection, args: &[&str]) -> Frame {
+    let frame = Frame::Array(
+        args.iter()
+            .map(|arg| Frame::Bulk(arg.to_string().into()))
+            .collect(),
+    );
+    connection.write_frame(&frame).await.unwrap();
+    connection.read_frame().await.unwrap().unwrap()
+}
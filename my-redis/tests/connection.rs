@@ -0,0 +1,365 @@
+use my_redis::{server, Connection, Frame, Protocol, ServerConfig};
+
+use std::time::Duration;
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// RESP3 frames written on one end of an in-memory pipe are read back as is
+/// on the other end.
+#[tokio::test]
+async fn resp3_frames_round_trip() {
+    let (client, server) = io::duplex(64);
+    let mut client = Connection::new(client);
+    let mut server = Connection::new(server);
+    server.set_protocol(Protocol::Resp3);
+
+    let frame = Frame::Map(vec![
+        (
+            Frame::Bulk("list".into()),
+            Frame::Array(vec![Frame::Integer(1), Frame::Null]),
+        ),
+        (Frame::Simple("score".into()), Frame::Double(1.5)),
+        (
+            Frame::Boolean(true),
+            Frame::Set(vec![Frame::BigNumber("12345678901234567890".into())]),
+        ),
+    ]);
+
+    // The frame is larger than the pipe, the reader receiving it in parts.
+    let (written, read) = tokio::join!(server.write_frame(&frame), client.read_frame());
+    written.unwrap();
+    let read = read.unwrap().unwrap();
+    assert_eq!(format!("{:?}", frame), format!("{:?}", read));
+}
+
+/// RESP3 frames written to a connection still speaking RESP2 are downgraded.
+#[tokio::test]
+async fn resp3_frames_downgraded_to_resp2() {
+    let (client, server) = io::duplex(1024);
+    let mut client = Connection::new(client);
+    let mut server = Connection::new(server);
+
+    let frame = Frame::Map(vec![(Frame::Bulk("score".into()), Frame::Double(1.5))]);
+    server.write_frame(&frame).await.unwrap();
+
+    let read = client.read_frame().await.unwrap().unwrap();
+    assert_eq!(
+        format!(
+            "{:?}",
+            Frame::Array(vec![Frame::Bulk("score".into()), Frame::Bulk("1.5".into())])
+        ),
+        format!("{:?}", read),
+    );
+}
+
+/// A peer closing its end between frames ends the stream cleanly, closing it
+/// in the middle of a frame is an error.
+#[tokio::test]
+async fn end_of_stream() {
+    let (client, server) = io::duplex(1024);
+    let mut client = Connection::new(client);
+    drop(server);
+    assert!(client.read_frame().await.unwrap().is_none());
+
+    let (client, mut server) = io::duplex(1024);
+    let mut client = Connection::new(client);
+    server.write_all(b"$5\r\nhel").await.unwrap();
+    drop(server);
+    assert!(client.read_frame().await.is_err());
+}
+
+/// The server serves connections over in-memory pipes, sharing its key space
+/// between them.
+#[tokio::test]
+async fn server_over_duplex() {
+    let tx = start_server("server-over-duplex");
+
+    let mut first = connect(&tx).await;
+    let mut second = connect(&tx).await;
+
+    let reply = request(&mut first, &["SET", "hello", "world"]).await;
+    assert!(matches!(reply, Frame::Simple(s) if s == "OK"));
+
+    let reply = request(&mut second, &["GET", "hello"]).await;
+    assert!(matches!(reply, Frame::Bulk(value) if value == "world"));
+}
+
+/// An inline command longer than 64 KiB is refused, and the connection
+/// closed, even before its end is received.
+#[tokio::test]
+async fn too_big_inline_request() {
+    let tx = start_server("too-big-inline-request");
+
+    let (mut client, server) = io::duplex(1024);
+    tx.send(server).await.unwrap();
+
+    let mut request = b"SET key ".to_vec();
+    request.resize(64 * 1024 + 1, b'a');
+    client.write_all(&request).await.unwrap();
+
+    let mut client = Connection::new(client);
+    let reply = client.read_frame().await.unwrap();
+    assert!(
+        matches!(reply, Some(Frame::Error(msg)) if msg == "ERR Protocol error: too big inline request")
+    );
+    assert!(client.read_frame().await.unwrap().is_none());
+}
+
+/// Lengths overflowing once the pairs of a map or the trailing `\r\n` are
+/// counted are refused as invalid frames.
+#[tokio::test]
+async fn overflowing_lengths_refused() {
+    for request in [
+        &b"*1\r\n%9223372036854775808\r\n"[..],
+        b"=18446744073709551615\r\n",
+        b"$18446744073709551615\r\n",
+    ] {
+        let (mut client, server) = io::duplex(64);
+        let mut server = Connection::new(server);
+
+        client.write_all(request).await.unwrap();
+        let err = server.read_frame().await.unwrap_err();
+        assert_eq!(err.to_string(), "protocol error; invalid frame format");
+    }
+}
+
+/// A transaction aborted because a watched key changed is replied a null
+/// array in RESP2, as clients expect, rather than a null bulk string.
+#[tokio::test]
+async fn watch_aborted_exec_null_array() {
+    let tx = start_server("watch-aborted-exec-null-array");
+    let mut other = connect(&tx).await;
+
+    let (mut client, server) = io::duplex(1024);
+    tx.send(server).await.unwrap();
+
+    for (request, expected) in [
+        (&b"WATCH key\r\n"[..], &b"+OK\r\n"[..]),
+        (b"MULTI\r\n", b"+OK\r\n"),
+        (b"GET key\r\n", b"+QUEUED\r\n"),
+    ] {
+        client.write_all(request).await.unwrap();
+        let mut reply = vec![0; expected.len()];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, expected);
+    }
+
+    let reply = request(&mut other, &["SET", "key", "value"]).await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "OK"));
+
+    client.write_all(b"EXEC\r\n").await.unwrap();
+    let mut reply = [0; 5];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(&reply, b"*-1\r\n");
+}
+
+/// Commands with invalid arguments are replied an error, the connection
+/// staying open.
+#[tokio::test]
+async fn invalid_arguments_replied() {
+    let tx = start_server("invalid-arguments-replied");
+    let mut client = connect(&tx).await;
+
+    for (args, expected) in [
+        (
+            &["INCRBY", "counter", "abc"][..],
+            "ERR value is not an integer or out of range",
+        ),
+        (
+            &["INCRBYFLOAT", "counter", "inf"][..],
+            "ERR value is not a valid float",
+        ),
+        (
+            &["GET"][..],
+            "ERR wrong number of arguments for 'get' command",
+        ),
+        (
+            &["GET", "a", "b"][..],
+            "ERR wrong number of arguments for 'get' command",
+        ),
+    ] {
+        let reply = request(&mut client, args).await;
+        assert!(
+            matches!(&reply, Frame::Error(msg) if msg == expected),
+            "{:?} replied {:?}",
+            args,
+            reply
+        );
+    }
+
+    let reply = request(&mut client, &["PING"]).await;
+    assert!(matches!(reply, Frame::Simple(s) if s == "PONG"));
+}
+
+/// A number of keys larger than the arguments of `EVAL` is refused, before
+/// anything is allocated for them.
+#[tokio::test]
+async fn eval_numkeys_too_large() {
+    let tx = start_server("eval-numkeys-too-large");
+    let mut client = connect(&tx).await;
+
+    for numkeys in ["2", "1099511627776", "9223372036854775807"] {
+        let reply = request(&mut client, &["EVAL", "return 1", numkeys, "key"]).await;
+        assert!(
+            matches!(&reply, Frame::Error(msg) if msg == "ERR Number of keys can't be greater than number of args"),
+            "{} replied {:?}",
+            numkeys,
+            reply
+        );
+    }
+
+    let reply = request(&mut client, &["EVAL", "return KEYS[1]", "1", "key"]).await;
+    assert!(matches!(reply, Frame::Bulk(key) if key == "key"));
+}
+
+/// Line breaks in the status and error replies of a script are replaced,
+/// rather than ending the reply and letting the rest through as another one.
+#[tokio::test]
+async fn script_replies_single_line() {
+    let tx = start_server("script-replies-single-line");
+    let mut client = connect(&tx).await;
+
+    let reply = request(&mut client, &["EVAL", "return {ok='x\\r\\n+OK'}", "0"]).await;
+    assert!(
+        matches!(&reply, Frame::Simple(msg) if msg == "x  +OK"),
+        "{:?}",
+        reply
+    );
+
+    let reply = request(&mut client, &["EVAL", "return {err='ERR x\\n:1'}", "0"]).await;
+    assert!(
+        matches!(&reply, Frame::Error(msg) if msg == "ERR x :1"),
+        "{:?}",
+        reply
+    );
+
+    let reply = request(&mut client, &["PING"]).await;
+    assert!(matches!(reply, Frame::Simple(msg) if msg == "PONG"));
+}
+
+/// With a zero script timeout, other clients are replied `BUSY` while a
+/// script runs, and may kill it unless it wrote to the key space.
+#[tokio::test]
+async fn busy_script_killed() {
+    let tx = start_server_with(
+        "busy-script-killed",
+        ServerConfig {
+            script_timeout: Duration::ZERO,
+            ..ServerConfig::default()
+        },
+    );
+
+    let mut first = connect(&tx).await;
+    let mut second = connect(&tx).await;
+
+    first.write_frame(&eval("while true do end")).await.unwrap();
+    while !matches!(request(&mut second, &["GET", "hello"]).await, Frame::Error(msg) if msg.starts_with("BUSY"))
+    {
+        tokio::task::yield_now().await;
+    }
+
+    let reply = request(&mut second, &["SCRIPT", "KILL"]).await;
+    assert!(matches!(reply, Frame::Simple(s) if s == "OK"));
+    let reply = first.read_frame().await.unwrap().unwrap();
+    assert!(matches!(reply, Frame::Error(msg) if msg.contains("SCRIPT KILL")));
+
+    let reply = request(&mut second, &["SCRIPT", "KILL"]).await;
+    assert!(matches!(reply, Frame::Error(msg) if msg.starts_with("NOTBUSY")));
+
+    let script = eval("redis.call('SET', 'hello', 'world') for i = 1, 2e8 do end return 1");
+    first.write_frame(&script).await.unwrap();
+    loop {
+        match request(&mut second, &["SCRIPT", "KILL"]).await {
+            Frame::Error(msg) if msg.starts_with("UNKILLABLE") => break,
+            Frame::Error(msg) if msg.starts_with("NOTBUSY") => tokio::task::yield_now().await,
+            // Killed before it wrote, it is run again.
+            Frame::Simple(_) => {
+                first.read_frame().await.unwrap().unwrap();
+                first.write_frame(&script).await.unwrap();
+            }
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+    }
+
+    let reply = first.read_frame().await.unwrap().unwrap();
+    assert!(matches!(reply, Frame::Integer(1)));
+    let reply = request(&mut second, &["GET", "hello"]).await;
+    assert!(matches!(reply, Frame::Bulk(value) if value == "world"));
+}
+
+/// A script reaching the script timeout is aborted, unless it wrote to the
+/// key space: it then runs to completion, other clients being replied `BUSY`
+/// meanwhile.
+#[tokio::test]
+async fn script_timeout() {
+    let tx = start_server_with(
+        "script-timeout",
+        ServerConfig {
+            script_timeout: Duration::from_millis(100),
+            ..ServerConfig::default()
+        },
+    );
+
+    let mut first = connect(&tx).await;
+    let mut second = connect(&tx).await;
+
+    first.write_frame(&eval("while true do end")).await.unwrap();
+    let reply = first.read_frame().await.unwrap().unwrap();
+    assert!(matches!(reply, Frame::Error(msg) if msg == "ERR Script timed out after 100 ms"));
+
+    let script = eval("redis.call('SET', 'hello', 'world') for i = 1, 2e8 do end return 1");
+    first.write_frame(&script).await.unwrap();
+    loop {
+        match request(&mut second, &["GET", "hello"]).await {
+            Frame::Error(msg) if msg.starts_with("BUSY") => break,
+            // The script did not start yet.
+            Frame::Null => tokio::task::yield_now().await,
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+    }
+
+    let reply = first.read_frame().await.unwrap().unwrap();
+    assert!(matches!(reply, Frame::Integer(1)));
+    let reply = request(&mut second, &["GET", "hello"]).await;
+    assert!(matches!(reply, Frame::Bulk(value) if value == "world"));
+}
+
+/// Start a server accepting the in-memory pipes sent on the returned channel.
+fn start_server(name: &str) -> mpsc::Sender<io::DuplexStream> {
+    start_server_with(name, ServerConfig::default())
+}
+
+fn start_server_with(name: &str, config: ServerConfig) -> mpsc::Sender<io::DuplexStream> {
+    let (tx, rx) = mpsc::channel(1);
+    let config = ServerConfig {
+        rdb_path: std::env::temp_dir().join(format!("{}-{}.rdb", name, std::process::id())),
+        save_rules: vec![],
+        ..config
+    };
+    tokio::spawn(server::run_with(rx, config, std::future::pending::<()>()));
+    tx
+}
+
+async fn connect(tx: &mpsc::Sender<io::DuplexStream>) -> Connection {
+    let (client, server) = io::duplex(1024);
+    tx.send(server).await.unwrap();
+    Connection::new(client)
+}
+
+async fn request(connection: &mut Connection, args: &[&str]) -> Frame {
+    let frame = Frame::Array(
+        args.iter()
+            .map(|arg| Frame::Bulk(arg.to_string().into()))
+            .collect(),
+    );
+    connection.write_frame(&frame).await.unwrap();
+    connection.read_frame().await.unwrap().unwrap()
+}
+
+fn eval(script: &str) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk("EVAL".into()),
+        Frame::Bulk(script.to_string().into()),
+        Frame::Bulk("0".into()),
+    ])
+}
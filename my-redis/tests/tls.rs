@@ -0,0 +1,152 @@
+use my_redis::{clients::Client, server, ServerConfig, TlsClientConfig, TlsConfig};
+
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+/// A client trusting the CA of the server connects over TLS, when the server
+/// does not ask for a client certificate.
+#[tokio::test]
+async fn connect_tls() {
+    let certs = Certs::generate("connect-tls");
+    let addr = start_server(&certs, false).await;
+
+    let mut client = Client::connect_tls(addr, "localhost", &certs.client_config(false))
+        .await
+        .unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+}
+
+/// A server requiring client certificates accepts a client presenting one
+/// signed by its CA, and refuses a client presenting none.
+#[tokio::test]
+async fn connect_tls_with_client_cert() {
+    let certs = Certs::generate("connect-tls-with-client-cert");
+    let addr = start_server(&certs, true).await;
+
+    let mut client = Client::connect_tls(addr, "localhost", &certs.client_config(true))
+        .await
+        .unwrap();
+    let pong = client.ping(None).await.unwrap();
+    assert_eq!(b"PONG", &pong[..]);
+
+    // With TLS 1.3 the handshake completes on the side of the client before
+    // the server checks its certificate, the refusal showing on first use.
+    let refused = match Client::connect_tls(addr, "localhost", &certs.client_config(false)).await {
+        Ok(mut client) => client.ping(None).await.map(drop),
+        Err(err) => Err(err),
+    };
+    assert!(refused.is_err());
+}
+
+/// With TLS configured, connections over the Unix socket are served in
+/// plaintext, those over TCP still requiring TLS.
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_socket_in_plaintext() {
+    let certs = Certs::generate("unix-socket-in-plaintext");
+    let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = tcp_listener.local_addr().unwrap();
+    let path = certs.dir.join("redis.sock");
+    let unix_listener = UnixListener::bind(&path).unwrap();
+
+    let config = ServerConfig {
+        rdb_path: certs.dir.join("dump.rdb"),
+        save_rules: vec![],
+        tls: Some(certs.server_config(false)),
+        ..ServerConfig::default()
+    };
+    tokio::spawn(server::run_with(
+        (tcp_listener, unix_listener),
+        config,
+        std::future::pending::<()>(),
+    ));
+
+    let mut client = Client::connect_unix(&path).await.unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+
+    let mut client = Client::connect_tls(addr, "localhost", &certs.client_config(false))
+        .await
+        .unwrap();
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+}
+
+/// PEM files of a CA, and of the server and client certificates it signed.
+struct Certs {
+    dir: PathBuf,
+}
+
+impl Certs {
+    fn generate(name: &str) -> Certs {
+        let dir =
+            std::env::temp_dir().join(format!("my-redis-tls-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        write(&dir, "ca.crt", &ca.pem());
+
+        for (name, san) in [("server", "localhost"), ("client", "client")] {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![san.to_string()])
+                .unwrap()
+                .signed_by(&key, &ca, &ca_key)
+                .unwrap();
+            write(&dir, &format!("{}.crt", name), &cert.pem());
+            write(&dir, &format!("{}.key", name), &key.serialize_pem());
+        }
+
+        Certs { dir }
+    }
+
+    fn server_config(&self, require_client_cert: bool) -> TlsConfig {
+        TlsConfig {
+            cert_file: self.dir.join("server.crt"),
+            key_file: self.dir.join("server.key"),
+            ca_cert_file: require_client_cert.then(|| self.dir.join("ca.crt")),
+        }
+    }
+
+    fn client_config(&self, with_cert: bool) -> TlsClientConfig {
+        TlsClientConfig {
+            ca_cert_file: self.dir.join("ca.crt"),
+            cert_file: with_cert.then(|| self.dir.join("client.crt")),
+            key_file: with_cert.then(|| self.dir.join("client.key")),
+        }
+    }
+}
+
+impl Drop for Certs {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn write(dir: &Path, name: &str, contents: &str) {
+    std::fs::write(dir.join(name), contents).unwrap();
+}
+
+/// Start a server accepting TLS connections only, returning its address.
+async fn start_server(certs: &Certs, require_client_cert: bool) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = ServerConfig {
+        rdb_path: certs.dir.join("dump.rdb"),
+        save_rules: vec![],
+        tls: Some(certs.server_config(require_client_cert)),
+        ..ServerConfig::default()
+    };
+    tokio::spawn(server::run(listener, config, std::future::pending::<()>()));
+
+    addr
+}